@@ -0,0 +1,309 @@
+//! Support for CocoaPods' `Podfile`, backed by the CocoaPods trunk API. Pods are versioned and
+//! constrained just like gems, so their versions are compared the way RubyGems does.
+
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use serde_json::Value;
+use tower_lsp::lsp_types::{Position, Range, Url};
+
+use crate::fetcher::PackageVersionFetcher;
+use crate::response_cache::ResponseCache;
+use crate::rubygems::{self, GemRelease, GemVersion};
+
+const TRUNK_API: &str = "https://trunk.cocoapods.org/api/v1";
+
+/// How long what trunk told about a pod is used before asking again.
+const POD_TTL: Duration = Duration::from_secs(10 * 60);
+
+pub(super) fn is_manifest(uri: &Url) -> bool {
+    uri.path().rsplit('/').next() == Some("Podfile")
+}
+
+/// A pod declaration such as `pod 'Alamofire', '~> 5.0'`.
+#[derive(Debug, PartialEq)]
+pub(super) struct Pod {
+    /// Name of the pod, without the subspec of declarations like `pod 'Firebase/Analytics'`.
+    pub name: String,
+    pub requirements: Vec<String>,
+    /// Range of the declared name, excluding the quotes around it.
+    pub name_range: Range,
+}
+
+/// Returns the pod declared on the line the cursor is on, if any.
+pub(super) fn pod_at(text: &str, position: Position) -> Option<Pod> {
+    let line = text.lines().nth(position.line as usize)?;
+    let indent = line.len() - line.trim_start().len();
+    let rest = line[indent..].strip_prefix("pod")?;
+    if !rest.starts_with([' ', '\t', '(']) {
+        return None;
+    }
+    let arguments = rubygems::string_arguments(line, indent + "pod".len());
+    let ((name_start, name), requirements) = arguments.split_first()?;
+    let column = |offset: usize| line[..offset].encode_utf16().count() as u32;
+    Some(Pod {
+        name: name.split('/').next().unwrap_or(name).to_string(),
+        requirements: requirements
+            .iter()
+            .map(|(_, requirement)| requirement.to_string())
+            .collect(),
+        name_range: Range::new(
+            Position::new(position.line, column(*name_start)),
+            Position::new(position.line, column(*name_start + name.len())),
+        ),
+    })
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct PodInfo {
+    /// The newest release that isn't a prerelease, unless there are only prereleases.
+    pub latest_version: String,
+    pub date: Option<DateTime<FixedOffset>>,
+    pub releases: Vec<GemRelease>,
+}
+
+/// What the podspec of a release tells about the pod.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct PodSpec {
+    pub summary: String,
+    pub homepage: Option<String>,
+}
+
+/// Remembers what trunk told about each pod, so that hovering it again doesn't ask again.
+pub(super) struct PodCache {
+    infos: ResponseCache<String, PodInfo>,
+    /// Keyed by pod and version.
+    specs: ResponseCache<(String, String), PodSpec>,
+}
+
+impl Default for PodCache {
+    fn default() -> Self {
+        Self {
+            infos: ResponseCache::new(POD_TTL),
+            specs: ResponseCache::new(POD_TTL),
+        }
+    }
+}
+
+impl PodCache {
+    pub(super) async fn info(
+        &self,
+        fetcher: &PackageVersionFetcher,
+        name: &str,
+    ) -> Option<PodInfo> {
+        self.infos
+            .get(&name.to_string(), || fetch_info(fetcher, name))
+            .await
+    }
+
+    pub(super) async fn spec(
+        &self,
+        fetcher: &PackageVersionFetcher,
+        name: &str,
+        version: &str,
+    ) -> Option<PodSpec> {
+        let key = (name.to_string(), version.to_string());
+        self.specs
+            .get(&key, || fetch_spec(fetcher, name, version))
+            .await
+    }
+}
+
+async fn fetch_info(fetcher: &PackageVersionFetcher, name: &str) -> Option<PodInfo> {
+    let pod = get_json(fetcher, &format!("pods/{}", urlencoding::encode(name))).await?;
+    parse_info(&pod)
+}
+
+async fn fetch_spec(fetcher: &PackageVersionFetcher, name: &str, version: &str) -> Option<PodSpec> {
+    let spec = get_json(
+        fetcher,
+        &format!(
+            "pods/{}/specs/{}",
+            urlencoding::encode(name),
+            urlencoding::encode(version)
+        ),
+    )
+    .await?;
+    Some(parse_spec(&spec))
+}
+
+async fn get_json(fetcher: &PackageVersionFetcher, path: &str) -> Option<Value> {
+    fetcher
+        .request_json(|client| client.get(format!("{TRUNK_API}/{path}")))
+        .await
+}
+
+/// Reads the versions trunk lists for a pod, each with when it was pushed, as in
+/// `"2024-03-26 17:57:07 UTC"`.
+fn parse_info(pod: &Value) -> Option<PodInfo> {
+    let versions: Vec<_> = pod["versions"]
+        .as_array()?
+        .iter()
+        .filter_map(|version| {
+            let number = version["name"].as_str()?;
+            let release = GemRelease {
+                version: GemVersion::parse(number)?,
+                number: number.to_string(),
+            };
+            let date = version["created_at"].as_str().and_then(|date| {
+                let date = date.trim_end_matches(" UTC");
+                let date = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").ok()?;
+                Some(date.and_utc().fixed_offset())
+            });
+            Some((release, date))
+        })
+        .collect();
+    let (latest, date) = versions
+        .iter()
+        .filter(|(release, _)| !release.version.is_prerelease())
+        .max_by(|(a, _), (b, _)| a.version.cmp(&b.version))
+        .or_else(|| {
+            versions
+                .iter()
+                .max_by(|(a, _), (b, _)| a.version.cmp(&b.version))
+        })?;
+    Some(PodInfo {
+        latest_version: latest.number.clone(),
+        date: *date,
+        releases: versions
+            .iter()
+            .map(|(release, _)| release.clone())
+            .collect(),
+    })
+}
+
+fn parse_spec(spec: &Value) -> PodSpec {
+    PodSpec {
+        summary: spec["summary"]
+            .as_str()
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        homepage: spec["homepage"]
+            .as_str()
+            .filter(|homepage| !homepage.is_empty())
+            .map(ToString::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::{FetchMode, RegistryConfig};
+    use serde_json::json;
+    use std::time::Instant;
+
+    #[test]
+    fn test_is_manifest() {
+        assert!(is_manifest(&Url::parse("file:///app/Podfile").unwrap()));
+        assert!(!is_manifest(
+            &Url::parse("file:///app/Podfile.lock").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_pod_at() {
+        let text = r#"platform :ios, '15.0'
+
+target 'App' do
+  pod 'Alamofire', '~> 5.0'
+  pod "Firebase/Analytics"
+  pod 'SnapKit', :git => 'https://github.com/SnapKit/SnapKit.git'
+  pods_for_tests
+end
+"#;
+        let pod = pod_at(text, Position::new(3, 4)).unwrap();
+        assert_eq!(pod.name, "Alamofire");
+        assert_eq!(pod.requirements, ["~> 5.0"]);
+        assert_eq!(
+            pod.name_range,
+            Range::new(Position::new(3, 7), Position::new(3, 16))
+        );
+
+        let pod = pod_at(text, Position::new(4, 0)).unwrap();
+        assert_eq!(pod.name, "Firebase");
+        assert_eq!(pod.name_range.end.character, 25);
+        let pod = pod_at(text, Position::new(5, 0)).unwrap();
+        assert_eq!(pod.name, "SnapKit");
+        assert!(pod.requirements.is_empty());
+
+        assert_eq!(pod_at(text, Position::new(0, 0)), None);
+        assert_eq!(pod_at(text, Position::new(2, 0)), None);
+        assert_eq!(pod_at(text, Position::new(6, 4)), None);
+
+        // Columns count UTF-16 code units, which the ideographic space is a single one of.
+        let pod = pod_at("\u{3000}pod 'Alamofire'", Position::new(0, 0)).unwrap();
+        assert_eq!(
+            pod.name_range,
+            Range::new(Position::new(0, 6), Position::new(0, 15))
+        );
+    }
+
+    #[test]
+    fn test_parse_info() {
+        let info = parse_info(&json!({
+            "name": "Alamofire",
+            "versions": [
+                { "name": "5.9.0", "created_at": "2024-03-24 14:10:00 UTC" },
+                { "name": "5.10.0-beta.1", "created_at": "2024-04-02 09:00:00 UTC" },
+                { "name": "5.9.1", "created_at": "2024-03-26 17:57:07 UTC" },
+                { "name": "4.9.1", "created_at": "2019-10-26 21:19:38 UTC" }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(info.latest_version, "5.9.1");
+        assert_eq!(
+            info.date.map(|date| date.to_rfc3339()).as_deref(),
+            Some("2024-03-26T17:57:07+00:00")
+        );
+        assert_eq!(info.releases.len(), 4);
+        let newest = rubygems::newest_matching(&info.releases, &["~> 4.0".to_string()]);
+        assert_eq!(newest.map(|release| release.number.as_str()), Some("4.9.1"));
+
+        let prereleases = parse_info(&json!({
+            "versions": [{ "name": "1.0.0-beta.2" }, { "name": "1.0.0-beta.1" }]
+        }))
+        .unwrap();
+        assert_eq!(prereleases.latest_version, "1.0.0-beta.2");
+        assert_eq!(prereleases.date, None);
+        assert!(parse_info(&json!({ "versions": [] })).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pod_cache() {
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            fetch_mode: FetchMode::Offline,
+            ..Default::default()
+        });
+        let cache = PodCache::default();
+        let info = PodInfo {
+            latest_version: "5.9.1".into(),
+            date: None,
+            releases: Vec::new(),
+        };
+        cache
+            .infos
+            .insert_for_tests("Alamofire".into(), Instant::now(), info);
+        // Trunk is never asked offline, so only cached pods are known.
+        let cached = cache.info(&fetcher, "Alamofire").await.unwrap();
+        assert_eq!(cached.latest_version, "5.9.1");
+        assert!(cache.info(&fetcher, "SnapKit").await.is_none());
+        assert_eq!(cache.spec(&fetcher, "Alamofire", "5.9.1").await, None);
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            parse_spec(&json!({
+                "name": "Alamofire",
+                "summary": "Elegant HTTP Networking in Swift ",
+                "homepage": "https://github.com/Alamofire/Alamofire"
+            })),
+            PodSpec {
+                summary: "Elegant HTTP Networking in Swift".into(),
+                homepage: Some("https://github.com/Alamofire/Alamofire".into()),
+            }
+        );
+    }
+}
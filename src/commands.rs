@@ -0,0 +1,78 @@
+//! Commands the server executes through `workspace/executeCommand`, e.g. when a code lens is clicked.
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::Url;
+
+/// Updates every outdated dependency of a section, or of the whole document, to its latest version.
+pub(super) const UPDATE_ALL: &str = "packageVersionServer.updateAllDependencies";
+
+/// Updates a single dependency to its latest version.
+pub(super) const UPDATE_DEPENDENCY: &str = "packageVersionServer.updateDependency";
+
+/// Forgets every fetched package and checks the open documents against the registries again.
+pub(super) const CLEAR_CACHE: &str = "packageVersionServer.clearCache";
+
+/// Shows the homepage, repository or npm page of a package, whichever is known first.
+pub(super) const OPEN_PACKAGE_PAGE: &str = "packageVersionServer.openPackagePage";
+
+/// Lists the dependencies of a section that have known vulnerabilities, offering to open their
+/// advisories.
+pub(super) const SHOW_VULNERABILITIES: &str = "packageVersionServer.showVulnerabilities";
+
+/// Fetches a well-known package from the default registry and reports how it went, to tell
+/// network, authentication and parsing problems apart.
+pub(super) const DIAGNOSE: &str = "packageVersionServer.diagnose";
+
+/// The package the `diagnose` command fetches.
+pub(super) const DIAGNOSED_PACKAGE: &str = "lodash";
+
+/// Every command the server advertises.
+pub(super) const ALL: &[&str] = &[
+    UPDATE_ALL,
+    UPDATE_DEPENDENCY,
+    CLEAR_CACHE,
+    DIAGNOSE,
+    OPEN_PACKAGE_PAGE,
+    SHOW_VULNERABILITIES,
+];
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(super) struct UpdateAllArguments {
+    pub uri: Url,
+    /// Name of the section to update, as it appears in `package.json`. Every section is updated
+    /// if it is missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(super) struct ShowVulnerabilitiesArguments {
+    pub uri: Url,
+    /// Name of the section, as it appears in `package.json`.
+    pub section: String,
+}
+
+/// Data of the code lens counting the vulnerable dependencies of a section, which is nested so
+/// that it isn't mistaken for the arguments of the section's update lens.
+#[derive(Debug, Deserialize, Serialize)]
+pub(super) struct SecurityLensData {
+    pub security: ShowVulnerabilitiesArguments,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(super) struct OpenPackagePageArguments {
+    /// Name of the package on the registry, which differs from the dependency's for aliases.
+    pub name: String,
+    /// The document declaring the package, which is fetched from the registries of its project.
+    /// The workspace's registries are used if it is missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(super) struct UpdateDependencyArguments {
+    pub uri: Url,
+    /// Name of the section the dependency is declared in, as it appears in `package.json`.
+    pub section: String,
+    pub name: String,
+}
@@ -0,0 +1,880 @@
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{
+    CompletionClientCapabilities, CompletionItem, CompletionItemKind, CompletionItemLabelDetails,
+    CompletionItemTag, CompletionList, CompletionTextEdit, Documentation, InsertTextFormat,
+    MarkupContent, MarkupKind, TextEdit, Url,
+};
+
+use crate::diagnostics;
+use crate::fetcher::{PackageVersion, SearchResult};
+use crate::parser::{BundledName, DependencyKey, NewDependency, ParseResult};
+use crate::settings::{CompletionMode, CompletionSettings, CompletionStyle};
+
+/// Operators that may precede the version the user is typing. Longer operators come first so that
+/// `>=` is not mistaken for `>`.
+const VERSION_OPERATORS: [&str; 11] = [
+    "===", "!==", ">=", "<=", "==", "!=", ">", "<", "=", "~", "^",
+];
+
+/// What the client can do with completion items beyond inserting their `insertText`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct CompletionCapabilities {
+    /// Whether items may replace a range of the document. LSP has no capability for it, so clients
+    /// that don't describe what they support of completion items at all are assumed to only honor
+    /// `insertText`.
+    pub text_edits: bool,
+    pub snippets: bool,
+    pub label_details: bool,
+    /// Whether what an item inserts may still change once it is resolved.
+    pub lazy_edits: bool,
+}
+
+impl CompletionCapabilities {
+    pub(super) fn from_client(capabilities: Option<&CompletionClientCapabilities>) -> Self {
+        let item = capabilities.and_then(|capabilities| capabilities.completion_item.as_ref());
+        let resolvable = |property: &str| {
+            item.and_then(|item| item.resolve_support.as_ref())
+                .is_some_and(|support| support.properties.iter().any(|name| name == property))
+        };
+        Self {
+            text_edits: item.is_some(),
+            snippets: item.and_then(|item| item.snippet_support) == Some(true),
+            label_details: item.and_then(|item| item.label_details_support) == Some(true),
+            lazy_edits: resolvable(if item.is_some() {
+                "textEdit"
+            } else {
+                "insertText"
+            }),
+        }
+    }
+}
+
+pub(super) fn strip_operator(version: &str) -> &str {
+    VERSION_OPERATORS
+        .iter()
+        .find_map(|operator| version.strip_prefix(operator))
+        .unwrap_or(version)
+}
+
+/// Builds the completion list for the version specifier the user is currently typing, out of
+/// `package_versions` sorted newest first.
+///
+/// Accepting an item replaces the whole specifier while keeping its operator, and wraps it in
+/// quotes if it is not a JSON string yet. Clients without support for text edits get the bare
+/// version inserted instead.
+///
+/// In [`CompletionStyle::Grouped`] mode only the newest release of each minor within the current
+/// major (and the newest release of each older major) is offered. Once a full `major.minor.`
+/// prefix has been typed, every matching version is listed.
+///
+/// In [`CompletionMode::Compatible`] mode, only versions satisfying the range that is being edited
+/// are offered, as long as it parses.
+///
+/// As what is offered depends on what has been typed so far, the list is always marked as
+/// incomplete, so that the client asks again as the user keeps typing instead of filtering a stale
+/// list.
+///
+/// Each item documents the version's description, and deprecated versions say so in their label.
+pub(super) fn version_completions(
+    package_versions: Vec<PackageVersion>,
+    target: &ParseResult,
+    settings: &CompletionSettings,
+    capabilities: CompletionCapabilities,
+) -> CompletionList {
+    let query = strip_operator(&target.version);
+    let operator = &target.version[..target.version.len() - query.len()];
+    let compatible_range = match settings.mode {
+        CompletionMode::All => None,
+        CompletionMode::Compatible => diagnostics::parse_range(&target.version),
+    };
+    let matching: Vec<_> = package_versions
+        .into_iter()
+        .filter(|package_version| {
+            compatible_range
+                .as_ref()
+                .map_or(true, |range| range.test(&package_version.version))
+        })
+        .filter(|package_version| package_version.version.to_string().starts_with(query))
+        .collect();
+
+    let exhaustive = settings.style == CompletionStyle::All || query.matches('.').count() >= 2;
+    let mut shown = if exhaustive {
+        matching
+    } else {
+        group_versions(matching, query)
+    };
+    if !targets_prerelease(query) {
+        // Stable sort, so both halves stay in descending order.
+        shown.sort_by_key(|package_version| package_version.version.has_prerelease());
+    }
+
+    let items = shown
+        .into_iter()
+        .enumerate()
+        .map(|(ix, package_version)| {
+            let version = package_version.version.to_string();
+            let mut item = CompletionItem {
+                detail: format_date(&package_version, &settings.date_format),
+                documentation: version_documentation(&package_version),
+                sort_text: Some(format!("{ix:05}")),
+                ..Default::default()
+            };
+            if capabilities.text_edits {
+                let specifier = format!("{operator}{version}");
+                let new_text = if target.quoted {
+                    specifier.clone()
+                } else {
+                    format!("\"{specifier}\"")
+                };
+                item.filter_text = Some(specifier);
+                item.text_edit = Some(CompletionTextEdit::Edit(TextEdit {
+                    range: target.version_range,
+                    new_text,
+                }));
+            } else {
+                item.insert_text = Some(version.clone());
+            }
+            if capabilities.label_details {
+                item.label_details = Some(CompletionItemLabelDetails {
+                    detail: None,
+                    description: package_version.date.map(crate::format_time),
+                });
+            }
+            let label = if package_version.deprecated.is_some() {
+                item.tags = Some(vec![CompletionItemTag::DEPRECATED]);
+                // Typing the version still matches it.
+                item.filter_text.get_or_insert_with(|| version.clone());
+                format!("{version} (deprecated)")
+            } else {
+                version
+            };
+            CompletionItem { label, ..item }
+        })
+        .collect();
+    CompletionList {
+        is_incomplete: true,
+        items,
+    }
+}
+
+/// The description of a version, followed by why it's deprecated if it is.
+fn version_documentation(package_version: &PackageVersion) -> Option<Documentation> {
+    let mut value = package_version.description.trim().to_string();
+    if let Some(message) = &package_version.deprecated {
+        if !value.is_empty() {
+            value.push_str("\n\n");
+        }
+        value.push_str(&format!("**Deprecated:** {message}"));
+    }
+    (!value.is_empty()).then_some(Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    }))
+}
+
+/// Formats the publish date of a version, falling back to the default format if the configured
+/// one is invalid.
+fn format_date(package_version: &PackageVersion, format: &str) -> Option<String> {
+    use std::fmt::Write;
+    let published = package_version.date?;
+    let mut date = String::new();
+    if write!(date, "{}", published.format(format)).is_err() {
+        date.clear();
+        let default_format = CompletionSettings::default().date_format;
+        write!(date, "{}", published.format(&default_format)).ok();
+    }
+    Some(date)
+}
+
+/// Name of the DefinitelyTyped package holding the declarations for `package_name`, following its
+/// name mangling rules for scoped packages (`@scope/name` becomes `@types/scope__name`).
+pub(super) fn types_package_name(package_name: &str) -> Option<String> {
+    if package_name.starts_with("@types/") {
+        return None;
+    }
+    match package_name.strip_prefix('@') {
+        Some(scoped) => {
+            let (scope, name) = scoped.split_once('/')?;
+            Some(format!("@types/{scope}__{name}"))
+        }
+        None => Some(format!("@types/{package_name}")),
+    }
+}
+
+/// Builds name completions for the given `@types` packages, each paired with the dependency it
+/// provides declarations for.
+pub(super) fn types_completions(
+    candidates: Vec<(String, String)>,
+    target: &DependencyKey,
+    capabilities: CompletionCapabilities,
+) -> CompletionList {
+    let items = candidates
+        .into_iter()
+        .map(|(types_package, package_name)| {
+            let (insert_text, text_edit) = if capabilities.text_edits {
+                let edit = TextEdit {
+                    range: target.range,
+                    new_text: types_package.clone(),
+                };
+                (None, Some(CompletionTextEdit::Edit(edit)))
+            } else {
+                (Some(types_package.clone()), None)
+            };
+            CompletionItem {
+                label: types_package,
+                kind: Some(CompletionItemKind::MODULE),
+                detail: Some(format!("types for {package_name}")),
+                insert_text,
+                text_edit,
+                ..Default::default()
+            }
+        })
+        .collect();
+    CompletionList {
+        is_incomplete: false,
+        items,
+    }
+}
+
+/// Builds completions for a name in a `bundleDependencies` array out of the dependencies that can
+/// be bundled.
+pub(super) fn bundled_completions(
+    candidates: Vec<String>,
+    target: &BundledName,
+    capabilities: CompletionCapabilities,
+) -> CompletionList {
+    let items = candidates
+        .into_iter()
+        .map(|package_name| {
+            let (insert_text, text_edit) = if capabilities.text_edits {
+                let edit = TextEdit {
+                    range: target.range,
+                    new_text: package_name.clone(),
+                };
+                (None, Some(CompletionTextEdit::Edit(edit)))
+            } else {
+                (Some(package_name.clone()), None)
+            };
+            CompletionItem {
+                label: package_name,
+                kind: Some(CompletionItemKind::MODULE),
+                insert_text,
+                text_edit,
+                ..Default::default()
+            }
+        })
+        .collect();
+    CompletionList {
+        is_incomplete: false,
+        items,
+    }
+}
+
+/// What resolving the completion of a new dependency needs to look up the version it inserts.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct NewDependencyData {
+    pub uri: Url,
+    pub name: String,
+    /// The latest version according to the search, which the entry holds until it is resolved.
+    pub version: String,
+}
+
+/// Builds completions for a dependency being added out of the packages the registry found for its
+/// name. Accepting one inserts the whole entry with a caret range of the latest version, which is
+/// left selected as a snippet placeholder in clients that support snippets.
+///
+/// The list is marked as incomplete, so that the registry is searched again as the name is typed.
+/// In clients that resolve edits lazily, items carry what [`resolve_new_dependency`] needs.
+pub(super) fn new_dependency_completions(
+    uri: &Url,
+    results: Vec<SearchResult>,
+    target: &NewDependency,
+    capabilities: CompletionCapabilities,
+) -> CompletionList {
+    let comma = if target.needs_comma { "," } else { "" };
+    let quoted = target.range.end.character - target.range.start.character
+        > target.query.encode_utf16().count() as u32;
+    let items = results
+        .into_iter()
+        .map(|result| {
+            let entry = format!("\"{}\": \"^{}\"{comma}", result.name, result.version);
+            let (insert_text, insert_text_format, text_edit) = match capabilities {
+                CompletionCapabilities {
+                    text_edits: true,
+                    snippets: true,
+                    ..
+                } => {
+                    let edit = TextEdit {
+                        range: target.range,
+                        new_text: format!(
+                            "\"{}\": \"^${{1:{}}}\"{comma}$0",
+                            result.name, result.version
+                        ),
+                    };
+                    (
+                        None,
+                        Some(InsertTextFormat::SNIPPET),
+                        Some(CompletionTextEdit::Edit(edit)),
+                    )
+                }
+                CompletionCapabilities {
+                    text_edits: true, ..
+                } => {
+                    let edit = TextEdit {
+                        range: target.range,
+                        new_text: entry,
+                    };
+                    (None, None, Some(CompletionTextEdit::Edit(edit)))
+                }
+                _ => (Some(entry), None, None),
+            };
+            let data = capabilities.lazy_edits.then(|| {
+                let data = NewDependencyData {
+                    uri: uri.clone(),
+                    name: result.name.clone(),
+                    version: result.version.clone(),
+                };
+                serde_json::to_value(data).ok()
+            });
+            CompletionItem {
+                filter_text: quoted.then(|| format!("\"{}\"", result.name)),
+                data: data.flatten(),
+                label: result.name,
+                kind: Some(CompletionItemKind::MODULE),
+                detail: Some(result.version),
+                documentation: result.description.map(Documentation::String),
+                insert_text,
+                insert_text_format,
+                text_edit,
+                ..Default::default()
+            }
+        })
+        .collect();
+    CompletionList {
+        is_incomplete: true,
+        items,
+    }
+}
+
+/// Makes the completion of a new dependency insert `version`, the latest one of the registry the
+/// package is fetched from, rather than the one the search found.
+pub(super) fn resolve_new_dependency(item: &mut CompletionItem, found: &str, version: &str) {
+    // The name is quoted before the range, so it can't hold `"^` itself.
+    let replace = |text: &mut String| {
+        *text = text
+            .replacen(&format!("\"^{found}\""), &format!("\"^{version}\""), 1)
+            .replacen(
+                &format!("\"^${{1:{found}}}\""),
+                &format!("\"^${{1:{version}}}\""),
+                1,
+            );
+    };
+    if let Some(text) = &mut item.insert_text {
+        replace(text);
+    }
+    if let Some(CompletionTextEdit::Edit(edit)) = &mut item.text_edit {
+        replace(&mut edit.new_text);
+    }
+    item.detail = Some(version.to_string());
+}
+
+/// Whether the user is explicitly looking for a prerelease, e.g. `5.0.0-` or `5.0.0-rc`.
+fn targets_prerelease(query: &str) -> bool {
+    query.contains('-')
+        || query
+            .chars()
+            .any(|c| c.is_ascii_alphabetic() && !c.eq_ignore_ascii_case(&'x'))
+}
+
+/// Picks one representative per group out of `versions`, which must be sorted in descending order.
+/// Stable releases are preferred as representatives; a group made up solely of prereleases is
+/// represented by its newest prerelease.
+fn group_versions(versions: Vec<PackageVersion>, query: &str) -> Vec<PackageVersion> {
+    let Some(current_major) = current_major(&versions, query) else {
+        return versions;
+    };
+    let group_key = |version: &semver_rs::Version| {
+        if version.major == current_major {
+            (version.major, Some(version.minor))
+        } else {
+            (version.major, None)
+        }
+    };
+
+    let mut groups: Vec<((i64, Option<i64>), PackageVersion)> = Vec::new();
+    for package_version in versions {
+        let key = group_key(&package_version.version);
+        match groups.last_mut() {
+            Some((last_key, representative)) if *last_key == key => {
+                if representative.version.has_prerelease()
+                    && !package_version.version.has_prerelease()
+                {
+                    *representative = package_version;
+                }
+            }
+            _ => groups.push((key, package_version)),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, representative)| representative)
+        .collect()
+}
+
+/// The major the user is working within: the one they typed explicitly, or the newest stable one.
+fn current_major(versions: &[PackageVersion], query: &str) -> Option<i64> {
+    if let Some((major, _)) = query.split_once('.') {
+        if let Ok(major) = major.parse() {
+            return Some(major);
+        }
+    }
+    versions
+        .iter()
+        .find(|package_version| !package_version.version.has_prerelease())
+        .or_else(|| versions.first())
+        .map(|package_version| package_version.version.major)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use crate::fetcher::tests::package_version;
+    use crate::parser::DependencyKind;
+
+    /// Newest first, as the fetcher stores them.
+    fn package_versions(versions: &[&str]) -> Vec<PackageVersion> {
+        let mut versions: Vec<_> = versions
+            .iter()
+            .map(|version| package_version(version))
+            .collect();
+        versions.sort_by(|lhs, rhs| rhs.version.cmp(&lhs.version));
+        versions
+    }
+
+    fn target(version: &str, quoted: bool) -> ParseResult {
+        let range = Range {
+            start: Position {
+                line: 2,
+                character: 5,
+            },
+            end: Position {
+                line: 2,
+                character: 5 + version.len() as u32,
+            },
+        };
+        ParseResult {
+            package_name: "lodash".into(),
+            version: version.into(),
+            section: DependencyKind::Dependencies,
+            match_range: range,
+            version_range: range,
+            quoted,
+        }
+    }
+
+    const RICH: CompletionCapabilities = CompletionCapabilities {
+        text_edits: true,
+        snippets: true,
+        label_details: true,
+        lazy_edits: false,
+    };
+
+    fn settings(style: CompletionStyle) -> CompletionSettings {
+        CompletionSettings {
+            style,
+            ..Default::default()
+        }
+    }
+
+    fn new_text(item: &CompletionItem) -> &str {
+        match item.text_edit.as_ref().unwrap() {
+            CompletionTextEdit::Edit(edit) => &edit.new_text,
+            CompletionTextEdit::InsertAndReplace(edit) => &edit.new_text,
+        }
+    }
+
+    fn labels(list: &CompletionList) -> Vec<&str> {
+        let mut items: Vec<_> = list.items.iter().collect();
+        items.sort_by(|lhs, rhs| lhs.sort_text.cmp(&rhs.sort_text));
+        items.into_iter().map(|item| item.label.as_str()).collect()
+    }
+
+    const FIXTURE: [&str; 10] = [
+        "2.0.0",
+        "2.1.0",
+        "3.0.0",
+        "3.5.2",
+        "4.16.0",
+        "4.16.4",
+        "4.17.0",
+        "4.17.21",
+        "4.17.20",
+        "5.0.0-beta.1",
+    ];
+
+    #[test]
+    fn test_strip_operator() {
+        assert_eq!(strip_operator("^4.17"), "4.17");
+        assert_eq!(strip_operator(">=1.0"), "1.0");
+        assert_eq!(strip_operator("~1"), "1");
+        assert_eq!(strip_operator("1.2.3"), "1.2.3");
+    }
+
+    #[test]
+    fn test_grouped_completions() {
+        let list = version_completions(
+            package_versions(&FIXTURE),
+            &target("^", true),
+            &settings(CompletionStyle::Grouped),
+            RICH,
+        );
+        assert!(list.is_incomplete);
+        assert_eq!(
+            labels(&list),
+            ["4.17.21", "4.16.4", "3.5.2", "2.1.0", "5.0.0-beta.1"]
+        );
+
+        let list = version_completions(
+            package_versions(&FIXTURE),
+            &target("3.", true),
+            &settings(CompletionStyle::Grouped),
+            RICH,
+        );
+        assert_eq!(labels(&list), ["3.5.2", "3.0.0"]);
+    }
+
+    #[test]
+    fn test_full_prefix_lists_every_version() {
+        let list = version_completions(
+            package_versions(&FIXTURE),
+            &target("^4.17.", true),
+            &settings(CompletionStyle::Grouped),
+            RICH,
+        );
+        // Typing more digits narrows the list down, so it is still asked for again.
+        assert!(list.is_incomplete);
+        assert_eq!(labels(&list), ["4.17.21", "4.17.20", "4.17.0"]);
+    }
+
+    #[test]
+    fn test_all_style_lists_every_version() {
+        let list = version_completions(
+            package_versions(&FIXTURE),
+            &target("", true),
+            &settings(CompletionStyle::All),
+            RICH,
+        );
+        assert!(list.is_incomplete);
+        assert_eq!(list.items.len(), FIXTURE.len());
+    }
+
+    #[test]
+    fn test_compatible_mode_filters_by_range() {
+        let settings = CompletionSettings {
+            mode: CompletionMode::Compatible,
+            ..settings(CompletionStyle::All)
+        };
+        let list = version_completions(
+            package_versions(&FIXTURE),
+            &target("^4.16", true),
+            &settings,
+            RICH,
+        );
+        assert_eq!(labels(&list), ["4.16.4", "4.16.0"]);
+
+        let list = version_completions(
+            package_versions(&FIXTURE),
+            &target("~4", true),
+            &settings,
+            RICH,
+        );
+        assert_eq!(
+            labels(&list),
+            ["4.17.21", "4.17.20", "4.17.0", "4.16.4", "4.16.0"]
+        );
+    }
+
+    const PRERELEASE_FIXTURE: [&str; 10] = [
+        "4.20.0",
+        "4.21.0-rc.1",
+        "4.21.0",
+        "4.21.2",
+        "4.22.0-canary.3",
+        "5.0.0-beta.1",
+        "5.0.0-beta.2",
+        "5.0.0-rc.1",
+        "5.1.0-canary.20240101",
+        "3.9.9",
+    ];
+
+    #[test]
+    fn test_stable_versions_come_first() {
+        let list = version_completions(
+            package_versions(&PRERELEASE_FIXTURE),
+            &target("", true),
+            &settings(CompletionStyle::All),
+            RICH,
+        );
+        assert_eq!(
+            labels(&list),
+            [
+                "4.21.2",
+                "4.21.0",
+                "4.20.0",
+                "3.9.9",
+                "5.1.0-canary.20240101",
+                "5.0.0-rc.1",
+                "5.0.0-beta.2",
+                "5.0.0-beta.1",
+                "4.22.0-canary.3",
+                "4.21.0-rc.1",
+            ]
+        );
+
+        let list = version_completions(
+            package_versions(&PRERELEASE_FIXTURE),
+            &target("^4.21.", true),
+            &settings(CompletionStyle::Grouped),
+            RICH,
+        );
+        assert_eq!(labels(&list), ["4.21.2", "4.21.0", "4.21.0-rc.1"]);
+    }
+
+    #[test]
+    fn test_prerelease_query_keeps_semver_order() {
+        let list = version_completions(
+            package_versions(&PRERELEASE_FIXTURE),
+            &target("5.0.0-", true),
+            &settings(CompletionStyle::All),
+            RICH,
+        );
+        assert_eq!(
+            labels(&list),
+            ["5.0.0-rc.1", "5.0.0-beta.2", "5.0.0-beta.1"]
+        );
+
+        assert!(targets_prerelease("5.0.0-rc"));
+        assert!(targets_prerelease("canary"));
+        assert!(!targets_prerelease("4.x"));
+        assert!(!targets_prerelease("4.21"));
+    }
+
+    #[test]
+    fn test_date_format() {
+        let mut settings = settings(CompletionStyle::All);
+        settings.date_format = String::from("%Y-%m-%d");
+        let list = version_completions(
+            package_versions(&["1.0.0"]),
+            &target("", true),
+            &settings,
+            RICH,
+        );
+        assert_eq!(list.items[0].detail.as_deref(), Some("2024-01-01"));
+
+        settings.date_format = String::from("%Q");
+        let list = version_completions(
+            package_versions(&["1.0.0"]),
+            &target("", true),
+            &settings,
+            RICH,
+        );
+        assert_eq!(list.items[0].detail.as_deref(), Some("01/01/2024 00:00"));
+    }
+
+    #[test]
+    fn test_completion_capability_profiles() {
+        let rich = version_completions(
+            package_versions(&FIXTURE),
+            &target("^3.5", false),
+            &settings(CompletionStyle::Grouped),
+            RICH,
+        );
+        let item = &rich.items[0];
+        assert_eq!(new_text(item), "\"^3.5.2\"");
+        assert!(item.insert_text.is_none());
+        assert!(item.label_details.is_some());
+
+        let plain = version_completions(
+            package_versions(&FIXTURE),
+            &target("^3.5", false),
+            &settings(CompletionStyle::Grouped),
+            CompletionCapabilities::default(),
+        );
+        let item = &plain.items[0];
+        assert_eq!(item.label, "3.5.2");
+        assert_eq!(item.insert_text.as_deref(), Some("3.5.2"));
+        assert!(item.text_edit.is_none());
+        assert!(item.filter_text.is_none());
+        assert!(item.label_details.is_none());
+    }
+
+    #[test]
+    fn test_capabilities_from_client() {
+        let capabilities: CompletionClientCapabilities =
+            serde_json::from_value(serde_json::json!({
+                "completionItem": { "snippetSupport": true, "labelDetailsSupport": true }
+            }))
+            .unwrap();
+        assert_eq!(
+            CompletionCapabilities::from_client(Some(&capabilities)),
+            RICH
+        );
+        assert_eq!(
+            CompletionCapabilities::from_client(None),
+            CompletionCapabilities::default()
+        );
+        let capabilities: CompletionClientCapabilities =
+            serde_json::from_value(serde_json::json!({ "completionItem": {} })).unwrap();
+        assert_eq!(
+            CompletionCapabilities::from_client(Some(&capabilities)),
+            CompletionCapabilities {
+                text_edits: true,
+                snippets: false,
+                label_details: false,
+                lazy_edits: false,
+            }
+        );
+        let capabilities: CompletionClientCapabilities = serde_json::from_value(serde_json::json!({
+            "completionItem": { "resolveSupport": { "properties": ["documentation", "textEdit"] } }
+        }))
+        .unwrap();
+        assert!(CompletionCapabilities::from_client(Some(&capabilities)).lazy_edits);
+    }
+
+    #[test]
+    fn test_new_dependency_completions() {
+        let results = || {
+            vec![SearchResult {
+                name: "lodash".into(),
+                version: "4.17.21".into(),
+                description: Some("Lodash modular utilities.".into()),
+            }]
+        };
+        let quoted = NewDependency {
+            section: DependencyKind::Dependencies,
+            query: "lod".into(),
+            range: Range::new(Position::new(0, 20), Position::new(0, 25)),
+            needs_comma: true,
+        };
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        let list = new_dependency_completions(&uri, results(), &quoted, RICH);
+        assert!(list.is_incomplete);
+        let item = &list.items[0];
+        assert_eq!(item.label, "lodash");
+        assert_eq!(item.detail.as_deref(), Some("4.17.21"));
+        assert_eq!(new_text(item), "\"lodash\": \"^${1:4.17.21}\",$0");
+        assert_eq!(item.insert_text_format, Some(InsertTextFormat::SNIPPET));
+        assert_eq!(item.filter_text.as_deref(), Some("\"lodash\""));
+        assert!(item.data.is_none());
+
+        let lazy = CompletionCapabilities {
+            lazy_edits: true,
+            ..RICH
+        };
+        let mut item = new_dependency_completions(&uri, results(), &quoted, lazy).items[0].clone();
+        let data: NewDependencyData = serde_json::from_value(item.data.clone().unwrap()).unwrap();
+        assert_eq!(
+            (data.name.as_str(), data.version.as_str()),
+            ("lodash", "4.17.21")
+        );
+        resolve_new_dependency(&mut item, &data.version, "4.18.0");
+        assert_eq!(new_text(&item), "\"lodash\": \"^${1:4.18.0}\",$0");
+        assert_eq!(item.detail.as_deref(), Some("4.18.0"));
+
+        let bare = NewDependency {
+            range: Range::new(Position::new(0, 20), Position::new(0, 23)),
+            needs_comma: false,
+            ..quoted
+        };
+        let without_snippets = CompletionCapabilities {
+            snippets: false,
+            ..RICH
+        };
+        let list = new_dependency_completions(&uri, results(), &bare, without_snippets);
+        let item = &list.items[0];
+        assert_eq!(new_text(item), "\"lodash\": \"^4.17.21\"");
+        assert_eq!(item.insert_text_format, None);
+
+        let list =
+            new_dependency_completions(&uri, results(), &bare, CompletionCapabilities::default());
+        let item = &list.items[0];
+        assert_eq!(
+            item.insert_text.as_deref(),
+            Some("\"lodash\": \"^4.17.21\"")
+        );
+        assert!(item.text_edit.is_none());
+        assert!(item.filter_text.is_none());
+    }
+
+    #[test]
+    fn test_types_package_name() {
+        assert_eq!(types_package_name("express").unwrap(), "@types/express");
+        assert_eq!(
+            types_package_name("@babel/core").unwrap(),
+            "@types/babel__core"
+        );
+        assert_eq!(types_package_name("@types/node"), None);
+        assert_eq!(types_package_name("@invalid"), None);
+    }
+
+    #[test]
+    fn test_completion_keeps_operator() {
+        let list = version_completions(
+            package_versions(&FIXTURE),
+            &target("^4.17.2", true),
+            &settings(CompletionStyle::Grouped),
+            RICH,
+        );
+        assert_eq!(list.items.len(), 2);
+        assert!(list
+            .items
+            .iter()
+            .all(|item| new_text(item).starts_with("^4.17.2")));
+    }
+
+    #[test]
+    fn test_completion_quotes_bare_version() {
+        let list = version_completions(
+            package_versions(&FIXTURE),
+            &target("~3.5", false),
+            &settings(CompletionStyle::Grouped),
+            RICH,
+        );
+        assert_eq!(list.items.len(), 1);
+        assert_eq!(new_text(&list.items[0]), "\"~3.5.2\"");
+        assert_eq!(list.items[0].filter_text.as_deref(), Some("~3.5.2"));
+    }
+
+    #[test]
+    fn test_version_documentation() {
+        let mut versions = package_versions(&["4.17.21", "4.17.20"]);
+        versions[0].description = "Lodash modular utilities.".into();
+        versions[1].description = "Lodash modular utilities.".into();
+        versions[1].deprecated = Some("Use 4.17.21 instead".into());
+        let list = version_completions(
+            versions,
+            &target("4.17.", true),
+            &settings(CompletionStyle::All),
+            RICH,
+        );
+        let documentation = |item: &CompletionItem| match &item.documentation {
+            Some(Documentation::MarkupContent(content)) => content.value.clone(),
+            documentation => panic!("expected markdown, got {documentation:?}"),
+        };
+        assert_eq!(labels(&list), ["4.17.21", "4.17.20 (deprecated)"]);
+        let deprecated = list
+            .items
+            .iter()
+            .find(|item| item.label.ends_with("(deprecated)"))
+            .unwrap();
+        assert_eq!(
+            documentation(deprecated),
+            "Lodash modular utilities.\n\n**Deprecated:** Use 4.17.21 instead"
+        );
+        assert_eq!(deprecated.tags, Some(vec![CompletionItemTag::DEPRECATED]));
+        assert_eq!(new_text(deprecated), "4.17.20");
+        assert_eq!(documentation(&list.items[0]), "Lodash modular utilities.");
+    }
+}
@@ -0,0 +1,1241 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::future::join_all;
+use semver_rs::{Options, Range};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DiagnosticTag, Location,
+    NumberOrString, Url,
+};
+use tree_sitter::Tree;
+
+use crate::fetcher::{
+    FetchError, FetchOptions, MetadataFromRegistry, PackageVersion, PackageVersionFetcher,
+};
+use crate::lockfile::InstalledPackages;
+use crate::osv::{self, Advisory};
+use crate::parser::{self, Dependency, DependencyKind};
+use crate::settings::DiagnosticSettings;
+use crate::update;
+
+const SOURCE: &str = env!("CARGO_PKG_NAME");
+
+/// Codes identifying each kind of diagnostic, so that other features can tell them apart.
+pub(super) const INVALID_RANGE: &str = "invalid-range";
+pub(super) const NOT_FOUND: &str = "not-found";
+pub(super) const DEPRECATED: &str = "deprecated";
+pub(super) const OUTDATED: &str = "outdated";
+pub(super) const DUPLICATE: &str = "duplicate";
+pub(super) const LOCKFILE_DRIFT: &str = "lockfile-drift";
+pub(super) const UNSATISFIABLE: &str = "unsatisfiable";
+pub(super) const PRERELEASE_ONLY: &str = "prerelease-only";
+pub(super) const UNPINNED: &str = "unpinned";
+pub(super) const PRERELEASE: &str = "prerelease";
+pub(super) const UNKNOWN_PROTOCOL: &str = "unknown-protocol";
+pub(super) const VULNERABLE: &str = "vulnerable";
+
+/// Protocols that npm, pnpm or Yarn understand in front of a version specifier.
+const PROTOCOLS: &[&str] = &[
+    "bitbucket",
+    "catalog",
+    "exec",
+    "file",
+    "gist",
+    "git",
+    "git+file",
+    "git+http",
+    "git+https",
+    "git+ssh",
+    "github",
+    "gitlab",
+    "http",
+    "https",
+    "jsr",
+    "link",
+    "npm",
+    "patch",
+    "portal",
+    "workspace",
+];
+
+fn code(code: &str) -> Option<NumberOrString> {
+    Some(NumberOrString::String(code.to_string()))
+}
+
+/// What the quick fix of a diagnostic changes the dependency to, carried in the diagnostic's
+/// `data` so that code actions are built from what was fetched for the diagnostics.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub(super) struct Fix {
+    pub name: String,
+    /// Name of the section the dependency is declared in, as it appears in `package.json`.
+    pub section: String,
+    /// The version specifier replacing the declared one, like `^4.17.21`.
+    pub version: String,
+    /// Whether the fix crosses a major version, and might thus break things.
+    pub is_major: bool,
+}
+
+impl Fix {
+    fn new(dependency: &Dependency, version: String, is_major: bool) -> Self {
+        Self {
+            name: dependency.name.clone(),
+            section: dependency.section.section_name().to_string(),
+            version,
+            is_major,
+        }
+    }
+
+    fn to_data(&self) -> Option<Value> {
+        serde_json::to_value(self).ok()
+    }
+
+    pub(super) fn from_diagnostic(diagnostic: &Diagnostic) -> Option<Self> {
+        serde_json::from_value(diagnostic.data.clone()?).ok()
+    }
+
+    /// The declared dependency the fix applies to.
+    pub(super) fn dependency<'a>(&self, dependencies: &'a [Dependency]) -> Option<&'a Dependency> {
+        dependencies.iter().find(|dependency| {
+            dependency.name == self.name && dependency.section.section_name() == self.section
+        })
+    }
+}
+
+/// The data of a quick fix whose edit is left to `codeAction/resolve`.
+#[derive(Debug, Deserialize, Serialize)]
+pub(super) struct ResolvableFix {
+    pub uri: Url,
+    #[serde(flatten)]
+    pub fix: Fix,
+}
+
+/// What checking the dependencies of a document found.
+#[derive(Clone, Debug)]
+pub(super) struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    /// The name ranges of the dependencies whose range excludes the latest version, whether or
+    /// not they are reported.
+    pub outdated: Vec<tower_lsp::lsp_types::Range>,
+}
+
+/// Computes the diagnostics for every dependency declared in a document.
+///
+/// Returns `None` when none of the packages could be fetched (e.g. while offline), so that the
+/// previously published diagnostics are kept instead of being cleared. `on_fetched` is called with
+/// how many of the packages have been fetched so far, out of how many, whenever one arrives.
+/// `installed` holds what the lockfile resolved dependencies to, if there is one.
+pub(super) async fn analyze(
+    fetcher: &PackageVersionFetcher,
+    settings: &DiagnosticSettings,
+    uri: &Url,
+    text: &str,
+    tree: &Tree,
+    installed: &InstalledPackages,
+    on_fetched: &(dyn Fn(usize, usize) + Sync),
+) -> Option<Analysis> {
+    let dependencies = parser::extract_all_dependencies(text, tree);
+    let mut diagnostics: Vec<_> = dependencies
+        .iter()
+        .filter_map(|dependency| {
+            unknown_protocol_diagnostic(dependency, settings)
+                .or_else(|| invalid_range_diagnostic(dependency, settings))
+        })
+        .collect();
+    diagnostics.extend(duplicate_diagnostics(uri, &dependencies, settings));
+    diagnostics.extend(dependencies.iter().filter_map(|dependency| {
+        let installed = installed.get(&dependency.name)?;
+        lockfile_drift_diagnostic(dependency, &installed.version, settings)
+    }));
+    let from_registry: Vec<_> = dependencies
+        .iter()
+        .filter(|dependency| is_from_registry(&dependency.version))
+        .collect();
+    // Telling what a range resolves to takes every published version.
+    let parse_all_versions = [
+        settings.unsatisfiable,
+        settings.prerelease_only,
+        settings.prerelease,
+    ]
+    .iter()
+    .any(|severity| severity.diagnostic_severity().is_some());
+    let fetched = AtomicUsize::new(0);
+    let metadata = join_all(from_registry.iter().map(|dependency| async {
+        let metadata = fetcher
+            .get_for(
+                uri,
+                &dependency.name,
+                FetchOptions {
+                    parse_all_versions,
+                    full_metadata: false,
+                    readme: false,
+                },
+            )
+            .await;
+        on_fetched(
+            fetched.fetch_add(1, Ordering::Relaxed) + 1,
+            from_registry.len(),
+        );
+        metadata
+    }))
+    .await;
+    let is_unknown = |metadata: &Result<_, FetchError>| {
+        matches!(
+            metadata,
+            Err(FetchError::Unavailable | FetchError::InvalidResponse)
+        )
+    };
+    if !from_registry.is_empty() && metadata.iter().all(is_unknown) {
+        return None;
+    }
+    let mut outdated = Vec::new();
+    for (dependency, metadata) in from_registry.into_iter().zip(metadata) {
+        match metadata {
+            Ok(metadata) => {
+                let latest = &metadata.latest_version;
+                if is_outdated(dependency, &latest.version) {
+                    outdated.push(dependency.name_range);
+                }
+                diagnostics.extend(deprecated_diagnostic(dependency, latest, settings));
+                diagnostics.extend(unpinned_diagnostic(dependency, &metadata, settings));
+                diagnostics.extend(prerelease_diagnostic(
+                    dependency,
+                    &metadata.package_versions,
+                    settings,
+                ));
+                // A range that is ahead of every release isn't behind the latest one.
+                match unsatisfiable_diagnostic(dependency, &metadata.package_versions, settings) {
+                    Some(diagnostic) => diagnostics.push(diagnostic),
+                    None => diagnostics.extend(outdated_diagnostic(
+                        dependency,
+                        &latest.version,
+                        settings,
+                    )),
+                }
+            }
+            Err(FetchError::NotFound { registry }) => {
+                diagnostics.extend(not_found_diagnostic(dependency, &registry, settings));
+            }
+            Err(_) => {}
+        }
+    }
+    Some(Analysis {
+        diagnostics,
+        outdated,
+    })
+}
+
+/// Parses a version range the way npm does. `semver_rs` accepts anything as a range and turns
+/// what it can't make sense of into one that never matches, which is reported as `None` here.
+pub(super) fn parse_range(specifier: &str) -> Option<Range> {
+    let options = Options::builder().loose(true).build();
+    let range = Range::new(specifier).with_options(options).parse().ok()?;
+    let comparators = serde_json::to_value(&range).ok()?;
+    let matches_anything = comparators["comparators"]
+        .as_array()
+        .is_some_and(|comparators| !comparators.is_empty());
+    matches_anything.then_some(range)
+}
+
+/// Whether the package is resolved by looking its name up in the registry, as opposed to a protocol
+/// (`workspace:`, `npm:`, `file:`, git and tarball URLs), a path or GitHub shorthand.
+pub(super) fn is_from_registry(specifier: &str) -> bool {
+    !specifier.contains([':', '/'])
+}
+
+/// Whether `specifier` names a dist-tag such as `latest`.
+fn is_tag(specifier: &str) -> bool {
+    specifier.starts_with(|c: char| c.is_ascii_alphabetic())
+        && specifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))
+}
+
+/// Flags a dependency whose version specifier npm would fail to parse. Needs no network access.
+fn invalid_range_diagnostic(
+    dependency: &Dependency,
+    settings: &DiagnosticSettings,
+) -> Option<Diagnostic> {
+    let severity = settings.invalid_range.diagnostic_severity()?;
+    let specifier = dependency.version.trim();
+    if parse_range(specifier).is_some() || !is_from_registry(specifier) || is_tag(specifier) {
+        return None;
+    }
+    // Loose parsing never fails, so ask the strict parser to explain what's wrong.
+    let reason = Range::new(specifier)
+        .parse()
+        .err()
+        .map_or_else(|| "invalid range".to_string(), |error| error.to_string());
+    Some(Diagnostic {
+        range: dependency.version_range,
+        severity: Some(severity),
+        code: code(INVALID_RANGE),
+        source: Some(SOURCE.to_string()),
+        message: format!("Invalid version range `{specifier}`: {reason}"),
+        ..Default::default()
+    })
+}
+
+/// How many single character insertions, deletions or substitutions turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+    let mut previous: Vec<_> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The protocol a specifier starts with when no package manager knows it, like `workpace:` or
+/// `npm;`, along with the known protocol it's closest to if that is only a typo away. Ranges never
+/// contain either separator, so they are never mistaken for one.
+fn unknown_protocol(specifier: &str) -> Option<(&str, Option<&'static str>)> {
+    let specifier = specifier.trim();
+    let end = specifier.find([':', ';'])?;
+    let (name, separator) = (&specifier[..end], &specifier[end..=end]);
+    let is_protocol_like = name.len() >= 2
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'));
+    let lowercase = name.to_ascii_lowercase();
+    if !is_protocol_like || (separator == ":" && PROTOCOLS.contains(&lowercase.as_str())) {
+        return None;
+    }
+    let nearest = PROTOCOLS
+        .iter()
+        .map(|protocol| (edit_distance(&lowercase, protocol), *protocol))
+        .min()
+        .filter(|(distance, _)| *distance <= 2 && *distance < name.len())
+        .map(|(_, protocol)| protocol);
+    // A semicolon is only taken for a colon in front of what is otherwise a known protocol.
+    if separator == ";" && nearest.is_none() {
+        return None;
+    }
+    Some((&specifier[..=end], nearest))
+}
+
+/// Flags a specifier starting with a protocol that doesn't exist, which fails confusingly at
+/// install time, suggesting the known protocol that was likely meant.
+fn unknown_protocol_diagnostic(
+    dependency: &Dependency,
+    settings: &DiagnosticSettings,
+) -> Option<Diagnostic> {
+    let severity = settings.unknown_protocol.diagnostic_severity()?;
+    let (protocol, nearest) = unknown_protocol(&dependency.version)?;
+    let message = match nearest {
+        Some(nearest) => format!("unknown protocol `{protocol}`, did you mean `{nearest}:`?"),
+        None => format!("unknown protocol `{protocol}`"),
+    };
+    Some(Diagnostic {
+        range: dependency.version_range,
+        severity: Some(severity),
+        code: code(UNKNOWN_PROTOCOL),
+        source: Some(SOURCE.to_string()),
+        message,
+        ..Default::default()
+    })
+}
+
+fn not_found_diagnostic(
+    dependency: &Dependency,
+    registry: &str,
+    settings: &DiagnosticSettings,
+) -> Option<Diagnostic> {
+    Some(Diagnostic {
+        range: dependency.name_range,
+        severity: Some(settings.not_found.diagnostic_severity()?),
+        code: code(NOT_FOUND),
+        source: Some(SOURCE.to_string()),
+        message: format!("package '{}' not found on {registry}", dependency.name),
+        ..Default::default()
+    })
+}
+
+/// Flags a dependency on a package whose latest version is deprecated, whichever version is used.
+fn deprecated_diagnostic(
+    dependency: &Dependency,
+    latest: &PackageVersion,
+    settings: &DiagnosticSettings,
+) -> Option<Diagnostic> {
+    let severity = settings.deprecated.diagnostic_severity()?;
+    let message = latest.deprecated.as_ref()?;
+    Some(Diagnostic {
+        range: dependency.name_range,
+        severity: Some(severity),
+        code: code(DEPRECATED),
+        source: Some(SOURCE.to_string()),
+        message: format!("{} is deprecated: {message}", dependency.name),
+        tags: Some(vec![DiagnosticTag::DEPRECATED]),
+        ..Default::default()
+    })
+}
+
+/// The version each dependency from the public registry installs: the one the lockfile recorded,
+/// or else the newest one its range allows. Dependencies that don't resolve to a version are left
+/// out, and so are private ones, as the versions are checked against public advisories.
+pub(super) async fn installed_versions<'a>(
+    fetcher: &PackageVersionFetcher,
+    uri: &Url,
+    dependencies: &'a [Dependency],
+    installed: &InstalledPackages,
+) -> Vec<(&'a Dependency, String)> {
+    let resolved = join_all(
+        dependencies
+            .iter()
+            .filter(|dependency| {
+                is_from_registry(&dependency.version) && fetcher.is_public(uri, &dependency.name)
+            })
+            .map(|dependency| async move {
+                if let Some(installed) = installed.get(&dependency.name) {
+                    return Some((dependency, installed.version.clone()));
+                }
+                let range = parse_range(dependency.version.trim())?;
+                let metadata = fetcher
+                    .get_for(
+                        uri,
+                        &dependency.name,
+                        FetchOptions {
+                            parse_all_versions: true,
+                            full_metadata: false,
+                            readme: false,
+                        },
+                    )
+                    .await
+                    .ok()?;
+                // Versions are sorted newest first.
+                let version = metadata
+                    .package_versions
+                    .iter()
+                    .find(|version| range.test(&version.version))?;
+                Some((dependency, version.version.to_string()))
+            }),
+    )
+    .await;
+    resolved.into_iter().flatten().collect()
+}
+
+/// Flags a dependency whose installed version has known vulnerabilities, listing the advisories.
+pub(super) fn vulnerability_diagnostic(
+    dependency: &Dependency,
+    version: &str,
+    advisories: &[Advisory],
+) -> Option<Diagnostic> {
+    if advisories.is_empty() {
+        return None;
+    }
+    let ids: Vec<_> = advisories
+        .iter()
+        .map(|advisory| advisory.id.as_str())
+        .collect();
+    let count = match advisories.len() {
+        1 => "a known vulnerability".to_string(),
+        count => format!("{count} known vulnerabilities"),
+    };
+    let severity = osv::max_severity(advisories)
+        .map(|severity| format!(" (max severity {})", severity.label()))
+        .unwrap_or_default();
+    Some(Diagnostic {
+        range: dependency.name_range,
+        severity: Some(DiagnosticSeverity::WARNING),
+        code: code(VULNERABLE),
+        source: Some(SOURCE.to_string()),
+        message: format!(
+            "{}@{version} has {count}{severity}: {}",
+            dependency.name,
+            ids.join(", ")
+        ),
+        ..Default::default()
+    })
+}
+
+/// Whether declaring a package in both sections is a mistake. Libraries commonly list their peer
+/// dependencies as dev dependencies too, to have them installed while developing.
+fn conflicts(a: DependencyKind, b: DependencyKind) -> bool {
+    use DependencyKind::{DevDependencies, PeerDependencies};
+    !matches!(
+        (a, b),
+        (PeerDependencies, DevDependencies) | (DevDependencies, PeerDependencies)
+    )
+}
+
+/// Flags every declaration of a package that is also declared elsewhere in the document, either
+/// in another section or twice in the same one, pointing at the other declarations.
+fn duplicate_diagnostics(
+    uri: &Url,
+    dependencies: &[Dependency],
+    settings: &DiagnosticSettings,
+) -> Vec<Diagnostic> {
+    let Some(severity) = settings.duplicate.diagnostic_severity() else {
+        return Vec::new();
+    };
+    dependencies
+        .iter()
+        .enumerate()
+        .filter_map(|(ix, dependency)| {
+            let others: Vec<_> = dependencies
+                .iter()
+                .enumerate()
+                .filter(|&(other_ix, other)| {
+                    other_ix != ix
+                        && other.name == dependency.name
+                        && conflicts(dependency.section, other.section)
+                })
+                .map(|(_, other)| other)
+                .collect();
+            let mut sections: Vec<_> = others
+                .iter()
+                .map(|other| other.section.section_name())
+                .collect();
+            let mut seen = HashSet::new();
+            sections.retain(|section| seen.insert(*section));
+            let message = match sections.as_slice() {
+                [] => return None,
+                [section] if *section == dependency.section.section_name() => {
+                    format!(
+                        "{} is declared more than once in {section}",
+                        dependency.name
+                    )
+                }
+                sections => format!(
+                    "{} is also declared in {}",
+                    dependency.name,
+                    sections.join(", ")
+                ),
+            };
+            let related_information = others
+                .iter()
+                .map(|other| DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), other.name_range),
+                    message: format!(
+                        "{} declared in {}",
+                        other.name,
+                        other.section.section_name()
+                    ),
+                })
+                .collect();
+            Some(Diagnostic {
+                range: dependency.name_range,
+                severity: Some(severity),
+                code: code(DUPLICATE),
+                source: Some(SOURCE.to_string()),
+                message,
+                related_information: Some(related_information),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Whether the declared range is valid and excludes the latest stable version.
+pub(super) fn is_outdated(dependency: &Dependency, latest: &semver_rs::Version) -> bool {
+    !latest.has_prerelease()
+        && parse_range(&dependency.version).is_some_and(|range| !range.test(latest))
+}
+
+/// The dist-tag an unpinned specifier follows: `latest` for wildcards like `*`, `x` or an empty
+/// string, and otherwise the tag the specifier names.
+pub(super) fn unpinned_tag(specifier: &str) -> Option<&str> {
+    match specifier.trim() {
+        "" | "*" | "x" | "X" => Some("latest"),
+        tag if is_tag(tag) => Some(tag),
+        _ => None,
+    }
+}
+
+/// Flags a dependency on a wildcard or a dist-tag, which makes installs non-reproducible. Tags
+/// other than `latest` are told what they currently resolve to instead.
+fn unpinned_diagnostic(
+    dependency: &Dependency,
+    metadata: &MetadataFromRegistry,
+    settings: &DiagnosticSettings,
+) -> Option<Diagnostic> {
+    let severity = settings.unpinned.diagnostic_severity()?;
+    if dependency.section == DependencyKind::DevDependencies && !settings.unpinned_dev_dependencies
+    {
+        return None;
+    }
+    let specifier = dependency.version.trim();
+    let (message, version) = match unpinned_tag(specifier)? {
+        "latest" => {
+            let version = metadata.latest_version.version.to_string();
+            let message =
+                format!("unpinned version specifier \"{specifier}\", currently {version}");
+            (message, version)
+        }
+        tag => {
+            let version = metadata.dist_tags.get(tag)?;
+            let message = format!("\"{tag}\" follows a dist-tag, currently {version}");
+            (message, version.clone())
+        }
+    };
+    Some(Diagnostic {
+        range: dependency.version_range,
+        severity: Some(severity),
+        code: code(UNPINNED),
+        source: Some(SOURCE.to_string()),
+        message,
+        data: Fix::new(dependency, format!("^{version}"), false).to_data(),
+        ..Default::default()
+    })
+}
+
+/// Flags a dependency whose declared range doesn't include the latest stable version.
+fn outdated_diagnostic(
+    dependency: &Dependency,
+    latest: &semver_rs::Version,
+    settings: &DiagnosticSettings,
+) -> Option<Diagnostic> {
+    let severity = settings.outdated.diagnostic_severity()?;
+    if !is_outdated(dependency, latest) {
+        return None;
+    }
+    Some(Diagnostic {
+        range: dependency.version_range,
+        severity: Some(severity),
+        code: code(OUTDATED),
+        source: Some(SOURCE.to_string()),
+        message: format!(
+            "{} {} → latest {latest}",
+            dependency.name, dependency.version
+        ),
+        data: Fix::new(
+            dependency,
+            update::updated_specifier(&dependency.version, latest),
+            update::has_newer_major(dependency, latest),
+        )
+        .to_data(),
+        ..Default::default()
+    })
+}
+
+/// Flags a dependency whose range excludes the version the lockfile installed, which happens when
+/// `package.json` is edited without installing again.
+fn lockfile_drift_diagnostic(
+    dependency: &Dependency,
+    installed: &str,
+    settings: &DiagnosticSettings,
+) -> Option<Diagnostic> {
+    let severity = settings.lockfile_drift.diagnostic_severity()?;
+    let specifier = dependency.version.trim();
+    if !is_from_registry(specifier) || is_tag(specifier) {
+        return None;
+    }
+    let range = parse_range(specifier)?;
+    let version = semver_rs::Version::new(installed).parse().ok()?;
+    if range.test(&version) {
+        return None;
+    }
+    Some(Diagnostic {
+        range: dependency.version_range,
+        severity: Some(severity),
+        code: code(LOCKFILE_DRIFT),
+        source: Some(SOURCE.to_string()),
+        message: format!(
+            "range {specifier} does not match installed {installed} — run npm install"
+        ),
+        ..Default::default()
+    })
+}
+
+/// The prerelease a production dependency resolves to, as with `"react": "19.0.0-rc.1"`. Packages
+/// that never had a stable release are left alone, as there is nothing else to depend on.
+pub(super) fn prerelease_match<'a>(
+    dependency: &Dependency,
+    versions: &'a [PackageVersion],
+) -> Option<&'a semver_rs::Version> {
+    if dependency.section != DependencyKind::Dependencies {
+        return None;
+    }
+    let range = parse_range(dependency.version.trim())?;
+    let best = versions
+        .iter()
+        .map(|version| &version.version)
+        .filter(|version| range.test(version))
+        .max()?;
+    let has_stable_release = versions
+        .iter()
+        .any(|version| !version.version.has_prerelease());
+    (best.has_prerelease() && has_stable_release).then_some(best)
+}
+
+/// The newest version that isn't a prerelease.
+pub(super) fn newest_stable(versions: &[PackageVersion]) -> Option<&semver_rs::Version> {
+    versions
+        .iter()
+        .map(|version| &version.version)
+        .filter(|version| !version.has_prerelease())
+        .max()
+}
+
+/// Flags a production dependency that resolves to a prerelease, which is usually an accident.
+fn prerelease_diagnostic(
+    dependency: &Dependency,
+    versions: &[PackageVersion],
+    settings: &DiagnosticSettings,
+) -> Option<Diagnostic> {
+    let severity = settings.prerelease.diagnostic_severity()?;
+    let prerelease = prerelease_match(dependency, versions)?;
+    let stable = newest_stable(versions)?;
+    Some(Diagnostic {
+        range: dependency.version_range,
+        severity: Some(severity),
+        code: code(PRERELEASE),
+        source: Some(SOURCE.to_string()),
+        message: format!(
+            "{} resolves to prerelease {prerelease}, the newest stable version is {stable}",
+            dependency.name
+        ),
+        data: Fix::new(
+            dependency,
+            update::updated_specifier(&dependency.version, stable),
+            stable.major != prerelease.major,
+        )
+        .to_data(),
+        ..Default::default()
+    })
+}
+
+/// Flags a dependency whose range no published version satisfies, so installing it fails. When
+/// only prereleases would satisfy it, which npm leaves out unless a range names one, that's told
+/// apart with its own message.
+fn unsatisfiable_diagnostic(
+    dependency: &Dependency,
+    versions: &[PackageVersion],
+    settings: &DiagnosticSettings,
+) -> Option<Diagnostic> {
+    let specifier = dependency.version.trim();
+    if versions.is_empty() || !is_from_registry(specifier) || is_tag(specifier) {
+        return None;
+    }
+    let range = parse_range(specifier)?;
+    if versions.iter().any(|version| range.test(&version.version)) {
+        return None;
+    }
+    let options = Options::builder()
+        .loose(true)
+        .include_prerelease(true)
+        .build();
+    let with_prereleases = Range::new(specifier).with_options(options).parse().ok()?;
+    let newest_prerelease = versions
+        .iter()
+        .map(|version| &version.version)
+        .filter(|version| with_prereleases.test(version))
+        .max();
+    let (severity, code_name, message) = match newest_prerelease {
+        Some(prerelease) => (
+            settings.prerelease_only.diagnostic_severity()?,
+            PRERELEASE_ONLY,
+            format!(
+                "only prereleases of {} satisfy {specifier}, such as {prerelease}, and npm won't \
+                 install them for this range",
+                dependency.name
+            ),
+        ),
+        None => (
+            settings.unsatisfiable.diagnostic_severity()?,
+            UNSATISFIABLE,
+            format!(
+                "no published version of {} satisfies {specifier}",
+                dependency.name
+            ),
+        ),
+    };
+    Some(Diagnostic {
+        range: dependency.version_range,
+        severity: Some(severity),
+        code: code(code_name),
+        source: Some(SOURCE.to_string()),
+        message,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semver_rs::Parseable;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use crate::fetcher::tests::package_version;
+    use crate::settings::Severity;
+
+    fn dependency(version: &str) -> Dependency {
+        Dependency {
+            name: "lodash".into(),
+            version: version.into(),
+            section: DependencyKind::Dependencies,
+            name_range: Range::default(),
+            version_range: Range::default(),
+        }
+    }
+
+    fn version(version: &str) -> semver_rs::Version {
+        semver_rs::Version::parse(version, None).unwrap()
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert!(parse_range("^4.17.0").is_some());
+        assert!(parse_range(">=1 <5").is_some());
+        assert!(parse_range("*").is_some());
+        for specifier in ["workspace:*", "npm:react@18", "latest", "^4.17..1"] {
+            assert!(parse_range(specifier).is_none(), "{specifier}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_range_diagnostic() {
+        let settings = DiagnosticSettings::default();
+        let diagnostic = invalid_range_diagnostic(&dependency("^4.17..1"), &settings).unwrap();
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(
+            diagnostic.message,
+            "Invalid version range `^4.17..1`: invalid comparator: ^4.17..1"
+        );
+        assert!(invalid_range_diagnostic(&dependency("1.2.3.4"), &settings).is_some());
+
+        for specifier in [
+            "^4.17.0",
+            "",
+            "*",
+            // npm reads `~>` as `~`.
+            "~>1.0",
+            "latest",
+            "next",
+            "workspace:*",
+            "npm:react@^18",
+            "file:../lodash",
+            "link:../lodash",
+            "lodash/lodash#v4.17.21",
+            "github:lodash/lodash",
+            "git+https://github.com/lodash/lodash.git#semver:^4",
+            "https://example.com/lodash.tgz",
+        ] {
+            assert!(
+                invalid_range_diagnostic(&dependency(specifier), &settings).is_none(),
+                "{specifier}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("workpace", "workspace"), 1);
+        assert_eq!(edit_distance("fle", "file"), 1);
+        assert_eq!(edit_distance("npm", "npm"), 0);
+        assert_eq!(edit_distance("", "git"), 3);
+        assert_eq!(edit_distance("gihtub", "github"), 2);
+    }
+
+    #[test]
+    fn test_unknown_protocol_diagnostic() {
+        let settings = DiagnosticSettings::default();
+        let message = |specifier| {
+            unknown_protocol_diagnostic(&dependency(specifier), &settings)
+                .map(|diagnostic| diagnostic.message)
+        };
+        assert_eq!(
+            message("workpace:*").as_deref(),
+            Some("unknown protocol `workpace:`, did you mean `workspace:`?")
+        );
+        assert_eq!(
+            message("fle:../lib").as_deref(),
+            Some("unknown protocol `fle:`, did you mean `file:`?")
+        );
+        assert_eq!(
+            message("npm;lodash@1").as_deref(),
+            Some("unknown protocol `npm;`, did you mean `npm:`?")
+        );
+        assert_eq!(
+            message("git+htps://github.com/lodash/lodash.git").as_deref(),
+            Some("unknown protocol `git+htps:`, did you mean `git+https:`?")
+        );
+        assert_eq!(
+            message("bogus:thing").as_deref(),
+            Some("unknown protocol `bogus:`")
+        );
+        for specifier in [
+            "^4.17.0",
+            ">=1 <3 || 4.x",
+            "latest",
+            "workspace:^",
+            "npm:lodash@^4",
+            "catalog:",
+            "git+ssh://git@github.com/lodash/lodash.git",
+            "https://example.com/lodash.tgz",
+            "github:lodash/lodash",
+            "lodash/lodash",
+            "file:../lodash",
+            "C:/lodash",
+        ] {
+            assert_eq!(message(specifier), None, "{specifier}");
+        }
+        let diagnostic = unknown_protocol_diagnostic(&dependency("fle:../lib"), &settings).unwrap();
+        assert_eq!(diagnostic.code, code(UNKNOWN_PROTOCOL));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        assert!(invalid_range_diagnostic(&dependency("npm;lodash@1"), &settings).is_some());
+
+        let settings = DiagnosticSettings {
+            unknown_protocol: Severity::Off,
+            ..Default::default()
+        };
+        assert!(unknown_protocol_diagnostic(&dependency("workpace:*"), &settings).is_none());
+    }
+
+    #[test]
+    fn test_not_found_diagnostic() {
+        let settings = DiagnosticSettings::default();
+        let diagnostic = not_found_diagnostic(
+            &dependency("^1.0.0"),
+            "https://registry.npmjs.org",
+            &settings,
+        )
+        .unwrap();
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            diagnostic.message,
+            "package 'lodash' not found on https://registry.npmjs.org"
+        );
+        assert!(is_from_registry("latest"));
+        assert!(!is_from_registry("workspace:*"));
+        assert!(!is_from_registry("lodash/lodash"));
+    }
+
+    #[test]
+    fn test_deprecated_diagnostic() {
+        let mut latest = package_version("2.88.2");
+        let settings = DiagnosticSettings::default();
+        assert!(deprecated_diagnostic(&dependency("^2"), &latest, &settings).is_none());
+
+        latest.deprecated = Some("request has been deprecated, see #3142".into());
+        let diagnostic = deprecated_diagnostic(&dependency("^2"), &latest, &settings).unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "lodash is deprecated: request has been deprecated, see #3142"
+        );
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+
+        let mut settings = DiagnosticSettings {
+            deprecated: Severity::Hint,
+            ..Default::default()
+        };
+        let diagnostic = deprecated_diagnostic(&dependency("^2"), &latest, &settings).unwrap();
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::HINT));
+
+        settings.deprecated = Severity::Off;
+        assert!(deprecated_diagnostic(&dependency("^2"), &latest, &settings).is_none());
+    }
+
+    #[test]
+    fn test_outdated_diagnostic() {
+        let settings = DiagnosticSettings::default();
+        let diagnostic =
+            outdated_diagnostic(&dependency("4.17.20"), &version("4.17.21"), &settings).unwrap();
+        assert_eq!(diagnostic.message, "lodash 4.17.20 → latest 4.17.21");
+        assert_eq!(diagnostic.code, code(OUTDATED));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::INFORMATION));
+        assert_eq!(
+            Fix::from_diagnostic(&diagnostic),
+            Some(Fix {
+                name: "lodash".into(),
+                section: "dependencies".into(),
+                version: "4.17.21".into(),
+                is_major: false,
+            })
+        );
+        let diagnostic =
+            outdated_diagnostic(&dependency("^3.10.0"), &version("4.17.21"), &settings).unwrap();
+        let fix = Fix::from_diagnostic(&diagnostic).unwrap();
+        assert_eq!((fix.version.as_str(), fix.is_major), ("^4.17.21", true));
+
+        assert!(
+            outdated_diagnostic(&dependency("^4.17.0"), &version("4.17.21"), &settings).is_none()
+        );
+        assert!(
+            outdated_diagnostic(&dependency("latest"), &version("4.17.21"), &settings).is_none()
+        );
+        assert!(outdated_diagnostic(
+            &dependency("file:../lodash"),
+            &version("4.17.21"),
+            &settings
+        )
+        .is_none());
+        assert!(
+            outdated_diagnostic(&dependency("^4"), &version("5.0.0-rc.1"), &settings).is_none()
+        );
+
+        let settings = DiagnosticSettings {
+            outdated: Severity::Off,
+            ..Default::default()
+        };
+        assert!(
+            outdated_diagnostic(&dependency("4.17.20"), &version("4.17.21"), &settings).is_none()
+        );
+    }
+
+    #[test]
+    fn test_lockfile_drift_diagnostic() {
+        let settings = DiagnosticSettings::default();
+        let diagnostic =
+            lockfile_drift_diagnostic(&dependency("^5.0.0"), "4.21.2", &settings).unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "range ^5.0.0 does not match installed 4.21.2 — run npm install"
+        );
+        assert_eq!(diagnostic.code, code(LOCKFILE_DRIFT));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+
+        assert!(lockfile_drift_diagnostic(&dependency("^4.17.0"), "4.21.2", &settings).is_none());
+        assert!(lockfile_drift_diagnostic(&dependency("latest"), "4.21.2", &settings).is_none());
+        assert!(
+            lockfile_drift_diagnostic(&dependency("file:../lodash"), "1.0.0", &settings).is_none()
+        );
+
+        let settings = DiagnosticSettings {
+            lockfile_drift: Severity::Off,
+            ..Default::default()
+        };
+        assert!(lockfile_drift_diagnostic(&dependency("^5.0.0"), "4.21.2", &settings).is_none());
+    }
+
+    #[test]
+    fn test_vulnerability_diagnostic() {
+        let advisory = |id: &str, severity| Advisory {
+            id: id.into(),
+            severity,
+        };
+        let diagnostic = vulnerability_diagnostic(
+            &dependency("^4.17.0"),
+            "4.17.20",
+            &[
+                advisory("GHSA-35jh-r3h4-6jhm", Some(osv::Severity::High)),
+                advisory("GHSA-29mw-wpgm-hmr9", Some(osv::Severity::Moderate)),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "lodash@4.17.20 has 2 known vulnerabilities (max severity high): GHSA-35jh-r3h4-6jhm, GHSA-29mw-wpgm-hmr9"
+        );
+        assert_eq!(diagnostic.code, code(VULNERABLE));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+
+        let diagnostic = vulnerability_diagnostic(
+            &dependency("4.17.20"),
+            "4.17.20",
+            &[advisory("CVE-2021-23337", None)],
+        )
+        .unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "lodash@4.17.20 has a known vulnerability: CVE-2021-23337"
+        );
+        assert!(vulnerability_diagnostic(&dependency("^4.17.21"), "4.17.21", &[]).is_none());
+    }
+
+    #[test]
+    fn test_unsatisfiable_diagnostic() {
+        let settings = DiagnosticSettings::default();
+        let versions: Vec<_> = ["3.9.0", "4.2.0", "5.1.0-beta.1"]
+            .into_iter()
+            .map(package_version)
+            .collect();
+        let diagnostic =
+            unsatisfiable_diagnostic(&dependency("^9.0.0"), &versions, &settings).unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "no published version of lodash satisfies ^9.0.0"
+        );
+        assert_eq!(diagnostic.code, code(UNSATISFIABLE));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+
+        let diagnostic =
+            unsatisfiable_diagnostic(&dependency("^5.0.0"), &versions, &settings).unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "only prereleases of lodash satisfy ^5.0.0, such as 5.1.0-beta.1, and npm won't \
+             install them for this range"
+        );
+        assert_eq!(diagnostic.code, code(PRERELEASE_ONLY));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+
+        for specifier in [
+            "^4.0.0",
+            "^5.1.0-beta.0",
+            "latest",
+            "file:../lodash",
+            "^4.17..1",
+        ] {
+            assert!(
+                unsatisfiable_diagnostic(&dependency(specifier), &versions, &settings).is_none(),
+                "{specifier}"
+            );
+        }
+        assert!(unsatisfiable_diagnostic(&dependency("^9.0.0"), &[], &settings).is_none());
+
+        let settings = DiagnosticSettings {
+            unsatisfiable: Severity::Off,
+            ..Default::default()
+        };
+        assert!(unsatisfiable_diagnostic(&dependency("^9.0.0"), &versions, &settings).is_none());
+        assert!(unsatisfiable_diagnostic(&dependency("^5.0.0"), &versions, &settings).is_some());
+    }
+
+    #[test]
+    fn test_prerelease_diagnostic() {
+        let settings = DiagnosticSettings::default();
+        let versions: Vec<_> = ["18.2.0", "18.3.1", "19.0.0-rc.1"]
+            .into_iter()
+            .map(package_version)
+            .collect();
+        let diagnostic =
+            prerelease_diagnostic(&dependency("19.0.0-rc.1"), &versions, &settings).unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "lodash resolves to prerelease 19.0.0-rc.1, the newest stable version is 18.3.1"
+        );
+        assert_eq!(diagnostic.code, code(PRERELEASE));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+        let fix = Fix::from_diagnostic(&diagnostic).unwrap();
+        assert_eq!((fix.version.as_str(), fix.is_major), ("18.3.1", true));
+        assert!(prerelease_diagnostic(&dependency("^18.2.0"), &versions, &settings).is_none());
+
+        let mut dev_dependency = dependency("19.0.0-rc.1");
+        dev_dependency.section = DependencyKind::DevDependencies;
+        assert!(prerelease_diagnostic(&dev_dependency, &versions, &settings).is_none());
+
+        // Once released, a range starting at a prerelease resolves to the stable version.
+        let mut released = versions.clone();
+        released.push(package_version("19.0.0"));
+        assert!(prerelease_diagnostic(&dependency("^19.0.0-rc.1"), &released, &settings).is_none());
+
+        let prereleases_only: Vec<_> = ["1.0.0-alpha.1", "1.0.0-beta.2"]
+            .into_iter()
+            .map(package_version)
+            .collect();
+        assert!(
+            prerelease_diagnostic(&dependency("^1.0.0-alpha.1"), &prereleases_only, &settings)
+                .is_none()
+        );
+
+        let settings = DiagnosticSettings {
+            prerelease: Severity::Off,
+            ..Default::default()
+        };
+        assert!(prerelease_diagnostic(&dependency("19.0.0-rc.1"), &versions, &settings).is_none());
+    }
+
+    #[test]
+    fn test_unpinned_diagnostic() {
+        let settings = DiagnosticSettings::default();
+        let mut metadata = MetadataFromRegistry::for_tests(package_version("4.17.21"), Vec::new());
+        metadata
+            .dist_tags
+            .insert("next".into(), "5.0.0-rc.1".into());
+        let message = |specifier: &str, settings: &DiagnosticSettings| {
+            unpinned_diagnostic(&dependency(specifier), &metadata, settings)
+                .map(|diagnostic| diagnostic.message)
+        };
+        for specifier in ["*", "", "x", "latest"] {
+            assert_eq!(
+                message(specifier, &settings),
+                Some(format!(
+                    "unpinned version specifier \"{specifier}\", currently 4.17.21"
+                ))
+            );
+        }
+        assert_eq!(
+            message("next", &settings).as_deref(),
+            Some("\"next\" follows a dist-tag, currently 5.0.0-rc.1")
+        );
+        for specifier in ["beta", "^4.17.0", "4.x", "file:../lodash"] {
+            assert_eq!(message(specifier, &settings), None, "{specifier}");
+        }
+        let diagnostic = unpinned_diagnostic(&dependency("*"), &metadata, &settings).unwrap();
+        assert_eq!(diagnostic.code, code(UNPINNED));
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::HINT));
+        let fix = Fix::from_diagnostic(&diagnostic).unwrap();
+        assert_eq!(fix.version, "^4.17.21");
+        assert_eq!(fix.dependency(&[dependency("*")]).unwrap().version, "*");
+
+        let mut dev_dependency = dependency("*");
+        dev_dependency.section = DependencyKind::DevDependencies;
+        assert!(unpinned_diagnostic(&dev_dependency, &metadata, &settings).is_none());
+        let settings = DiagnosticSettings {
+            unpinned_dev_dependencies: true,
+            ..Default::default()
+        };
+        assert!(unpinned_diagnostic(&dev_dependency, &metadata, &settings).is_some());
+        let settings = DiagnosticSettings {
+            unpinned: Severity::Off,
+            ..Default::default()
+        };
+        assert_eq!(message("*", &settings), None);
+    }
+
+    #[test]
+    fn test_duplicate_diagnostics() {
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        let declared = |name: &str, section, line| Dependency {
+            name: name.into(),
+            version: "^18.0.0".into(),
+            section,
+            name_range: Range::new(Position::new(line, 4), Position::new(line, 9)),
+            version_range: Range::default(),
+        };
+        let dependencies = [
+            declared("react", DependencyKind::Dependencies, 2),
+            declared("react", DependencyKind::DevDependencies, 5),
+            declared("vitest", DependencyKind::DevDependencies, 6),
+            declared("vitest", DependencyKind::DevDependencies, 7),
+            declared("vue", DependencyKind::PeerDependencies, 10),
+            declared("vue", DependencyKind::DevDependencies, 11),
+        ];
+        let diagnostics =
+            duplicate_diagnostics(&uri, &dependencies, &DiagnosticSettings::default());
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (2, "react is also declared in devDependencies"),
+                (5, "react is also declared in dependencies"),
+                (6, "vitest is declared more than once in devDependencies"),
+                (7, "vitest is declared more than once in devDependencies"),
+            ]
+        );
+        assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+        assert_eq!(diagnostics[0].code, code(DUPLICATE));
+        let related = diagnostics[0].related_information.as_ref().unwrap();
+        assert_eq!(related.len(), 1);
+        assert_eq!(
+            related[0].location,
+            Location::new(uri.clone(), dependencies[1].name_range)
+        );
+
+        let interleaved = [
+            declared("react", DependencyKind::Dependencies, 2),
+            declared("react", DependencyKind::DevDependencies, 5),
+            declared("react", DependencyKind::Dependencies, 3),
+            declared("react", DependencyKind::DevDependencies, 6),
+        ];
+        let diagnostics = duplicate_diagnostics(&uri, &interleaved, &DiagnosticSettings::default());
+        assert_eq!(
+            diagnostics[0].message,
+            "react is also declared in devDependencies, dependencies"
+        );
+        assert_eq!(
+            diagnostics[0].related_information.as_ref().unwrap().len(),
+            3
+        );
+    }
+}
@@ -1,18 +1,31 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
-    sync::Arc,
+    collections::{HashMap, HashSet},
+    io,
+    num::NonZeroUsize,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, RwLock},
     time::{Duration, Instant},
 };
 
 use chrono::{DateTime, FixedOffset};
 use itertools::{Either, Itertools};
-use reqwest::Client;
+use lru::LruCache;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, AUTHORIZATION},
+    Client, RequestBuilder, StatusCode, Url,
+};
 use semver_rs::Parseable;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
+use tokio_util::sync::CancellationToken;
 
 type PackageName = String;
 
+/// A package along with the registry it is fetched from, as two registries may hold different
+/// packages under the same name.
+type CacheKey = (String, PackageName);
+
 static APP_USER_AGENT: &str = concat!(
     env!("CARGO_PKG_NAME"),
     "/",
@@ -21,61 +34,750 @@ static APP_USER_AGENT: &str = concat!(
 );
 pub(super) struct PackageVersionFetcher {
     client: Client,
-    cache: Arc<Mutex<HashMap<PackageName, MetadataFromRegistry>>>,
+    /// Least recently used entries are evicted first, as those holding every version can be big.
+    cache: Arc<Mutex<LruCache<CacheKey, MetadataFromRegistry>>>,
+    /// Packages the registries reported as nonexistent, keyed by the registries that were asked,
+    /// along with when. Bounded like `cache`, as every prefix of a name being typed may end up here.
+    missing: Arc<Mutex<LruCache<CacheKey, Instant>>>,
+    config: RwLock<RegistryConfig>,
+    /// Configurations of the projects whose own `.npmrc` sets up registries, keyed by the
+    /// project's directory. The documents of a project are fetched from its registries, with its
+    /// headers and credentials, instead of the ones in `config`.
+    projects: RwLock<HashMap<PathBuf, RegistryConfig>>,
+    /// Bounds how many requests are in flight at once, whichever feature asked for them.
+    requests: RwLock<Arc<Semaphore>>,
+    /// Stops the refreshes of stale entries that are still running in the background.
+    background: CancellationToken,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum FetchError {
+    /// The registry definitively answered that there is no such package.
+    NotFound { registry: String },
+    /// The registry couldn't be reached, or refused to answer (e.g. due to missing credentials).
+    Unavailable,
+    /// The registry answered with something that isn't package metadata.
+    InvalidResponse,
+}
+
+/// What npm itself asks for, so registries that don't support the abbreviated format still answer.
+const ABBREVIATED_METADATA: &str =
+    "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*";
+
+pub(super) const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org";
+
 /// How long do we keep data about a package around before requerying it the second time.
-const REFRESH_DURATION: Duration = Duration::from_secs(30);
+pub(super) const REFRESH_DURATION: Duration = Duration::from_secs(30);
+
+/// How many packages are kept in the cache at most.
+pub(super) const MAX_CACHE_ENTRIES: NonZeroUsize = match NonZeroUsize::new(500) {
+    Some(entries) => entries,
+    None => unreachable!(),
+};
+
+/// How many requests are sent to registries at once at most.
+pub(super) const MAX_CONCURRENT_REQUESTS: NonZeroUsize = match NonZeroUsize::new(8) {
+    Some(requests) => requests,
+    None => unreachable!(),
+};
+
+/// Where and how often package metadata is fetched. Can be swapped out while the server is running.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct RegistryConfig {
+    pub default_registry: String,
+    /// Registries to use for scoped packages, keyed by scope (including the leading `@`), in the
+    /// order they are tried in.
+    pub scope_registries: HashMap<String, Vec<String>>,
+    pub cache_ttl: Duration,
+    pub max_cache_entries: NonZeroUsize,
+    pub max_concurrent_requests: NonZeroUsize,
+    pub fetch_mode: FetchMode,
+    /// Headers for the registries that expect custom ones, on top of the ones the server sets
+    /// itself.
+    pub headers: Vec<RegistryHeaders>,
+    /// `Authorization` headers for the registries that need one.
+    pub credentials: Vec<Credential>,
+}
+
+/// The `Authorization` header sent along with the requests to URLs starting with `prefix`,
+/// written without the scheme like in `.npmrc` (e.g. `//npm.acme.dev/`).
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Credential {
+    pub prefix: String,
+    pub authorization: HeaderValue,
+}
+
+impl Credential {
+    /// `None` when `authorization` can't be sent as a header value.
+    pub(super) fn new(prefix: &str, authorization: &str) -> Option<Self> {
+        let mut authorization = HeaderValue::try_from(authorization).ok()?;
+        // Keeps credentials out of logs.
+        authorization.set_sensitive(true);
+        Some(Self {
+            prefix: prefix.to_string(),
+            authorization,
+        })
+    }
+}
+
+/// Headers sent along with the requests to URLs starting with `prefix`, which is written like the
+/// one of a [`Credential`].
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct RegistryHeaders {
+    pub prefix: String,
+    pub headers: HeaderMap,
+}
+
+/// `url` without its scheme, like the prefixes of credentials are written.
+fn url_target(url: &Url) -> Option<String> {
+    let port = url
+        .port()
+        .map(|port| format!(":{port}"))
+        .unwrap_or_default();
+    Some(format!("//{}{port}{}", url.host_str()?, url.path()))
+}
+
+/// The credential for the longest prefix of `url`, like npm picks the most specific one.
+fn credential_for<'a>(credentials: &'a [Credential], url: &Url) -> Option<&'a HeaderValue> {
+    let target = url_target(url)?;
+    credentials
+        .iter()
+        .filter(|credential| target.starts_with(&credential.prefix))
+        .max_by_key(|credential| credential.prefix.len())
+        .map(|credential| &credential.authorization)
+}
+
+/// The headers for the longest prefix of `url`, picked like credentials are.
+fn headers_for<'a>(headers: &'a [RegistryHeaders], url: &Url) -> Option<&'a HeaderMap> {
+    let target = url_target(url)?;
+    headers
+        .iter()
+        .filter(|headers| target.starts_with(&headers.prefix))
+        .max_by_key(|headers| headers.prefix.len())
+        .map(|headers| &headers.headers)
+}
+
+/// The prefix of the URLs of a configured registry, like `//npm.acme.dev/`, or why there is none.
+/// Prefixes written that way already are kept as they are.
+pub(super) fn registry_prefix(registry: &str) -> Result<String, String> {
+    let registry = registry.trim();
+    if registry.starts_with("//") {
+        return Ok(format!("{}/", registry.trim_end_matches('/')));
+    }
+    let registry = normalize_registry(registry)?;
+    if registry.starts_with("file://") {
+        return Err("headers can't be sent to a registry on disk".to_string());
+    }
+    let url = Url::parse(&format!("{registry}/")).map_err(|error| error.to_string())?;
+    url_target(&url).ok_or_else(|| "missing host".to_string())
+}
+
+/// When cached metadata is used instead of asking the registry.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum FetchMode {
+    /// Use cached metadata until it expires, then wait for the registry.
+    #[default]
+    Online,
+    /// Use cached metadata even once expired, and refresh it in the background.
+    PreferCache,
+    /// Only ever use cached metadata, however old, and never reach out to the registry.
+    Offline,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            default_registry: DEFAULT_REGISTRY.to_string(),
+            scope_registries: HashMap::new(),
+            cache_ttl: REFRESH_DURATION,
+            max_cache_entries: MAX_CACHE_ENTRIES,
+            max_concurrent_requests: MAX_CONCURRENT_REQUESTS,
+            fetch_mode: FetchMode::default(),
+            headers: Vec::new(),
+            credentials: Vec::new(),
+        }
+    }
+}
+
+/// Turns a configured registry into the form URLs are built from, without a trailing slash, or
+/// explains why it can't be used. Registries missing a scheme are assumed to use HTTPS.
+pub(super) fn normalize_registry(registry: &str) -> Result<String, String> {
+    let registry = registry.trim();
+    if let Some(directory) = registry.strip_prefix("file://") {
+        // Kept as is, as paths are read from disk without being decoded.
+        let directory = match directory.trim_end_matches('/') {
+            "" => "/",
+            directory => directory,
+        };
+        return Ok(format!("file://{directory}"));
+    }
+    let url = if registry.contains("://") {
+        Url::parse(registry)
+    } else {
+        Url::parse(&format!("https://{registry}"))
+    }
+    .map_err(|error| error.to_string())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme `{}`", url.scheme()));
+    }
+    if url.cannot_be_a_base() || url.host().is_none() {
+        return Err("missing host".to_string());
+    }
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+/// Turns a configured header into one that can be sent, or explains why it can't be. `${NAME}` in
+/// the value is replaced with the environment variable `NAME`, as `.npmrc` does.
+pub(super) fn parse_registry_header(
+    name: &str,
+    value: &str,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<(HeaderName, HeaderValue), String> {
+    let name = HeaderName::try_from(name.trim()).map_err(|_| "invalid header name".to_string())?;
+    let value = interpolate_env(value, env)?;
+    let mut value = HeaderValue::try_from(value).map_err(|_| "invalid header value".to_string())?;
+    // Keeps credentials out of logs.
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// Replaces every `${NAME}` with the environment variable `NAME`, failing when one isn't set so
+/// that a header isn't sent with a blank credential.
+pub(super) fn interpolate_env(
+    value: &str,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut interpolated = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let variable = &rest[start + 2..start + 2 + len];
+        let substitute =
+            env(variable).ok_or_else(|| format!("environment variable `{variable}` isn't set"))?;
+        interpolated.push_str(&rest[..start]);
+        interpolated.push_str(&substitute);
+        rest = &rest[start + 3 + len..];
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+/// Where the metadata of a package is requested from.
+fn package_url(registry: &str, package_name: &str) -> Option<Url> {
+    // Without a trailing slash, joining would replace the last segment of the registry's path.
+    let base = Url::parse(&format!("{}/", registry.trim_end_matches('/'))).ok()?;
+    base.join(&urlencoding::encode(package_name)).ok()
+}
+
+/// Where the metadata of a package is read from in a registry mirrored to `directory`. `None` for
+/// names that would point outside of it, like `../x` or `/etc/x`, which no package is named.
+fn package_path(directory: &str, package_name: &str) -> Option<PathBuf> {
+    let name = Path::new(package_name);
+    let is_within = name
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    (is_within && !package_name.is_empty()).then(|| Path::new(directory).join(name))
+}
+
+impl RegistryConfig {
+    /// Registries to fetch a package from, each tried when the previous ones couldn't provide it.
+    pub(super) fn registries_for(&self, package_name: &str) -> &[String] {
+        package_name
+            .split_once('/')
+            .and_then(|(scope, _)| self.scope_registries.get(scope))
+            .filter(|registries| !registries.is_empty())
+            .map_or(std::slice::from_ref(&self.default_registry), Vec::as_slice)
+    }
+    /// Every registry packages may be fetched from.
+    fn registries(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.default_registry).chain(
+            self.scope_registries
+                .values()
+                .flat_map(|registries| registries.iter()),
+        )
+    }
+}
+
+/// The configuration of the innermost project containing `document`, if any of them configures
+/// its own registries.
+fn project_config<'a>(
+    projects: &'a HashMap<PathBuf, RegistryConfig>,
+    document: Option<&Url>,
+) -> Option<&'a RegistryConfig> {
+    let path = document?.to_file_path().ok()?;
+    projects
+        .iter()
+        .filter(|(directory, _)| path.starts_with(directory))
+        .max_by_key(|(directory, _)| directory.components().count())
+        .map(|(_, config)| config)
+}
 
 impl PackageVersionFetcher {
     pub(super) fn new() -> reqwest::Result<Self> {
-        let client = reqwest::Client::builder()
+        // Metadata of big packages shrinks to a fraction of its size when compressed.
+        let builder = reqwest::Client::builder()
             .user_agent(APP_USER_AGENT)
-            .build()?;
+            .gzip(true)
+            .brotli(true);
+        #[cfg(feature = "native-tls")]
+        let builder = builder.use_native_tls();
+        #[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+        let builder = builder.use_rustls_tls();
+        let client = builder.build()?;
         Ok(Self {
             client,
-            cache: Default::default(),
+            cache: Arc::new(Mutex::new(LruCache::new(MAX_CACHE_ENTRIES))),
+            missing: Arc::new(Mutex::new(LruCache::new(MAX_CACHE_ENTRIES))),
+            config: Default::default(),
+            projects: Default::default(),
+            requests: RwLock::new(Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS.get()))),
+            background: CancellationToken::new(),
         })
     }
+    /// Stops refreshing stale entries in the background, for good.
+    pub(super) fn cancel_background_work(&self) {
+        self.background.cancel();
+    }
+    /// Forgets every fetched package, including the ones that were missing.
+    pub(super) async fn clear_cache(&self) {
+        self.cache.lock().await.clear();
+        self.missing.lock().await.clear();
+    }
+    /// How many requests to registries are in flight.
+    pub(super) fn in_flight_requests(&self) -> usize {
+        let limit = self.config.read().unwrap().max_concurrent_requests.get();
+        limit.saturating_sub(self.requests.read().unwrap().available_permits())
+    }
+    /// Whether nothing may be asked for, and only what has been cached so far is used.
+    pub(super) fn is_offline(&self) -> bool {
+        self.config.read().unwrap().fetch_mode == FetchMode::Offline
+    }
+    /// Sends a request to a service other than the registries, within the same limit on requests
+    /// in flight, and reads its JSON answer. `None` without sending anything in offline mode, or
+    /// when the service couldn't be reached or answered with an error.
+    pub(super) async fn request_json(
+        &self,
+        request: impl FnOnce(&Client) -> RequestBuilder,
+    ) -> Option<Value> {
+        if self.is_offline() {
+            return None;
+        }
+        let requests = self.requests.read().unwrap().clone();
+        let _permit = requests.acquire().await;
+        request(&self.client)
+            .send()
+            .await
+            .ok()?
+            .error_for_status()
+            .ok()?
+            .json()
+            .await
+            .ok()
+    }
+    /// Whether a package declared by `document` is only ever fetched from the public registry, so
+    /// that public services may be asked about it without giving away the names of private ones.
+    pub(super) fn is_public(&self, document: &Url, package_name: &str) -> bool {
+        let config = self.config.read().unwrap();
+        let projects = self.projects.read().unwrap();
+        let project = project_config(&projects, Some(document)).unwrap_or(&config);
+        project
+            .registries_for(package_name)
+            .iter()
+            .all(|registry| registry == DEFAULT_REGISTRY)
+    }
+    /// Fetches a package from the default registry, bypassing the cache, and reports how it went.
+    pub(super) async fn diagnose(&self, package_name: &str) -> Diagnosis {
+        let (registry, headers, credentials) = {
+            let config = self.config.read().unwrap();
+            (
+                config.default_registry.clone(),
+                config.headers.clone(),
+                config.credentials.clone(),
+            )
+        };
+        let in_flight_requests = self.in_flight_requests();
+        let requests = self.requests.read().unwrap().clone();
+        let _permit = requests.acquire().await;
+        let mut log = FetchLog::default();
+        let start = Instant::now();
+        let result = fetch_with_log(
+            &self.client,
+            &headers,
+            &credentials,
+            &registry,
+            package_name,
+            FetchOptions {
+                parse_all_versions: false,
+                full_metadata: false,
+                readme: false,
+            },
+            &mut log,
+        )
+        .await;
+        let latency_ms = start.elapsed().as_millis() as u64;
+        let (latest_version, error) = match result {
+            Ok(metadata) => (Some(metadata.latest_version.version.to_string()), None),
+            Err(FetchError::NotFound { .. }) => (None, Some("package not found")),
+            Err(FetchError::Unavailable) => (None, Some("registry unavailable")),
+            Err(FetchError::InvalidResponse) => (None, Some("invalid response")),
+        };
+        Diagnosis {
+            package: package_name.to_string(),
+            registry,
+            url: log.url,
+            status: log.status,
+            latency_ms,
+            authenticated: log.authenticated,
+            latest_version,
+            error: error.map(ToString::to_string),
+            in_flight_requests,
+        }
+    }
+    /// Asks the registry of `document` that `text` would be fetched from for the packages matching
+    /// it, at their latest version, in the order it ranks them. Packages of scopes that have their
+    /// own registry are only found there.
+    pub(super) async fn search(
+        &self,
+        document: &Url,
+        text: &str,
+        size: usize,
+    ) -> Result<Vec<SearchResult>, FetchError> {
+        let (registries, mode) = {
+            let config = self.config.read().unwrap();
+            let projects = self.projects.read().unwrap();
+            let registries = project_config(&projects, Some(document)).unwrap_or(&config);
+            (registries.clone(), config.fetch_mode)
+        };
+        // The first registry of a scope is the one its packages are published to.
+        let registry = &registries.registries_for(text)[0];
+        // Copies of a registry on disk have no search endpoint.
+        if mode == FetchMode::Offline || registry.starts_with("file://") {
+            return Err(FetchError::Unavailable);
+        }
+        let mut url =
+            Url::parse(&format!("{registry}/-/v1/search")).map_err(|_| FetchError::Unavailable)?;
+        url.query_pairs_mut()
+            .append_pair("text", text)
+            .append_pair("size", &size.to_string());
+        let requests = self.requests.read().unwrap().clone();
+        let _permit = requests.acquire().await;
+        let headers = headers_for(&registries.headers, &url)
+            .cloned()
+            .unwrap_or_default();
+        let response = authorized_get(&self.client, url, &registries.credentials)
+            .headers(headers)
+            .send()
+            .await
+            .map_err(|_| FetchError::Unavailable)?
+            .error_for_status()
+            .map_err(|_| FetchError::Unavailable)?
+            .json::<Value>()
+            .await
+            .map_err(|_| FetchError::InvalidResponse)?;
+        let mut results = parse_search(&response).ok_or(FetchError::InvalidResponse)?;
+        // Another registry may publish packages under the same scope, which would never be fetched.
+        results.retain(|result| registries.registries_for(&result.name).contains(registry));
+        Ok(results)
+    }
+    pub(super) fn configure(&self, config: RegistryConfig) {
+        let mut current = self.config.write().unwrap();
+        // Requests in flight keep their permits from the previous limit.
+        if config.max_concurrent_requests != current.max_concurrent_requests {
+            *self.requests.write().unwrap() =
+                Arc::new(Semaphore::new(config.max_concurrent_requests.get()));
+        }
+        *current = config;
+    }
+    /// Replaces the configurations of the projects that set up their own registries. Only their
+    /// registries, headers and credentials are used, the rest is taken from the main configuration.
+    pub(super) fn configure_projects(&self, projects: HashMap<PathBuf, RegistryConfig>) {
+        *self.projects.write().unwrap() = projects;
+    }
+    /// Forgets the packages fetched from registries that are no longer configured, and which
+    /// packages were missing, as the new registries or credentials may provide them.
+    pub(super) async fn forget_unconfigured(&self) {
+        let configured: HashSet<String> = {
+            let config = self.config.read().unwrap();
+            let projects = self.projects.read().unwrap();
+            config
+                .registries()
+                .chain(projects.values().flat_map(RegistryConfig::registries))
+                .cloned()
+                .collect()
+        };
+        let mut cache = self.cache.lock().await;
+        let unconfigured: Vec<_> = cache
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|(registry, _)| !configured.contains(registry))
+            .cloned()
+            .collect();
+        for key in unconfigured {
+            cache.pop(&key);
+        }
+        self.missing.lock().await.clear();
+    }
+    /// Fetches a package from the registries of the workspace.
     pub(super) async fn get(
         &self,
         package_name: &str,
         fetch_options: FetchOptions,
-    ) -> Option<MetadataFromRegistry> {
+    ) -> Result<MetadataFromRegistry, FetchError> {
+        self.get_with(None, package_name, fetch_options).await
+    }
+    /// Fetches a package declared by `document`, from the registries of its project.
+    pub(super) async fn get_for(
+        &self,
+        document: &Url,
+        package_name: &str,
+        fetch_options: FetchOptions,
+    ) -> Result<MetadataFromRegistry, FetchError> {
+        self.get_with(Some(document), package_name, fetch_options)
+            .await
+    }
+    async fn get_with(
+        &self,
+        document: Option<&Url>,
+        package_name: &str,
+        fetch_options: FetchOptions,
+    ) -> Result<MetadataFromRegistry, FetchError> {
+        let (registries, headers, credentials, cache_ttl, max_cache_entries, mode) = {
+            let config = self.config.read().unwrap();
+            let projects = self.projects.read().unwrap();
+            let project = project_config(&projects, document).unwrap_or(&config);
+            (
+                project.registries_for(package_name).to_vec(),
+                project.headers.clone(),
+                project.credentials.clone(),
+                config.cache_ttl,
+                config.max_cache_entries,
+                config.fetch_mode,
+            )
+        };
+        let offline = mode == FetchMode::Offline;
+        let is_fresh =
+            |fetch_timestamp: Instant| offline || fetch_timestamp + cache_ttl > Instant::now();
+        let missing_key = (registries.join(", "), package_name.to_string());
         {
-            let lock = self.cache.lock().await;
-            let cached_entry = lock.get(package_name);
-            if let Some(cached_entry) = cached_entry {
-                if cached_entry.fetch_timestamp + REFRESH_DURATION > std::time::Instant::now() {
-                    return Some(cached_entry.clone());
+            let mut missing = self.missing.lock().await;
+            if missing.cap() != max_cache_entries {
+                missing.resize(max_cache_entries);
+            }
+            if let Some(fetch_timestamp) = missing.get(&missing_key) {
+                if is_fresh(*fetch_timestamp) {
+                    return Err(FetchError::NotFound {
+                        registry: missing_key.0,
+                    });
                 }
             }
         }
-        let latest_version = fetch(&self.client, package_name, fetch_options).await?;
-        {
-            match self.cache.lock().await.entry(package_name.into()) {
-                Entry::Occupied(mut entry) => {
-                    entry.insert(latest_version.clone());
-                }
-                Entry::Vacant(entry) => {
-                    entry.insert(latest_version.clone());
+        let (stale_entry, fetch_options) = {
+            let mut lock = self.cache.lock().await;
+            if lock.cap() != max_cache_entries {
+                lock.resize(max_cache_entries);
+            }
+            // An entry fetched for hover lacks the version list completions need. Details are nice
+            // to have, but not worth going online for.
+            let is_usable = |cached_entry: &MetadataFromRegistry| {
+                (cached_entry.parsed_all_versions || !fetch_options.parse_all_versions)
+                    && (cached_entry.full_metadata || !fetch_options.full_metadata || offline)
+                    && (cached_entry.kept_readme || !fetch_options.readme || offline)
+            };
+            // Only the configured registries are looked at, as the package may differ elsewhere.
+            let keys: Vec<_> = registries
+                .iter()
+                .map(|registry| (registry.clone(), package_name.to_string()))
+                .collect();
+            // Whatever replaces the kept entry holds everything it did too, so that hover,
+            // completion and diagnostics don't keep refetching what the others left out.
+            let fetch_options = keys
+                .iter()
+                .find_map(|key| lock.peek(key))
+                .map_or(fetch_options, |cached_entry| {
+                    fetch_options.union(cached_entry.fetch_options())
+                });
+            let key = keys
+                .into_iter()
+                .find(|key| lock.peek(key).is_some_and(is_usable));
+            let stale_entry = match key.and_then(|key| lock.get_mut(&key)) {
+                Some(cached_entry) => {
+                    if is_fresh(cached_entry.fetch_timestamp) {
+                        return Ok(cached_entry.clone());
+                    }
+                    // Counts as fresh while it is refreshed, so that only one refresh is started.
+                    (mode == FetchMode::PreferCache).then(|| {
+                        cached_entry.fetch_timestamp = Instant::now();
+                        cached_entry.clone()
+                    })
                 }
+                _ => None,
+            };
+            (stale_entry, fetch_options)
+        };
+        let requests = self.requests.read().unwrap().clone();
+        if let Some(stale_entry) = stale_entry {
+            if self.background.is_cancelled() {
+                return Ok(stale_entry);
             }
+            let (client, cache) = (self.client.clone(), self.cache.clone());
+            let (package_name, background) = (package_name.to_string(), self.background.clone());
+            tokio::spawn(async move {
+                let refresh = fetch_first(
+                    &client,
+                    &headers,
+                    &credentials,
+                    &requests,
+                    &registries,
+                    &package_name,
+                    fetch_options,
+                );
+                let metadata = tokio::select! {
+                    metadata = refresh => metadata,
+                    () = background.cancelled() => return,
+                };
+                if let Ok(metadata) = metadata {
+                    let key = (metadata.registry.clone(), package_name);
+                    cache.lock().await.put(key, metadata);
+                }
+            });
+            return Ok(stale_entry);
         }
-        Some(latest_version)
+        if offline {
+            return Err(FetchError::Unavailable);
+        }
+        let latest_version = match fetch_first(
+            &self.client,
+            &headers,
+            &credentials,
+            &requests,
+            &registries,
+            package_name,
+            fetch_options,
+        )
+        .await
+        {
+            Ok(latest_version) => latest_version,
+            Err(error) => {
+                if let FetchError::NotFound { .. } = &error {
+                    self.missing.lock().await.put(missing_key, Instant::now());
+                }
+                return Err(error);
+            }
+        };
+        self.missing.lock().await.pop(&missing_key);
+        let key = (latest_version.registry.clone(), package_name.to_string());
+        self.cache.lock().await.put(key, latest_version.clone());
+        Ok(latest_version)
     }
 }
 
+/// The outcome of a test fetch, returned by the `diagnose` command.
+#[derive(Debug, Serialize)]
+pub(super) struct Diagnosis {
+    pub package: String,
+    pub registry: String,
+    /// Where the package's metadata was requested from.
+    pub url: String,
+    /// HTTP status of the response, if the registry answered over HTTP at all.
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    /// Whether an `Authorization` header was sent.
+    pub authenticated: bool,
+    pub latest_version: Option<String>,
+    pub error: Option<String>,
+    /// How many other requests to registries were in flight.
+    pub in_flight_requests: usize,
+}
+
+#[derive(Clone, Copy)]
 pub(super) struct FetchOptions {
     pub parse_all_versions: bool,
+    /// Whether descriptions, publish dates and publishers are needed. Otherwise the much smaller
+    /// abbreviated document is requested, which only holds what's needed to install a package.
+    pub full_metadata: bool,
+    /// Whether the package's README is kept, which can be large. Implies `full_metadata`, as
+    /// abbreviated documents leave it out.
+    pub readme: bool,
+}
+
+impl FetchOptions {
+    /// Asks for everything either of them asks for.
+    fn union(self, other: Self) -> Self {
+        Self {
+            parse_all_versions: self.parse_all_versions || other.parse_all_versions,
+            full_metadata: self.full_metadata || other.full_metadata,
+            readme: self.readme || other.readme,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub(super) struct MetadataFromRegistry {
     fetch_timestamp: Instant,
+    /// The registry this was fetched from.
+    registry: String,
+    parsed_all_versions: bool,
+    full_metadata: bool,
+    /// Whether `readme` was asked for, so that packages without one aren't fetched again.
+    kept_readme: bool,
     pub latest_version: PackageVersion,
+    /// Every version that could be parsed, newest first, so that completion doesn't need to sort
+    /// them again on every request.
     pub package_versions: Vec<PackageVersion>,
     pub failed_versions: Vec<String>,
+    /// Versions that dist-tags like `latest` or `next` point at, keyed by tag.
+    pub dist_tags: HashMap<String, String>,
+    pub maintainer_count: Option<usize>,
+    pub readme: Option<String>,
+}
+
+impl MetadataFromRegistry {
+    /// What was asked for when this was fetched.
+    fn fetch_options(&self) -> FetchOptions {
+        FetchOptions {
+            parse_all_versions: self.parsed_all_versions,
+            full_metadata: self.full_metadata,
+            readme: self.kept_readme,
+        }
+    }
+}
+
+#[cfg(test)]
+impl MetadataFromRegistry {
+    pub(super) fn for_tests(
+        latest_version: PackageVersion,
+        mut package_versions: Vec<PackageVersion>,
+    ) -> Self {
+        package_versions.sort_by(|lhs, rhs| rhs.version.cmp(&lhs.version));
+        Self {
+            fetch_timestamp: Instant::now(),
+            registry: DEFAULT_REGISTRY.to_string(),
+            parsed_all_versions: true,
+            full_metadata: true,
+            kept_readme: false,
+            dist_tags: HashMap::from([("latest".to_string(), latest_version.version.to_string())]),
+            latest_version,
+            package_versions,
+            failed_versions: Vec::new(),
+            maintainer_count: None,
+            readme: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl PackageVersionFetcher {
+    pub(super) async fn insert_for_tests(
+        &self,
+        package_name: &str,
+        metadata: MetadataFromRegistry,
+    ) {
+        let key = (metadata.registry.clone(), package_name.to_string());
+        self.cache.lock().await.put(key, metadata);
+    }
 }
 
 #[derive(Clone)]
@@ -83,50 +785,224 @@ pub(super) struct PackageVersion {
     pub version: semver_rs::Version,
     pub description: String,
     pub homepage: Option<String>,
-    pub date: DateTime<FixedOffset>,
+    /// URL of the source repository, as declared (e.g. `git+https://github.com/lodash/lodash.git`).
+    pub repository: Option<String>,
+    /// Missing from abbreviated metadata.
+    pub date: Option<DateTime<FixedOffset>>,
+    /// Whether the registry holds provenance attestations for this version.
+    pub has_provenance: bool,
+    /// Whether the package ships its own TypeScript declarations.
+    pub has_types: bool,
+    /// Username of whoever published this version.
+    pub published_by: Option<String>,
+    /// Deprecation message set by the maintainers, which often names a replacement.
+    pub deprecated: Option<String>,
+    pub keywords: Vec<String>,
+    /// Where to support the maintainers, such as GitHub Sponsors or Open Collective pages.
+    pub funding: Vec<String>,
+    /// Subresource integrity of the tarball, like `sha512-...`.
+    pub integrity: Option<String>,
+    /// SHA-1 of the tarball, which is all older versions have.
+    pub shasum: Option<String>,
+}
+
+/// A package found by searching the registry.
+#[derive(Debug, PartialEq)]
+pub(super) struct SearchResult {
+    pub name: String,
+    /// The latest version.
+    pub version: String,
+    pub description: Option<String>,
+}
+
+/// Reads the results of npm's search endpoint, which wraps each package along with its scores.
+fn parse_search(response: &Value) -> Option<Vec<SearchResult>> {
+    let results = response["objects"].as_array()?.iter().filter_map(|object| {
+        let package = &object["package"];
+        Some(SearchResult {
+            name: package["name"].as_str()?.to_string(),
+            version: package["version"].as_str()?.to_string(),
+            description: package["description"]
+                .as_str()
+                .filter(|description| !description.is_empty())
+                .map(ToString::to_string),
+        })
+    });
+    Some(results.collect())
+}
+
+/// Starts a request to a registry, with the credential the registry needs, if any.
+fn authorized_get(
+    client: &reqwest::Client,
+    url: Url,
+    credentials: &[Credential],
+) -> reqwest::RequestBuilder {
+    match credential_for(credentials, &url) {
+        Some(authorization) => client.get(url).header(AUTHORIZATION, authorization),
+        None => client.get(url),
+    }
+}
+
+/// What a fetch sent and received, to tell why a registry can't be used.
+#[derive(Debug, Default)]
+struct FetchLog {
+    url: String,
+    status: Option<u16>,
+    authenticated: bool,
 }
 
 async fn fetch(
     client: &reqwest::Client,
+    headers: &[RegistryHeaders],
+    credentials: &[Credential],
+    registry: &str,
     package_name: &str,
     fetch_options: FetchOptions,
-) -> Option<MetadataFromRegistry> {
-    let package_name = urlencoding::encode(package_name);
-    let url = format!("https://registry.npmjs.org/{}", package_name);
-    let response = client
-        .get(url)
-        .send()
-        .await
-        .ok()?
-        .json::<Value>()
+) -> Result<MetadataFromRegistry, FetchError> {
+    fetch_with_log(
+        client,
+        headers,
+        credentials,
+        registry,
+        package_name,
+        fetch_options,
+        &mut FetchLog::default(),
+    )
+    .await
+}
+
+/// Fetches a package from the first of `registries` that has it. The package is only reported as
+/// missing when none of them have it, and otherwise the last failure is returned. Each request waits
+/// for a permit from `requests`.
+async fn fetch_first(
+    client: &reqwest::Client,
+    headers: &[RegistryHeaders],
+    credentials: &[Credential],
+    requests: &Semaphore,
+    registries: &[String],
+    package_name: &str,
+    fetch_options: FetchOptions,
+) -> Result<MetadataFromRegistry, FetchError> {
+    let mut failure = None;
+    for registry in registries {
+        let _permit = requests.acquire().await;
+        match fetch(
+            client,
+            headers,
+            credentials,
+            registry,
+            package_name,
+            fetch_options,
+        )
         .await
-        .ok()?;
-    let latest_version_str = response["dist-tags"]["latest"].as_str()?;
-    let Some(latest_version) =
-        parse_version_info(&response, &response["versions"][latest_version_str])
-    else {
-        return None;
+        {
+            Ok(metadata) => return Ok(metadata),
+            Err(FetchError::NotFound { .. }) => {}
+            Err(error) => failure = Some(error),
+        }
+    }
+    Err(failure.unwrap_or_else(|| FetchError::NotFound {
+        registry: registries.join(", "),
+    }))
+}
+
+async fn fetch_with_log(
+    client: &reqwest::Client,
+    headers: &[RegistryHeaders],
+    credentials: &[Credential],
+    registry: &str,
+    package_name: &str,
+    fetch_options: FetchOptions,
+    log: &mut FetchLog,
+) -> Result<MetadataFromRegistry, FetchError> {
+    let not_found = || FetchError::NotFound {
+        registry: registry.to_string(),
+    };
+    let response = if let Some(directory) = registry.strip_prefix("file://") {
+        // A registry mirrored to disk, laid out as one JSON document per package.
+        let path = package_path(directory, package_name).ok_or_else(not_found)?;
+        log.url = format!("file://{}", path.display());
+        let contents = tokio::fs::read(path).await.map_err(|error| {
+            if error.kind() == io::ErrorKind::NotFound {
+                not_found()
+            } else {
+                FetchError::Unavailable
+            }
+        })?;
+        serde_json::from_slice::<Value>(&contents).map_err(|_| FetchError::InvalidResponse)?
+    } else {
+        let url = package_url(registry, package_name).ok_or(FetchError::Unavailable)?;
+        log.url = url.to_string();
+        let headers = headers_for(headers, &url);
+        let mut request = authorized_get(client, url, credentials);
+        if !fetch_options.full_metadata && !fetch_options.readme {
+            request = request.header(ACCEPT, ABBREVIATED_METADATA);
+        }
+        // Configured last, so that they can stand in for the server's own headers.
+        if let Some(headers) = headers {
+            request = request.headers(headers.clone());
+        }
+        let request = request.build().map_err(|_| FetchError::Unavailable)?;
+        log.authenticated = request.headers().contains_key(AUTHORIZATION);
+        let response = client
+            .execute(request)
+            .await
+            .map_err(|_| FetchError::Unavailable)?;
+        log.status = Some(response.status().as_u16());
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(not_found());
+        }
+        response
+            .error_for_status()
+            .map_err(|_| FetchError::Unavailable)?
+            .json::<Value>()
+            .await
+            .map_err(|_| FetchError::InvalidResponse)?
     };
+    let latest_version = response["dist-tags"]["latest"]
+        .as_str()
+        .and_then(|latest_version| {
+            parse_version_info(&response, &response["versions"][latest_version])
+        })
+        .ok_or(FetchError::InvalidResponse)?;
 
-    let (package_versions, failed_versions) = if fetch_options.parse_all_versions {
-        response["versions"].as_object()?.into_iter().partition_map(
-            |(version_name, version_info)| {
-                if let Some(parsed_version_info) = parse_version_info(&response, &version_info) {
+    let (mut package_versions, failed_versions): (Vec<_>, _) = if fetch_options.parse_all_versions {
+        response["versions"]
+            .as_object()
+            .ok_or(FetchError::InvalidResponse)?
+            .into_iter()
+            .partition_map(|(version_name, version_info)| {
+                if let Some(parsed_version_info) = parse_version_info(&response, version_info) {
                     Either::Left(parsed_version_info)
                 } else {
                     Either::Right(version_name.clone())
                 }
-            },
-        )
+            })
     } else {
         (vec![], vec![])
     };
+    package_versions.sort_by(|lhs, rhs| rhs.version.cmp(&lhs.version));
 
-    Some(MetadataFromRegistry {
+    Ok(MetadataFromRegistry {
         fetch_timestamp: Instant::now(),
+        registry: registry.to_string(),
+        parsed_all_versions: fetch_options.parse_all_versions,
+        full_metadata: fetch_options.full_metadata || fetch_options.readme,
+        kept_readme: fetch_options.readme,
         latest_version,
         package_versions,
         failed_versions,
+        dist_tags: response["dist-tags"]
+            .as_object()
+            .iter()
+            .flat_map(|tags| tags.iter())
+            .filter_map(|(tag, version)| Some((tag.clone(), version.as_str()?.to_string())))
+            .collect(),
+        maintainer_count: response["maintainers"].as_array().map(Vec::len),
+        readme: response["readme"]
+            .as_str()
+            .filter(|readme| fetch_options.readme && !readme.trim().is_empty())
+            .map(ToString::to_string),
     })
 }
 
@@ -140,14 +1016,1174 @@ fn parse_version_info(response: &Value, version_info: &Value) -> Option<PackageV
         }),
     )
     .ok()?;
-    let description = version_info["description"].as_str()?.to_string();
+    let description = version_info["description"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
     let homepage = version_info["homepage"].as_str().map(ToString::to_string);
-    let date_str = response["time"][version_str].as_str()?;
-    let date = DateTime::parse_from_rfc3339(date_str).ok()?;
+    let repository = version_info["repository"]["url"]
+        .as_str()
+        .or_else(|| version_info["repository"].as_str())
+        .map(ToString::to_string);
+    let date = response["time"][version_str]
+        .as_str()
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok());
+    let has_provenance = version_info["dist"]["attestations"].is_object();
+    let has_types = version_info["types"].is_string() || version_info["typings"].is_string();
+    let published_by = version_info["_npmUser"]["name"]
+        .as_str()
+        .map(ToString::to_string);
+    let deprecated = version_info["deprecated"]
+        .as_str()
+        .filter(|message| !message.is_empty())
+        .map(ToString::to_string);
+    let keywords = version_info["keywords"]
+        .as_array()
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .filter_map(Value::as_str)
+        .filter(|keyword| !keyword.trim().is_empty())
+        .map(ToString::to_string)
+        .collect();
+    let funding = funding_urls(&version_info["funding"]);
+    let dist_field = |field: &str| {
+        version_info["dist"][field]
+            .as_str()
+            .map(ToString::to_string)
+    };
     Some(PackageVersion {
         version,
         description,
         homepage,
+        repository,
         date,
+        has_provenance,
+        has_types,
+        published_by,
+        deprecated,
+        keywords,
+        funding,
+        integrity: dist_field("integrity"),
+        shasum: dist_field("shasum"),
     })
 }
+
+/// `funding` is either a URL, an object with a `url`, or an array of either.
+fn funding_urls(funding: &Value) -> Vec<String> {
+    let url = |funding: &Value| {
+        funding
+            .as_str()
+            .or_else(|| funding["url"].as_str())
+            .filter(|url| !url.trim().is_empty())
+            .map(ToString::to_string)
+    };
+    match funding.as_array() {
+        Some(entries) => entries.iter().filter_map(url).collect(),
+        None => url(funding).into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::mock_registry::{MockRegistry, MockResponse};
+    use crate::temp_dir::TempDir;
+    use serde_json::json;
+
+    /// A version published at the start of 2024 with no extra metadata.
+    pub(crate) fn package_version(version: &str) -> PackageVersion {
+        PackageVersion {
+            version: semver_rs::Version::parse(version, None).unwrap(),
+            description: String::new(),
+            homepage: None,
+            repository: None,
+            date: DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z").ok(),
+            has_provenance: false,
+            has_types: false,
+            published_by: None,
+            deprecated: None,
+            keywords: Vec::new(),
+            funding: Vec::new(),
+            integrity: None,
+            shasum: None,
+        }
+    }
+
+    fn registry_response() -> Value {
+        json!({
+            "dist-tags": { "latest": "1.1.0" },
+            "readme": "# package\n\nDoes things.",
+            "time": {
+                "1.0.0": "2023-05-01T10:00:00.000Z",
+                "1.1.0": "2024-02-03T12:30:00.000Z"
+            },
+            "versions": {
+                "1.0.0": {
+                    "version": "1.0.0",
+                    "description": "A package",
+                    "deprecated": "Use another-package instead",
+                    "dist": { "shasum": "abc" }
+                },
+                "1.1.0": {
+                    "version": "1.1.0",
+                    "description": "A package",
+                    "homepage": "https://example.com",
+                    "repository": { "type": "git", "url": "git+https://github.com/example/package.git" },
+                    "types": "./index.d.ts",
+                    "keywords": ["http", "", "client"],
+                    "funding": { "type": "opencollective", "url": "https://opencollective.com/example" },
+                    "_npmUser": { "name": "alice", "email": "alice@example.com" },
+                    "dist": {
+                        "shasum": "def",
+                        "integrity": "sha512-ZGVm",
+                        "attestations": {
+                            "url": "https://registry.npmjs.org/-/npm/v1/attestations/package@1.1.0",
+                            "provenance": { "predicateType": "https://slsa.dev/provenance/v1" }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_registry_for_scoped_packages() {
+        let config = RegistryConfig {
+            scope_registries: HashMap::from([
+                (
+                    "@acme".to_string(),
+                    vec![
+                        "https://npm.acme.dev".to_string(),
+                        DEFAULT_REGISTRY.to_string(),
+                    ],
+                ),
+                ("@empty".to_string(), Vec::new()),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.registries_for("@acme/ui"),
+            ["https://npm.acme.dev", DEFAULT_REGISTRY]
+        );
+        assert_eq!(config.registries_for("@empty/ui"), [DEFAULT_REGISTRY]);
+        assert_eq!(config.registries_for("@other/ui"), [DEFAULT_REGISTRY]);
+        assert_eq!(config.registries_for("lodash"), [DEFAULT_REGISTRY]);
+    }
+
+    #[test]
+    fn test_registry_prefix() {
+        assert_eq!(
+            registry_prefix("https://npm.acme.dev/api/npm").as_deref(),
+            Ok("//npm.acme.dev/api/npm/")
+        );
+        assert_eq!(
+            registry_prefix("localhost:4873").as_deref(),
+            Ok("//localhost:4873/")
+        );
+        assert_eq!(
+            registry_prefix("//npm.acme.dev").as_deref(),
+            Ok("//npm.acme.dev/")
+        );
+        assert!(registry_prefix("file:///mirror").is_err());
+    }
+
+    #[test]
+    fn test_normalize_registry() {
+        for (registry, normalized) in [
+            ("https://registry.npmjs.org", "https://registry.npmjs.org"),
+            ("https://registry.npmjs.org/", "https://registry.npmjs.org"),
+            ("npm.acme.dev", "https://npm.acme.dev"),
+            (
+                "http://localhost:4873/api/npm//",
+                "http://localhost:4873/api/npm",
+            ),
+            ("file:///srv/registry/", "file:///srv/registry"),
+        ] {
+            assert_eq!(normalize_registry(registry).as_deref(), Ok(normalized));
+        }
+        assert_eq!(
+            normalize_registry("ftp://npm.acme.dev"),
+            Err("unsupported scheme `ftp`".to_string())
+        );
+        assert!(normalize_registry("https://").is_err());
+    }
+
+    #[test]
+    fn test_package_url() {
+        for registry in [
+            "https://npm.acme.dev/api/npm",
+            "https://npm.acme.dev/api/npm/",
+        ] {
+            assert_eq!(
+                package_url(registry, "@acme/ui").unwrap().as_str(),
+                "https://npm.acme.dev/api/npm/%40acme%2Fui"
+            );
+            assert_eq!(
+                package_url(registry, "lodash").unwrap().as_str(),
+                "https://npm.acme.dev/api/npm/lodash"
+            );
+        }
+    }
+
+    #[test]
+    fn test_package_path() {
+        assert_eq!(
+            package_path("/srv/registry", "@acme/ui"),
+            Some(PathBuf::from("/srv/registry/@acme/ui"))
+        );
+        assert_eq!(
+            package_path("/srv/registry", "lodash"),
+            Some(PathBuf::from("/srv/registry/lodash"))
+        );
+        for name in [
+            "../../home/user/x",
+            "@acme/../../x",
+            "/etc/foo",
+            "./lodash",
+            "",
+        ] {
+            assert_eq!(package_path("/srv/registry", name), None, "{name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_from_file_registry() {
+        let registry = TempDir::new("file-registry");
+        std::fs::create_dir_all(registry.join("@acme")).unwrap();
+        let response = registry_response().to_string();
+        std::fs::write(registry.join("@acme/ui"), &response).unwrap();
+
+        let client = Client::new();
+        let registry_url = format!("file://{}", registry.display());
+        let metadata = fetch(
+            &client,
+            &[],
+            &[],
+            &registry_url,
+            "@acme/ui",
+            FetchOptions {
+                parse_all_versions: true,
+                full_metadata: true,
+                readme: false,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(metadata.latest_version.version.to_string(), "1.1.0");
+        let versions: Vec<_> = metadata
+            .package_versions
+            .iter()
+            .map(|version| version.version.to_string())
+            .collect();
+        assert_eq!(versions, ["1.1.0", "1.0.0"]);
+        assert_eq!(metadata.dist_tags["latest"], "1.1.0");
+        assert_eq!(metadata.readme, None);
+
+        let metadata = fetch(
+            &client,
+            &[],
+            &[],
+            &registry_url,
+            "@acme/ui",
+            FetchOptions {
+                parse_all_versions: false,
+                full_metadata: true,
+                readme: true,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            metadata.readme.as_deref(),
+            Some("# package\n\nDoes things.")
+        );
+
+        let missing = fetch(
+            &client,
+            &[],
+            &[],
+            &registry_url,
+            "lodash",
+            FetchOptions {
+                parse_all_versions: false,
+                full_metadata: true,
+                readme: false,
+            },
+        )
+        .await;
+        assert_eq!(
+            missing.err(),
+            Some(FetchError::NotFound {
+                registry: registry_url.clone()
+            })
+        );
+
+        // Documents outside of the mirror are never read, however the dependency is named.
+        let outside = TempDir::new("file-registry-outside");
+        std::fs::write(outside.join("package"), &response).unwrap();
+        let escaping = [
+            format!(
+                "../{}/package",
+                outside.file_name().unwrap().to_string_lossy()
+            ),
+            outside.join("package").display().to_string(),
+        ];
+        for package_name in escaping {
+            let escaped = fetch(
+                &client,
+                &[],
+                &[],
+                &registry_url,
+                &package_name,
+                FetchOptions {
+                    parse_all_versions: false,
+                    full_metadata: true,
+                    readme: false,
+                },
+            )
+            .await;
+            assert_eq!(
+                escaped.err(),
+                Some(FetchError::NotFound {
+                    registry: registry_url.clone()
+                })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_gzip_encoded_response() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(registry_response().to_string().as_bytes())
+            .unwrap();
+        let response = MockResponse::json(&Value::Null).body(encoder.finish().unwrap());
+        let registry = MockRegistry::start(vec![(
+            "/package",
+            response.header("content-encoding", "gzip"),
+        )])
+        .await;
+
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        let metadata = fetch(
+            &fetcher.client,
+            &[],
+            &[],
+            &registry.url,
+            "package",
+            FetchOptions {
+                parse_all_versions: true,
+                full_metadata: false,
+                readme: false,
+            },
+        )
+        .await
+        .unwrap();
+        let request = &registry.requests()[0];
+        assert!(request.contains("accept-encoding: gzip, br"), "{request}");
+        assert!(
+            request.contains("accept: application/vnd.npm.install-v1+json"),
+            "{request}"
+        );
+        assert_eq!(metadata.latest_version.version.to_string(), "1.1.0");
+        assert_eq!(metadata.package_versions.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_registry_header() {
+        let env = |name: &str| (name == "NPM_KEY").then(|| "s3cr3t".to_string());
+        let (name, value) = parse_registry_header("X-Api-Key", "Key ${NPM_KEY}!", env).unwrap();
+        assert_eq!(name, "x-api-key");
+        assert_eq!(value, "Key s3cr3t!");
+        assert!(value.is_sensitive());
+        assert_eq!(
+            parse_registry_header("X-Token", "${NPM_KEY}${OTHER_KEY}", env).err(),
+            Some("environment variable `OTHER_KEY` isn't set".to_string())
+        );
+        // Unterminated references are kept as they are.
+        assert_eq!(
+            parse_registry_header("X-Token", "${NPM_KEY", env)
+                .unwrap()
+                .1,
+            "${NPM_KEY"
+        );
+        assert!(parse_registry_header("X Token", "value", env).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_registry_headers_are_sent() {
+        let registry =
+            MockRegistry::start(vec![("/package", MockResponse::json(&registry_response()))]).await;
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            default_registry: registry.url.clone(),
+            scope_registries: HashMap::from([(
+                "@acme".to_string(),
+                vec![format!("{}/scoped", registry.url)],
+            )]),
+            headers: vec![RegistryHeaders {
+                prefix: registry_prefix(&format!("{}/scoped", registry.url)).unwrap(),
+                headers: HeaderMap::from_iter([(
+                    HeaderName::from_static("x-api-key"),
+                    HeaderValue::from_static("s3cr3t"),
+                )]),
+            }],
+            ..Default::default()
+        });
+        let options = FetchOptions {
+            parse_all_versions: false,
+            full_metadata: false,
+            readme: false,
+        };
+        assert!(fetcher.get("@acme/ui", options).await.is_err());
+        fetcher.get("package", options).await.unwrap();
+        let requests = registry.requests();
+        assert!(requests[0].contains("x-api-key: s3cr3t"), "{}", requests[0]);
+        // Other registries never see them.
+        assert!(!requests[1].contains("x-api-key"), "{}", requests[1]);
+        assert!(
+            requests[1].contains("user-agent: package-version-server/"),
+            "{}",
+            requests[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_credentials_are_sent() {
+        let (private, public) = (
+            MockRegistry::start(vec![(
+                "/scoped/%40acme%2Fui",
+                MockResponse::json(&registry_response()),
+            )])
+            .await,
+            MockRegistry::start(vec![("/package", MockResponse::json(&registry_response()))]).await,
+        );
+        let private_prefix = private.url.trim_start_matches("http:");
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            default_registry: public.url.clone(),
+            scope_registries: HashMap::from([(
+                "@acme".to_string(),
+                vec![format!("{}/scoped", private.url)],
+            )]),
+            credentials: vec![
+                Credential::new(&format!("{private_prefix}/"), "Bearer general").unwrap(),
+                Credential::new(&format!("{private_prefix}/scoped/"), "Bearer s3cr3t").unwrap(),
+            ],
+            ..Default::default()
+        });
+        let options = FetchOptions {
+            parse_all_versions: false,
+            full_metadata: false,
+            readme: false,
+        };
+        fetcher.get("@acme/ui", options).await.unwrap();
+        fetcher.get("package", options).await.unwrap();
+        let request = &private.requests()[0];
+        assert!(
+            request.contains("authorization: bearer s3cr3t"),
+            "{request}"
+        );
+        let request = &public.requests()[0];
+        assert!(!request.contains("authorization"), "{request}");
+
+        let config = fetcher.config.read().unwrap().clone();
+        assert!(!format!("{config:?}").contains("s3cr3t"));
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let results = json!({
+            "objects": [
+                { "package": { "name": "lodash", "version": "4.17.21", "description": "Lodash modular utilities." }, "score": { "final": 0.9 } },
+                { "package": { "name": "lodash-es", "version": "4.17.21", "description": "" } },
+                { "package": { "name": "broken" } }
+            ],
+            "total": 3
+        });
+        let registry = MockRegistry::start(vec![(
+            "/-/v1/search?text=lod&size=2",
+            MockResponse::json(&results),
+        )])
+        .await;
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            default_registry: registry.url.clone(),
+            ..Default::default()
+        });
+        let document = Url::parse("file:///project/package.json").unwrap();
+        assert_eq!(
+            fetcher.search(&document, "lod", 2).await.unwrap(),
+            [
+                SearchResult {
+                    name: "lodash".into(),
+                    version: "4.17.21".into(),
+                    description: Some("Lodash modular utilities.".into()),
+                },
+                SearchResult {
+                    name: "lodash-es".into(),
+                    version: "4.17.21".into(),
+                    description: None,
+                },
+            ]
+        );
+        assert_eq!(
+            fetcher.search(&document, "react", 2).await.err(),
+            Some(FetchError::Unavailable)
+        );
+
+        // Scopes with their own registry are searched there, and left out of other registries.
+        let scoped = json!({
+            "objects": [
+                { "package": { "name": "@acme/ui", "version": "2.0.0" } },
+                { "package": { "name": "@acme-labs/ui", "version": "1.0.0" } }
+            ]
+        });
+        let (public, private) = (
+            MockRegistry::start(vec![(
+                "/-/v1/search?text=%40acme&size=2",
+                MockResponse::json(&scoped),
+            )])
+            .await,
+            MockRegistry::start(vec![(
+                "/-/v1/search?text=%40acme%2Fu&size=2",
+                MockResponse::json(&scoped),
+            )])
+            .await,
+        );
+        fetcher.configure(RegistryConfig {
+            default_registry: public.url.clone(),
+            scope_registries: HashMap::from([("@acme".to_string(), vec![private.url.clone()])]),
+            ..Default::default()
+        });
+        let names = |results: Vec<SearchResult>| {
+            results
+                .into_iter()
+                .map(|result| result.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(fetcher.search(&document, "@acme/u", 2).await.unwrap()),
+            ["@acme/ui"]
+        );
+        assert_eq!(
+            names(fetcher.search(&document, "@acme", 2).await.unwrap()),
+            ["@acme-labs/ui"]
+        );
+
+        fetcher.configure(RegistryConfig {
+            default_registry: "file:///registry".into(),
+            ..Default::default()
+        });
+        assert_eq!(
+            fetcher.search(&document, "lod", 2).await.err(),
+            Some(FetchError::Unavailable)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_diagnose() {
+        let registry = MockRegistry::start(Vec::new()).await.url;
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            default_registry: registry.clone(),
+            ..Default::default()
+        });
+        let diagnosis = fetcher.diagnose("lodash").await;
+        assert_eq!(diagnosis.url, format!("{registry}/lodash"));
+        assert_eq!(diagnosis.status, Some(404));
+        assert!(!diagnosis.authenticated);
+        assert_eq!(diagnosis.latest_version, None);
+        assert_eq!(diagnosis.error.as_deref(), Some("package not found"));
+        assert_eq!(diagnosis.in_flight_requests, 0);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_from_http_registry() {
+        let mut scoped = registry_response();
+        scoped["dist-tags"]["latest"] = json!("1.0.0");
+        let registry = MockRegistry::start(vec![
+            ("/package", MockResponse::json(&registry_response())),
+            ("/%40acme%2Fui", MockResponse::json(&scoped)),
+            ("/broken", MockResponse::status(500)),
+            ("/garbled", MockResponse::json(&json!({ "dist-tags": {} }))),
+        ])
+        .await;
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            default_registry: registry.url.clone(),
+            ..Default::default()
+        });
+        let get = |package| {
+            fetcher.get(
+                package,
+                FetchOptions {
+                    parse_all_versions: true,
+                    full_metadata: true,
+                    readme: false,
+                },
+            )
+        };
+        let metadata = get("package").await.unwrap();
+        assert_eq!(metadata.latest_version.version.to_string(), "1.1.0");
+        assert_eq!(metadata.registry, registry.url);
+        let ui = get("@acme/ui").await.unwrap();
+        assert_eq!(ui.latest_version.version.to_string(), "1.0.0");
+        assert_eq!(
+            get("missing").await.err(),
+            Some(FetchError::NotFound {
+                registry: registry.url.clone()
+            })
+        );
+        assert_eq!(get("broken").await.err(), Some(FetchError::Unavailable));
+        assert_eq!(
+            get("garbled").await.err(),
+            Some(FetchError::InvalidResponse)
+        );
+        // Full metadata is asked for as plain JSON.
+        assert!(!registry.requests()[0].contains("application/vnd.npm.install-v1+json"));
+    }
+
+    #[tokio::test]
+    async fn test_cache_ttl() {
+        let registry =
+            MockRegistry::start(vec![("/package", MockResponse::json(&registry_response()))]).await;
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        let configure = |cache_ttl| {
+            fetcher.configure(RegistryConfig {
+                default_registry: registry.url.clone(),
+                cache_ttl,
+                ..Default::default()
+            })
+        };
+        let options = FetchOptions {
+            parse_all_versions: false,
+            full_metadata: false,
+            readme: false,
+        };
+        configure(REFRESH_DURATION);
+        fetcher.get("package", options).await.unwrap();
+        fetcher.get("package", options).await.unwrap();
+        assert_eq!(registry.request_count("/package"), 1);
+        // Asking for more than was fetched bypasses the entry, however fresh.
+        fetcher
+            .get(
+                "package",
+                FetchOptions {
+                    full_metadata: true,
+                    ..options
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(registry.request_count("/package"), 2);
+        // What replaces an entry keeps what it held, so asking for either again needs no request.
+        let all_versions = fetcher
+            .get(
+                "package",
+                FetchOptions {
+                    parse_all_versions: true,
+                    ..options
+                },
+            )
+            .await
+            .unwrap();
+        assert!(!all_versions.package_versions.is_empty());
+        assert_eq!(registry.request_count("/package"), 3);
+        let full_metadata = FetchOptions {
+            full_metadata: true,
+            ..options
+        };
+        let metadata = fetcher.get("package", full_metadata).await.unwrap();
+        assert_eq!(metadata.package_versions.len(), 2);
+        assert_eq!(registry.request_count("/package"), 3);
+
+        configure(Duration::ZERO);
+        fetcher.get("package", options).await.unwrap();
+        fetcher.get("package", options).await.unwrap();
+        assert_eq!(registry.request_count("/package"), 5);
+    }
+
+    #[tokio::test]
+    async fn test_scope_registry_selection() {
+        let (public, acme, mirror) = (
+            MockRegistry::start(vec![
+                ("/package", MockResponse::json(&registry_response())),
+                ("/%40acme%2Fui", MockResponse::json(&registry_response())),
+            ])
+            .await,
+            MockRegistry::start(vec![("/%40acme%2Fui", MockResponse::status(503))]).await,
+            MockRegistry::start(vec![(
+                "/%40corp%2Fui",
+                MockResponse::json(&registry_response()),
+            )])
+            .await,
+        );
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            default_registry: public.url.clone(),
+            scope_registries: HashMap::from([
+                (
+                    "@acme".to_string(),
+                    vec![acme.url.clone(), public.url.clone()],
+                ),
+                ("@corp".to_string(), vec![mirror.url.clone()]),
+            ]),
+            ..Default::default()
+        });
+        let options = FetchOptions {
+            parse_all_versions: false,
+            full_metadata: true,
+            readme: false,
+        };
+        assert_eq!(
+            fetcher.get("package", options).await.unwrap().registry,
+            public.url
+        );
+        assert_eq!(
+            fetcher.get("@corp/ui", options).await.unwrap().registry,
+            mirror.url
+        );
+        // A registry that fails is skipped in favor of the next one.
+        assert_eq!(
+            fetcher.get("@acme/ui", options).await.unwrap().registry,
+            public.url
+        );
+        assert_eq!(acme.request_count("/%40acme%2Fui"), 1);
+        assert_eq!(
+            (
+                public.requests().len(),
+                acme.requests().len(),
+                mirror.requests().len()
+            ),
+            (2, 1, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_project_registries() {
+        let (workspace, project) = (
+            MockRegistry::start(vec![("/package", MockResponse::json(&registry_response()))]).await,
+            MockRegistry::start(vec![("/package", MockResponse::json(&registry_response()))]).await,
+        );
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            default_registry: workspace.url.clone(),
+            ..Default::default()
+        });
+        fetcher.configure_projects(HashMap::from([(
+            PathBuf::from("/project/packages/app"),
+            RegistryConfig {
+                default_registry: project.url.clone(),
+                ..Default::default()
+            },
+        )]));
+        let options = FetchOptions {
+            parse_all_versions: false,
+            full_metadata: false,
+            readme: false,
+        };
+        let document = |path| Url::parse(&format!("file:///project/{path}")).unwrap();
+        let (fetcher, options) = (&fetcher, &options);
+        let registry = |path| {
+            let document = document(path);
+            async move {
+                let metadata = fetcher.get_for(&document, "package", *options).await;
+                metadata.unwrap().registry
+            }
+        };
+        assert_eq!(registry("packages/app/src/package.json").await, project.url);
+        assert_eq!(registry("packages/lib/package.json").await, workspace.url);
+        assert_eq!(registry("package.json").await, workspace.url);
+        assert_eq!(
+            fetcher.get("package", *options).await.unwrap().registry,
+            workspace.url
+        );
+        // Each registry's copy of the package is cached apart from the other one.
+        assert_eq!(registry("packages/app/package.json").await, project.url);
+        assert_eq!(project.request_count("/package"), 1);
+        assert_eq!(workspace.request_count("/package"), 1);
+    }
+
+    #[test]
+    fn test_public_packages() {
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            scope_registries: HashMap::from([(
+                "@acme".to_string(),
+                vec!["https://npm.acme.dev".to_string()],
+            )]),
+            ..Default::default()
+        });
+        fetcher.configure_projects(HashMap::from([(
+            PathBuf::from("/project/internal"),
+            RegistryConfig {
+                default_registry: "https://npm.acme.dev".to_string(),
+                ..Default::default()
+            },
+        )]));
+        let document = Url::parse("file:///project/package.json").unwrap();
+        assert!(fetcher.is_public(&document, "lodash"));
+        assert!(fetcher.is_public(&document, "@types/node"));
+        assert!(!fetcher.is_public(&document, "@acme/ui"));
+        let internal = Url::parse("file:///project/internal/package.json").unwrap();
+        assert!(!fetcher.is_public(&internal, "lodash"));
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_packages_are_evicted() {
+        let registry = TempDir::new("lru-registry");
+        for package in ["a", "b", "c"] {
+            std::fs::write(registry.join(package), registry_response().to_string()).unwrap();
+        }
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            default_registry: format!("file://{}", registry.display()),
+            max_cache_entries: NonZeroUsize::new(2).unwrap(),
+            ..Default::default()
+        });
+        for package in ["a", "b", "a", "c"] {
+            let options = FetchOptions {
+                parse_all_versions: false,
+                full_metadata: true,
+                readme: false,
+            };
+            fetcher.get(package, options).await.unwrap();
+        }
+        let cache = fetcher.cache.lock().await;
+        let key = |package: &str| {
+            (
+                format!("file://{}", registry.display()),
+                package.to_string(),
+            )
+        };
+        assert!(cache.contains(&key("a")));
+        assert!(!cache.contains(&key("b")));
+        assert!(cache.contains(&key("c")));
+        drop(cache);
+    }
+
+    #[tokio::test]
+    async fn test_cached_fetch_modes() {
+        let registry = TempDir::new("mode-registry");
+        let path = registry.join("package");
+        std::fs::write(&path, registry_response().to_string()).unwrap();
+        let fetcher = &PackageVersionFetcher::new().unwrap();
+        let configure = |fetch_mode| {
+            fetcher.configure(RegistryConfig {
+                default_registry: format!("file://{}", registry.display()),
+                cache_ttl: Duration::ZERO,
+                fetch_mode,
+                ..Default::default()
+            })
+        };
+        let latest = |package| async move {
+            let options = FetchOptions {
+                parse_all_versions: false,
+                full_metadata: true,
+                readme: false,
+            };
+            let metadata = fetcher.get(package, options).await?;
+            Ok::<_, FetchError>(metadata.latest_version.version.to_string())
+        };
+        configure(FetchMode::Online);
+        assert_eq!(latest("package").await.unwrap(), "1.1.0");
+
+        let mut response = registry_response();
+        response["dist-tags"]["latest"] = json!("1.0.0");
+        std::fs::write(&path, response.to_string()).unwrap();
+        configure(FetchMode::PreferCache);
+        assert_eq!(latest("package").await.unwrap(), "1.1.0");
+        // The background refresh lands shortly after.
+        let mut refreshed = latest("package").await.unwrap();
+        for _ in 0..50 {
+            if refreshed == "1.0.0" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            refreshed = latest("package").await.unwrap();
+        }
+        assert_eq!(refreshed, "1.0.0");
+
+        std::fs::remove_dir_all(&registry).unwrap();
+        configure(FetchMode::Offline);
+        assert_eq!(latest("package").await.unwrap(), "1.0.0");
+        assert_eq!(latest("other").await.err(), Some(FetchError::Unavailable));
+    }
+
+    #[tokio::test]
+    async fn test_missing_packages_are_cached() {
+        let registry = MockRegistry::start(Vec::new()).await;
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            default_registry: registry.url.clone(),
+            ..Default::default()
+        });
+        let get = || {
+            fetcher.get(
+                "reactt",
+                FetchOptions {
+                    parse_all_versions: false,
+                    full_metadata: true,
+                    readme: false,
+                },
+            )
+        };
+        let not_found = Some(FetchError::NotFound {
+            registry: registry.url.clone(),
+        });
+        assert_eq!(get().await.err(), not_found);
+        assert_eq!(get().await.err(), not_found);
+        assert_eq!(registry.request_count("/reactt"), 1);
+
+        fetcher.configure(RegistryConfig {
+            default_registry: registry.url.clone(),
+            cache_ttl: Duration::ZERO,
+            ..Default::default()
+        });
+        assert_eq!(get().await.err(), not_found);
+        assert_eq!(registry.request_count("/reactt"), 2);
+
+        // Only as many missing packages as packages are remembered.
+        fetcher.configure(RegistryConfig {
+            default_registry: registry.url.clone(),
+            max_cache_entries: NonZeroUsize::new(2).unwrap(),
+            ..Default::default()
+        });
+        for package_name in ["r", "re", "rea"] {
+            let options = FetchOptions {
+                parse_all_versions: false,
+                full_metadata: false,
+                readme: false,
+            };
+            assert!(fetcher.get(package_name, options).await.is_err());
+        }
+        let missing = fetcher.missing.lock().await;
+        assert_eq!(missing.len(), 2);
+        assert!(!missing.contains(&(registry.url.clone(), "r".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_unconfigured_registries_are_forgotten() {
+        let registry = TempDir::new("forgotten-registry");
+        std::fs::write(registry.join("package"), registry_response().to_string()).unwrap();
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        let configure = |default_registry: String| {
+            fetcher.configure(RegistryConfig {
+                default_registry,
+                ..Default::default()
+            })
+        };
+        let options = FetchOptions {
+            parse_all_versions: false,
+            full_metadata: false,
+            readme: false,
+        };
+        configure(format!("file://{}", registry.display()));
+        assert!(fetcher.get("package", options).await.is_ok());
+        assert!(fetcher.get("missing", options).await.is_err());
+
+        fetcher.forget_unconfigured().await;
+        assert_eq!(fetcher.cache.lock().await.len(), 1);
+        assert!(fetcher.missing.lock().await.is_empty());
+
+        configure("https://npm.acme.dev".into());
+        fetcher.forget_unconfigured().await;
+        assert!(fetcher.cache.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_are_limited() {
+        let registry = TempDir::new("limit-registry");
+        std::fs::write(registry.join("package"), registry_response().to_string()).unwrap();
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            default_registry: format!("file://{}", registry.display()),
+            max_concurrent_requests: NonZeroUsize::new(1).unwrap(),
+            ..Default::default()
+        });
+        let options = FetchOptions {
+            parse_all_versions: false,
+            full_metadata: true,
+            readme: false,
+        };
+        let requests = fetcher.requests.read().unwrap().clone();
+        let permit = requests.acquire().await.unwrap();
+        assert_eq!(fetcher.in_flight_requests(), 1);
+        let waiting =
+            tokio::time::timeout(Duration::from_millis(50), fetcher.get("package", options));
+        assert!(waiting.await.is_err());
+
+        drop(permit);
+        assert_eq!(fetcher.in_flight_requests(), 0);
+        assert!(fetcher.get("package", options).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_requests_to_other_services() {
+        let service = MockRegistry::start(vec![(
+            "/releases",
+            MockResponse::json(&json!({ "latest": "1.0.0" })),
+        )])
+        .await;
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            max_concurrent_requests: NonZeroUsize::new(1).unwrap(),
+            ..Default::default()
+        });
+        let url = format!("{}/releases", service.url);
+        let requests = fetcher.requests.read().unwrap().clone();
+        let permit = requests.acquire().await.unwrap();
+        let waiting = tokio::time::timeout(
+            Duration::from_millis(50),
+            fetcher.request_json(|client| client.get(&url)),
+        );
+        assert!(waiting.await.is_err());
+        assert_eq!(service.request_count("/releases"), 0);
+
+        drop(permit);
+        let response = fetcher.request_json(|client| client.get(&url)).await;
+        assert_eq!(response, Some(json!({ "latest": "1.0.0" })));
+
+        fetcher.configure(RegistryConfig {
+            fetch_mode: FetchMode::Offline,
+            ..Default::default()
+        });
+        assert_eq!(fetcher.request_json(|client| client.get(&url)).await, None);
+        assert_eq!(service.request_count("/releases"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scope_registry_fallback() {
+        let mut icons = registry_response();
+        icons["dist-tags"]["latest"] = json!("1.0.0");
+        let (mirror, public) = (
+            MockRegistry::start(vec![("/%40acme%2Ficons", MockResponse::json(&icons))]).await,
+            MockRegistry::start(vec![(
+                "/%40acme%2Fui",
+                MockResponse::json(&registry_response()),
+            )])
+            .await,
+        );
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            scope_registries: HashMap::from([(
+                "@acme".to_string(),
+                vec![mirror.url.clone(), public.url.clone()],
+            )]),
+            ..Default::default()
+        });
+        let get = |package| {
+            fetcher.get(
+                package,
+                FetchOptions {
+                    parse_all_versions: false,
+                    full_metadata: true,
+                    readme: false,
+                },
+            )
+        };
+        let icons = get("@acme/icons").await.unwrap();
+        assert_eq!(icons.latest_version.version.to_string(), "1.0.0");
+        assert_eq!(icons.registry, mirror.url);
+        assert_eq!(public.request_count("/%40acme%2Ficons"), 0);
+        let ui = get("@acme/ui").await.unwrap();
+        assert_eq!(ui.latest_version.version.to_string(), "1.1.0");
+        assert_eq!(ui.registry, public.url);
+        assert_eq!(mirror.request_count("/%40acme%2Fui"), 1);
+        assert_eq!(
+            get("@acme/missing").await.err(),
+            Some(FetchError::NotFound {
+                registry: format!("{}, {}", mirror.url, public.url)
+            })
+        );
+
+        // Once fetched, the registry that had the package keeps being used.
+        assert_eq!(get("@acme/ui").await.unwrap().registry, public.url);
+        assert_eq!(mirror.request_count("/%40acme%2Fui"), 1);
+        assert_eq!(public.request_count("/%40acme%2Fui"), 1);
+    }
+
+    #[test]
+    fn test_parse_version_info() {
+        let response = registry_response();
+        let version = parse_version_info(&response, &response["versions"]["1.1.0"]).unwrap();
+        assert_eq!(version.version.to_string(), "1.1.0");
+        assert_eq!(version.description, "A package");
+        assert_eq!(version.homepage.as_deref(), Some("https://example.com"));
+        assert_eq!(
+            version.repository.as_deref(),
+            Some("git+https://github.com/example/package.git")
+        );
+        assert_eq!(
+            version.date,
+            DateTime::parse_from_rfc3339("2024-02-03T12:30:00.000Z").ok()
+        );
+        assert!(version.has_provenance);
+        assert!(version.has_types);
+        assert_eq!(version.published_by.as_deref(), Some("alice"));
+        assert_eq!(version.deprecated, None);
+        assert_eq!(version.keywords, ["http", "client"]);
+        assert_eq!(version.funding, ["https://opencollective.com/example"]);
+        assert_eq!(version.integrity.as_deref(), Some("sha512-ZGVm"));
+        assert_eq!(version.shasum.as_deref(), Some("def"));
+
+        let version = parse_version_info(&response, &response["versions"]["1.0.0"]).unwrap();
+        assert!(!version.has_provenance);
+        assert!(!version.has_types);
+        assert_eq!(version.published_by, None);
+        assert_eq!(version.integrity, None);
+        assert_eq!(version.shasum.as_deref(), Some("abc"));
+        assert_eq!(
+            version.deprecated.as_deref(),
+            Some("Use another-package instead")
+        );
+    }
+
+    #[test]
+    fn test_parse_abbreviated_version_info() {
+        let response = json!({
+            "name": "package",
+            "modified": "2024-02-03T12:30:00.000Z",
+            "dist-tags": { "latest": "1.1.0" },
+            "versions": {
+                "1.1.0": {
+                    "name": "package",
+                    "version": "1.1.0",
+                    "deprecated": "Use another-package instead",
+                    "dist": { "shasum": "def" }
+                }
+            }
+        });
+        let version = parse_version_info(&response, &response["versions"]["1.1.0"]).unwrap();
+        assert_eq!(version.version.to_string(), "1.1.0");
+        assert_eq!(version.description, "");
+        assert_eq!(version.date, None);
+        assert_eq!(
+            version.deprecated.as_deref(),
+            Some("Use another-package instead")
+        );
+    }
+
+    #[test]
+    fn test_funding_urls() {
+        assert_eq!(
+            funding_urls(&json!("https://github.com/sponsors/alice")),
+            ["https://github.com/sponsors/alice"]
+        );
+        assert_eq!(
+            funding_urls(&json!([
+                { "type": "github", "url": "https://github.com/sponsors/alice" },
+                "https://opencollective.com/example",
+                { "type": "patreon" }
+            ])),
+            [
+                "https://github.com/sponsors/alice",
+                "https://opencollective.com/example"
+            ]
+        );
+        assert!(funding_urls(&Value::Null).is_empty());
+    }
+}
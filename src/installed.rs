@@ -0,0 +1,95 @@
+//! Locating the packages installed in `node_modules`.
+
+use std::path::{Path, PathBuf};
+
+/// Finds the `package.json` of the installed `package_name`, as Node would resolve it from a
+/// manifest in `directory`: in the closest `node_modules` that has it, which is a parent's when
+/// dependencies are hoisted in a monorepo. Packages only present in pnpm's virtual store
+/// (`node_modules/.pnpm`) are found there as a last resort.
+pub(super) async fn find_manifest(directory: &Path, package_name: &str) -> Option<PathBuf> {
+    for ancestor in directory.ancestors() {
+        let manifest = ancestor
+            .join("node_modules")
+            .join(package_name)
+            .join("package.json");
+        if tokio::fs::try_exists(&manifest).await.unwrap_or(false) {
+            return Some(manifest);
+        }
+    }
+    for ancestor in directory.ancestors() {
+        if let Some(manifest) = find_in_pnpm_store(ancestor, package_name).await {
+            return Some(manifest);
+        }
+    }
+    None
+}
+
+/// pnpm stores `@scope/name@1.0.0` as `.pnpm/@scope+name@1.0.0/node_modules/@scope/name`, with
+/// peer dependencies appended to the directory name. The newest matching directory wins.
+async fn find_in_pnpm_store(directory: &Path, package_name: &str) -> Option<PathBuf> {
+    let store = directory.join("node_modules").join(".pnpm");
+    let prefix = format!("{}@", package_name.replace('/', "+"));
+    let mut entries = tokio::fs::read_dir(&store).await.ok()?;
+    let mut candidates = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(version) = name.strip_prefix(&prefix) else {
+            continue;
+        };
+        let version = version.split('_').next().unwrap_or_default();
+        if let Ok(version) = semver_rs::Version::new(version).parse() {
+            candidates.push((version, name));
+        }
+    }
+    candidates.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (_, candidate) in candidates.into_iter().rev() {
+        let manifest = store
+            .join(candidate)
+            .join("node_modules")
+            .join(package_name)
+            .join("package.json");
+        if tokio::fs::try_exists(&manifest).await.unwrap_or(false) {
+            return Some(manifest);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    fn write_manifest(path: PathBuf) -> PathBuf {
+        std::fs::create_dir_all(&path).unwrap();
+        let manifest = path.join("package.json");
+        std::fs::write(&manifest, r#"{ "version": "1.0.0" }"#).unwrap();
+        manifest
+    }
+
+    #[tokio::test]
+    async fn test_find_hoisted_manifest() {
+        let root = TempDir::new("hoisted");
+        let project = root.join("packages/app");
+        std::fs::create_dir_all(&project).unwrap();
+        let hoisted = write_manifest(root.join("node_modules/@acme/ui"));
+        let local = write_manifest(project.join("node_modules/lodash"));
+
+        assert_eq!(find_manifest(&project, "@acme/ui").await, Some(hoisted));
+        assert_eq!(find_manifest(&project, "lodash").await, Some(local));
+        assert_eq!(find_manifest(&project, "react").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_find_manifest_in_pnpm_store() {
+        let root = TempDir::new("pnpm-store");
+        let store = root.join("node_modules/.pnpm");
+        write_manifest(store.join("@acme+ui@1.9.0/node_modules/@acme/ui"));
+        let newest =
+            write_manifest(store.join("@acme+ui@1.10.0_react@18.3.1/node_modules/@acme/ui"));
+        write_manifest(store.join("@acme+ui-icons@2.0.0/node_modules/@acme/ui-icons"));
+
+        assert_eq!(find_manifest(&root, "@acme/ui").await, Some(newest));
+        assert_eq!(find_manifest(&root, "@acme/icons").await, None);
+    }
+}
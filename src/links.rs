@@ -0,0 +1,155 @@
+//! Web pages that dependency names link to.
+
+use tower_lsp::lsp_types::Url;
+
+use crate::fetcher::PackageVersion;
+use crate::parser::{self, Dependency};
+
+/// The package a dependency installs, which differs from its name for aliases like
+/// `"lodash4": "npm:lodash@^4"`.
+pub(super) fn package_name(dependency: &Dependency) -> &str {
+    parser::npm_alias(&dependency.version)
+        .map_or(&dependency.name, |(package_name, _)| package_name)
+}
+
+/// The package's page on npmjs.com.
+pub(super) fn npm_page(package_name: &str) -> Option<Url> {
+    Url::parse(&format!("https://www.npmjs.com/package/{package_name}")).ok()
+}
+
+/// A browsable URL for a repository declared in `package.json`, which is often a git URL or a
+/// shorthand like `github:user/repo`.
+pub(super) fn repository_page(repository: &str) -> Option<Url> {
+    let repository = repository.trim();
+    let url = if let Some(path) = repository.strip_prefix("github:") {
+        format!("https://github.com/{path}")
+    } else if let Some(path) = repository.strip_prefix("gitlab:") {
+        format!("https://gitlab.com/{path}")
+    } else if let Some(path) = repository.strip_prefix("bitbucket:") {
+        format!("https://bitbucket.org/{path}")
+    } else if !repository.contains(':') && repository.matches('/').count() == 1 {
+        format!("https://github.com/{repository}")
+    } else {
+        let url = repository.strip_prefix("git+").unwrap_or(repository);
+        let url = url
+            .strip_prefix("git://")
+            .or_else(|| url.strip_prefix("ssh://git@"))
+            .map_or_else(|| url.to_string(), |host| format!("https://{host}"));
+        url.replacen("https://git@", "https://", 1)
+    };
+    let url = url.strip_suffix(".git").unwrap_or(&url);
+    Url::parse(url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// The most specific page about a package: its homepage, then its repository, then npmjs.com.
+pub(super) fn preferred_page(package_name: &str, latest: &PackageVersion) -> Option<Url> {
+    latest
+        .homepage
+        .as_deref()
+        .and_then(|homepage| Url::parse(homepage).ok())
+        .or_else(|| latest.repository.as_deref().and_then(repository_page))
+        .or_else(|| npm_page(package_name))
+}
+
+/// A short rendition of a URL to show as link text: its host and path, without the scheme or a
+/// leading `www.`.
+pub(super) fn link_label(url: &str) -> String {
+    let Ok(parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    let Some(host) = parsed.host_str() else {
+        return url.to_string();
+    };
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    match parsed.path().trim_matches('/') {
+        "" => host.to_string(),
+        path => format!("{host}/{path}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Range;
+
+    use crate::fetcher::tests::package_version;
+    use crate::parser::DependencyKind;
+
+    fn dependency(name: &str, version: &str) -> Dependency {
+        Dependency {
+            name: name.into(),
+            version: version.into(),
+            section: DependencyKind::Dependencies,
+            name_range: Range::default(),
+            version_range: Range::default(),
+        }
+    }
+
+    #[test]
+    fn test_package_name() {
+        assert_eq!(package_name(&dependency("lodash", "^4")), "lodash");
+        assert_eq!(
+            package_name(&dependency("lodash4", "npm:lodash@^4")),
+            "lodash"
+        );
+        assert_eq!(
+            package_name(&dependency("ui", "npm:@acme/ui@1.0.0")),
+            "@acme/ui"
+        );
+        assert_eq!(package_name(&dependency("ui", "npm:@acme/ui")), "@acme/ui");
+    }
+
+    #[test]
+    fn test_repository_page() {
+        let page = |repository| repository_page(repository).map(String::from);
+        let expected = Some("https://github.com/lodash/lodash".to_string());
+        for repository in [
+            "git+https://github.com/lodash/lodash.git",
+            "git://github.com/lodash/lodash.git",
+            "git+ssh://git@github.com/lodash/lodash.git",
+            "github:lodash/lodash",
+            "lodash/lodash",
+            "https://github.com/lodash/lodash",
+        ] {
+            assert_eq!(page(repository), expected, "{repository}");
+        }
+        assert_eq!(page("file:../lodash"), None);
+    }
+
+    #[test]
+    fn test_link_label() {
+        assert_eq!(
+            link_label("https://github.com/sponsors/alice"),
+            "github.com/sponsors/alice"
+        );
+        assert_eq!(link_label("https://www.example.com/"), "example.com");
+        assert_eq!(link_label("not a url"), "not a url");
+    }
+
+    #[test]
+    fn test_preferred_page() {
+        let mut latest = package_version("4.17.21");
+        assert_eq!(
+            preferred_page("@acme/ui", &latest)
+                .map(String::from)
+                .as_deref(),
+            Some("https://www.npmjs.com/package/@acme/ui")
+        );
+        latest.repository = Some("github:acme/ui".into());
+        assert_eq!(
+            preferred_page("@acme/ui", &latest)
+                .map(String::from)
+                .as_deref(),
+            Some("https://github.com/acme/ui")
+        );
+        latest.homepage = Some("https://ui.acme.dev/".into());
+        assert_eq!(
+            preferred_page("@acme/ui", &latest)
+                .map(String::from)
+                .as_deref(),
+            Some("https://ui.acme.dev/")
+        );
+    }
+}
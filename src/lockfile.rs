@@ -0,0 +1,202 @@
+//! Reading the versions npm's `package-lock.json` resolved dependencies to, and the integrity it
+//! recorded for them.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use serde_json::Value;
+
+/// The direct dependencies of a manifest installed according to a lockfile, keyed by name.
+pub(super) type InstalledPackages = HashMap<String, InstalledPackage>;
+
+#[derive(Debug, PartialEq)]
+pub(super) struct InstalledPackage {
+    pub version: String,
+    /// Subresource integrity of the tarball that was installed, like `sha512-...`.
+    pub integrity: Option<String>,
+}
+
+impl InstalledPackage {
+    fn from_entry(entry: &Value) -> Option<Self> {
+        Some(Self {
+            version: entry["version"].as_str()?.to_string(),
+            integrity: entry["integrity"].as_str().map(ToString::to_string),
+        })
+    }
+}
+
+/// Finds the lockfile of the manifest in `directory`, which is the root one for packages of a
+/// workspace, and reads what it resolved the manifest's dependencies to. `None` when there is no
+/// lockfile.
+pub(super) async fn installed_packages(directory: &Path) -> Option<InstalledPackages> {
+    for ancestor in directory.ancestors() {
+        let path = ancestor.join("package-lock.json");
+        let Ok(metadata) = tokio::fs::metadata(&path).await else {
+            continue;
+        };
+        let lockfile = read_lockfile(&path, metadata.modified().ok()).await?;
+        let package = directory.strip_prefix(ancestor).ok()?.to_str()?;
+        return Some(resolved_packages(&lockfile, &package.replace('\\', "/")));
+    }
+    None
+}
+
+/// Parsed lockfiles, keyed by path, along with when they had been modified.
+type ParsedLockfiles = HashMap<PathBuf, (SystemTime, Arc<Value>)>;
+
+/// Parses the lockfile at `path`, unless it hasn't been modified since it was last parsed. Lockfiles
+/// are looked into on every analysis and hover, and can be several megabytes large.
+async fn read_lockfile(path: &Path, modified: Option<SystemTime>) -> Option<Arc<Value>> {
+    static LOCKFILES: OnceLock<Mutex<ParsedLockfiles>> = OnceLock::new();
+    let lockfiles = LOCKFILES.get_or_init(Default::default);
+    if let Some((parsed_at, lockfile)) = lockfiles.lock().unwrap().get(path) {
+        if Some(*parsed_at) == modified {
+            return Some(lockfile.clone());
+        }
+    }
+    let contents = tokio::fs::read(path).await.ok()?;
+    let lockfile: Arc<Value> = Arc::new(serde_json::from_slice(&contents).ok()?);
+    if let Some(modified) = modified {
+        lockfiles
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (modified, lockfile.clone()));
+    }
+    Some(lockfile)
+}
+
+/// What a lockfile resolved the dependencies of a package to, given where the package is
+/// relative to the lockfile (`""` for the root one). Like Node, a package's own `node_modules` is
+/// looked into before the hoisted ones of its parents.
+fn resolved_packages(lockfile: &Value, package: &str) -> InstalledPackages {
+    let mut installed = InstalledPackages::new();
+    if let Some(packages) = lockfile["packages"].as_object() {
+        let mut directories = vec![package];
+        let mut directory = package;
+        while let Some((parent, _)) = directory.rsplit_once('/') {
+            directories.push(parent);
+            directory = parent;
+        }
+        if !package.is_empty() {
+            directories.push("");
+        }
+        for directory in directories {
+            let prefix = match directory {
+                "" => "node_modules/".to_string(),
+                directory => format!("{directory}/node_modules/"),
+            };
+            for (path, entry) in packages {
+                let Some(name) = path.strip_prefix(&prefix) else {
+                    continue;
+                };
+                // Dependencies of dependencies are nested in their own `node_modules`.
+                if name.contains("/node_modules/") {
+                    continue;
+                }
+                if installed.contains_key(name) {
+                    continue;
+                }
+                if let Some(package) = InstalledPackage::from_entry(entry) {
+                    installed.insert(name.to_string(), package);
+                }
+            }
+        }
+    } else if let Some(dependencies) = lockfile["dependencies"].as_object() {
+        // Lockfiles from before npm 7 only describe the root package.
+        if package.is_empty() {
+            installed.extend(dependencies.iter().filter_map(|(name, entry)| {
+                Some((name.clone(), InstalledPackage::from_entry(entry)?))
+            }));
+        }
+    }
+    installed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use serde_json::json;
+
+    fn lockfile() -> Value {
+        json!({
+            "lockfileVersion": 3,
+            "packages": {
+                "": { "workspaces": ["packages/*"] },
+                "node_modules/express": { "version": "4.21.2", "integrity": "sha512-ZXhw" },
+                "node_modules/@acme/ui": { "version": "1.2.0" },
+                "node_modules/express/node_modules/debug": { "version": "2.6.9" },
+                "node_modules/debug": { "version": "4.3.7" },
+                "packages/app": { "version": "1.0.0" },
+                "packages/app/node_modules/express": { "version": "5.0.1" }
+            }
+        })
+    }
+
+    #[test]
+    fn test_resolved_packages() {
+        let version = |installed: &InstalledPackages, name| installed[name].version.clone();
+        let root = resolved_packages(&lockfile(), "");
+        assert_eq!(
+            root["express"],
+            InstalledPackage {
+                version: "4.21.2".into(),
+                integrity: Some("sha512-ZXhw".into()),
+            }
+        );
+        assert_eq!(version(&root, "@acme/ui"), "1.2.0");
+        assert_eq!(version(&root, "debug"), "4.3.7");
+
+        let app = resolved_packages(&lockfile(), "packages/app");
+        assert_eq!(version(&app, "express"), "5.0.1");
+        assert_eq!(app["express"].integrity, None);
+        assert_eq!(version(&app, "debug"), "4.3.7");
+
+        let legacy = json!({
+            "lockfileVersion": 1,
+            "dependencies": { "lodash": { "version": "4.17.21" } }
+        });
+        assert_eq!(
+            version(&resolved_packages(&legacy, ""), "lodash"),
+            "4.17.21"
+        );
+        assert!(resolved_packages(&legacy, "packages/app").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_installed_packages() {
+        let root = TempDir::new("lockfile");
+        let app = root.join("packages/app");
+        std::fs::create_dir_all(&app).unwrap();
+        assert_eq!(installed_packages(&app).await, None);
+
+        std::fs::write(root.join("package-lock.json"), lockfile().to_string()).unwrap();
+        let installed = installed_packages(&app).await.unwrap();
+        assert_eq!(installed["express"].version, "5.0.1");
+        let installed = installed_packages(&root).await.unwrap();
+        assert_eq!(installed["express"].version, "4.21.2");
+
+        // The lockfile is only parsed again once it has been modified.
+        let path = root.join("package-lock.json");
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        let mut updated = lockfile();
+        updated["packages"]["node_modules/express"]["version"] = json!("4.21.3");
+        std::fs::write(&path, updated.to_string()).unwrap();
+        let set_modified = |time| {
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap()
+        };
+        set_modified(modified);
+        let installed = installed_packages(&root).await.unwrap();
+        assert_eq!(installed["express"].version, "4.21.2");
+        set_modified(modified + std::time::Duration::from_secs(1));
+        let installed = installed_packages(&root).await.unwrap();
+        assert_eq!(installed["express"].version, "4.21.3");
+    }
+}
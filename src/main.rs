@@ -1,23 +1,164 @@
+mod bunfig;
+mod cocoapods;
+mod commands;
+mod completion;
+mod diagnostics;
 mod fetcher;
+mod installed;
+mod links;
+mod lockfile;
+mod maven;
+#[cfg(test)]
+mod mock_registry;
+mod npmrc;
+mod osv;
 mod parser;
+mod pnpm;
+mod progress;
+mod response_cache;
+mod rubygems;
+mod semantic_tokens;
+mod settings;
+#[cfg(test)]
+mod temp_dir;
+mod tool_versions;
+mod update;
+mod workspace;
+mod yarnrc;
 
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
 use chrono::{DateTime, FixedOffset};
 use chrono_humanize::{Accuracy, HumanTime, Tense};
-use fetcher::{FetchOptions, PackageVersionFetcher};
-use parser::ParseResult;
+use completion::CompletionCapabilities;
+use fetcher::{FetchOptions, MetadataFromRegistry, PackageVersion, PackageVersionFetcher};
+use futures::future::join_all;
+use parser::{BundledName, Dependency, DependencyKey, DependencyKind, ParseResult};
+use progress::Progress;
+use serde_json::Value;
+use settings::{FeatureSettings, RunOn, Settings};
+use tokio_util::sync::CancellationToken;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
-use tree_sitter::Parser;
+use tree_sitter::{Parser, Tree};
 use tree_sitter_json::language;
 
+type DocumentContents = HashMap<Url, (Arc<str>, tree_sitter::Tree)>;
+
+/// Diagnostics computed for a document, identified by the contents they were computed for.
+#[derive(Clone)]
+struct AnalyzedDocument {
+    text: Arc<str>,
+    result_id: String,
+    diagnostics: Vec<Diagnostic>,
+    /// The name ranges of the outdated dependencies, which are counted even when they aren't
+    /// reported.
+    outdated: Vec<Range>,
+}
+
+/// A completion request in flight, which stops waiting for the registry once a newer request for
+/// the same document arrives, as its suggestions would be out of date.
+struct PendingCompletion {
+    uri: Url,
+    id: u64,
+    token: CancellationToken,
+    pending_completions: Arc<Mutex<HashMap<Url, (u64, CancellationToken)>>>,
+}
+
+impl PendingCompletion {
+    /// Waits for `future`, unless the request is superseded first.
+    async fn unless_superseded<T>(&self, future: impl Future<Output = T>) -> Result<T> {
+        tokio::select! {
+            output = future => Ok(output),
+            () = self.token.cancelled() => Err(tower_lsp::jsonrpc::Error::content_modified()),
+        }
+    }
+}
+
+impl Drop for PendingCompletion {
+    fn drop(&mut self) {
+        let mut pending = self.pending_completions.lock().unwrap();
+        if pending.get(&self.uri).is_some_and(|(id, _)| *id == self.id) {
+            pending.remove(&self.uri);
+        }
+    }
+}
+
+/// Code action kind of the in-range updates applied by editors that fix all problems on save.
+const FIX_ALL_KIND: &str = "source.fixAll.packageVersionServer";
+
+/// Code action kind of sorting every dependency section by name.
+const SORT_KIND: &str = "source.sortDependencies";
+
+/// How many packages the registry is asked for when completing the name of a new dependency.
+const NEW_DEPENDENCY_RESULTS: usize = 20;
+
+/// How long to wait for the user to stop typing before analyzing a changed document.
+const DIAGNOSTICS_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Cheap to clone, so that work can be moved onto background tasks.
+#[derive(Clone)]
 struct Backend {
     client: Client,
-    file_contents: Arc<Mutex<HashMap<Url, (Arc<str>, tree_sitter::Tree)>>>,
-    fetcher: PackageVersionFetcher,
+    file_contents: Arc<Mutex<DocumentContents>>,
+    fetcher: Arc<PackageVersionFetcher>,
+    settings: Arc<RwLock<Settings>>,
+    /// Registries configured by the workspace's and the user's `.npmrc`, which settings override.
+    npmrc: Arc<RwLock<npmrc::NpmrcRegistries>>,
+    /// Registries configured by the `.npmrc` or `.yarnrc.yml` of projects within the workspace
+    /// folders, each layered over the user's, keyed by the directory of the project. Read once one
+    /// of their documents is looked at, and again when their configuration changes.
+    project_npmrc: Arc<RwLock<HashMap<PathBuf, npmrc::NpmrcRegistries>>>,
+    /// The workspace folders, which the `.npmrc` of a document's project is looked for within.
+    roots: Arc<RwLock<Vec<PathBuf>>>,
+    completion_capabilities: Arc<RwLock<CompletionCapabilities>>,
+    /// Shared between all documents so that the language only has to be set up once.
+    parser: Arc<Mutex<Parser>>,
+    /// The latest diagnostics of each document, so that repeated pulls don't refetch anything.
+    analyzed_documents: Arc<Mutex<HashMap<Url, AnalyzedDocument>>>,
+    /// Known vulnerabilities of installed packages, cached apart from their metadata as they
+    /// change far less often.
+    advisories: Arc<osv::AdvisoryCache>,
+    /// Releases of the tools pinned in `.tool-versions` and `mise.toml`.
+    tool_releases: Arc<tool_versions::ReleaseCache>,
+    /// What rubygems.org, the CocoaPods trunk and Maven Central told about the dependencies
+    /// hovered in Ruby, CocoaPods and Maven manifests.
+    gems: Arc<rubygems::GemCache>,
+    pods: Arc<cocoapods::PodCache>,
+    artifacts: Arc<maven::ArtifactCache>,
+    /// Documents edited since they were last saved while diagnostics only run on save.
+    unsaved_documents: Arc<Mutex<HashSet<Url>>>,
+    next_result_id: Arc<AtomicU64>,
+    /// Whether the client asks for diagnostics itself rather than having them published.
+    pull_diagnostics: Arc<AtomicBool>,
+    /// Dependencies declared by the manifests of the workspace folders, whether open or not.
+    workspace_index: Arc<Mutex<workspace::WorkspaceIndex>>,
+    /// Whether the client can be asked to report changes to manifests.
+    watch_manifests: Arc<AtomicBool>,
+    /// The completion request in flight for each document, which is cancelled once a newer one
+    /// arrives as its suggestions would be out of date.
+    pending_completions: Arc<Mutex<HashMap<Url, (u64, CancellationToken)>>>,
+    next_completion_id: Arc<AtomicU64>,
+    /// Compiled for the sections hover and completion are enabled in, whenever settings change.
+    dependency_query: Arc<RwLock<Arc<parser::DependencyQuery>>>,
+    /// Whether the client can be asked to request semantic tokens again once a document has been
+    /// analyzed.
+    refresh_semantic_tokens: Arc<AtomicBool>,
+    /// Whether the client can show progress reported by the server on its own.
+    work_done_progress: Arc<AtomicBool>,
+    /// Whether the client resolves the edits of code actions once they are picked.
+    resolve_code_action_edits: Arc<AtomicBool>,
+    /// Whether the client can be asked to open a URL.
+    show_document: Arc<AtomicBool>,
+    /// Cancelled once the client shuts the server down, which stops the work running in the
+    /// background.
+    shutdown: CancellationToken,
 }
 
 impl Backend {
@@ -25,8 +166,35 @@ impl Backend {
         Ok(Self {
             client: lsp_client,
             file_contents: Default::default(),
-            fetcher: PackageVersionFetcher::new()
-                .map_err(|_| tower_lsp::jsonrpc::Error::internal_error())?,
+            fetcher: Arc::new(
+                PackageVersionFetcher::new()
+                    .map_err(|_| tower_lsp::jsonrpc::Error::internal_error())?,
+            ),
+            settings: Default::default(),
+            npmrc: Default::default(),
+            project_npmrc: Default::default(),
+            roots: Default::default(),
+            completion_capabilities: Default::default(),
+            parser: Arc::new(Mutex::new(Self::get_parser())),
+            analyzed_documents: Default::default(),
+            unsaved_documents: Default::default(),
+            advisories: Default::default(),
+            tool_releases: Default::default(),
+            gems: Default::default(),
+            pods: Default::default(),
+            artifacts: Default::default(),
+            next_result_id: Default::default(),
+            pull_diagnostics: Default::default(),
+            workspace_index: Default::default(),
+            watch_manifests: Default::default(),
+            pending_completions: Default::default(),
+            next_completion_id: Default::default(),
+            dependency_query: Default::default(),
+            resolve_code_action_edits: Default::default(),
+            show_document: Default::default(),
+            shutdown: CancellationToken::new(),
+            refresh_semantic_tokens: Default::default(),
+            work_done_progress: Default::default(),
         })
     }
     fn get_parser() -> Parser {
@@ -35,242 +203,5740 @@ impl Backend {
 
         parser
     }
-}
-
-#[tower_lsp::async_trait]
-impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
-        Ok(InitializeResult {
-            capabilities: ServerCapabilities {
-                hover_provider: Some(HoverProviderCapability::Simple(true)),
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
-                )),
-                completion_provider: Some(CompletionOptions {
-                    trigger_characters: Some(vec![String::from(".")]),
-                    ..Default::default()
-                }),
-                ..ServerCapabilities::default()
-            },
-            ..InitializeResult::default()
-        })
-    }
-
-    async fn shutdown(&self) -> Result<()> {
-        Ok(())
+    fn update_document(&self, uri: Url, text: String) -> Arc<str> {
+        let text: Arc<str> = text.into();
+        // Other manifests aren't JSON, so they get the tree of an empty document, which declares
+        // nothing.
+        let json = match uri.path().ends_with("package.json") {
+            true => text.as_bytes(),
+            false => &[],
+        };
+        let parse_tree = self
+            .parser
+            .lock()
+            .unwrap()
+            .parse(json, None)
+            .expect("We should always get a new parse tree.");
+        self.file_contents
+            .lock()
+            .unwrap()
+            .insert(uri, (text.clone(), parse_tree));
+        text
     }
 
-    async fn initialized(&self, _: InitializedParams) {
-        self.client
-            .log_message(MessageType::INFO, "Language server initialized.")
-            .await;
+    /// The contents of a document, or those of its file when it hasn't been opened yet, as some
+    /// clients send requests before `didOpen`. Documents that aren't files are only known once
+    /// opened.
+    async fn document(&self, uri: &Url) -> Option<(Arc<str>, Tree)> {
+        let document = self.file_contents.lock().unwrap().get(uri).cloned();
+        if document.is_some() {
+            return document;
+        }
+        let path = uri.to_file_path().ok()?;
+        let text: Arc<str> = tokio::fs::read_to_string(path).await.ok()?.into();
+        let tree = self.parser.lock().unwrap().parse(text.as_bytes(), None)?;
+        // Its packages are fetched from the registries of its project, as if it had been opened.
+        self.discover_npmrc(uri).await;
+        Some((text, tree))
     }
-
-    async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        if !params.text_document.uri.path().ends_with("package.json") {
+    /// Runs work in the background until it completes or the server shuts down, whichever comes
+    /// first. Nothing is started anymore after shutdown.
+    fn spawn(&self, task: impl Future + Send + 'static) {
+        if self.shutdown.is_cancelled() {
             return;
         }
-        if let Some(change) = params.content_changes.into_iter().next() {
-            let mut parser = Self::get_parser();
-            let text: Arc<str> = change.text.into();
-            self.file_contents
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = task => {}
+                () = shutdown.cancelled() => {}
+            }
+        });
+    }
+    /// Whether `text` is still the latest known contents of the document.
+    fn is_current(&self, uri: &Url, text: &Arc<str>) -> bool {
+        self.file_contents
+            .lock()
+            .unwrap()
+            .get(uri)
+            .is_some_and(|(contents, _)| Arc::ptr_eq(contents, text))
+    }
+    /// Computes the diagnostics for the given contents of a document, reusing the previous result if
+    /// they haven't changed since. Returns `None` if the registry couldn't be reached.
+    async fn analyze(&self, uri: &Url, text: &Arc<str>, tree: &Tree) -> Option<AnalyzedDocument> {
+        self.analyze_reporting(uri, text, tree, &|_, _| {}).await
+    }
+    /// Like [`Self::analyze`], calling `on_fetched` with how many packages have been fetched so
+    /// far, out of how many.
+    async fn analyze_reporting(
+        &self,
+        uri: &Url,
+        text: &Arc<str>,
+        tree: &Tree,
+        on_fetched: &(dyn Fn(usize, usize) + Sync),
+    ) -> Option<AnalyzedDocument> {
+        if let Some(analyzed) = self.analyzed_documents.lock().unwrap().get(uri) {
+            if Arc::ptr_eq(&analyzed.text, text) {
+                return Some(analyzed.clone());
+            }
+        }
+        let settings = self.settings.read().unwrap().diagnostics.clone();
+        let installed = installed_packages(uri).await;
+        let analysis = diagnostics::analyze(
+            &self.fetcher,
+            &settings,
+            uri,
+            text,
+            tree,
+            &installed,
+            on_fetched,
+        )
+        .await?;
+        let analyzed = AnalyzedDocument {
+            text: text.clone(),
+            result_id: self
+                .next_result_id
+                .fetch_add(1, Ordering::Relaxed)
+                .to_string(),
+            diagnostics: analysis.diagnostics,
+            outdated: analysis.outdated,
+        };
+        if self.is_current(uri, text) {
+            self.analyzed_documents
                 .lock()
                 .unwrap()
-                .entry(params.text_document.uri)
-                .and_modify(|(contents, parse_tree)| {
-                    let new_parse_tree = parser
-                        .parse(text.as_bytes(), None)
-                        .expect("We should always get a new parse tree.");
-                    *contents = text.clone();
-                    *parse_tree = new_parse_tree;
-                })
-                .or_insert_with(|| {
-                    let parse_tree = parser
-                        .parse(text.as_bytes(), None)
-                        .expect("We should always get a new parse tree.");
-                    (text, parse_tree)
-                });
+                .insert(uri.clone(), analyzed.clone());
+            // Semantic tokens are only computed from analyzed documents.
+            if self.refresh_semantic_tokens.load(Ordering::Relaxed) {
+                let client = self.client.clone();
+                self.spawn(async move { client.semantic_tokens_refresh().await });
+            }
         }
+        Some(analyzed)
     }
-
-    async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        if !params.text_document.uri.path().ends_with("package.json") {
+    /// The installed version of each dependency from the registry, along with its known
+    /// vulnerabilities. `None` when OSV couldn't be asked.
+    async fn vulnerabilities<'a>(
+        &self,
+        uri: &Url,
+        dependencies: &'a [Dependency],
+    ) -> Option<Vec<(&'a Dependency, String, Vec<osv::Advisory>)>> {
+        let installed = installed_packages(uri).await;
+        let versions =
+            diagnostics::installed_versions(&self.fetcher, uri, dependencies, &installed).await;
+        let packages: Vec<_> = versions
+            .iter()
+            .map(|(dependency, version)| (dependency.name.clone(), version.clone()))
+            .collect();
+        if packages.is_empty() {
+            return Some(Vec::new());
+        }
+        let advisories = self.advisories.check(&self.fetcher, &packages).await?;
+        // Offline, packages that weren't checked before are left out rather than looking safe.
+        let vulnerabilities = versions
+            .into_iter()
+            .zip(&packages)
+            .filter_map(|((dependency, version), package)| {
+                let advisories = advisories.get(package)?.clone();
+                Some((dependency, version, advisories))
+            })
+            .collect();
+        Some(vulnerabilities)
+    }
+    /// Adds the known vulnerabilities of the installed dependencies to the analysis of a document.
+    /// OSV is asked apart from [`Self::analyze`], so that it never holds the other diagnostics
+    /// back. Returns `None` when the vulnerabilities are the same as before.
+    async fn check_advisories(
+        &self,
+        uri: &Url,
+        text: &Arc<str>,
+        tree: &Tree,
+    ) -> Option<AnalyzedDocument> {
+        if !self.settings.read().unwrap().diagnostics.security {
+            return None;
+        }
+        let dependencies = parser::extract_all_dependencies(text, tree);
+        let vulnerabilities: Vec<_> = self
+            .vulnerabilities(uri, &dependencies)
+            .await?
+            .into_iter()
+            .filter_map(|(dependency, version, advisories)| {
+                diagnostics::vulnerability_diagnostic(dependency, &version, &advisories)
+            })
+            .collect();
+        let is_vulnerability = |diagnostic: &Diagnostic| {
+            diagnostic.code == Some(NumberOrString::String(diagnostics::VULNERABLE.to_string()))
+        };
+        let mut documents = self.analyzed_documents.lock().unwrap();
+        let analyzed = documents
+            .get_mut(uri)
+            .filter(|analyzed| Arc::ptr_eq(&analyzed.text, text))?;
+        let previous: Vec<_> = analyzed
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| is_vulnerability(diagnostic))
+            .collect();
+        if previous.into_iter().eq(&vulnerabilities) {
+            return None;
+        }
+        analyzed
+            .diagnostics
+            .retain(|diagnostic| !is_vulnerability(diagnostic));
+        analyzed.diagnostics.extend(vulnerabilities);
+        analyzed.result_id = self
+            .next_result_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
+        Some(analyzed.clone())
+    }
+    /// Like [`Self::analyze`], showing how many packages have been fetched as work done progress
+    /// when the client supports it. Gives up once the document is edited.
+    async fn analyze_with_progress(
+        &self,
+        uri: &Url,
+        text: &Arc<str>,
+        tree: &Tree,
+    ) -> Option<AnalyzedDocument> {
+        let progress = if self.work_done_progress.load(Ordering::Relaxed) {
+            Progress::begin(&self.client, "Checking npm versions").await
+        } else {
+            None
+        };
+        let Some(progress) = progress else {
+            return self.analyze(uri, text, tree).await;
+        };
+        let (sender, mut fetched) = tokio::sync::mpsc::unbounded_channel();
+        let on_fetched = move |done, total| {
+            sender.send((done, total)).ok();
+        };
+        let analysis = self.analyze_reporting(uri, text, tree, &on_fetched);
+        tokio::pin!(analysis);
+        let analyzed = loop {
+            tokio::select! {
+                analyzed = &mut analysis => break analyzed,
+                Some((done, total)) = fetched.recv() => {
+                    if !self.is_current(uri, text) {
+                        progress.end(Some("Cancelled by an edit".to_string())).await;
+                        return None;
+                    }
+                    progress.report(done, total).await;
+                }
+            }
+        };
+        progress.end(None).await;
+        analyzed
+    }
+    /// Analyzes the document in the background once it has not changed for `delay`, and publishes
+    /// the resulting diagnostics unless it was edited again in the meantime. Does nothing for
+    /// clients that pull diagnostics instead.
+    fn schedule_diagnostics(
+        &self,
+        uri: Url,
+        text: Arc<str>,
+        delay: Duration,
+        report_progress: bool,
+    ) {
+        if self.pull_diagnostics.load(Ordering::Relaxed) || !self.features().diagnostics {
             return;
         }
-        let mut parser = Self::get_parser();
-        let text: Arc<str> = params.text_document.text.into();
-        self.file_contents
+        let backend = self.clone();
+        self.spawn(async move {
+            tokio::time::sleep(delay).await;
+            let Some((contents, tree)) = backend.file_contents.lock().unwrap().get(&uri).cloned()
+            else {
+                return;
+            };
+            if !Arc::ptr_eq(&contents, &text) {
+                return;
+            }
+            let analyzed = if report_progress {
+                backend.analyze_with_progress(&uri, &text, &tree).await
+            } else {
+                backend.analyze(&uri, &text, &tree).await
+            };
+            let Some(analyzed) = analyzed else {
+                return;
+            };
+            if !backend.is_current(&uri, &text) {
+                return;
+            }
+            backend
+                .client
+                .publish_diagnostics(uri.clone(), analyzed.diagnostics, None)
+                .await;
+            if let Some(analyzed) = backend.check_advisories(&uri, &text, &tree).await {
+                if backend.is_current(&uri, &text) {
+                    backend
+                        .client
+                        .publish_diagnostics(uri, analyzed.diagnostics, None)
+                        .await;
+                }
+            }
+        });
+    }
+    fn features(&self) -> FeatureSettings {
+        self.settings.read().unwrap().features
+    }
+    fn run_on(&self) -> RunOn {
+        self.settings.read().unwrap().diagnostics.run_on
+    }
+    /// Forgets every fetched package, then analyzes the open manifests again so that their
+    /// diagnostics reflect what the registries currently serve.
+    async fn clear_cache(&self) {
+        self.fetcher.clear_cache().await;
+        self.analyzed_documents.lock().unwrap().clear();
+        self.reanalyze_open_documents();
+    }
+    /// Publishes fresh diagnostics for every open manifest, or asks clients that pull diagnostics
+    /// to do so again.
+    fn reanalyze_open_documents(&self) {
+        if self.pull_diagnostics.load(Ordering::Relaxed) {
+            let client = self.client.clone();
+            self.spawn(async move { client.workspace_diagnostic_refresh().await });
+            return;
+        }
+        let documents: Vec<_> = self
+            .file_contents
             .lock()
             .unwrap()
-            .entry(params.text_document.uri)
-            .and_modify(|(contents, parse_tree)| {
-                let new_parse_tree = parser
-                    .parse(text.as_bytes(), None)
-                    .expect("We should always get a new parse tree.");
-                *contents = text.clone();
-                *parse_tree = new_parse_tree;
-            })
-            .or_insert_with(|| {
-                let parse_tree = parser
-                    .parse(text.as_bytes(), None)
-                    .expect("We should always get a new parse tree.");
-                (text, parse_tree)
-            });
+            .iter()
+            .filter(|(uri, _)| uri.path().ends_with("package.json"))
+            .map(|(uri, (text, _))| (uri.clone(), text.clone()))
+            .collect();
+        for (uri, text) in documents {
+            self.schedule_diagnostics(uri, text, Duration::ZERO, false);
+        }
     }
-
-    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
-        let uri = params.text_document_position_params.text_document.uri;
-
-        if !uri.path().ends_with("package.json") {
-            return Ok(None);
+    fn apply_settings(&self, settings: Settings) {
+        self.fetcher
+            .configure(settings.registry_config(&self.npmrc.read().unwrap()));
+        *self.dependency_query.write().unwrap() =
+            Arc::new(parser::DependencyQuery::new(&settings.active_sections()));
+        if settings.diagnostics.run_on == RunOn::Change {
+            self.unsaved_documents.lock().unwrap().clear();
         }
-        let Some((contents, parse_tree)) = self.file_contents.lock().unwrap().get(&uri).cloned()
-        else {
-            return Ok(None);
+        *self.settings.write().unwrap() = settings;
+        self.configure_projects();
+        // Diagnostics computed with the previous settings may have different severities.
+        self.analyzed_documents.lock().unwrap().clear();
+    }
+    /// Gives the fetcher the registries of each project with an `.npmrc`, which settings override
+    /// just like the workspace's ones.
+    fn configure_projects(&self) {
+        let settings = self.settings.read().unwrap();
+        let projects = self
+            .project_npmrc
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(directory, npmrc)| (directory.clone(), settings.registry_config(npmrc)))
+            .collect();
+        self.fetcher.configure_projects(projects);
+    }
+    /// Reads the `.npmrc` or `.yarnrc.yml` closest to a document within its workspace folder,
+    /// unless it has been read already, so that the packages of a project are fetched from the
+    /// registries it sets up in a monorepo whose packages have their own configuration.
+    async fn discover_npmrc(&self, uri: &Url) {
+        let Ok(path) = uri.to_file_path() else {
+            return;
+        };
+        let root = self
+            .roots
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count())
+            .cloned();
+        let Some(root) = root else {
+            return;
         };
+        let Some(directory) = npmrc::project_directory(&path, &root).await else {
+            return;
+        };
+        if self.project_npmrc.read().unwrap().contains_key(&directory) {
+            return;
+        }
+        let registries =
+            npmrc::read_registries(Some(&directory), &npmrc::UserConfig::locate()).await;
+        self.project_npmrc
+            .write()
+            .unwrap()
+            .insert(directory, registries);
+        self.configure_projects();
+    }
+    /// Forgets the registries read from the configuration of a project once it changes, and reads
+    /// the configurations of the open documents' projects again, as theirs may have been added or
+    /// removed too.
+    async fn reload_project_registries(&self, directory: &Path) {
+        self.project_npmrc.write().unwrap().remove(directory);
+        let documents: Vec<_> = self.file_contents.lock().unwrap().keys().cloned().collect();
+        for uri in documents {
+            self.discover_npmrc(&uri).await;
+        }
+        self.configure_projects();
+        self.fetcher.forget_unconfigured().await;
+        self.reanalyze_open_documents();
+    }
+    /// Logs the parts of the settings that are ignored because they can't be used.
+    async fn warn_about_ignored_settings(&self, settings: &Settings) {
+        for (registry, error) in settings.invalid_registries() {
+            self.client
+                .log_message(
+                    MessageType::ERROR,
+                    format!(
+                        "Invalid registry `{registry}`, using the default one instead: {error}"
+                    ),
+                )
+                .await;
+        }
+        for (registry, header, error) in settings.invalid_registry_headers() {
+            self.client
+                .log_message(
+                    MessageType::ERROR,
+                    format!(
+                        "Invalid header `{header}` for registry `{registry}`, leaving it out: {error}"
+                    ),
+                )
+                .await;
+        }
+        let unknown = settings.unknown_sections();
+        if unknown.is_empty() {
+            return;
+        }
+        self.client
+            .log_message(
+                MessageType::WARNING,
+                format!(
+                    "Ignoring unknown dependency sections: {}",
+                    unknown.join(", ")
+                ),
+            )
+            .await;
+    }
+    /// Registers a completion request for a document, cancelling the one it supersedes.
+    fn begin_completion(&self, uri: &Url) -> PendingCompletion {
+        let id = self.next_completion_id.fetch_add(1, Ordering::Relaxed);
+        let token = CancellationToken::new();
+        let superseded = self
+            .pending_completions
+            .lock()
+            .unwrap()
+            .insert(uri.clone(), (id, token.clone()));
+        if let Some((_, superseded)) = superseded {
+            superseded.cancel();
+        }
+        PendingCompletion {
+            uri: uri.clone(),
+            id,
+            token,
+            pending_completions: self.pending_completions.clone(),
+        }
+    }
+    /// Suggests the packages the registry finds for the name of a dependency being added, leaving
+    /// out the ones the section already declares.
+    async fn new_dependency_completions(
+        &self,
+        uri: &Url,
+        text: &str,
+        tree: &Tree,
+        entry: &parser::NewDependency,
+    ) -> CompletionList {
+        let capabilities = *self.completion_capabilities.read().unwrap();
+        // Searching for nothing finds nothing, so the list fills up once a name is typed.
+        if entry.query.is_empty() {
+            return completion::new_dependency_completions(uri, Vec::new(), entry, capabilities);
+        }
+        let declared: HashSet<_> = parser::extract_all_dependencies(text, tree)
+            .into_iter()
+            .filter(|dependency| dependency.section == entry.section)
+            .map(|dependency| dependency.name)
+            .collect();
+        let results = self
+            .fetcher
+            .search(uri, &entry.query, NEW_DEPENDENCY_RESULTS)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|result| !declared.contains(&result.name))
+            .collect();
+        completion::new_dependency_completions(uri, results, entry, capabilities)
+    }
 
-        let Some(ParseResult {
-            package_name,
-            match_range,
-            ..
-        }) = parser::extract_package_name(
-            contents,
-            parse_tree,
-            params.text_document_position_params.position,
+    /// Suggests `@types` packages for runtime dependencies that don't ship their own declarations.
+    async fn types_completions(
+        &self,
+        uri: &Url,
+        text: &str,
+        tree: &Tree,
+        key: &DependencyKey,
+    ) -> CompletionList {
+        let dependencies = parser::extract_all_dependencies(text, tree);
+        let declared: HashSet<_> = dependencies
+            .iter()
+            .map(|dependency| dependency.name.as_str())
+            .collect();
+        let untyped = dependencies
+            .iter()
+            .filter(|dependency| dependency.section == DependencyKind::Dependencies)
+            .filter_map(|dependency| {
+                let types_package = completion::types_package_name(&dependency.name)?;
+                (types_package.contains(&key.name) && !declared.contains(types_package.as_str()))
+                    .then_some((types_package, dependency.name.as_str()))
+            });
+        let candidates = join_all(untyped.map(|(types, name)| async move {
+            self.missing_types(uri, name, &types).await?;
+            Some((types, name.to_string()))
+        }))
+        .await;
+        let capabilities = *self.completion_capabilities.read().unwrap();
+        completion::types_completions(
+            candidates.into_iter().flatten().collect(),
+            key,
+            capabilities,
         )
-        else {
-            return Ok(None);
-        };
+    }
 
-        let response = self
+    /// The latest version of `types_package` if `package_name` doesn't ship its own declarations
+    /// and `types_package` exists on the registry.
+    async fn missing_types(
+        &self,
+        uri: &Url,
+        package_name: &str,
+        types_package: &str,
+    ) -> Option<PackageVersion> {
+        let package = self
             .fetcher
-            .get(
-                &package_name,
+            .get_for(
+                uri,
+                package_name,
                 FetchOptions {
                     parse_all_versions: false,
+                    full_metadata: true,
+                    readme: false,
                 },
             )
             .await
-            .ok_or_else(tower_lsp::jsonrpc::Error::internal_error)?;
-        let offset = format_time(response.latest_version.date);
-        let mut description = format!(
-            "**{package_name}**\n\n{}\n\nLatest version: {} (published {offset})\n\n",
-            response.latest_version.description, response.latest_version.version
-        );
-        if let Some(homepage) = response.latest_version.homepage {
-            use std::fmt::Write;
-            write!(&mut description, "[{0}]({0})", homepage).ok();
+            .ok()?;
+        if package.latest_version.has_types {
+            return None;
         }
-        Ok(Some(Hover {
-            contents: HoverContents::Markup(MarkupContent {
-                kind: MarkupKind::Markdown,
-                value: description,
-            }),
-            range: Some(match_range),
+        let types = self
+            .fetcher
+            .get_for(
+                uri,
+                types_package,
+                FetchOptions {
+                    parse_all_versions: false,
+                    full_metadata: false,
+                    readme: false,
+                },
+            )
+            .await
+            .ok()?;
+        Some(types.latest_version)
+    }
+
+    /// Fetches the latest version of each dependency that is resolved through the registry.
+    async fn latest_versions(
+        &self,
+        uri: &Url,
+        dependencies: &[Dependency],
+    ) -> Vec<Option<PackageVersion>> {
+        join_all(dependencies.iter().map(|dependency| async {
+            if !diagnostics::is_from_registry(&dependency.version) {
+                return None;
+            }
+            let metadata = self
+                .fetcher
+                .get_for(
+                    uri,
+                    &dependency.name,
+                    FetchOptions {
+                        parse_all_versions: false,
+                        full_metadata: false,
+                        readme: false,
+                    },
+                )
+                .await
+                .ok()?;
+            Some(metadata.latest_version)
         }))
+        .await
     }
 
-    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
-        let uri = params.text_document_position.text_document.uri;
+    async fn update_all(&self, arguments: commands::UpdateAllArguments) {
+        let section = match arguments.section.as_deref() {
+            Some(name) => match DependencyKind::from_section_name(name) {
+                Some(section) => Some(section),
+                None => return,
+            },
+            None => None,
+        };
+        self.update_dependencies(arguments.uri, |dependency| {
+            section.map_or(true, |section| dependency.section == section)
+        })
+        .await;
+    }
 
-        if !uri.path().ends_with("package.json") {
-            return Ok(None);
-        }
-        let Some((contents, parse_tree)) = self.file_contents.lock().unwrap().get(&uri).cloned()
-        else {
-            return Ok(None);
+    async fn update_dependency(&self, arguments: commands::UpdateDependencyArguments) {
+        let Some(section) = DependencyKind::from_section_name(&arguments.section) else {
+            return;
         };
+        self.update_dependencies(arguments.uri, |dependency| {
+            dependency.section == section && dependency.name == arguments.name
+        })
+        .await;
+    }
 
-        let Some(ParseResult {
-            package_name,
-            version,
-            ..
-        }) = parser::extract_package_name(
-            contents,
-            parse_tree,
-            params.text_document_position.position,
-        )
-        else {
-            return Ok(None);
+    /// Updates the selected dependencies of a document to their latest versions, and reports how
+    /// that went.
+    async fn update_dependencies(&self, uri: Url, selected: impl Fn(&Dependency) -> bool) {
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return;
         };
+        let dependencies: Vec<_> = parser::extract_all_dependencies(&contents, &tree)
+            .into_iter()
+            .filter(|dependency| selected(dependency))
+            .filter(|dependency| diagnostics::is_from_registry(&dependency.version))
+            .collect();
+        let latest_versions = self.latest_versions(&uri, &dependencies).await;
+        let failed = latest_versions
+            .iter()
+            .filter(|latest| latest.is_none())
+            .count();
+        let edits: Vec<_> = dependencies
+            .iter()
+            .zip(latest_versions)
+            .filter_map(|(dependency, latest)| update::update_edit(dependency, &latest?.version))
+            .collect();
+        let summary = update_summary(
+            edits.len(),
+            dependencies.len() - edits.len() - failed,
+            failed,
+        );
+        if !edits.is_empty() {
+            if !self.is_current(&uri, &contents) {
+                return;
+            }
+            let edit = WorkspaceEdit {
+                changes: Some(HashMap::from([(uri, edits)])),
+                ..Default::default()
+            };
+            match self.client.apply_edit(edit).await {
+                Ok(response) if response.applied => {}
+                Ok(_) => return,
+                Err(error) => {
+                    self.client
+                        .log_message(
+                            MessageType::ERROR,
+                            format!("Failed to update dependencies: {error}"),
+                        )
+                        .await;
+                    return;
+                }
+            }
+        }
+        self.client.show_message(MessageType::INFO, summary).await;
+    }
 
-        let response = self
+    /// Command of the code lens showing a dependency's latest version, which updates it if needed.
+    async fn dependency_lens_command(
+        &self,
+        arguments: commands::UpdateDependencyArguments,
+    ) -> Option<Command> {
+        let (contents, tree) = self
+            .file_contents
+            .lock()
+            .unwrap()
+            .get(&arguments.uri)
+            .cloned()?;
+        let section = DependencyKind::from_section_name(&arguments.section)?;
+        let dependency = parser::extract_all_dependencies(&contents, &tree)
+            .into_iter()
+            .find(|dependency| {
+                dependency.section == section && dependency.name == arguments.name
+            })?;
+        let metadata = self
             .fetcher
-            .get(
-                &package_name,
+            .get_for(
+                &arguments.uri,
+                &dependency.name,
                 FetchOptions {
-                    parse_all_versions: true,
+                    parse_all_versions: false,
+                    full_metadata: false,
+                    readme: false,
                 },
             )
-            .await
-            .ok_or_else(tower_lsp::jsonrpc::Error::internal_error)?;
-
-        if !response.failed_versions.is_empty() {
-            let some_or_all = if response.package_versions.is_empty() {
-                "all"
-            } else {
-                "some"
+            .await;
+        let Ok(metadata) = metadata else {
+            // Updating tries again, and reports if it still can't.
+            return Some(Command {
+                title: "Couldn't check for updates".to_string(),
+                command: commands::UPDATE_DEPENDENCY.to_string(),
+                arguments: Some(vec![serde_json::to_value(arguments).ok()?]),
+            });
+        };
+        let latest = metadata.latest_version;
+        if update::update_edit(&dependency, &latest.version).is_none() {
+            let arguments = commands::OpenPackagePageArguments {
+                name: links::package_name(&dependency).to_string(),
+                uri: Some(arguments.uri.clone()),
             };
-            self.client
-                .log_message(
-                    MessageType::WARNING,
-                    format!(
-                        "Failed to parse {} package versions: {:?}",
-                        some_or_all, response.failed_versions
-                    ),
-                )
-                .await;
+            return Some(Command {
+                title: "✓ up to date".to_string(),
+                command: commands::OPEN_PACKAGE_PAGE.to_string(),
+                arguments: Some(vec![serde_json::to_value(arguments).ok()?]),
+            });
         }
+        Some(Command {
+            title: format!("latest: {}", latest.version),
+            command: commands::UPDATE_DEPENDENCY.to_string(),
+            arguments: Some(vec![serde_json::to_value(arguments).ok()?]),
+        })
+    }
 
-        let mut query = version.to_string();
-        for prefix in [">", "<", ">=", "<=", "=", "==", "!=", "===", "!==", "~"] {
-            if let Some(stripped) = query.strip_prefix(prefix) {
-                query = stripped.to_string();
-                break;
-            }
+    /// Shows the preferred page of a package in the client, or logs it for clients that can't open
+    /// URLs.
+    async fn open_package_page(&self, arguments: commands::OpenPackagePageArguments) {
+        let Some(page) = self.package_page(&arguments).await else {
+            return;
+        };
+        if self.show_external(&page).await {
+            return;
+        }
+        self.client
+            .log_message(
+                MessageType::INFO,
+                format!("The page of {} is {page}", arguments.name),
+            )
+            .await;
+    }
+
+    /// The homepage, repository or npm page of a package, fetched from the registries of the
+    /// document declaring it. Its npm page when it can't be fetched.
+    async fn package_page(&self, arguments: &commands::OpenPackagePageArguments) -> Option<Url> {
+        let options = FetchOptions {
+            parse_all_versions: false,
+            full_metadata: true,
+            readme: false,
+        };
+        let metadata = match &arguments.uri {
+            Some(uri) => self.fetcher.get_for(uri, &arguments.name, options).await,
+            None => self.fetcher.get(&arguments.name, options).await,
+        };
+        match metadata {
+            Ok(metadata) => links::preferred_page(&arguments.name, &metadata.latest_version),
+            Err(_) => links::npm_page(&arguments.name),
+        }
+    }
+
+    /// Asks the client to open a URL in the browser. Returns whether it did.
+    async fn show_external(&self, page: &Url) -> bool {
+        if !self.show_document.load(Ordering::Relaxed) {
+            return false;
         }
-        let mut completion_items: Vec<_> = response
-            .package_versions
+        let shown = self
+            .client
+            .show_document(ShowDocumentParams {
+                uri: page.clone(),
+                external: Some(true),
+                take_focus: Some(true),
+                selection: None,
+            })
+            .await;
+        shown == Ok(true)
+    }
+
+    /// The packages of a section that have known vulnerabilities, at their installed version.
+    /// `None` when OSV couldn't be asked.
+    async fn section_vulnerabilities(
+        &self,
+        arguments: &commands::ShowVulnerabilitiesArguments,
+    ) -> Option<Vec<(osv::Installed, Vec<osv::Advisory>)>> {
+        let kind = DependencyKind::from_section_name(&arguments.section)?;
+        let (contents, tree) = self
+            .file_contents
+            .lock()
+            .unwrap()
+            .get(&arguments.uri)
+            .cloned()?;
+        let dependencies: Vec<_> = parser::extract_all_dependencies(&contents, &tree)
             .into_iter()
-            .filter_map(|package_version| {
-                let label = package_version.version.to_string();
-                if !label.starts_with(&query) {
-                    return None;
+            .filter(|dependency| dependency.section == kind)
+            .collect();
+        let vulnerable = self
+            .vulnerabilities(&arguments.uri, &dependencies)
+            .await?
+            .into_iter()
+            .filter(|(_, _, advisories)| !advisories.is_empty())
+            .map(|(dependency, version, advisories)| {
+                ((dependency.name.clone(), version), advisories)
+            })
+            .collect();
+        Some(vulnerable)
+    }
+
+    /// Lists the vulnerable dependencies when clicked, or explains why there are none, checking
+    /// again in case OSV couldn't be reached.
+    async fn security_lens_command(
+        &self,
+        arguments: commands::ShowVulnerabilitiesArguments,
+    ) -> Command {
+        let title = match self
+            .section_vulnerabilities(&arguments)
+            .await
+            .map(|vulnerable| vulnerable.len())
+        {
+            None => "Couldn't check for vulnerabilities".to_string(),
+            Some(0) => "No known vulnerabilities".to_string(),
+            Some(1) => "1 dependency with known vulnerabilities".to_string(),
+            Some(count) => format!("{count} dependencies with known vulnerabilities"),
+        };
+        Command {
+            title,
+            command: commands::SHOW_VULNERABILITIES.to_string(),
+            arguments: serde_json::to_value(arguments)
+                .ok()
+                .map(|arguments| vec![arguments]),
+        }
+    }
+
+    /// Lists the vulnerable dependencies of a section in a message, whose actions open the
+    /// advisories.
+    async fn show_vulnerabilities(&self, arguments: commands::ShowVulnerabilitiesArguments) {
+        let Some(vulnerable) = self.section_vulnerabilities(&arguments).await else {
+            self.client
+                .show_message(MessageType::WARNING, "Couldn't check for vulnerabilities")
+                .await;
+            return;
+        };
+        if vulnerable.is_empty() {
+            self.client
+                .show_message(
+                    MessageType::INFO,
+                    format!("No known vulnerabilities in {}", arguments.section),
+                )
+                .await;
+            return;
+        }
+        let packages: Vec<_> = vulnerable
+            .iter()
+            .map(|((name, version), advisories)| {
+                let ids: Vec<_> = advisories
+                    .iter()
+                    .map(|advisory| advisory.id.as_str())
+                    .collect();
+                format!("{name}@{version} ({})", ids.join(", "))
+            })
+            .collect();
+        let message = format!(
+            "Known vulnerabilities in {}: {}",
+            arguments.section,
+            packages.join("; ")
+        );
+        let mut ids: Vec<_> = vulnerable
+            .iter()
+            .flat_map(|(_, advisories)| advisories)
+            .map(|advisory| advisory.id.as_str())
+            .collect();
+        // Packages of a section are often affected by the same advisory.
+        let mut seen = HashSet::new();
+        ids.retain(|id| seen.insert(*id));
+        let actions = ids
+            .into_iter()
+            .map(|id| MessageActionItem {
+                title: id.to_string(),
+                properties: Default::default(),
+            })
+            .collect();
+        let picked = self
+            .client
+            .show_message_request(MessageType::WARNING, message, Some(actions))
+            .await;
+        let Ok(Some(picked)) = picked else {
+            return;
+        };
+        let Ok(page) = Url::parse(&osv::advisory_page(&picked.title)) else {
+            return;
+        };
+        if !self.show_external(&page).await {
+            self.client
+                .log_message(
+                    MessageType::INFO,
+                    format!("The advisory {} is {page}", picked.title),
+                )
+                .await;
+        }
+    }
+
+    /// Indexes the manifests of the workspace folders in the background, then reads the `.npmrc`
+    /// of their projects.
+    fn index_workspace(&self, roots: Vec<PathBuf>) {
+        let backend = self.clone();
+        self.spawn(async move {
+            let indexing = backend.clone();
+            let manifests = tokio::task::spawn_blocking(move || {
+                let mut manifests = Vec::new();
+                for manifest in roots
+                    .iter()
+                    .flat_map(|root| workspace::discover_manifests(root))
+                {
+                    if indexing.shutdown.is_cancelled() {
+                        break;
+                    }
+                    indexing.index_manifest(&manifest);
+                    manifests.extend(Url::from_file_path(manifest));
                 }
-                let insert_text = Some(label.clone());
-                Some(CompletionItem {
-                    label,
-                    insert_text,
-                    detail: Some(package_version.date.format("%d/%m/%Y %H:%M").to_string()),
-                    ..Default::default()
-                })
+                manifests
+            })
+            .await
+            .unwrap_or_default();
+            for manifest in manifests {
+                backend.discover_npmrc(&manifest).await;
+            }
+        });
+    }
+
+    fn index_manifest(&self, path: &Path) {
+        let (Ok(text), Ok(uri)) = (std::fs::read_to_string(path), Url::from_file_path(path)) else {
+            return;
+        };
+        let dependencies = workspace::index_manifest(&mut self.parser.lock().unwrap(), &text);
+        self.workspace_index
+            .lock()
+            .unwrap()
+            .insert(uri, dependencies);
+    }
+
+    /// Tells the user about dependencies of a document that have a new major version.
+    async fn notify_major_updates(&self, uri: &Url) {
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(uri).cloned() else {
+            return;
+        };
+        let dependencies = parser::extract_all_dependencies(&contents, &tree);
+        let latest_versions = self.latest_versions(uri, &dependencies).await;
+        let names: Vec<_> = dependencies
+            .iter()
+            .zip(latest_versions)
+            .filter(|(dependency, latest)| {
+                latest
+                    .as_ref()
+                    .is_some_and(|latest| update::has_newer_major(dependency, &latest.version))
             })
+            .map(|(dependency, _)| dependency.name.as_str())
             .collect();
-        completion_items
-            .sort_by(|lhs_version, rhs_version| rhs_version.label.cmp(&lhs_version.label));
-        Ok(Some(CompletionResponse::Array(completion_items)))
+        if let Some(summary) = major_updates_summary(&names) {
+            self.client.show_message(MessageType::INFO, summary).await;
+        }
     }
-}
 
-fn format_time(time: DateTime<FixedOffset>) -> String {
-    let ht = HumanTime::from(time);
-    ht.to_text_en(Accuracy::Rough, Tense::Past)
+    async fn maven_hover(&self, uri: &Url, position: Position) -> Result<Option<Hover>> {
+        let Some((contents, _)) = self.file_contents.lock().unwrap().get(uri).cloned() else {
+            return Ok(None);
+        };
+        let Some(artifact) = maven::artifact_at(uri, &contents, position) else {
+            return Ok(None);
+        };
+        let info = self
+            .artifacts
+            .info(&self.fetcher, &artifact.group, &artifact.artifact)
+            .await
+            .ok_or_else(tower_lsp::jsonrpc::Error::internal_error)?;
+        let mut description = format!(
+            "**{}:{}**\n\nLatest version: {}",
+            artifact.group, artifact.artifact, info.latest_version
+        );
+        if let Some(date) = info.date {
+            description.push_str(&format!(" (published {})", format_time(date)));
+        }
+        description.push_str("\n\n");
+        if artifact.version.as_deref() == Some(info.latest_version.as_str()) {
+            description.push_str("✓ Up to date\n\n");
+        }
+        {
+            use std::fmt::Write;
+            let page = maven::central_page(&artifact.group, &artifact.artifact);
+            write!(&mut description, "[Maven Central]({page})").ok();
+        }
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: description,
+            }),
+            range: Some(artifact.range),
+        }))
+    }
+
+    async fn tool_hover(&self, uri: &Url, position: Position) -> Result<Option<Hover>> {
+        let Some((contents, _)) = self.file_contents.lock().unwrap().get(uri).cloned() else {
+            return Ok(None);
+        };
+        let Some(pinned) = tool_versions::tool_at(uri, &contents, position) else {
+            return Ok(None);
+        };
+        // Tools without a known release index are left alone.
+        let Some(tool) = tool_versions::Tool::from_name(&pinned.name) else {
+            return Ok(None);
+        };
+        let releases = self
+            .tool_releases
+            .releases(tool, &self.fetcher)
+            .await
+            .ok_or_else(tower_lsp::jsonrpc::Error::internal_error)?;
+        let description = format!(
+            "**{}** {}\n\n{}",
+            pinned.name,
+            pinned.version,
+            tool_versions::summary(tool, &pinned.version, &releases)
+        );
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: description,
+            }),
+            range: Some(pinned.range),
+        }))
+    }
+
+    async fn gem_hover(&self, uri: &Url, position: Position) -> Result<Option<Hover>> {
+        let Some((contents, _)) = self.file_contents.lock().unwrap().get(uri).cloned() else {
+            return Ok(None);
+        };
+        let Some(gem) = rubygems::gem_at(&contents, position) else {
+            return Ok(None);
+        };
+        let (info, releases) = futures::join!(
+            self.gems.info(&self.fetcher, &gem.name),
+            self.gems.releases(&self.fetcher, &gem.name)
+        );
+        let info = info.ok_or_else(tower_lsp::jsonrpc::Error::internal_error)?;
+        let mut description = format!(
+            "**{}**\n\n{}\n\nLatest version: {}",
+            gem.name, info.info, info.latest_version
+        );
+        if let Some(date) = info.date {
+            description.push_str(&format!(" (published {})", format_time(date)));
+        }
+        description.push_str("\n\n");
+        if !gem.requirements.is_empty() {
+            let latest = rubygems::GemVersion::parse(&info.latest_version);
+            let up_to_date = latest
+                .and_then(|latest| rubygems::satisfies(&gem.requirements, &latest))
+                .unwrap_or(false);
+            if up_to_date {
+                description.push_str("✓ Up to date\n\n");
+            } else if let Some(newest) = releases
+                .as_deref()
+                .and_then(|releases| rubygems::newest_matching(releases, &gem.requirements))
+            {
+                description.push_str(&format!(
+                    "Newest version matching `{}`: {}\n\n",
+                    gem.requirements.join(", "),
+                    newest.number
+                ));
+            }
+        }
+        if let Some(homepage) = info.homepage {
+            use std::fmt::Write;
+            write!(&mut description, "[Homepage]({homepage})").ok();
+        }
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: description,
+            }),
+            range: Some(gem.name_range),
+        }))
+    }
+
+    async fn pod_hover(&self, uri: &Url, position: Position) -> Result<Option<Hover>> {
+        let Some((contents, _)) = self.file_contents.lock().unwrap().get(uri).cloned() else {
+            return Ok(None);
+        };
+        let Some(pod) = cocoapods::pod_at(&contents, position) else {
+            return Ok(None);
+        };
+        let info = self
+            .pods
+            .info(&self.fetcher, &pod.name)
+            .await
+            .ok_or_else(tower_lsp::jsonrpc::Error::internal_error)?;
+        let spec = self
+            .pods
+            .spec(&self.fetcher, &pod.name, &info.latest_version)
+            .await;
+        let mut description = format!("**{}**\n\n", pod.name);
+        if let Some(summary) = spec.as_ref().map(|spec| &spec.summary) {
+            description.push_str(&format!("{summary}\n\n"));
+        }
+        description.push_str(&format!("Latest version: {}", info.latest_version));
+        if let Some(date) = info.date {
+            description.push_str(&format!(" (published {})", format_time(date)));
+        }
+        description.push_str("\n\n");
+        if !pod.requirements.is_empty() {
+            let latest = rubygems::GemVersion::parse(&info.latest_version);
+            let up_to_date = latest
+                .and_then(|latest| rubygems::satisfies(&pod.requirements, &latest))
+                .unwrap_or(false);
+            if up_to_date {
+                description.push_str("✓ Up to date\n\n");
+            } else if let Some(newest) =
+                rubygems::newest_matching(&info.releases, &pod.requirements)
+            {
+                description.push_str(&format!(
+                    "Newest version matching `{}`: {}\n\n",
+                    pod.requirements.join(", "),
+                    newest.number
+                ));
+            }
+        }
+        if let Some(homepage) = spec.and_then(|spec| spec.homepage) {
+            use std::fmt::Write;
+            write!(&mut description, "[Homepage]({homepage})").ok();
+        }
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: description,
+            }),
+            range: Some(pod.name_range),
+        }))
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    if std::env::args()
-        .nth(1)
-        .filter(|arg| arg == "--version")
-        .is_some()
-    {
-        println!("package-version-server {}", env!("CARGO_PKG_VERSION"));
-        return;
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        *self.completion_capabilities.write().unwrap() = CompletionCapabilities::from_client(
+            params
+                .capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| text_document.completion.as_ref()),
+        );
+        let pull_diagnostics = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .is_some_and(|text_document| text_document.diagnostic.is_some());
+        self.pull_diagnostics
+            .store(pull_diagnostics, Ordering::Relaxed);
+        let watch_manifests = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files)
+            .and_then(|watched_files| watched_files.dynamic_registration)
+            .unwrap_or(false);
+        self.watch_manifests
+            .store(watch_manifests, Ordering::Relaxed);
+        let refresh_semantic_tokens = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.semantic_tokens.as_ref())
+            .and_then(|semantic_tokens| semantic_tokens.refresh_support)
+            .unwrap_or(false);
+        self.refresh_semantic_tokens
+            .store(refresh_semantic_tokens, Ordering::Relaxed);
+        let work_done_progress = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        self.work_done_progress
+            .store(work_done_progress, Ordering::Relaxed);
+        let resolve_code_action_edits = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.code_action.as_ref())
+            .and_then(|code_action| code_action.resolve_support.as_ref())
+            .is_some_and(|resolve| resolve.properties.iter().any(|property| property == "edit"));
+        self.resolve_code_action_edits
+            .store(resolve_code_action_edits, Ordering::Relaxed);
+        let show_document = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.show_document.as_ref())
+            .is_some_and(|show_document| show_document.support);
+        self.show_document.store(show_document, Ordering::Relaxed);
+        #[allow(deprecated)]
+        let roots = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect::<Vec<_>>(),
+        };
+        let roots: Vec<_> = roots
+            .iter()
+            .filter_map(|root| root.to_file_path().ok())
+            .collect();
+        // Like npm, which reads the `.npmrc` of the project it runs in.
+        *self.npmrc.write().unwrap() = npmrc::read_registries(
+            roots.first().map(PathBuf::as_path),
+            &npmrc::UserConfig::locate(),
+        )
+        .await;
+        *self.roots.write().unwrap() = roots.clone();
+        self.index_workspace(roots);
+        match Settings::from_value(params.initialization_options) {
+            Ok(settings) => {
+                self.warn_about_ignored_settings(&settings).await;
+                self.apply_settings(settings);
+            }
+            Err(error) => {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Invalid initialization options, using defaults: {error}"),
+                    )
+                    .await;
+            }
+        }
+        // Disabled features aren't advertised, so that clients don't ask for them at all.
+        let features = self.features();
+        Ok(InitializeResult {
+            capabilities: ServerCapabilities {
+                hover_provider: features
+                    .hover
+                    .then_some(HoverProviderCapability::Simple(true)),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::FULL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..Default::default()
+                    },
+                )),
+                completion_provider: features.completion.then(|| CompletionOptions {
+                    trigger_characters: Some(vec![String::from(".")]),
+                    resolve_provider: Some(true),
+                    ..Default::default()
+                }),
+                diagnostic_provider: features.diagnostics.then(|| {
+                    DiagnosticServerCapabilities::Options(DiagnosticOptions {
+                        identifier: Some(env!("CARGO_PKG_NAME").to_string()),
+                        inter_file_dependencies: false,
+                        workspace_diagnostics: false,
+                        ..Default::default()
+                    })
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR_REWRITE,
+                            CodeActionKind::SOURCE,
+                            CodeActionKind::new(FIX_ALL_KIND),
+                            CodeActionKind::new(SORT_KIND),
+                        ]),
+                        resolve_provider: Some(true),
+                        ..Default::default()
+                    },
+                )),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                }),
+                inlay_hint_provider: (features.hover && features.diagnostics)
+                    .then_some(OneOf::Left(true)),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: semantic_tokens::legend(),
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            range: None,
+                            work_done_progress_options: Default::default(),
+                        },
+                    ),
+                ),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(true),
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: commands::ALL.iter().map(ToString::to_string).collect(),
+                    ..Default::default()
+                }),
+                ..ServerCapabilities::default()
+            },
+            ..InitializeResult::default()
+        })
     }
-    let stdin = tokio::io::stdin();
-    let stdout = tokio::io::stdout();
 
-    let (service, socket) =
-        LspService::new(|client| Backend::new(client).expect("Failed to initialize backend"));
-    Server::new(stdin, stdout, socket).serve(service).await;
+    async fn shutdown(&self) -> Result<()> {
+        self.shutdown.cancel();
+        self.fetcher.cancel_background_work();
+        Ok(())
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        self.client
+            .log_message(MessageType::INFO, "Language server initialized.")
+            .await;
+        if self.watch_manifests.load(Ordering::Relaxed) {
+            let options = DidChangeWatchedFilesRegistrationOptions {
+                watchers: std::iter::once("package.json")
+                    .chain(npmrc::REGISTRY_CONFIGS)
+                    .map(|name| FileSystemWatcher {
+                        glob_pattern: GlobPattern::String(format!("**/{name}")),
+                        kind: None,
+                    })
+                    .collect(),
+            };
+            let registration = Registration {
+                id: "watch-manifests".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(options).ok(),
+            };
+            if let Err(error) = self.client.register_capability(vec![registration]).await {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Failed to watch manifests: {error}"),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        for change in params.changes {
+            let Ok(path) = change.uri.to_file_path() else {
+                continue;
+            };
+            if npmrc::is_registry_config(&path) {
+                if let Some(directory) = path.parent() {
+                    self.reload_project_registries(directory).await;
+                }
+                continue;
+            }
+            if change.typ == FileChangeType::DELETED {
+                self.workspace_index.lock().unwrap().remove(&change.uri);
+                continue;
+            }
+            let roots = self.roots.read().unwrap().clone();
+            // The packages of a workspace change along with the `workspaces` of its root manifest.
+            if let Some(root) = roots.iter().find(|root| path == root.join("package.json")) {
+                self.workspace_index
+                    .lock()
+                    .unwrap()
+                    .retain(|uri, _| !uri.to_file_path().is_ok_and(|path| path.starts_with(root)));
+                self.index_workspace(vec![root.clone()]);
+            } else if roots
+                .iter()
+                .any(|root| workspace::is_workspace_manifest(root, &path))
+            {
+                self.index_manifest(&path);
+            }
+        }
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let index = self.workspace_index.lock().unwrap();
+        Ok(Some(workspace::search(&index, &params.query)))
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        if params.settings.is_null() {
+            return;
+        }
+        match Settings::from_value(Some(params.settings)) {
+            Ok(settings) => {
+                self.warn_about_ignored_settings(&settings).await;
+                self.apply_settings(settings);
+                self.fetcher.forget_unconfigured().await;
+                self.reanalyze_open_documents();
+                self.client
+                    .log_message(MessageType::INFO, "Configuration reloaded.")
+                    .await;
+            }
+            Err(error) => {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Invalid configuration, keeping the previous one: {error}"),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        if !is_supported(&params.text_document.uri) {
+            return;
+        }
+        if params.content_changes.is_empty() {
+            return;
+        }
+        let uri = params.text_document.uri;
+        // Full changes replace the document, ranged ones edit the result of the previous change.
+        let mut text = self
+            .file_contents
+            .lock()
+            .unwrap()
+            .get(&uri)
+            .map(|(text, _)| text.to_string())
+            .unwrap_or_default();
+        for change in params.content_changes {
+            apply_change(&mut text, change);
+        }
+        let text = self.update_document(uri.clone(), text);
+        if !uri.path().ends_with("package.json") {
+            return;
+        }
+        if self.run_on() == RunOn::Save {
+            self.unsaved_documents.lock().unwrap().insert(uri);
+            return;
+        }
+        self.schedule_diagnostics(uri, text, DIAGNOSTICS_DEBOUNCE, false);
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        let uri = params.text_document.uri;
+        if !self.unsaved_documents.lock().unwrap().remove(&uri) {
+            return;
+        }
+        if self.pull_diagnostics.load(Ordering::Relaxed) {
+            let client = self.client.clone();
+            self.spawn(async move { client.workspace_diagnostic_refresh().await });
+            return;
+        }
+        let Some((text, _)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return;
+        };
+        self.schedule_diagnostics(uri, text, Duration::ZERO, false);
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        if !is_supported(&params.text_document.uri) {
+            return;
+        }
+        let uri = params.text_document.uri;
+        let text = self.update_document(uri.clone(), params.text_document.text);
+        // Other manifests are only looked at on hover.
+        if !uri.path().ends_with("package.json") {
+            return;
+        }
+        self.discover_npmrc(&uri).await;
+        if self.settings.read().unwrap().notify_major_updates {
+            let backend = self.clone();
+            let uri = uri.clone();
+            self.spawn(async move { backend.notify_major_updates(&uri).await });
+        }
+        self.schedule_diagnostics(uri, text, Duration::ZERO, true);
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.analyzed_documents.lock().unwrap().remove(&uri);
+        self.unsaved_documents.lock().unwrap().remove(&uri);
+        let was_open = self.file_contents.lock().unwrap().remove(&uri).is_some();
+        if was_open && !self.pull_diagnostics.load(Ordering::Relaxed) {
+            self.client.publish_diagnostics(uri, Vec::new(), None).await;
+        }
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        if !self.features().hover {
+            return Ok(None);
+        }
+        let uri = params.text_document_position_params.text_document.uri;
+
+        if rubygems::is_manifest(&uri) {
+            return self
+                .gem_hover(&uri, params.text_document_position_params.position)
+                .await;
+        }
+        if cocoapods::is_manifest(&uri) {
+            return self
+                .pod_hover(&uri, params.text_document_position_params.position)
+                .await;
+        }
+        if maven::is_manifest(&uri) {
+            return self
+                .maven_hover(&uri, params.text_document_position_params.position)
+                .await;
+        }
+        if tool_versions::is_manifest(&uri) {
+            return self
+                .tool_hover(&uri, params.text_document_position_params.position)
+                .await;
+        }
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let Some((contents, parse_tree)) = self.document(&uri).await else {
+            return Ok(None);
+        };
+
+        let position = params.text_document_position_params.position;
+        let dependency_query = self.dependency_query.read().unwrap().clone();
+        let bundled_name = dependency_query
+            .includes(DependencyKind::BundledDependencies)
+            .then(|| parser::extract_bundled_name(&contents, &parse_tree, position))
+            .flatten()
+            .filter(|entry| !entry.name.is_empty());
+        let (mut target, has_version) = match bundled_name {
+            // Names in a `bundleDependencies` array come without a version.
+            Some(entry) => (
+                ParseResult {
+                    package_name: entry.name,
+                    version: String::new(),
+                    section: DependencyKind::BundledDependencies,
+                    match_range: entry.range,
+                    version_range: entry.range,
+                    quoted: false,
+                },
+                false,
+            ),
+            None => {
+                let Some(target) =
+                    parser::extract_package_name(contents, parse_tree, position, &dependency_query)
+                else {
+                    return Ok(None);
+                };
+                (target, true)
+            }
+        };
+        let aliased_as = target.resolve_npm_alias();
+        let ParseResult {
+            package_name,
+            version,
+            section,
+            match_range,
+            ..
+        } = target;
+
+        let directory = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| path.parent().map(ToOwned::to_owned));
+        let installed = match &directory {
+            // Aliased packages are installed under their alias.
+            Some(directory) => {
+                lockfile::installed_packages(directory)
+                    .await
+                    .and_then(|mut installed| {
+                        installed.remove(aliased_as.as_deref().unwrap_or(&package_name))
+                    })
+            }
+            None => None,
+        };
+        let (show_readme_excerpt, show_advisories) = {
+            let settings = self.settings.read().unwrap();
+            (settings.show_readme_excerpt, settings.show_advisories)
+        };
+        let response = self
+            .fetcher
+            .get_for(
+                &uri,
+                &package_name,
+                FetchOptions {
+                    // The installed version is needed to check its integrity, and every version
+                    // to tell what a range resolves to.
+                    parse_all_versions: installed
+                        .as_ref()
+                        .is_some_and(|installed| installed.integrity.is_some())
+                        || update::specifier_kind(&version)
+                            .is_some_and(|kind| kind != update::SpecifierKind::Exact),
+                    full_metadata: true,
+                    readme: show_readme_excerpt,
+                },
+            )
+            .await
+            .map_err(|_| tower_lsp::jsonrpc::Error::internal_error())?;
+        // The version of a `catalog:` dependency lives in the pnpm workspace.
+        let catalog_version = match &directory {
+            Some(directory) if version.trim().starts_with("catalog:") => {
+                pnpm::resolve_catalog(directory, &version, &package_name).await
+            }
+            _ => None,
+        };
+        let mut description = match &aliased_as {
+            Some(alias) => format!("**{alias}** (alias for {package_name})"),
+            None => format!("**{package_name}**"),
+        };
+        if catalog_version.is_some() {
+            description.push_str(" (via pnpm catalog)");
+        }
+        let version = catalog_version.unwrap_or(version);
+        // An extra request to OSV, whose failures leave the warning out. Private packages are never
+        // named to it.
+        let advisories = match resolved_version(&version, installed.as_ref(), &response) {
+            Some(resolved)
+                if show_advisories
+                    && has_version
+                    && self.fetcher.is_public(&uri, &package_name) =>
+            {
+                let package = (package_name.clone(), resolved);
+                self.advisories
+                    .check(&self.fetcher, std::slice::from_ref(&package))
+                    .await
+                    .and_then(|mut checked| checked.remove(&package))
+                    .and_then(|advisories| advisories_line(&package.1, &advisories))
+            }
+            _ => None,
+        };
+        if let Some(note) = section.hover_note() {
+            description.push(' ');
+            description.push_str(note);
+        }
+        description.push_str(&format!("\n\n{}", response.latest_version.description));
+        if let Some(excerpt) = response.readme.as_deref().and_then(readme_excerpt) {
+            description.push_str(&format!("\n\n{excerpt}"));
+        }
+        description.push_str(&format!(
+            "\n\nLatest version: {}",
+            response.latest_version.version
+        ));
+        // Some mirrors leave out the `time` object, which holds every publish date.
+        match response.latest_version.date {
+            Some(date) => description.push_str(&format!(" (published {})", format_time(date))),
+            None => description.push_str(" (published date unknown)"),
+        }
+        description.push_str("\n\n");
+        if let Some(line) = advisories {
+            description.push_str(&line);
+            description.push_str("\n\n");
+        }
+        if let Some(line) = integrity_line(&response.latest_version) {
+            description.push_str(&line);
+            description.push_str("\n\n");
+        }
+        if let Some(line) = installed
+            .as_ref()
+            .and_then(|installed| integrity_mismatch_line(installed, &response))
+        {
+            description.push_str(&line);
+            description.push_str("\n\n");
+        }
+        if let Some(line) = resolution_line(&version, &response).filter(|_| has_version) {
+            description.push_str(&line);
+            description.push_str("\n\n");
+        }
+        if has_version && is_up_to_date(&version, &response.latest_version.version) {
+            description.push_str("✓ Up to date\n\n");
+        }
+        if let Some(line) = publisher_line(
+            response.latest_version.published_by.as_deref(),
+            response.maintainer_count,
+        ) {
+            description.push_str(&line);
+            description.push_str("\n\n");
+        }
+        if response.latest_version.has_provenance {
+            description.push_str("✓ Published with provenance\n\n");
+        }
+        if let Some(line) = keywords_line(&response.latest_version.keywords) {
+            description.push_str(&line);
+            description.push_str("\n\n");
+        }
+        if let Some(line) = funding_line(&response.latest_version.funding) {
+            description.push_str(&line);
+            description.push_str("\n\n");
+        }
+        if let Some(line) = links_line(&response.latest_version) {
+            description.push_str(&line);
+        }
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: description,
+            }),
+            range: Some(match_range),
+        }))
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        if !self.features().diagnostics {
+            return Ok(DocumentDiagnosticReportResult::Report(
+                DocumentDiagnosticReport::Full(Default::default()),
+            ));
+        }
+        let uri = params.text_document.uri;
+        let document = self.file_contents.lock().unwrap().get(&uri).cloned();
+        let is_unsaved =
+            self.run_on() == RunOn::Save && self.unsaved_documents.lock().unwrap().contains(&uri);
+        let previous = is_unsaved
+            .then(|| self.analyzed_documents.lock().unwrap().get(&uri).cloned())
+            .flatten();
+        let analyzed = match document {
+            // Edits are only looked at once saved, so the last analysis stands until then.
+            _ if previous.is_some() => previous,
+            Some((contents, tree)) => {
+                // The first pull after opening a document is likely to fetch every package.
+                let is_fresh = !self.analyzed_documents.lock().unwrap().contains_key(&uri);
+                let analyzed = if is_fresh {
+                    self.analyze_with_progress(&uri, &contents, &tree).await
+                } else {
+                    self.analyze(&uri, &contents, &tree).await
+                };
+                // Vulnerabilities are pulled again once OSV answered.
+                let (backend, document) = (self.clone(), uri.clone());
+                self.spawn(async move {
+                    if backend
+                        .check_advisories(&document, &contents, &tree)
+                        .await
+                        .is_some()
+                    {
+                        backend.client.workspace_diagnostic_refresh().await.ok();
+                    }
+                });
+                match analyzed {
+                    // Keep reporting what is known while the registry is unreachable.
+                    None => self.analyzed_documents.lock().unwrap().get(&uri).cloned(),
+                    analyzed => analyzed,
+                }
+            }
+            None => None,
+        };
+        let report = match analyzed {
+            Some(analyzed) if params.previous_result_id.as_ref() == Some(&analyzed.result_id) => {
+                DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                    related_documents: None,
+                    unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                        result_id: analyzed.result_id,
+                    },
+                })
+            }
+            analyzed => {
+                let (result_id, items) = analyzed
+                    .map(|analyzed| (Some(analyzed.result_id), analyzed.diagnostics))
+                    .unwrap_or_default();
+                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                    related_documents: None,
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id,
+                        items,
+                    },
+                })
+            }
+        };
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return Ok(None);
+        };
+        let uri = &uri;
+        let is_requested = |kind: &CodeActionKind| {
+            params.context.only.as_ref().map_or(true, |only| {
+                only.iter()
+                    .any(|requested| kind.as_str().starts_with(requested.as_str()))
+            })
+        };
+        let (dependencies, other_sources): (Vec<_>, Vec<_>) =
+            parser::extract_all_dependencies(&contents, &tree)
+                .into_iter()
+                .partition(|dependency| diagnostics::is_from_registry(&dependency.version));
+        let mut actions = Vec::new();
+        // The fixes of reported diagnostics come along with them, so they need no fetching.
+        let mut fixes = Vec::new();
+        if is_requested(&CodeActionKind::QUICKFIX) {
+            let resolve_edits = self.resolve_code_action_edits.load(Ordering::Relaxed);
+            for diagnostic in &params.context.diagnostics {
+                let Some(NumberOrString::String(code)) = &diagnostic.code else {
+                    continue;
+                };
+                let Some(fix) = diagnostics::Fix::from_diagnostic(diagnostic) else {
+                    continue;
+                };
+                let Some(title) = update::fix_title(code, &fix) else {
+                    continue;
+                };
+                let Some(dependency) = fix
+                    .dependency(&dependencies)
+                    .filter(|dependency| dependency.version_range == diagnostic.range)
+                else {
+                    continue;
+                };
+                let edit = TextEdit::new(dependency.version_range, fix.version.clone());
+                fixes.push(edit.clone());
+                let is_preferred = Some(!fix.is_major);
+                let (edit, data) = if resolve_edits {
+                    let resolvable = diagnostics::ResolvableFix {
+                        uri: uri.clone(),
+                        fix,
+                    };
+                    (None, serde_json::to_value(resolvable).ok())
+                } else {
+                    let edit = WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                        ..Default::default()
+                    };
+                    (Some(edit), None)
+                };
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit,
+                    is_preferred,
+                    data,
+                    ..Default::default()
+                }));
+            }
+            // Both declarations of a duplicate are flagged, and either merges the pair the same way.
+            let declared: Vec<_> = dependencies.iter().chain(&other_sources).collect();
+            let mut merged = Vec::new();
+            for diagnostic in &params.context.diagnostics {
+                if diagnostic.code
+                    != Some(NumberOrString::String(diagnostics::DUPLICATE.to_string()))
+                {
+                    continue;
+                }
+                let declaration_at = |range: Range| {
+                    declared
+                        .iter()
+                        .find(|dependency| dependency.name_range == range)
+                };
+                let Some((dependency, other)) = declaration_at(diagnostic.range).zip(
+                    diagnostic
+                        .related_information
+                        .iter()
+                        .flatten()
+                        .find_map(|related| declaration_at(related.location.range)),
+                ) else {
+                    continue;
+                };
+                let merge = update::merge_duplicates(dependency, other);
+                if merged.contains(&merge.removed.name_range) {
+                    continue;
+                }
+                merged.push(merge.removed.name_range);
+                let Some(removal) = parser::remove_dependency_edit(&contents, &tree, merge.removed)
+                else {
+                    continue;
+                };
+                let mut edits = vec![removal];
+                edits.extend(
+                    merge
+                        .version
+                        .map(|version| TextEdit::new(merge.kept.version_range, version)),
+                );
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: merge.title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(vec![diagnostic.clone()]),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), edits)])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }));
+            }
+        }
+        if is_requested(&CodeActionKind::QUICKFIX)
+            || is_requested(&CodeActionKind::REFACTOR_REWRITE)
+        {
+            let range = params.range;
+            let hovered = dependencies.iter().filter(|dependency| {
+                dependency.name_range.start.line <= range.end.line
+                    && dependency.version_range.end.line >= range.start.line
+            });
+            // Only the dependencies in range are fetched, from the cache when it has their versions.
+            let updates = join_all(hovered.map(|dependency| async move {
+                let options = FetchOptions {
+                    parse_all_versions: true,
+                    full_metadata: false,
+                    readme: false,
+                };
+                let metadata = self
+                    .fetcher
+                    .get_for(uri, &dependency.name, options)
+                    .await
+                    .ok()?;
+                let rewrites = update::range_rewrites(dependency, &metadata);
+                let mut updates = update::available_updates(dependency, &metadata);
+                updates.extend(update::unpinned_pin(dependency, &metadata));
+                updates.extend(update::stable_update(dependency, &metadata));
+                Some((updates, rewrites, dependency))
+            }))
+            .await;
+            for (mut updates, rewrites, dependency) in updates.into_iter().flatten() {
+                if !is_requested(&CodeActionKind::QUICKFIX) {
+                    updates.clear();
+                }
+                updates.retain(|update| !fixes.contains(&update.edit));
+                let diagnostics: Vec<_> = params
+                    .context
+                    .diagnostics
+                    .iter()
+                    .filter(|diagnostic| diagnostic.range == dependency.version_range)
+                    .cloned()
+                    .collect();
+                actions.extend(updates.into_iter().map(|update| {
+                    CodeActionOrCommand::CodeAction(CodeAction {
+                        title: update.title,
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: (!diagnostics.is_empty()).then(|| diagnostics.clone()),
+                        edit: Some(WorkspaceEdit {
+                            changes: Some(HashMap::from([(uri.clone(), vec![update.edit])])),
+                            ..Default::default()
+                        }),
+                        is_preferred: Some(!update.is_major),
+                        ..Default::default()
+                    })
+                }));
+                if is_requested(&CodeActionKind::REFACTOR_REWRITE) {
+                    actions.extend(rewrites.into_iter().map(|rewrite| {
+                        CodeActionOrCommand::CodeAction(CodeAction {
+                            title: rewrite.title,
+                            kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                            edit: Some(WorkspaceEdit {
+                                changes: Some(HashMap::from([(uri.clone(), vec![rewrite.edit])])),
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        })
+                    }));
+                }
+            }
+        }
+        if is_requested(&CodeActionKind::QUICKFIX) {
+            let range = params.range;
+            let hovered = other_sources.iter().filter(|dependency| {
+                dependency.name_range.start.line <= range.end.line
+                    && dependency.version_range.end.line >= range.start.line
+            });
+            // Packages missing from the registry can't be replaced, so they get no action.
+            let replacements = join_all(hovered.map(|dependency| async move {
+                let metadata = self
+                    .fetcher
+                    .get_for(
+                        uri,
+                        &dependency.name,
+                        FetchOptions {
+                            parse_all_versions: true,
+                            full_metadata: false,
+                            readme: false,
+                        },
+                    )
+                    .await
+                    .ok()?;
+                update::registry_replacement(dependency, &metadata)
+            }))
+            .await;
+            actions.extend(replacements.into_iter().flatten().map(|replacement| {
+                CodeActionOrCommand::CodeAction(CodeAction {
+                    title: replacement.title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), vec![replacement.edit])])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+            }));
+        }
+        if is_requested(&CodeActionKind::QUICKFIX) {
+            let range = params.range;
+            let declared: HashSet<_> = dependencies
+                .iter()
+                .chain(&other_sources)
+                .map(|dependency| dependency.name.as_str())
+                .collect();
+            let hovered = dependencies.iter().filter(|dependency| {
+                dependency.name_range.start.line <= range.end.line
+                    && dependency.version_range.end.line >= range.start.line
+            });
+            // Only offered for successors that exist and aren't declared already.
+            let migrations = join_all(hovered.map(|dependency| {
+                let declared = &declared;
+                async move {
+                    let options = FetchOptions {
+                        parse_all_versions: false,
+                        full_metadata: false,
+                        readme: false,
+                    };
+                    let metadata = self
+                        .fetcher
+                        .get_for(uri, &dependency.name, options)
+                        .await
+                        .ok()?;
+                    let deprecated = metadata.latest_version.deprecated.as_deref()?;
+                    let successor = update::deprecation_successor(deprecated)?;
+                    if successor == dependency.name || declared.contains(successor) {
+                        return None;
+                    }
+                    let successor_metadata =
+                        self.fetcher.get_for(uri, successor, options).await.ok()?;
+                    Some((
+                        dependency,
+                        successor.to_string(),
+                        successor_metadata.latest_version.version,
+                    ))
+                }
+            }))
+            .await;
+            actions.extend(migrations.into_iter().flatten().map(
+                |(dependency, successor, version)| {
+                    let diagnostics: Vec<_> = params
+                        .context
+                        .diagnostics
+                        .iter()
+                        .filter(|diagnostic| {
+                            diagnostic.range == dependency.name_range
+                                && diagnostic.code
+                                    == Some(NumberOrString::String(
+                                        diagnostics::DEPRECATED.to_string(),
+                                    ))
+                        })
+                        .cloned()
+                        .collect();
+                    let edits = vec![
+                        TextEdit {
+                            range: dependency.name_range,
+                            new_text: successor.clone(),
+                        },
+                        TextEdit {
+                            range: dependency.version_range,
+                            new_text: format!("^{version}"),
+                        },
+                    ];
+                    CodeActionOrCommand::CodeAction(CodeAction {
+                        title: format!("Replace with {successor}@^{version}"),
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: (!diagnostics.is_empty()).then_some(diagnostics),
+                        edit: Some(WorkspaceEdit {
+                            changes: Some(HashMap::from([(uri.clone(), edits)])),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    })
+                },
+            ));
+        }
+        if is_requested(&CodeActionKind::QUICKFIX) {
+            let range = params.range;
+            let declared: HashSet<_> = dependencies
+                .iter()
+                .chain(&other_sources)
+                .map(|dependency| dependency.name.as_str())
+                .collect();
+            let untyped = dependencies
+                .iter()
+                .filter(|dependency| {
+                    dependency.section == DependencyKind::Dependencies
+                        && dependency.name_range.start.line <= range.end.line
+                        && dependency.version_range.end.line >= range.start.line
+                })
+                .filter_map(|dependency| {
+                    let types_package = completion::types_package_name(&dependency.name)?;
+                    (!declared.contains(types_package.as_str()))
+                        .then_some((types_package, dependency.name.as_str()))
+                });
+            let additions = join_all(untyped.map(|(types_package, package_name)| {
+                let (contents, tree) = (&contents, &tree);
+                async move {
+                    let types = self
+                        .missing_types(uri, package_name, &types_package)
+                        .await?;
+                    let edit = parser::insert_dependency_edit(
+                        contents,
+                        tree,
+                        DependencyKind::DevDependencies,
+                        &types_package,
+                        &format!("^{}", types.version),
+                    )?;
+                    Some((types_package, edit))
+                }
+            }))
+            .await;
+            actions.extend(
+                additions
+                    .into_iter()
+                    .flatten()
+                    .map(|(types_package, edit)| {
+                        CodeActionOrCommand::CodeAction(CodeAction {
+                            title: format!("Add {types_package} to devDependencies"),
+                            kind: Some(CodeActionKind::QUICKFIX),
+                            edit: Some(WorkspaceEdit {
+                                changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        })
+                    }),
+            );
+        }
+        // Not an edit, so it only comes up when no particular kind is asked for.
+        if params.context.only.is_none() {
+            let range = params.range;
+            actions.extend(
+                dependencies
+                    .iter()
+                    .filter(|dependency| {
+                        dependency.name_range.start.line <= range.end.line
+                            && dependency.version_range.end.line >= range.start.line
+                    })
+                    .filter_map(|dependency| {
+                        let package_name = links::package_name(dependency);
+                        let arguments = commands::OpenPackagePageArguments {
+                            name: package_name.to_string(),
+                            uri: Some(uri.clone()),
+                        };
+                        let title = format!("Open {package_name}'s page");
+                        Some(CodeActionOrCommand::CodeAction(CodeAction {
+                            title: title.clone(),
+                            command: Some(Command {
+                                title,
+                                command: commands::OPEN_PACKAGE_PAGE.to_string(),
+                                arguments: Some(vec![serde_json::to_value(arguments).ok()?]),
+                            }),
+                            ..Default::default()
+                        }))
+                    }),
+            );
+        }
+        if is_requested(&CodeActionKind::SOURCE) && !dependencies.is_empty() {
+            // Resolved when executed, so that opening the menu doesn't fetch every package.
+            let arguments = commands::UpdateAllArguments {
+                uri: uri.clone(),
+                section: None,
+            };
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: "Update all dependencies to latest".to_string(),
+                kind: Some(CodeActionKind::SOURCE),
+                command: Some(Command {
+                    title: "Update all dependencies to latest".to_string(),
+                    command: commands::UPDATE_ALL.to_string(),
+                    arguments: serde_json::to_value(arguments)
+                        .ok()
+                        .map(|value| vec![value]),
+                }),
+                ..Default::default()
+            }));
+        }
+        if is_requested(&CodeActionKind::new(SORT_KIND)) {
+            let edits = parser::sort_dependencies_edits(&contents, &tree);
+            if !edits.is_empty() {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: "Sort dependencies".to_string(),
+                    kind: Some(CodeActionKind::new(SORT_KIND)),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), edits)])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }));
+            }
+        }
+        // Only on explicit request, as this fetches every package in the file.
+        let is_fix_all_requested = params.context.only.iter().flatten().any(|requested| {
+            FIX_ALL_KIND.starts_with(requested.as_str())
+                && requested
+                    .as_str()
+                    .starts_with(CodeActionKind::SOURCE_FIX_ALL.as_str())
+        });
+        if is_fix_all_requested {
+            let edits = join_all(dependencies.iter().map(|dependency| async move {
+                let metadata = self
+                    .fetcher
+                    .get_for(
+                        uri,
+                        &dependency.name,
+                        FetchOptions {
+                            parse_all_versions: true,
+                            full_metadata: false,
+                            readme: false,
+                        },
+                    )
+                    .await
+                    .ok()?;
+                update::in_range_update(dependency, &metadata)
+            }))
+            .await;
+            let edits: Vec<_> = edits.into_iter().flatten().collect();
+            if !edits.is_empty() {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title: "Update dependencies within their ranges".to_string(),
+                    kind: Some(CodeActionKind::new(FIX_ALL_KIND)),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), edits)])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }));
+            }
+        }
+        Ok(Some(actions))
+    }
+
+    async fn code_action_resolve(&self, mut action: CodeAction) -> Result<CodeAction> {
+        let Some(resolvable) = action
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<diagnostics::ResolvableFix>(data).ok())
+        else {
+            return Ok(action);
+        };
+        let Some((contents, tree)) = self
+            .file_contents
+            .lock()
+            .unwrap()
+            .get(&resolvable.uri)
+            .cloned()
+        else {
+            return Ok(action);
+        };
+        // The document may have changed since, so the dependency is looked up again.
+        let dependencies = parser::extract_all_dependencies(&contents, &tree);
+        if let Some(dependency) = resolvable.fix.dependency(&dependencies) {
+            let edit = TextEdit::new(dependency.version_range, resolvable.fix.version);
+            action.edit = Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(resolvable.uri, vec![edit])])),
+                ..Default::default()
+            });
+        }
+        Ok(action)
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let uri = params.text_document.uri;
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return Ok(None);
+        };
+        let dependencies = parser::extract_all_dependencies(&contents, &tree);
+        #[allow(deprecated)]
+        let symbols = parser::extract_sections(&contents, &tree)
+            .into_iter()
+            .map(|section| DocumentSymbol {
+                name: section.kind.section_name().to_string(),
+                detail: None,
+                kind: SymbolKind::MODULE,
+                tags: None,
+                deprecated: None,
+                range: section.range,
+                selection_range: section.name_range,
+                children: Some(
+                    dependencies
+                        .iter()
+                        .filter(|dependency| dependency.section == section.kind)
+                        .map(|dependency| DocumentSymbol {
+                            name: dependency.name.clone(),
+                            detail: Some(dependency.version.clone()),
+                            kind: SymbolKind::CONSTANT,
+                            tags: None,
+                            deprecated: None,
+                            range: Range::new(
+                                dependency.name_range.start,
+                                dependency.version_range.end,
+                            ),
+                            selection_range: dependency.name_range,
+                            children: None,
+                        })
+                        .collect(),
+                ),
+            })
+            .collect();
+        Ok(Some(DocumentSymbolResponse::Nested(symbols)))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return Ok(None);
+        };
+        let position = params.text_document_position_params.position;
+        let Some(key) = parser::extract_dependency_key(&contents, &tree, position) else {
+            return Ok(None);
+        };
+        let Some(directory) = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| path.parent().map(ToOwned::to_owned))
+        else {
+            return Ok(None);
+        };
+        // Packages that aren't installed have nothing to go to, which isn't an error.
+        let Some(manifest) = installed::find_manifest(&directory, &key.name).await else {
+            return Ok(None);
+        };
+        let Ok(text) = tokio::fs::read_to_string(&manifest).await else {
+            return Ok(None);
+        };
+        let Some(tree) = self.parser.lock().unwrap().parse(&text, None) else {
+            return Ok(None);
+        };
+        let range = parser::extract_field(&tree, &text, "version").unwrap_or_default();
+        let Ok(target) = Url::from_file_path(&manifest) else {
+            return Ok(None);
+        };
+        Ok(Some(GotoDefinitionResponse::Scalar(Location::new(
+            target, range,
+        ))))
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let uri = params.text_document.uri;
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return Ok(None);
+        };
+        // Linking to the homepage needs the registry, so it is left to `document_link_resolve`.
+        let links = parser::extract_all_dependencies(&contents, &tree)
+            .iter()
+            .filter(|dependency| {
+                diagnostics::is_from_registry(&dependency.version)
+                    || dependency.version.starts_with("npm:")
+            })
+            .map(|dependency| {
+                let package_name = links::package_name(dependency);
+                let arguments = commands::OpenPackagePageArguments {
+                    name: package_name.to_string(),
+                    uri: Some(uri.clone()),
+                };
+                DocumentLink {
+                    range: dependency.name_range,
+                    target: links::npm_page(package_name),
+                    tooltip: None,
+                    data: serde_json::to_value(arguments).ok(),
+                }
+            })
+            .collect();
+        Ok(Some(links))
+    }
+
+    async fn document_link_resolve(&self, mut link: DocumentLink) -> Result<DocumentLink> {
+        let Some(arguments) = link
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value(data).ok())
+        else {
+            return Ok(link);
+        };
+        if let Some(page) = self.package_page(&arguments).await {
+            link.target = Some(page);
+        }
+        Ok(link)
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let uri = params.text_document.uri;
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return Ok(None);
+        };
+        // Rather than waiting for the registry, answer with what the latest analysis found, and
+        // have the client ask again once the document is analyzed.
+        let analyzed = self.analyzed_documents.lock().unwrap().get(&uri).cloned();
+        let data = match analyzed {
+            Some(analyzed) if Arc::ptr_eq(&analyzed.text, &contents) => semantic_tokens::tokens(
+                &parser::extract_all_dependencies(&contents, &tree),
+                &analyzed.diagnostics,
+            ),
+            _ => Vec::new(),
+        };
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data,
+        })))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        // Hints tell what hover and the outdated diagnostics do, so they go along with both.
+        let features = self.features();
+        if !features.hover || !features.diagnostics {
+            return Ok(None);
+        }
+        let uri = params.text_document.uri;
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return Ok(None);
+        };
+        let show_up_to_date = self.settings.read().unwrap().inlay_hints.show_up_to_date;
+        let range = params.range;
+        let dependency_query = self.dependency_query.read().unwrap().clone();
+        let visible: Vec<_> = parser::extract_all_dependencies(&contents, &tree)
+            .into_iter()
+            .filter(|dependency| {
+                dependency_query.includes(dependency.section)
+                    && dependency.version_range.end >= range.start
+                    && dependency.version_range.start <= range.end
+            })
+            .collect();
+        // Only the latest version is needed, which the abbreviated entries diagnostics cache hold
+        // too, so scrolling back and forth is answered from the cache. Its publish date shows up
+        // once hover has fetched the package's details.
+        let latest_versions = join_all(visible.iter().map(|dependency| async {
+            if !diagnostics::is_from_registry(&dependency.version) {
+                return None;
+            }
+            let metadata = self
+                .fetcher
+                .get_for(
+                    &uri,
+                    &dependency.name,
+                    FetchOptions {
+                        parse_all_versions: false,
+                        full_metadata: false,
+                        readme: false,
+                    },
+                )
+                .await
+                .ok()?;
+            Some(metadata.latest_version)
+        }))
+        .await;
+        let hints = visible
+            .iter()
+            .zip(latest_versions)
+            .filter_map(|(dependency, latest)| {
+                let latest = latest?;
+                let label = if diagnostics::is_outdated(dependency, &latest.version) {
+                    format!(" ⟶ {}", latest.version)
+                } else if show_up_to_date && is_up_to_date(&dependency.version, &latest.version) {
+                    " ✓".to_string()
+                } else {
+                    return None;
+                };
+                // After the closing quote of the version.
+                let mut position = dependency.version_range.end;
+                position.character += 1;
+                Some(InlayHint {
+                    position,
+                    label: InlayHintLabel::String(label),
+                    kind: None,
+                    text_edits: None,
+                    tooltip: latest.date.map(|date| {
+                        InlayHintTooltip::String(format!(
+                            "{} published on {} ({})",
+                            latest.version,
+                            date.format("%Y-%m-%d"),
+                            format_time(date)
+                        ))
+                    }),
+                    padding_left: None,
+                    padding_right: None,
+                    data: None,
+                })
+            })
+            .collect();
+        Ok(Some(hints))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let uri = params.text_document.uri;
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return Ok(None);
+        };
+        let key = parser::extract_dependency_key(&contents, &tree, params.position);
+        Ok(key.map(|key| PrepareRenameResponse::RangeWithPlaceholder {
+            range: key.range,
+            placeholder: key.name,
+        }))
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let uri = params.text_document_position.text_document.uri;
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return Ok(None);
+        };
+        let Some(key) = parser::extract_dependency_key(
+            &contents,
+            &tree,
+            params.text_document_position.position,
+        ) else {
+            return Ok(None);
+        };
+        let new_name = params.new_name.trim();
+        if new_name.is_empty() || new_name.contains(['"', '\\']) {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "`{new_name}` is not a valid package name"
+            )));
+        }
+        let edits = parser::extract_package_references(&contents, &tree, &key.name)
+            .into_iter()
+            .map(|range| TextEdit::new(range, new_name.to_string()))
+            .collect();
+        Ok(Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, edits)])),
+            ..Default::default()
+        }))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return Ok(None);
+        };
+        let Some(key) = parser::extract_dependency_key(&contents, &tree, position.position) else {
+            return Ok(None);
+        };
+        // The same places a rename would touch, so that duplicates and overrides stand out.
+        let highlights = parser::extract_package_references(&contents, &tree, &key.name)
+            .into_iter()
+            .map(|range| DocumentHighlight {
+                range,
+                kind: Some(DocumentHighlightKind::TEXT),
+            })
+            .collect();
+        Ok(Some(highlights))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let uri = params.text_document.uri;
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let Some((contents, tree)) = self.file_contents.lock().unwrap().get(&uri).cloned() else {
+            return Ok(None);
+        };
+        // Counting outdated dependencies needs the registry, so it is left to `code_lens_resolve`.
+        let mut lenses = parser::extract_sections(&contents, &tree)
+            .into_iter()
+            .filter_map(|section| {
+                let arguments = commands::UpdateAllArguments {
+                    uri: uri.clone(),
+                    section: Some(section.kind.section_name().to_string()),
+                };
+                Some(CodeLens {
+                    range: section.name_range,
+                    command: None,
+                    data: Some(serde_json::to_value(arguments).ok()?),
+                })
+            })
+            .collect::<Vec<_>>();
+        if self.settings.read().unwrap().code_lens.per_dependency {
+            let dependencies = parser::extract_all_dependencies(&contents, &tree);
+            lenses.extend(
+                dependencies
+                    .into_iter()
+                    .filter(|dependency| diagnostics::is_from_registry(&dependency.version))
+                    .filter_map(|dependency| {
+                        let arguments = commands::UpdateDependencyArguments {
+                            uri: uri.clone(),
+                            section: dependency.section.section_name().to_string(),
+                            name: dependency.name,
+                        };
+                        Some(CodeLens {
+                            range: dependency.name_range,
+                            command: None,
+                            data: Some(serde_json::to_value(arguments).ok()?),
+                        })
+                    }),
+            );
+        }
+        // Vulnerabilities are never looked up once OSV isn't to be asked.
+        let security = {
+            let settings = self.settings.read().unwrap();
+            settings.code_lens.security && settings.diagnostics.security
+        };
+        if security {
+            lenses.extend(
+                parser::extract_sections(&contents, &tree)
+                    .into_iter()
+                    .filter_map(|section| {
+                        let data = commands::SecurityLensData {
+                            security: commands::ShowVulnerabilitiesArguments {
+                                uri: uri.clone(),
+                                section: section.kind.section_name().to_string(),
+                            },
+                        };
+                        Some(CodeLens {
+                            range: section.name_range,
+                            command: None,
+                            data: Some(serde_json::to_value(data).ok()?),
+                        })
+                    }),
+            );
+        }
+        lenses.sort_by_key(|lens| lens.range.start);
+        Ok(Some(lenses))
+    }
+
+    async fn code_lens_resolve(&self, mut lens: CodeLens) -> Result<CodeLens> {
+        if let Some(data) = lens
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<commands::SecurityLensData>(data).ok())
+        {
+            lens.command = Some(self.security_lens_command(data.security).await);
+            return Ok(lens);
+        }
+        // Section lenses would also accept dependency arguments, so those are tried first.
+        if let Some(arguments) = lens.data.clone().and_then(|data| {
+            serde_json::from_value::<commands::UpdateDependencyArguments>(data).ok()
+        }) {
+            lens.command = self.dependency_lens_command(arguments).await;
+            return Ok(lens);
+        }
+        let Some(arguments) = lens
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<commands::UpdateAllArguments>(data).ok())
+        else {
+            return Ok(lens);
+        };
+        let Some(kind) = arguments
+            .section
+            .as_deref()
+            .and_then(DependencyKind::from_section_name)
+        else {
+            return Ok(lens);
+        };
+        let Some((contents, tree)) = self
+            .file_contents
+            .lock()
+            .unwrap()
+            .get(&arguments.uri)
+            .cloned()
+        else {
+            return Ok(lens);
+        };
+        if !parser::extract_sections(&contents, &tree)
+            .iter()
+            .any(|section| section.kind == kind)
+        {
+            return Ok(lens);
+        }
+        let dependencies: Vec<_> = parser::extract_all_dependencies(&contents, &tree)
+            .into_iter()
+            .filter(|dependency| dependency.section == kind)
+            .collect();
+        let total = dependencies.len();
+        // Counted from the same analysis as the diagnostics, whether or not outdated dependencies
+        // are reported.
+        let outdated = self
+            .analyze(&arguments.uri, &contents, &tree)
+            .await
+            .map(|analyzed| {
+                dependencies
+                    .iter()
+                    .filter(|dependency| analyzed.outdated.contains(&dependency.name_range))
+                    .count()
+            });
+        let dependencies = if total == 1 {
+            "dependency"
+        } else {
+            "dependencies"
+        };
+        let title = match outdated {
+            Some(outdated) if outdated > 0 => {
+                format!("{outdated} of {total} {dependencies} outdated — Update all")
+            }
+            Some(_) => format!("{total} {dependencies} up to date"),
+            None => "Couldn't check for updates".to_string(),
+        };
+        // Updating reports what is up to date, and what still can't be fetched.
+        lens.command = Some(Command {
+            title,
+            command: commands::UPDATE_ALL.to_string(),
+            arguments: Some(vec![serde_json::to_value(arguments)
+                .map_err(|_| tower_lsp::jsonrpc::Error::internal_error())?]),
+        });
+        Ok(lens)
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        let argument = params.arguments.into_iter().next().unwrap_or_default();
+        match params.command.as_str() {
+            commands::UPDATE_ALL => {
+                let arguments = serde_json::from_value(argument).map_err(|_| {
+                    tower_lsp::jsonrpc::Error::invalid_params("expected a document and a section")
+                })?;
+                // Edits are applied by the client, which is waiting for this request to finish.
+                let backend = self.clone();
+                self.spawn(async move { backend.update_all(arguments).await });
+            }
+            commands::UPDATE_DEPENDENCY => {
+                let arguments = serde_json::from_value(argument).map_err(|_| {
+                    tower_lsp::jsonrpc::Error::invalid_params(
+                        "expected a document, a section and a dependency",
+                    )
+                })?;
+                let backend = self.clone();
+                self.spawn(async move { backend.update_dependency(arguments).await });
+            }
+            commands::OPEN_PACKAGE_PAGE => {
+                let arguments = serde_json::from_value(argument).map_err(|_| {
+                    tower_lsp::jsonrpc::Error::invalid_params("expected a package name")
+                })?;
+                // The client is asked to open the page, so it mustn't be kept waiting on this.
+                let backend = self.clone();
+                self.spawn(async move { backend.open_package_page(arguments).await });
+            }
+            commands::SHOW_VULNERABILITIES => {
+                let arguments = serde_json::from_value(argument).map_err(|_| {
+                    tower_lsp::jsonrpc::Error::invalid_params("expected a document and a section")
+                })?;
+                // The client is waiting for this request while the user picks an advisory.
+                let backend = self.clone();
+                self.spawn(async move { backend.show_vulnerabilities(arguments).await });
+            }
+            commands::CLEAR_CACHE => self.clear_cache().await,
+            commands::DIAGNOSE => {
+                let diagnosis = self.fetcher.diagnose(commands::DIAGNOSED_PACKAGE).await;
+                return Ok(serde_json::to_value(diagnosis).ok());
+            }
+            command => {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "unknown command `{command}`"
+                )))
+            }
+        }
+        Ok(None)
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        if !self.features().completion {
+            return Ok(None);
+        }
+        let uri = params.text_document_position.text_document.uri;
+
+        if !uri.path().ends_with("package.json") {
+            return Ok(None);
+        }
+        let pending = self.begin_completion(&uri);
+        let Some((contents, parse_tree)) = self.document(&uri).await else {
+            return Ok(None);
+        };
+
+        let position = params.text_document_position.position;
+        let dependency_query = self.dependency_query.read().unwrap().clone();
+        let key = parser::extract_dependency_key(&contents, &parse_tree, position);
+        if let Some(key) = key
+            .as_ref()
+            .filter(|key| key.section == DependencyKind::DevDependencies)
+            .filter(|key| dependency_query.includes(key.section))
+        {
+            let completions = pending
+                .unless_superseded(self.types_completions(&uri, &contents, &parse_tree, key))
+                .await?;
+            if !completions.items.is_empty() {
+                return Ok(Some(CompletionResponse::List(completions)));
+            }
+        }
+        if let Some(entry) = parser::extract_new_dependency(&contents, &parse_tree, position)
+            .filter(|entry| dependency_query.includes(entry.section))
+        {
+            let completions = pending
+                .unless_superseded(self.new_dependency_completions(
+                    &uri,
+                    &contents,
+                    &parse_tree,
+                    &entry,
+                ))
+                .await?;
+            return Ok(Some(CompletionResponse::List(completions)));
+        }
+        if key.is_some() {
+            return Ok(None);
+        }
+        if dependency_query.includes(DependencyKind::BundledDependencies) {
+            if let Some(entry) = parser::extract_bundled_name(&contents, &parse_tree, position) {
+                let capabilities = *self.completion_capabilities.read().unwrap();
+                return Ok(Some(CompletionResponse::List(
+                    completion::bundled_completions(
+                        bundle_candidates(&contents, &parse_tree, &entry),
+                        &entry,
+                        capabilities,
+                    ),
+                )));
+            }
+        }
+
+        let Some(mut target) =
+            parser::extract_package_name(contents, parse_tree, position, &dependency_query)
+        else {
+            return Ok(None);
+        };
+        // Only the range of an alias is completed.
+        target.resolve_npm_alias();
+
+        let response = pending
+            .unless_superseded(self.fetcher.get_for(
+                &uri,
+                &target.package_name,
+                FetchOptions {
+                    parse_all_versions: true,
+                    full_metadata: true,
+                    readme: false,
+                },
+            ))
+            .await?
+            .map_err(|_| tower_lsp::jsonrpc::Error::internal_error())?;
+
+        if !response.failed_versions.is_empty() {
+            let some_or_all = if response.package_versions.is_empty() {
+                "all"
+            } else {
+                "some"
+            };
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    format!(
+                        "Failed to parse {} package versions: {:?}",
+                        some_or_all, response.failed_versions
+                    ),
+                )
+                .await;
+        }
+
+        let settings = self.settings.read().unwrap().completion.clone();
+        let capabilities = *self.completion_capabilities.read().unwrap();
+        Ok(Some(CompletionResponse::List(
+            completion::version_completions(
+                response.package_versions,
+                &target,
+                &settings,
+                capabilities,
+            ),
+        )))
+    }
+
+    async fn completion_resolve(&self, mut item: CompletionItem) -> Result<CompletionItem> {
+        let Some(data) = item
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<completion::NewDependencyData>(data).ok())
+        else {
+            return Ok(item);
+        };
+        // The search may lag behind, or not be the registry the package is fetched from.
+        let options = FetchOptions {
+            parse_all_versions: false,
+            full_metadata: false,
+            readme: false,
+        };
+        if let Ok(metadata) = self.fetcher.get_for(&data.uri, &data.name, options).await {
+            let version = metadata.latest_version.version.to_string();
+            completion::resolve_new_dependency(&mut item, &data.version, &version);
+        }
+        Ok(item)
+    }
+}
+
+/// Summarizes who is behind a package, e.g. `Published by alice · 3 maintainers`.
+fn publisher_line(published_by: Option<&str>, maintainer_count: Option<usize>) -> Option<String> {
+    let maintainers = maintainer_count.filter(|count| *count > 0).map(|count| {
+        let plural = if count == 1 { "" } else { "s" };
+        format!("{count} maintainer{plural}")
+    });
+    match (published_by, maintainers) {
+        (Some(publisher), Some(maintainers)) => {
+            Some(format!("Published by {publisher} · {maintainers}"))
+        }
+        (Some(publisher), None) => Some(format!("Published by {publisher}")),
+        (None, Some(maintainers)) => Some(maintainers),
+        (None, None) => None,
+    }
+}
+
+/// The version a dependency installs: the one the lockfile recorded, or else the one it pins or the
+/// newest its range allows.
+fn resolved_version(
+    declared: &str,
+    installed: Option<&lockfile::InstalledPackage>,
+    metadata: &MetadataFromRegistry,
+) -> Option<String> {
+    if let Some(installed) = installed {
+        return Some(installed.version.clone());
+    }
+    let declared = declared.trim();
+    if update::specifier_kind(declared)? == update::SpecifierKind::Exact {
+        return Some(completion::strip_operator(declared).to_string());
+    }
+    let range = diagnostics::parse_range(declared)?;
+    metadata
+        .package_versions
+        .iter()
+        .map(|package_version| &package_version.version)
+        .filter(|version| range.test(version))
+        .max()
+        .map(ToString::to_string)
+}
+
+/// Warns about the known advisories of a version, linking to each of them on OSV.
+fn advisories_line(version: &str, advisories: &[osv::Advisory]) -> Option<String> {
+    if advisories.is_empty() {
+        return None;
+    }
+    let links: Vec<_> = advisories
+        .iter()
+        .map(|advisory| {
+            let severity = advisory
+                .severity
+                .map(|severity| format!(" ({})", severity.label()))
+                .unwrap_or_default();
+            let page = osv::advisory_page(&advisory.id);
+            format!("[{}]({page}){severity}", advisory.id)
+        })
+        .collect();
+    let advisories = match advisories.len() {
+        1 => "1 known advisory".to_string(),
+        count => format!("{count} known advisories"),
+    };
+    Some(format!(
+        "⚠️ {advisories} for {version}: {}",
+        links.join(", ")
+    ))
+}
+
+/// Whether the declared range already permits the latest version, so there is nothing to update.
+fn is_up_to_date(declared: &str, latest: &semver_rs::Version) -> bool {
+    diagnostics::parse_range(declared).is_some_and(|range| range.test(latest))
+}
+
+/// Dependencies that a `bundleDependencies` array could list at `entry`: the ones that get installed
+/// with the package and aren't bundled yet, starting with what has been typed so far.
+fn bundle_candidates(text: &str, tree: &Tree, entry: &BundledName) -> Vec<String> {
+    let mut excluded: HashSet<_> = parser::extract_bundled_names(text, tree)
+        .into_iter()
+        .filter(|bundled| bundled.range != entry.range)
+        .map(|bundled| bundled.name)
+        .collect();
+    parser::extract_all_dependencies(text, tree)
+        .into_iter()
+        .filter(|dependency| {
+            matches!(
+                dependency.section,
+                DependencyKind::Dependencies | DependencyKind::OptionalDependencies
+            ) && dependency.name.starts_with(&entry.name)
+        })
+        .filter_map(|dependency| {
+            excluded
+                .insert(dependency.name.clone())
+                .then_some(dependency.name)
+        })
+        .collect()
+}
+
+/// What the lockfile next to a manifest installed. Without a lockfile, there is nothing installed
+/// to compare with.
+async fn installed_packages(uri: &Url) -> lockfile::InstalledPackages {
+    match uri.to_file_path() {
+        Ok(path) => lockfile::installed_packages(path.parent().unwrap_or(&path))
+            .await
+            .unwrap_or_default(),
+        Err(()) => Default::default(),
+    }
+}
+
+/// Whether the document is a manifest the server knows about.
+fn is_supported(uri: &Url) -> bool {
+    uri.path().ends_with("package.json")
+        || rubygems::is_manifest(uri)
+        || cocoapods::is_manifest(uri)
+        || maven::is_manifest(uri)
+        || tool_versions::is_manifest(uri)
+}
+
+/// Applies a change sent by the client to the text of a document.
+fn apply_change(text: &mut String, change: TextDocumentContentChangeEvent) {
+    match change.range {
+        Some(range) => {
+            let start = offset_at(text, range.start);
+            let end = offset_at(text, range.end).max(start);
+            text.replace_range(start..end, &change.text);
+        }
+        None => *text = change.text,
+    }
+}
+
+/// Byte offset of a position, whose character is counted in UTF-16 code units as LSP requires.
+/// Positions past the end of a line or of the document are clamped to it.
+fn offset_at(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(ix) => line_start += ix + 1,
+            None => return text.len(),
+        }
+    }
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let mut units = 0;
+    for (ix, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + ix;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+/// Position of a byte offset, the inverse of [`offset_at`].
+fn position_at(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |ix| ix + 1);
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+/// Lists the first few keywords of a package, which hint at what it's about.
+fn keywords_line(keywords: &[String]) -> Option<String> {
+    const MAX_KEYWORDS: usize = 8;
+    if keywords.is_empty() {
+        return None;
+    }
+    let mut line = format!(
+        "Keywords: {}",
+        keywords[..keywords.len().min(MAX_KEYWORDS)].join(", ")
+    );
+    if keywords.len() > MAX_KEYWORDS {
+        line.push_str(&format!(" and {} more", keywords.len() - MAX_KEYWORDS));
+    }
+    Some(line)
+}
+
+/// Tells whether the declared version is pinned or can move, and to what.
+fn resolution_line(declared: &str, metadata: &MetadataFromRegistry) -> Option<String> {
+    let declared = declared.trim();
+    let latest = &metadata.latest_version.version;
+    let kind = match update::specifier_kind(declared)? {
+        update::SpecifierKind::Exact => {
+            let pinned = completion::strip_operator(declared);
+            return Some(if *pinned == latest.to_string() {
+                format!("Pinned to {pinned} (latest)")
+            } else {
+                format!("Pinned to {pinned} (latest {latest})")
+            });
+        }
+        update::SpecifierKind::Caret => "Caret range",
+        update::SpecifierKind::Tilde => "Tilde range",
+        update::SpecifierKind::Range => "Range",
+    };
+    let range = diagnostics::parse_range(declared)?;
+    let resolved = metadata
+        .package_versions
+        .iter()
+        .map(|package_version| &package_version.version)
+        .filter(|version| range.test(version))
+        .max()?;
+    Some(format!("{kind} `{declared}` resolves to {resolved}"))
+}
+
+/// The checksum of the latest version's tarball, to verify what gets installed.
+fn integrity_line(latest: &PackageVersion) -> Option<String> {
+    match (&latest.integrity, &latest.shasum) {
+        (Some(integrity), _) => Some(format!("Integrity: `{integrity}`")),
+        (None, Some(shasum)) => Some(format!("Shasum: `{shasum}`")),
+        (None, None) => None,
+    }
+}
+
+/// Warns when the lockfile recorded a different checksum for the installed version than the
+/// registry serves, which means the tarball changed or came from elsewhere. Checksums can only be
+/// compared when both use the same algorithm.
+fn integrity_mismatch_line(
+    installed: &lockfile::InstalledPackage,
+    metadata: &MetadataFromRegistry,
+) -> Option<String> {
+    let recorded = installed.integrity.as_deref()?;
+    let served = std::iter::once(&metadata.latest_version)
+        .chain(&metadata.package_versions)
+        .find(|package_version| package_version.version.to_string() == installed.version)?
+        .integrity
+        .as_deref()?;
+    let hashes = |integrity: &str| -> HashMap<String, String> {
+        integrity
+            .split_whitespace()
+            .filter_map(|hash| {
+                let (algorithm, digest) = hash.split_once('-')?;
+                Some((algorithm.to_string(), digest.to_string()))
+            })
+            .collect()
+    };
+    let (recorded_hashes, served_hashes) = (hashes(recorded), hashes(served));
+    let compared: Vec<_> = recorded_hashes
+        .iter()
+        .filter_map(|(algorithm, digest)| Some(served_hashes.get(algorithm)? == digest))
+        .collect();
+    (!compared.is_empty() && !compared.contains(&true)).then(|| {
+        format!(
+            "⚠️ Integrity mismatch: package-lock.json records `{recorded}` for {}, but the \
+             registry serves `{served}`",
+            installed.version
+        )
+    })
+}
+
+/// Links to where the maintainers accept donations.
+fn funding_line(funding: &[String]) -> Option<String> {
+    if funding.is_empty() {
+        return None;
+    }
+    let links: Vec<_> = funding
+        .iter()
+        .map(|url| format!("[{}]({url})", links::link_label(url)))
+        .collect();
+    Some(format!("💖 Funding: {}", links.join(", ")))
+}
+
+/// Links to the package's homepage and, when it's somewhere else, its repository.
+fn links_line(latest: &PackageVersion) -> Option<String> {
+    let homepage = latest.homepage.as_deref();
+    let repository = latest
+        .repository
+        .as_deref()
+        .and_then(links::repository_page)
+        .filter(|page| {
+            homepage.map(|homepage| homepage.trim_end_matches('/'))
+                != Some(page.as_str().trim_end_matches('/'))
+        });
+    let links: Vec<_> = homepage
+        .map(|homepage| format!("[Homepage]({homepage})"))
+        .into_iter()
+        .chain(repository.map(|page| format!("[Repository]({page})")))
+        .collect();
+    (!links.is_empty()).then(|| links.join(" · "))
+}
+
+/// The first paragraph of prose in a README, skipping headings, badges, HTML and code blocks, and
+/// cut short at a sentence boundary when it's long.
+fn readme_excerpt(readme: &str) -> Option<String> {
+    const MAX_LENGTH: usize = 500;
+    let mut in_code_block = false;
+    let lines: Vec<_> = readme
+        .lines()
+        .map(str::trim)
+        .filter(|line| {
+            let is_fence = line.starts_with("```") || line.starts_with("~~~");
+            in_code_block ^= is_fence;
+            !in_code_block && !is_fence
+        })
+        .collect();
+    let paragraph = lines
+        .split(|line| line.is_empty())
+        .find(|paragraph| {
+            !paragraph.is_empty() && !paragraph[0].starts_with(['#', '<', '!', '[', '|', '='])
+        })?
+        .join(" ");
+    if paragraph.chars().count() <= MAX_LENGTH {
+        return Some(paragraph);
+    }
+    let (end, _) = paragraph.char_indices().nth(MAX_LENGTH)?;
+    let truncated = &paragraph[..end];
+    let sentence_end = truncated
+        .rmatch_indices(['.', '!', '?'])
+        .map(|(ix, _)| ix + 1)
+        .find(|ix| truncated[*ix..].starts_with(' '));
+    Some(match sentence_end {
+        Some(sentence_end) => truncated[..sentence_end].to_string(),
+        None => {
+            let word_end = truncated.rfind(' ').unwrap_or(truncated.len());
+            format!("{}…", &truncated[..word_end])
+        }
+    })
+}
+
+/// Summarizes the outcome of updating several dependencies at once.
+fn update_summary(updated: usize, up_to_date: usize, failed: usize) -> String {
+    let plural = |count| if count == 1 { "" } else { "s" };
+    let mut summary = format!(
+        "Updated {updated} dependenc{}",
+        if updated == 1 { "y" } else { "ies" }
+    );
+    if up_to_date > 0 {
+        summary.push_str(&format!(", {up_to_date} already up to date"));
+    }
+    if failed > 0 {
+        summary.push_str(&format!(
+            ", {failed} package{} could not be fetched",
+            plural(failed)
+        ));
+    }
+    summary
+}
+
+/// Summarizes which dependencies have a new major version, if any do.
+fn major_updates_summary(names: &[&str]) -> Option<String> {
+    match names {
+        [] => None,
+        [name] => Some(format!("{name} has a newer major version available")),
+        names => Some(format!(
+            "{} dependencies have newer major versions available: {}",
+            names.len(),
+            names.join(", ")
+        )),
+    }
+}
+
+fn format_time(time: DateTime<FixedOffset>) -> String {
+    let ht = HumanTime::from(time);
+    ht.to_text_en(Accuracy::Rough, Tense::Past)
+}
+
+#[tokio::main]
+async fn main() {
+    if std::env::args()
+        .nth(1)
+        .filter(|arg| arg == "--version")
+        .is_some()
+    {
+        println!("package-version-server {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+    let stdin = tokio::io::stdin();
+    let stdout = tokio::io::stdout();
+
+    let (service, socket) =
+        LspService::new(|client| Backend::new(client).expect("Failed to initialize backend"));
+    Server::new(stdin, stdout, socket).serve(service).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::AtomicUsize;
+
+    use futures::StreamExt;
+    use serde_json::json;
+    use tower::{Service, ServiceExt};
+    use tower_lsp::jsonrpc::Request;
+    use tower_lsp::ClientSocket;
+
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn test_advisories_line() {
+        let advisories = [
+            osv::Advisory {
+                id: "GHSA-35jh-r3h4-6jhm".into(),
+                severity: Some(osv::Severity::High),
+            },
+            osv::Advisory {
+                id: "CVE-2021-23337".into(),
+                severity: None,
+            },
+        ];
+        assert_eq!(
+            advisories_line("4.17.20", &advisories).as_deref(),
+            Some("⚠️ 2 known advisories for 4.17.20: [GHSA-35jh-r3h4-6jhm](https://osv.dev/vulnerability/GHSA-35jh-r3h4-6jhm) (high), [CVE-2021-23337](https://osv.dev/vulnerability/CVE-2021-23337)")
+        );
+        assert!(advisories_line("4.17.20", &advisories[1..])
+            .unwrap()
+            .starts_with("⚠️ 1 known advisory for 4.17.20:"));
+        assert_eq!(advisories_line("4.17.21", &[]), None);
+    }
+
+    #[tokio::test]
+    async fn test_hover_advisories() {
+        let registry = file_registry("advisories-registry", &[]);
+        publish(&registry, "@acme/ui", &["1.0.0"]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        publish_publicly(backend, "lodash", &["4.17.20", "4.17.21"]).await;
+        let advisory = || {
+            vec![osv::Advisory {
+                id: "GHSA-35jh-r3h4-6jhm".into(),
+                severity: Some(osv::Severity::High),
+            }]
+        };
+        backend
+            .advisories
+            .insert_for_tests(("lodash".into(), "4.17.20".into()), advisory());
+        backend
+            .advisories
+            .insert_for_tests(("lodash".into(), "4.17.21".into()), Vec::new());
+        backend
+            .advisories
+            .insert_for_tests(("@acme/ui".into(), "1.0.0".into()), advisory());
+        let hover = |text: &str, show_advisories| {
+            backend.apply_settings(
+                Settings::from_value(Some(json!({
+                    "scope_registries": { "@acme": format!("file://{}", registry.display()) },
+                    "show_advisories": show_advisories
+                })))
+                .unwrap(),
+            );
+            let uri = Url::parse("file:///project/package.json").unwrap();
+            backend.update_document(uri.clone(), text.to_string());
+            async move { hover_markup(backend, &uri, Position::new(0, 23)).await }
+        };
+        let pinned = r#"{ "dependencies": { "lodash": "4.17.20" } }"#;
+        assert!(hover(pinned, true)
+            .await
+            .contains("⚠️ 1 known advisory for 4.17.20: [GHSA-35jh-r3h4-6jhm]"));
+        assert!(!hover(pinned, false).await.contains("⚠️"));
+        // The range resolves to the newest version, which has none.
+        let range = r#"{ "dependencies": { "lodash": "^4.17.0" } }"#;
+        assert!(!hover(range, true).await.contains("⚠️"));
+        let private = r#"{ "dependencies": { "@acme/ui": "1.0.0" } }"#;
+        assert!(!hover(private, true).await.contains("⚠️"));
+    }
+
+    #[tokio::test]
+    async fn test_hover_readme_excerpt() {
+        let registry = file_registry("readme-registry", &[]);
+        let document = json!({
+            "dist-tags": { "latest": "4.17.21" },
+            "time": { "4.17.21": "2024-01-01T00:00:00.000Z" },
+            "versions": {
+                "4.17.21": { "version": "4.17.21", "description": "Lodash modular utilities." }
+            },
+            "readme": "# lodash\n\nA modern JavaScript utility library delivering modularity."
+        });
+        std::fs::write(registry.join("lodash"), document.to_string()).unwrap();
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        backend.apply_settings(
+            Settings::from_value(Some(json!({
+                "default_registry": format!("file://{}", registry.display()),
+                "show_readme_excerpt": true
+            })))
+            .unwrap(),
+        );
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend.update_document(
+            uri.clone(),
+            r#"{ "dependencies": { "lodash": "^4.17.0" } }"#.to_string(),
+        );
+        let contents = hover_markup(backend, &uri, Position::new(0, 23)).await;
+        // The excerpt goes between the description and the latest version, which stays on one line.
+        assert!(
+            contents.contains(
+                "Lodash modular utilities.\n\n\
+                 A modern JavaScript utility library delivering modularity.\n\n\
+                 Latest version: 4.17.21 (published "
+            ),
+            "{contents}"
+        );
+    }
+
+    #[test]
+    fn test_publisher_line() {
+        assert_eq!(
+            publisher_line(Some("alice"), Some(3)).as_deref(),
+            Some("Published by alice · 3 maintainers")
+        );
+        assert_eq!(
+            publisher_line(None, Some(1)).as_deref(),
+            Some("1 maintainer")
+        );
+        assert_eq!(
+            publisher_line(Some("alice"), None).as_deref(),
+            Some("Published by alice")
+        );
+        assert_eq!(publisher_line(None, Some(0)), None);
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let change = |range: Option<Range>, text: &str| TextDocumentContentChangeEvent {
+            range,
+            range_length: None,
+            text: text.to_string(),
+        };
+        let at = |line, character| Position::new(line, character);
+        let mut text = String::from("old");
+        apply_change(&mut text, change(None, "{\n  \"a\": \"1\"\n}"));
+        assert_eq!(text, "{\n  \"a\": \"1\"\n}");
+        apply_change(
+            &mut text,
+            change(Some(Range::new(at(1, 8), at(1, 9))), "2.0"),
+        );
+        assert_eq!(text, "{\n  \"a\": \"2.0\"\n}");
+        apply_change(&mut text, change(Some(Range::new(at(0, 1), at(1, 2))), ""));
+        assert_eq!(text, "{\"a\": \"2.0\"\n}");
+
+        // Characters are counted in UTF-16 code units, so 😀 takes up two.
+        let mut text = String::from("\"😀é\": 1");
+        apply_change(&mut text, change(Some(Range::new(at(0, 3), at(0, 4))), "e"));
+        assert_eq!(text, "\"😀e\": 1");
+        apply_change(
+            &mut text,
+            change(Some(Range::new(at(5, 0), at(5, 0))), "\n"),
+        );
+        assert_eq!(text, "\"😀e\": 1\n");
+    }
+
+    #[test]
+    fn test_resolution_line() {
+        let versions = ["4.17.1", "4.17.21", "4.18.2", "5.0.0-rc.1"]
+            .map(fetcher::tests::package_version)
+            .to_vec();
+        let metadata =
+            MetadataFromRegistry::for_tests(fetcher::tests::package_version("4.18.2"), versions);
+        let line = |declared| resolution_line(declared, &metadata);
+        assert_eq!(
+            line("4.17.1").as_deref(),
+            Some("Pinned to 4.17.1 (latest 4.18.2)")
+        );
+        assert_eq!(
+            line("=4.18.2").as_deref(),
+            Some("Pinned to 4.18.2 (latest)")
+        );
+        assert_eq!(
+            line("^4.17.1").as_deref(),
+            Some("Caret range `^4.17.1` resolves to 4.18.2")
+        );
+        assert_eq!(
+            line("~4.17.1").as_deref(),
+            Some("Tilde range `~4.17.1` resolves to 4.17.21")
+        );
+        assert_eq!(
+            line(">=4 <4.18").as_deref(),
+            Some("Range `>=4 <4.18` resolves to 4.17.21")
+        );
+        assert_eq!(line("^3.0.0"), None);
+        assert_eq!(line("latest"), None);
+    }
+
+    #[test]
+    fn test_integrity_lines() {
+        let mut latest = fetcher::tests::package_version("4.21.2");
+        assert_eq!(integrity_line(&latest), None);
+        latest.shasum = Some("8b3f".into());
+        assert_eq!(integrity_line(&latest).as_deref(), Some("Shasum: `8b3f`"));
+        latest.integrity = Some("sha512-AAAA".into());
+        assert_eq!(
+            integrity_line(&latest).as_deref(),
+            Some("Integrity: `sha512-AAAA`")
+        );
+
+        let metadata = MetadataFromRegistry::for_tests(latest, Vec::new());
+        let installed = |integrity: &str| lockfile::InstalledPackage {
+            version: "4.21.2".into(),
+            integrity: Some(integrity.into()),
+        };
+        assert_eq!(
+            integrity_mismatch_line(&installed("sha512-BBBB"), &metadata).as_deref(),
+            Some(
+                "⚠️ Integrity mismatch: package-lock.json records `sha512-BBBB` for 4.21.2, but \
+                 the registry serves `sha512-AAAA`"
+            )
+        );
+        assert_eq!(
+            integrity_mismatch_line(&installed("sha512-AAAA"), &metadata),
+            None
+        );
+        // Different algorithms can't be compared.
+        assert_eq!(
+            integrity_mismatch_line(&installed("sha1-CCCC"), &metadata),
+            None
+        );
+    }
+
+    #[test]
+    fn test_funding_line() {
+        assert_eq!(funding_line(&[]), None);
+        let funding: Vec<_> = [
+            "https://github.com/sponsors/alice",
+            "https://opencollective.com/example",
+        ]
+        .map(String::from)
+        .into();
+        assert_eq!(
+            funding_line(&funding).as_deref(),
+            Some(
+                "💖 Funding: [github.com/sponsors/alice](https://github.com/sponsors/alice), \
+                 [opencollective.com/example](https://opencollective.com/example)"
+            )
+        );
+    }
+
+    #[test]
+    fn test_links_line() {
+        let mut latest = fetcher::tests::package_version("4.17.21");
+        assert_eq!(links_line(&latest), None);
+        latest.repository = Some("git+https://github.com/lodash/lodash.git".into());
+        assert_eq!(
+            links_line(&latest).as_deref(),
+            Some("[Repository](https://github.com/lodash/lodash)")
+        );
+        latest.homepage = Some("https://lodash.com/".into());
+        assert_eq!(
+            links_line(&latest).as_deref(),
+            Some(
+                "[Homepage](https://lodash.com/) · [Repository](https://github.com/lodash/lodash)"
+            )
+        );
+        latest.homepage = Some("https://github.com/lodash/lodash".into());
+        assert_eq!(
+            links_line(&latest).as_deref(),
+            Some("[Homepage](https://github.com/lodash/lodash)")
+        );
+    }
+
+    #[test]
+    fn test_readme_excerpt() {
+        let readme = "# lodash\n\n[![npm](https://img.shields.io/npm/v/lodash.svg)](https://npm.im/lodash)\n\n```js\nconst _ = require('lodash');\n\nconsole.log(_);\n```\n\nThe modern build of lodash\nas modules. Use it anywhere.\n\n## Installation";
+        assert_eq!(
+            readme_excerpt(readme).as_deref(),
+            Some("The modern build of lodash as modules. Use it anywhere.")
+        );
+        assert_eq!(readme_excerpt("# lodash\n\n<p>logo</p>"), None);
+
+        let sentence = "Lodash makes JavaScript easier. ";
+        let long = sentence.repeat(20);
+        let excerpt = readme_excerpt(&long).unwrap();
+        assert!(
+            excerpt.len() <= 500 && excerpt.ends_with("easier."),
+            "{excerpt}"
+        );
+        let excerpt = readme_excerpt(&"word ".repeat(200)).unwrap();
+        assert!(excerpt.ends_with("word…"), "{excerpt}");
+    }
+
+    #[test]
+    fn test_keywords_line() {
+        assert_eq!(keywords_line(&[]), None);
+        let keywords: Vec<_> = ["http", "client", "fetch"].map(String::from).into();
+        assert_eq!(
+            keywords_line(&keywords).as_deref(),
+            Some("Keywords: http, client, fetch")
+        );
+        let keywords: Vec<_> = (1..=10).map(|ix| format!("k{ix}")).collect();
+        assert_eq!(
+            keywords_line(&keywords).as_deref(),
+            Some("Keywords: k1, k2, k3, k4, k5, k6, k7, k8 and 2 more")
+        );
+    }
+
+    #[test]
+    fn test_update_summary() {
+        assert_eq!(update_summary(1, 0, 0), "Updated 1 dependency");
+        assert_eq!(
+            update_summary(3, 2, 1),
+            "Updated 3 dependencies, 2 already up to date, 1 package could not be fetched"
+        );
+    }
+
+    #[test]
+    fn test_major_updates_summary() {
+        assert_eq!(major_updates_summary(&[]), None);
+        assert_eq!(
+            major_updates_summary(&["react"]).as_deref(),
+            Some("react has a newer major version available")
+        );
+        assert_eq!(
+            major_updates_summary(&["react", "eslint"]).as_deref(),
+            Some("2 dependencies have newer major versions available: react, eslint")
+        );
+    }
+
+    #[test]
+    fn test_is_up_to_date() {
+        let latest = semver_rs::Version::new("4.18.2").parse().unwrap();
+        assert!(is_up_to_date("^4", &latest));
+        assert!(is_up_to_date("4.18.2", &latest));
+        assert!(!is_up_to_date("~4.17.0", &latest));
+        assert!(!is_up_to_date("latest", &latest));
+    }
+
+    #[tokio::test]
+    async fn test_parser_is_reused_across_edits() {
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        // Counts the parses of the backend's own parser, which would miss those of any other.
+        let parses = Arc::new(AtomicUsize::new(0));
+        backend.parser.lock().unwrap().set_logger(Some(Box::new({
+            let parses = parses.clone();
+            move |_, message| {
+                if message == "new_parse" {
+                    parses.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        })));
+
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "json".into(),
+                    version: 0,
+                    text: String::from("{}"),
+                },
+            })
+            .await;
+        for version in 1..100 {
+            backend
+                .did_change(DidChangeTextDocumentParams {
+                    text_document: VersionedTextDocumentIdentifier {
+                        uri: uri.clone(),
+                        version,
+                    },
+                    content_changes: vec![TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: format!(r#"{{"dependencies": {{"lodash": "^4.{version}"}}}}"#),
+                    }],
+                })
+                .await;
+        }
+
+        assert_eq!(parses.load(Ordering::Relaxed), 100);
+        let (contents, tree) = backend.file_contents.lock().unwrap()[&uri].clone();
+        assert_eq!(&*contents, r#"{"dependencies": {"lodash": "^4.99"}}"#);
+        assert!(!tree.root_node().has_error());
+    }
+
+    #[tokio::test]
+    async fn test_other_manifests_are_stored() {
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = Url::parse("file:///project/Gemfile").unwrap();
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "ruby".into(),
+                    version: 0,
+                    text: String::from("gem \"rails\", \"~> 7.1\"\n"),
+                },
+            })
+            .await;
+        let (contents, tree) = backend.file_contents.lock().unwrap()[&uri].clone();
+        assert_eq!(&*contents, "gem \"rails\", \"~> 7.1\"\n");
+        // It isn't parsed as JSON.
+        assert_eq!(tree.root_node().child_count(), 0);
+    }
+
+    /// A backend along with the socket its requests and notifications to the client go to, which
+    /// tests that don't look at them drop.
+    fn start_backend() -> (LspService<Backend>, ClientSocket) {
+        LspService::new(|client| Backend::new(client).unwrap())
+    }
+
+    /// Hovers `position`, returning the hover's markup.
+    async fn hover_markup(backend: &Backend, uri: &Url, position: Position) -> String {
+        let hover = backend
+            .hover(hover_params(uri, position))
+            .await
+            .unwrap()
+            .unwrap();
+        let HoverContents::Markup(contents) = hover.contents else {
+            panic!("expected markup, got {:?}", hover.contents);
+        };
+        contents.value
+    }
+
+    fn hover_params(uri: &Url, position: Position) -> HoverParams {
+        HoverParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position,
+            },
+            work_done_progress_params: Default::default(),
+        }
+    }
+
+    /// A `file://` registry holding the given packages, each with a single latest version.
+    fn file_registry(name: &str, packages: &[(&str, &str)]) -> TempDir {
+        let registry = TempDir::new(name);
+        for (package, latest) in packages {
+            publish(&registry, package, &[latest]);
+        }
+        registry
+    }
+
+    /// Writes the document of a package that published `versions`, the last one being the latest.
+    fn publish(registry: &Path, package: &str, versions: &[&str]) {
+        let latest = versions.last().unwrap();
+        let document = json!({
+            "dist-tags": { "latest": latest },
+            "time": versions
+                .iter()
+                .map(|version| (version.to_string(), json!("2024-01-01T00:00:00.000Z")))
+                .collect::<serde_json::Map<_, _>>(),
+            "versions": versions
+                .iter()
+                .map(|version| (version.to_string(), json!({ "version": version, "description": "" })))
+                .collect::<serde_json::Map<_, _>>()
+        });
+        let path = registry.join(package);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, document.to_string()).unwrap();
+    }
+
+    async fn open(backend: &Backend, registry: &Path, text: &str) -> Url {
+        open_at(backend, &format!("file://{}", registry.display()), text).await
+    }
+
+    /// Opens `text` as a manifest whose packages are fetched from the registry at `registry_url`,
+    /// like the one of a [`mock_registry::MockRegistry`].
+    async fn open_at(backend: &Backend, registry_url: &str, text: &str) -> Url {
+        backend.apply_settings(
+            Settings::from_value(Some(json!({ "default_registry": registry_url }))).unwrap(),
+        );
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend.update_document(uri.clone(), text.to_string());
+        uri
+    }
+
+    #[tokio::test]
+    async fn test_section_code_lenses() {
+        let registry = file_registry(
+            "code-lens-registry",
+            &[
+                ("express", "5.0.1"),
+                ("lodash", "4.17.21"),
+                ("typescript", "5.6.3"),
+            ],
+        );
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let text = r#"{
+  "dependencies": {
+    "express": "^4.21.0",
+    "lodash": "^4.17.0",
+    "left-pad": "workspace:*"
+  },
+  "devDependencies": {
+    "typescript": "~5.6.2"
+  }
+}"#;
+        let uri = open(backend, &registry, text).await;
+
+        let lenses = backend
+            .code_lens(CodeLensParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(lenses.iter().all(|lens| lens.command.is_none()));
+        assert_eq!(lenses[0].range.start, Position::new(1, 3));
+        let mut resolved = Vec::new();
+        for lens in lenses {
+            resolved.push(backend.code_lens_resolve(lens).await.unwrap());
+        }
+        let titles: Vec<_> = resolved
+            .iter()
+            .map(|lens| lens.command.as_ref().unwrap().title.as_str())
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                "1 of 3 dependencies outdated — Update all",
+                "1 dependency up to date"
+            ]
+        );
+
+        let command = resolved[0].command.clone().unwrap();
+        assert_eq!(command.command, commands::UPDATE_ALL);
+        let arguments: commands::UpdateAllArguments =
+            serde_json::from_value(command.arguments.unwrap()[0].clone()).unwrap();
+        assert_eq!(arguments.uri, uri);
+        assert_eq!(arguments.section.as_deref(), Some("dependencies"));
+        assert!(resolved
+            .iter()
+            .all(|lens| lens.command.as_ref().unwrap().command == commands::UPDATE_ALL));
+
+        // Outdated dependencies are counted from the analysis even when they aren't reported.
+        backend.apply_settings(
+            Settings::from_value(Some(json!({
+                "default_registry": format!("file://{}", registry.display()),
+                "diagnostics": { "outdated": "off" }
+            })))
+            .unwrap(),
+        );
+        let lens = backend
+            .code_lens_resolve(CodeLens {
+                command: None,
+                ..resolved[0].clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            lens.command.unwrap().title,
+            "1 of 3 dependencies outdated — Update all"
+        );
+        let (text, _) = backend.file_contents.lock().unwrap()[&uri].clone();
+        let analyzed = backend.analyzed_documents.lock().unwrap()[&uri].clone();
+        assert!(Arc::ptr_eq(&analyzed.text, &text));
+        assert!(analyzed.diagnostics.iter().all(|diagnostic| {
+            diagnostic.code != Some(NumberOrString::String(diagnostics::OUTDATED.into()))
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_configured_diagnostic_severities() {
+        let registry = file_registry("severity-registry", &[]);
+        publish(&registry, "express", &["4.21.2", "5.0.1"]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = open(
+            backend,
+            &registry,
+            r#"{ "dependencies": { "express": "^4.21.0", "lodash": "^4..1" } }"#,
+        )
+        .await;
+        let severities = || async {
+            let (text, tree) = backend.file_contents.lock().unwrap()[&uri].clone();
+            let analyzed = backend.analyze(&uri, &text, &tree).await.unwrap();
+            analyzed
+                .diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.code, diagnostic.severity))
+                .collect::<Vec<_>>()
+        };
+        let code = |code: &str| Some(NumberOrString::String(code.into()));
+        assert_eq!(
+            severities().await,
+            [
+                (
+                    code(diagnostics::INVALID_RANGE),
+                    Some(DiagnosticSeverity::WARNING)
+                ),
+                (
+                    code(diagnostics::OUTDATED),
+                    Some(DiagnosticSeverity::INFORMATION)
+                ),
+                (
+                    code(diagnostics::NOT_FOUND),
+                    Some(DiagnosticSeverity::ERROR)
+                ),
+            ]
+        );
+
+        backend.apply_settings(
+            Settings::from_value(Some(json!({
+                "default_registry": format!("file://{}", registry.display()),
+                "diagnostics": { "outdated": "off", "invalid_range": "hint" }
+            })))
+            .unwrap(),
+        );
+        assert_eq!(
+            severities().await,
+            [
+                (
+                    code(diagnostics::INVALID_RANGE),
+                    Some(DiagnosticSeverity::HINT)
+                ),
+                (
+                    code(diagnostics::NOT_FOUND),
+                    Some(DiagnosticSeverity::ERROR)
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_analysis_reports_fetched_packages() {
+        let registry = file_registry(
+            "progress-registry",
+            &[("express", "5.0.1"), ("lodash", "4.17.21")],
+        );
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = open(
+            backend,
+            &registry,
+            r#"{ "dependencies": { "express": "^4", "lodash": "^4", "ui": "workspace:*" } }"#,
+        )
+        .await;
+        let (text, tree) = backend.file_contents.lock().unwrap()[&uri].clone();
+        let fetched = Mutex::new(Vec::new());
+        let analyzed = backend
+            .analyze_reporting(&uri, &text, &tree, &|done, total| {
+                fetched.lock().unwrap().push((done, total));
+            })
+            .await;
+        assert!(analyzed.is_some());
+        assert_eq!(fetched.into_inner().unwrap(), [(1, 2), (2, 2)]);
+
+        // Without client support, documents are analyzed all the same.
+        backend.analyzed_documents.lock().unwrap().clear();
+        assert!(backend
+            .analyze_with_progress(&uri, &text, &tree)
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_on_save() {
+        let registry = file_registry("run-on-save-registry", &[]);
+        publish(&registry, "lodash", &["3.10.1", "4.17.21"]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        backend.pull_diagnostics.store(true, Ordering::Relaxed);
+        let settings = |run_on| {
+            json!({
+                "default_registry": format!("file://{}", registry.display()),
+                "diagnostics": { "run_on": run_on }
+            })
+        };
+        backend.apply_settings(Settings::from_value(Some(settings("save"))).unwrap());
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "json".into(),
+                    version: 0,
+                    text: r#"{ "dependencies": { "lodash": "^3.0.0" } }"#.into(),
+                },
+            })
+            .await;
+        let codes = || async {
+            let report = backend
+                .diagnostic(DocumentDiagnosticParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    identifier: None,
+                    previous_result_id: None,
+                    work_done_progress_params: Default::default(),
+                    partial_result_params: Default::default(),
+                })
+                .await
+                .unwrap();
+            let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) =
+                report
+            else {
+                panic!("expected a full report, got {report:?}");
+            };
+            report
+                .full_document_diagnostic_report
+                .items
+                .into_iter()
+                .map(|diagnostic| diagnostic.code)
+                .collect::<Vec<_>>()
+        };
+        let outdated = [Some(NumberOrString::String(diagnostics::OUTDATED.into()))];
+        assert_eq!(codes().await, outdated);
+
+        backend
+            .did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: 1,
+                },
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: r#"{ "dependencies": { "lodash": "^4.17.21" } }"#.into(),
+                }],
+            })
+            .await;
+        // The tree is kept up to date for hover and completion all the same.
+        let (contents, _) = backend.file_contents.lock().unwrap()[&uri].clone();
+        assert!(contents.contains("^4.17.21"));
+        assert_eq!(codes().await, outdated);
+
+        backend
+            .did_save(DidSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                text: None,
+            })
+            .await;
+        assert_eq!(codes().await, []);
+
+        // Switching back to analyzing on change takes effect right away.
+        backend
+            .did_change(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: 2,
+                },
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: r#"{ "dependencies": { "lodash": "^3.0.0" } }"#.into(),
+                }],
+            })
+            .await;
+        assert_eq!(codes().await, []);
+        backend
+            .did_change_configuration(DidChangeConfigurationParams {
+                settings: settings("change"),
+            })
+            .await;
+        assert_eq!(codes().await, outdated);
+    }
+
+    #[tokio::test]
+    async fn test_registry_change_recomputes_diagnostics() {
+        let express = json!({
+            "dist-tags": { "latest": "4.21.0" },
+            "versions": { "4.21.0": { "version": "4.21.0" } }
+        });
+        let (registry, private_registry) = (
+            mock_registry::MockRegistry::start(vec![(
+                "/express",
+                mock_registry::MockResponse::json(&express),
+            )])
+            .await,
+            mock_registry::MockRegistry::start(Vec::new()).await,
+        );
+        let (mut service, mut socket) = start_backend();
+        // The client only gets notifications once the server is initialized.
+        for request in [
+            Request::build("initialize")
+                .params(json!({
+                    "capabilities": {},
+                    "initializationOptions": {
+                        "default_registry": registry.url
+                    }
+                }))
+                .id(1)
+                .finish(),
+            Request::build("initialized").params(json!({})).finish(),
+        ] {
+            service.ready().await.unwrap().call(request).await.unwrap();
+        }
+        let backend = service.inner().clone();
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend
+            .did_open(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "json".into(),
+                    version: 0,
+                    text: r#"{ "dependencies": { "express": "^4.21.0" } }"#.into(),
+                },
+            })
+            .await;
+        async fn published_codes(socket: &mut ClientSocket) -> Vec<Option<NumberOrString>> {
+            loop {
+                let request = socket.next().await.unwrap();
+                if request.method() != "textDocument/publishDiagnostics" {
+                    continue;
+                }
+                let params: PublishDiagnosticsParams =
+                    serde_json::from_value(request.params().unwrap().clone()).unwrap();
+                break params
+                    .diagnostics
+                    .into_iter()
+                    .map(|diagnostic| diagnostic.code)
+                    .collect();
+            }
+        }
+        assert_eq!(published_codes(&mut socket).await, []);
+
+        backend
+            .did_change_configuration(DidChangeConfigurationParams {
+                settings: json!({
+                    "defaultRegistry": private_registry.url
+                }),
+            })
+            .await;
+        assert_eq!(
+            published_codes(&mut socket).await,
+            [Some(NumberOrString::String(diagnostics::NOT_FOUND.into()))]
+        );
+        assert_eq!(private_registry.request_count("/express"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_per_dependency_code_lenses() {
+        let registry = file_registry(
+            "dependency-lens-registry",
+            &[("express", "5.0.1"), ("lodash", "4.17.21")],
+        );
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let text = r#"{
+  "dependencies": {
+    "express": "^4.21.0",
+    "lodash": "^4.17.0",
+    "unpublished": "^1.0.0",
+    "left-pad": "workspace:*"
+  }
+}"#;
+        let uri = open(backend, &registry, text).await;
+        let params = || CodeLensParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        assert_eq!(backend.code_lens(params()).await.unwrap().unwrap().len(), 1);
+
+        backend.apply_settings(
+            Settings::from_value(Some(json!({
+                "default_registry": format!("file://{}", registry.display()),
+                "code_lens": { "per_dependency": true }
+            })))
+            .unwrap(),
+        );
+        let lenses = backend.code_lens(params()).await.unwrap().unwrap();
+        let lines: Vec<_> = lenses.iter().map(|lens| lens.range.start.line).collect();
+        assert_eq!(lines, [1, 2, 3, 4]);
+        let mut resolved = Vec::new();
+        for lens in lenses.into_iter().skip(1) {
+            resolved.push(
+                backend
+                    .code_lens_resolve(lens)
+                    .await
+                    .unwrap()
+                    .command
+                    .unwrap(),
+            );
+        }
+        assert_eq!(resolved[0].title, "latest: 5.0.1");
+        assert_eq!(resolved[0].command, commands::UPDATE_DEPENDENCY);
+        let arguments: commands::UpdateDependencyArguments =
+            serde_json::from_value(resolved[0].arguments.clone().unwrap()[0].clone()).unwrap();
+        assert_eq!(
+            (arguments.section.as_str(), arguments.name.as_str()),
+            ("dependencies", "express")
+        );
+        assert_eq!(resolved[1].title, "✓ up to date");
+        assert_eq!(resolved[1].command, commands::OPEN_PACKAGE_PAGE);
+        let arguments: commands::OpenPackagePageArguments =
+            serde_json::from_value(resolved[1].arguments.clone().unwrap()[0].clone()).unwrap();
+        assert_eq!(arguments.name, "lodash");
+        assert_eq!(resolved[2].title, "Couldn't check for updates");
+        assert_eq!(resolved[2].command, commands::UPDATE_DEPENDENCY);
+    }
+
+    /// Caches the versions of a package as if they were fetched from the public registry, the last
+    /// one being the latest.
+    async fn publish_publicly(backend: &Backend, package_name: &str, versions: &[&str]) {
+        let versions: Vec<_> = versions
+            .iter()
+            .map(|version| fetcher::tests::package_version(version))
+            .collect();
+        let latest = versions.last().unwrap().clone();
+        backend
+            .fetcher
+            .insert_for_tests(
+                package_name,
+                MetadataFromRegistry::for_tests(latest, versions),
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_vulnerability_diagnostics() {
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        publish_publicly(backend, "lodash", &["4.17.20", "4.17.21"]).await;
+        publish_publicly(backend, "@acme/ui", &["1.0.0"]).await;
+        let advisory = || {
+            vec![osv::Advisory {
+                id: "GHSA-35jh-r3h4-6jhm".into(),
+                severity: Some(osv::Severity::High),
+            }]
+        };
+        backend
+            .advisories
+            .insert_for_tests(("lodash".into(), "4.17.20".into()), advisory());
+        backend
+            .advisories
+            .insert_for_tests(("@acme/ui".into(), "1.0.0".into()), advisory());
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend.update_document(
+            uri.clone(),
+            r#"{ "dependencies": { "lodash": "4.17.20", "@acme/ui": "1.0.0" } }"#.to_string(),
+        );
+        let (text, tree) = backend.document(&uri).await.unwrap();
+        let vulnerable = |security: bool| {
+            // Packages of a private registry are never sent to OSV, whatever is cached for them.
+            backend.apply_settings(
+                Settings::from_value(Some(json!({
+                    "scope_registries": { "@acme": "https://npm.acme.dev" },
+                    "diagnostics": { "security": security }
+                })))
+                .unwrap(),
+            );
+            backend.analyzed_documents.lock().unwrap().clear();
+            let (uri, text, tree) = (&uri, &text, &tree);
+            async move {
+                backend.analyze(uri, text, tree).await.unwrap();
+                let analyzed = backend.check_advisories(uri, text, tree).await;
+                analyzed
+                    .map(|analyzed| analyzed.diagnostics)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|diagnostic| {
+                        diagnostic.code
+                            == Some(NumberOrString::String(diagnostics::VULNERABLE.into()))
+                    })
+                    .map(|diagnostic| diagnostic.range.start)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(vulnerable(true).await, [Position::new(0, 21)]);
+        assert_eq!(vulnerable(false).await, []);
+    }
+
+    #[tokio::test]
+    async fn test_security_code_lenses() {
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        publish_publicly(backend, "typescript", &["5.6.3"]).await;
+        publish_publicly(backend, "lodash", &["4.17.20", "4.17.21"]).await;
+        publish_publicly(backend, "minimist", &["1.2.5", "1.2.8"]).await;
+        let text = r#"{
+  "dependencies": {
+    "lodash": "4.17.20",
+    "minimist": "1.2.5"
+  },
+  "devDependencies": {
+    "typescript": "~5.6.2"
+  }
+}"#;
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend.update_document(uri.clone(), text.to_string());
+        let advisory = |id: &str| osv::Advisory {
+            id: id.into(),
+            severity: None,
+        };
+        backend.advisories.insert_for_tests(
+            ("lodash".into(), "4.17.20".into()),
+            vec![advisory("GHSA-35jh-r3h4-6jhm")],
+        );
+        backend.advisories.insert_for_tests(
+            ("minimist".into(), "1.2.5".into()),
+            vec![advisory("GHSA-xvch-5gv4-984h")],
+        );
+        backend
+            .advisories
+            .insert_for_tests(("typescript".into(), "5.6.3".into()), Vec::new());
+        backend.apply_settings(
+            Settings::from_value(Some(json!({ "code_lens": { "security": true } }))).unwrap(),
+        );
+
+        let lenses = backend
+            .code_lens(CodeLensParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(lenses.len(), 4);
+        let mut resolved = Vec::new();
+        for lens in lenses {
+            resolved.push(backend.code_lens_resolve(lens).await.unwrap());
+        }
+        let titles: Vec<_> = resolved
+            .iter()
+            .map(|lens| lens.command.as_ref().unwrap().title.as_str())
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                "2 of 2 dependencies outdated — Update all",
+                "2 dependencies with known vulnerabilities",
+                "1 dependency up to date",
+                "No known vulnerabilities"
+            ]
+        );
+        let command = resolved[1].command.clone().unwrap();
+        assert_eq!(command.command, commands::SHOW_VULNERABILITIES);
+        let arguments: commands::ShowVulnerabilitiesArguments =
+            serde_json::from_value(command.arguments.unwrap()[0].clone()).unwrap();
+        assert_eq!(
+            (arguments.uri, arguments.section.as_str()),
+            (uri.clone(), "dependencies")
+        );
+        assert_eq!(
+            resolved[3].command.as_ref().unwrap().command,
+            commands::SHOW_VULNERABILITIES
+        );
+
+        backend.apply_settings(
+            Settings::from_value(Some(json!({
+                "code_lens": { "security": true },
+                "diagnostics": { "security": false }
+            })))
+            .unwrap(),
+        );
+        let lenses = backend
+            .code_lens(CodeLensParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(lenses.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_execute_command() {
+        let registry = file_registry("clear-cache-registry", &[("express", "4.21.0")]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        open(
+            backend,
+            &registry,
+            r#"{ "dependencies": { "express": "^4.0.0" } }"#,
+        )
+        .await;
+        let execute = |command: &str, arguments: Vec<Value>| {
+            backend.execute_command(ExecuteCommandParams {
+                command: command.into(),
+                arguments,
+                work_done_progress_params: Default::default(),
+            })
+        };
+        let latest = || async {
+            let options = FetchOptions {
+                parse_all_versions: false,
+                full_metadata: false,
+                readme: false,
+            };
+            let package = backend.fetcher.get("express", options).await.unwrap();
+            package.latest_version.version.to_string()
+        };
+        assert_eq!(latest().await, "4.21.0");
+
+        publish(&registry, "express", &["5.0.1"]);
+        assert_eq!(latest().await, "4.21.0");
+        assert_eq!(execute(commands::CLEAR_CACHE, Vec::new()).await, Ok(None));
+        assert_eq!(latest().await, "5.0.1");
+
+        let error = execute(commands::UPDATE_ALL, vec![json!(42)])
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, tower_lsp::jsonrpc::ErrorCode::InvalidParams);
+        let page = json!({ "name": "express" });
+        assert_eq!(
+            execute(commands::OPEN_PACKAGE_PAGE, vec![page]).await,
+            Ok(None)
+        );
+        let error = execute(commands::OPEN_PACKAGE_PAGE, Vec::new())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, tower_lsp::jsonrpc::ErrorCode::InvalidParams);
+        let error = execute("packageVersionServer.unknown", Vec::new())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, tower_lsp::jsonrpc::ErrorCode::InvalidParams);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_background_work() {
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let finished = Arc::new(AtomicBool::new(false));
+        let task = |finished: Arc<AtomicBool>| async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            finished.store(true, Ordering::Relaxed);
+        };
+        backend.spawn(task(finished.clone()));
+        backend.shutdown().await.unwrap();
+        backend.spawn(task(finished.clone()));
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(!finished.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_stale_completions_are_cancelled() {
+        // A registry that accepts connections but never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (service, _) = start_backend();
+        let backend = service.inner().clone();
+        backend.apply_settings(
+            Settings::from_value(Some(json!({
+                "default_registry": format!("http://{}", listener.local_addr().unwrap())
+            })))
+            .unwrap(),
+        );
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend.update_document(
+            uri.clone(),
+            r#"{ "dependencies": { "lodash": "^4" } }"#.to_string(),
+        );
+        let params = |line, character| CompletionParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position::new(line, character),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+
+        let stale = tokio::spawn({
+            let (backend, params) = (backend.clone(), params(0, 33));
+            async move { backend.completion(params).await }
+        });
+        let (mut connection, _) = listener.accept().await.unwrap();
+        assert_eq!(backend.completion(params(0, 1)).await, Ok(None));
+        let error = stale.await.unwrap().unwrap_err();
+        assert_eq!(error.code, tower_lsp::jsonrpc::ErrorCode::ContentModified);
+        assert!(backend.pending_completions.lock().unwrap().is_empty());
+
+        // The superseded request to the registry is given up on rather than left waiting.
+        let mut request = Vec::new();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            tokio::io::AsyncReadExt::read_to_end(&mut connection, &mut request),
+        )
+        .await
+        .expect("the registry connection should be closed")
+        .ok();
+    }
+
+    #[tokio::test]
+    async fn test_semantic_tokens() {
+        let registry = file_registry("semantic-tokens-registry", &[]);
+        publish(&registry, "express", &["4.21.2", "5.0.1"]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = open(
+            backend,
+            &registry,
+            r#"{ "dependencies": { "express": "^4.21.0" } }"#,
+        )
+        .await;
+        let tokens = || async {
+            let params = SemanticTokensParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            };
+            match backend.semantic_tokens_full(params).await.unwrap().unwrap() {
+                SemanticTokensResult::Tokens(tokens) => tokens.data,
+                SemanticTokensResult::Partial(_) => unreachable!(),
+            }
+        };
+        assert!(tokens().await.is_empty());
+
+        let (text, tree) = backend.file_contents.lock().unwrap()[&uri].clone();
+        backend.analyze(&uri, &text, &tree).await.unwrap();
+        let tokens = tokens().await;
+        assert_eq!(tokens.len(), 1);
+        assert_eq!((tokens[0].delta_start, tokens[0].length), (32, 7));
+    }
+
+    #[tokio::test]
+    async fn test_inlay_hints() {
+        let registry = file_registry(
+            "inlay-hint-registry",
+            &[("express", "5.0.1"), ("lodash", "4.17.21")],
+        );
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let text = r#"{
+  "dependencies": {
+    "express": "^4.21.0",
+    "lodash": "^4.17.0"
+  }
+}"#;
+        let uri = open(backend, &registry, text).await;
+        let hints = |range| {
+            backend.inlay_hint(InlayHintParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range,
+                work_done_progress_params: Default::default(),
+            })
+        };
+        let labels = |hints: Vec<InlayHint>| -> Vec<_> {
+            hints
+                .into_iter()
+                .map(|hint| match hint.label {
+                    InlayHintLabel::String(label) => (hint.position, label),
+                    InlayHintLabel::LabelParts(_) => panic!("expected a plain label"),
+                })
+                .collect()
+        };
+        let all = hints(Range::new(Position::new(0, 0), Position::new(5, 0)))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            &all[0].tooltip,
+            Some(InlayHintTooltip::String(tooltip))
+                if tooltip.starts_with("5.0.1 published on 2024-01-01")
+        ));
+        assert_eq!(
+            labels(all),
+            [(Position::new(2, 24), " ⟶ 5.0.1".to_string())]
+        );
+        let lodash_only = Range::new(Position::new(3, 0), Position::new(3, 20));
+        assert!(hints(lodash_only).await.unwrap().unwrap().is_empty());
+
+        backend.apply_settings(
+            Settings::from_value(Some(json!({
+                "default_registry": format!("file://{}", registry.display()),
+                "inlay_hints": { "show_up_to_date": true }
+            })))
+            .unwrap(),
+        );
+        assert_eq!(
+            labels(hints(lodash_only).await.unwrap().unwrap()),
+            [(Position::new(3, 23), " ✓".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inlay_hints_reuse_analyzed_packages() {
+        let express = json!({
+            "dist-tags": { "latest": "5.0.1" },
+            "versions": { "5.0.1": { "version": "5.0.1" } }
+        });
+        let registry = mock_registry::MockRegistry::start(vec![(
+            "/express",
+            mock_registry::MockResponse::json(&express),
+        )])
+        .await;
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = open_at(
+            backend,
+            &registry.url,
+            r#"{ "dependencies": { "express": "^4.21.0" } }"#,
+        )
+        .await;
+        backend
+            .diagnostic(DocumentDiagnosticParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                identifier: None,
+                previous_result_id: None,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(registry.request_count("/express"), 1);
+        let hints = || {
+            backend.inlay_hint(InlayHintParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range: Range::new(Position::new(0, 0), Position::new(1, 0)),
+                work_done_progress_params: Default::default(),
+            })
+        };
+        for _ in 0..2 {
+            let hints = hints().await.unwrap().unwrap();
+            assert_eq!(hints.len(), 1);
+        }
+        assert_eq!(registry.request_count("/express"), 1);
+
+        backend.apply_settings(
+            Settings::from_value(Some(json!({
+                "default_registry": registry.url,
+                "features": { "diagnostics": false }
+            })))
+            .unwrap(),
+        );
+        assert!(hints().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_document_links() {
+        let registry = file_registry("document-link-registry", &[("lodash", "4.17.21")]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let text = r#"{
+  "dependencies": {
+    "lodash4": "npm:lodash@^4",
+    "@acme/ui": "^1.0.0",
+    "left-pad": "workspace:*"
+  }
+}"#;
+        let uri = open(backend, &registry, text).await;
+        let links = backend
+            .document_link(DocumentLinkParams {
+                text_document: TextDocumentIdentifier { uri },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let targets: Vec<_> = links
+            .iter()
+            .map(|link| link.target.as_ref().unwrap().as_str())
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                "https://www.npmjs.com/package/lodash",
+                "https://www.npmjs.com/package/@acme/ui"
+            ]
+        );
+        assert_eq!(
+            links[0].range,
+            Range::new(Position::new(2, 5), Position::new(2, 12))
+        );
+
+        // Without a homepage or repository, the resolved link stays on npmjs.com.
+        let resolved = backend
+            .document_link_resolve(links[0].clone())
+            .await
+            .unwrap();
+        assert_eq!(resolved.target, links[0].target);
+
+        // Scoped packages are fetched from the registry the document's project sets up.
+        let private = file_registry("document-link-private-registry", &[]);
+        let document = json!({
+            "dist-tags": { "latest": "1.0.0" },
+            "versions": {
+                "1.0.0": { "version": "1.0.0", "homepage": "https://ui.acme.dev" }
+            }
+        });
+        std::fs::create_dir_all(private.join("@acme")).unwrap();
+        std::fs::write(private.join("@acme/ui"), document.to_string()).unwrap();
+        backend.fetcher.configure_projects(HashMap::from([(
+            PathBuf::from("/project"),
+            fetcher::RegistryConfig {
+                scope_registries: HashMap::from([(
+                    "@acme".to_string(),
+                    vec![format!("file://{}", private.display())],
+                )]),
+                ..Default::default()
+            },
+        )]));
+        let resolved = backend
+            .document_link_resolve(links[1].clone())
+            .await
+            .unwrap();
+        assert_eq!(resolved.target.unwrap().as_str(), "https://ui.acme.dev/");
+    }
+
+    #[tokio::test]
+    async fn test_goto_installed_package() {
+        let project = TempDir::new("definition");
+        let installed = project.join("node_modules/lodash");
+        std::fs::create_dir_all(&installed).unwrap();
+        std::fs::write(
+            installed.join("package.json"),
+            "{\n  \"name\": \"lodash\",\n  \"version\": \"4.17.21\"\n}",
+        )
+        .unwrap();
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = Url::from_file_path(project.join("package.json")).unwrap();
+        backend.update_document(
+            uri.clone(),
+            r#"{ "dependencies": { "lodash": "^4", "react": "^18" } }"#.to_string(),
+        );
+        let definition = |character| {
+            backend.goto_definition(GotoDefinitionParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: Position::new(0, character),
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+        };
+        let Some(GotoDefinitionResponse::Scalar(location)) = definition(24).await.unwrap() else {
+            panic!("expected the installed manifest");
+        };
+        assert_eq!(
+            location.uri,
+            Url::from_file_path(installed.join("package.json")).unwrap()
+        );
+        assert_eq!(
+            location.range,
+            Range::new(Position::new(2, 13), Position::new(2, 22))
+        );
+        assert!(definition(41).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pull_diagnostics_report_unchanged_results() {
+        let registry = file_registry("pull-diagnostics-registry", &[]);
+        publish(&registry, "lodash", &["4.17.20", "4.17.21"]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = open(
+            backend,
+            &registry,
+            r#"{"dependencies": {"lodash": "4.17.20"}}"#,
+        )
+        .await;
+        let pull = |previous_result_id| {
+            backend.diagnostic(DocumentDiagnosticParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                identifier: None,
+                previous_result_id,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+        };
+
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) =
+            pull(None).await.unwrap()
+        else {
+            panic!("expected a full report");
+        };
+        let report = report.full_document_diagnostic_report;
+        assert_eq!(report.items.len(), 1);
+        assert_eq!(report.items[0].message, "lodash 4.17.20 → latest 4.17.21");
+
+        let result = pull(report.result_id.clone()).await.unwrap();
+        assert!(matches!(
+            result,
+            DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(_))
+        ));
+
+        backend.update_document(
+            uri.clone(),
+            r#"{"dependencies": {"lodash": "^4.17.20"}}"#.to_string(),
+        );
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) =
+            pull(report.result_id).await.unwrap()
+        else {
+            panic!("expected a full report after an edit");
+        };
+        assert!(report.full_document_diagnostic_report.items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_diagnostic_quick_fixes() {
+        let registry = file_registry("quick-fix-registry", &[]);
+        publish(
+            &registry,
+            "lodash",
+            &["3.10.0", "3.10.1", "3.11.0", "4.17.21"],
+        );
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = open(
+            backend,
+            &registry,
+            r#"{ "dependencies": { "lodash": "~3.10.0" } }"#,
+        )
+        .await;
+        let report = backend
+            .diagnostic(DocumentDiagnosticParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                identifier: None,
+                previous_result_id: None,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap();
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) = report
+        else {
+            panic!("expected a full report, got {report:?}");
+        };
+        let diagnostics = report.full_document_diagnostic_report.items;
+        let code_actions = || {
+            backend.code_action(CodeActionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range: diagnostics[0].range,
+                context: CodeActionContext {
+                    diagnostics: diagnostics.clone(),
+                    only: Some(vec![CodeActionKind::QUICKFIX]),
+                    ..Default::default()
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+        };
+        let titles = |actions: &[CodeActionOrCommand]| {
+            actions
+                .iter()
+                .map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) => action.title.clone(),
+                    CodeActionOrCommand::Command(command) => command.title.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let actions = code_actions().await.unwrap().unwrap();
+        assert_eq!(
+            titles(&actions),
+            [
+                "Update lodash to ~4.17.21 (new major version)",
+                "Update to newest 3.x (3.11.0)"
+            ]
+        );
+        let CodeActionOrCommand::CodeAction(fix) = &actions[0] else {
+            panic!("expected a code action, got {:?}", actions[0]);
+        };
+        assert_eq!(fix.diagnostics.as_deref(), Some(&diagnostics[..1]));
+        assert_eq!(fix.is_preferred, Some(false));
+        let edits = &fix.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(edits[0].new_text, "~4.17.21");
+
+        // Clients that resolve edits get them once the action is picked.
+        backend
+            .resolve_code_action_edits
+            .store(true, Ordering::Relaxed);
+        let actions = code_actions().await.unwrap().unwrap();
+        let CodeActionOrCommand::CodeAction(fix) = actions[0].clone() else {
+            panic!("expected a code action, got {:?}", actions[0]);
+        };
+        assert!(fix.edit.is_none());
+        let fix = backend.code_action_resolve(fix).await.unwrap();
+        let edits = &fix.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(edits[0].range, diagnostics[0].range);
+        assert_eq!(edits[0].new_text, "~4.17.21");
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_quick_fix() {
+        let registry = file_registry("duplicate-registry", &[("lodash", "4.17.21")]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = open(
+            backend,
+            &registry,
+            r#"{
+  "dependencies": {
+    "lodash": "^4.17.0"
+  },
+  "devDependencies": {
+    "lodash": "^4.17.21",
+    "vitest": "^2.1.0"
+  }
+}"#,
+        )
+        .await;
+        let report = backend
+            .diagnostic(DocumentDiagnosticParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                identifier: None,
+                previous_result_id: None,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap();
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) = report
+        else {
+            panic!("expected a full report, got {report:?}");
+        };
+        let duplicates: Vec<_> = report
+            .full_document_diagnostic_report
+            .items
+            .into_iter()
+            .filter(|diagnostic| {
+                diagnostic.code == Some(NumberOrString::String(diagnostics::DUPLICATE.into()))
+            })
+            .collect();
+        assert_eq!(duplicates.len(), 2);
+        let actions = backend
+            .code_action(CodeActionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range: Range::default(),
+                context: CodeActionContext {
+                    diagnostics: duplicates.clone(),
+                    only: Some(vec![CodeActionKind::QUICKFIX]),
+                    ..Default::default()
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let [CodeActionOrCommand::CodeAction(merge)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        assert_eq!(
+            merge.title,
+            "Keep lodash@^4.17.21 in dependencies, removing the one in devDependencies"
+        );
+        let edits = &merge.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits,
+            &[
+                TextEdit::new(
+                    Range::new(Position::new(5, 4), Position::new(6, 4)),
+                    String::new()
+                ),
+                TextEdit::new(
+                    Range::new(Position::new(2, 15), Position::new(2, 22)),
+                    "^4.17.21".into()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_code_actions() {
+        let registry = file_registry(
+            "code-action-registry",
+            &[("lodash", "4.17.21"), ("react", "18.3.1")],
+        );
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let text = r#"{
+  "dependencies": {
+    "lodash": "~4.17.0",
+    "left-pad": "file:../left-pad",
+    "react": "github:facebook/react#v18.3.1",
+    "underscore": "jashkenas/underscore"
+  }
+}"#;
+        let uri = open(backend, &registry, text).await;
+        // Nothing else fetches the versions updates are offered from, and hovering caches an
+        // entry without them.
+        backend.apply_settings(
+            Settings::from_value(Some(json!({
+                "default_registry": format!("file://{}", registry.display()),
+                "features": { "diagnostics": false }
+            })))
+            .unwrap(),
+        );
+        hover_markup(backend, &uri, Position::new(2, 6)).await;
+        let code_actions = |line, only: CodeActionKind| {
+            backend.code_action(CodeActionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range: Range::new(Position::new(line, 6), Position::new(line, 6)),
+                context: CodeActionContext {
+                    only: Some(vec![only]),
+                    ..Default::default()
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+        };
+
+        let actions = code_actions(2, CodeActionKind::QUICKFIX)
+            .await
+            .unwrap()
+            .unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        assert_eq!(action.title, "Update to newest 4.x (4.17.21)");
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(2, 15), Position::new(2, 22))
+        );
+        assert_eq!(edits[0].new_text, "~4.17.21");
+
+        let actions = code_actions(3, CodeActionKind::QUICKFIX);
+        assert!(actions.await.unwrap().unwrap().is_empty());
+
+        let actions = code_actions(4, CodeActionKind::QUICKFIX)
+            .await
+            .unwrap()
+            .unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        assert_eq!(action.title, "Replace with registry version ^18.3.1");
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(edits[0].new_text, "^18.3.1");
+        let actions = code_actions(5, CodeActionKind::QUICKFIX);
+        assert!(actions.await.unwrap().unwrap().is_empty());
+
+        let actions = code_actions(3, CodeActionKind::SOURCE)
+            .await
+            .unwrap()
+            .unwrap();
+        let [CodeActionOrCommand::CodeAction(action), CodeActionOrCommand::CodeAction(sort)] =
+            actions.as_slice()
+        else {
+            panic!("expected two source actions, got {actions:?}");
+        };
+        assert_eq!(sort.title, "Sort dependencies");
+        let edits = &sort.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert!(edits[0]
+            .new_text
+            .starts_with(r#""left-pad": "file:../left-pad","#));
+        assert_eq!(action.title, "Update all dependencies to latest");
+        let command = action.command.as_ref().unwrap();
+        assert_eq!(command.command, commands::UPDATE_ALL);
+        let arguments: commands::UpdateAllArguments =
+            serde_json::from_value(command.arguments.as_ref().unwrap()[0].clone()).unwrap();
+        assert_eq!(arguments.section, None);
+
+        // Opening the package's page is only offered when no kind is asked for.
+        let actions = backend
+            .code_action(CodeActionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range: Range::new(Position::new(2, 6), Position::new(2, 6)),
+                context: Default::default(),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let open_page = actions
+            .iter()
+            .find_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) if action.kind.is_none() => Some(action),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(open_page.title, "Open lodash's page");
+        let command = open_page.command.as_ref().unwrap();
+        assert_eq!(command.command, commands::OPEN_PACKAGE_PAGE);
+        assert!(open_page.edit.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_add_types_code_action() {
+        let registry = file_registry(
+            "types-code-action-registry",
+            &[
+                ("express", "4.21.1"),
+                ("@types/express", "5.0.0"),
+                ("left-pad", "1.3.0"),
+            ],
+        );
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let text = r#"{
+  "dependencies": {
+    "express": "^4.21.1",
+    "left-pad": "^1.3.0"
+  }
+}"#;
+        let uri = open(backend, &registry, text).await;
+        let code_actions = |line| {
+            backend.code_action(CodeActionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range: Range::new(Position::new(line, 6), Position::new(line, 6)),
+                context: CodeActionContext {
+                    only: Some(vec![CodeActionKind::QUICKFIX]),
+                    ..Default::default()
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+        };
+
+        let actions = code_actions(2).await.unwrap().unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        assert_eq!(action.title, "Add @types/express to devDependencies");
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(
+            edits[0].new_text,
+            ",\n  \"devDependencies\": {\n    \"@types/express\": \"^5.0.0\"\n  }"
+        );
+        assert_eq!(edits[0].range.start, Position::new(4, 3));
+        // No `@types/left-pad` on the registry.
+        assert!(code_actions(3).await.unwrap().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_deprecated_successor_code_action() {
+        let registry = file_registry("successor-registry", &[("uuid", "9.0.1")]);
+        for (package, deprecated) in [
+            ("node-uuid", "Use the `uuid` package instead."),
+            ("left-pad", "use String.prototype.padStart()"),
+        ] {
+            let document = json!({
+                "dist-tags": { "latest": "1.4.8" },
+                "versions": { "1.4.8": { "version": "1.4.8", "deprecated": deprecated } }
+            });
+            std::fs::write(registry.join(package), document.to_string()).unwrap();
+        }
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let text = r#"{
+  "dependencies": {
+    "node-uuid": "^1.4.8",
+    "left-pad": "^1.4.8"
+  }
+}"#;
+        let uri = open(backend, &registry, text).await;
+        let deprecation = Diagnostic {
+            range: Range::new(Position::new(2, 5), Position::new(2, 14)),
+            code: Some(NumberOrString::String(diagnostics::DEPRECATED.into())),
+            ..Default::default()
+        };
+        let code_actions = |line| {
+            backend.code_action(CodeActionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range: Range::new(Position::new(line, 6), Position::new(line, 6)),
+                context: CodeActionContext {
+                    diagnostics: vec![deprecation.clone()],
+                    only: Some(vec![CodeActionKind::QUICKFIX]),
+                    ..Default::default()
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+        };
+
+        let actions = code_actions(2).await.unwrap().unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single code action, got {actions:?}");
+        };
+        assert_eq!(action.title, "Replace with uuid@^9.0.1");
+        assert_eq!(action.diagnostics, Some(vec![deprecation.clone()]));
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        let new_texts: Vec<_> = edits.iter().map(|edit| edit.new_text.as_str()).collect();
+        assert_eq!(new_texts, ["uuid", "^9.0.1"]);
+        assert_eq!(edits[0].range, deprecation.range);
+        // The message names no package.
+        assert!(code_actions(3).await.unwrap().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_new_dependency_completion() {
+        let results = json!({
+            "objects": [
+                { "package": { "name": "lodash", "version": "4.17.21" } },
+                { "package": { "name": "lodash-es", "version": "4.17.21" } }
+            ]
+        });
+        let lodash = json!({
+            "dist-tags": { "latest": "4.18.0" },
+            "versions": { "4.18.0": { "version": "4.18.0" } }
+        });
+        let registry = mock_registry::MockRegistry::start(vec![
+            (
+                "/-/v1/search?text=lod&size=20",
+                mock_registry::MockResponse::json(&results),
+            ),
+            ("/lodash", mock_registry::MockResponse::json(&lodash)),
+        ])
+        .await;
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        backend.apply_settings(
+            Settings::from_value(Some(json!({ "default_registry": registry.url }))).unwrap(),
+        );
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend.update_document(
+            uri.clone(),
+            r#"{ "dependencies": { "lodash-es": "^4.17.0", "lod" } }"#.to_string(),
+        );
+        let complete = |character| {
+            backend.completion(CompletionParams {
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: Position::new(0, character),
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            })
+        };
+        let Ok(Some(CompletionResponse::List(completions))) = complete(48).await else {
+            panic!("expected completions");
+        };
+        // `lodash-es` is already a dependency.
+        assert!(completions.is_incomplete);
+        let labels: Vec<_> = completions.items.iter().map(|item| &item.label).collect();
+        assert_eq!(labels, ["lodash"]);
+        assert_eq!(
+            completions.items[0].insert_text.as_deref(),
+            Some("\"lodash\": \"^4.17.21\"")
+        );
+        assert_eq!(registry.request_count("/-/v1/search?text=lod&size=20"), 1);
+
+        // Clients that resolve edits lazily insert the latest version the registry itself serves.
+        *backend.completion_capabilities.write().unwrap() = completion::CompletionCapabilities {
+            lazy_edits: true,
+            ..Default::default()
+        };
+        let Ok(Some(CompletionResponse::List(completions))) = complete(48).await else {
+            panic!("expected completions");
+        };
+        let item = completions.items[0].clone();
+        assert_eq!(
+            item.insert_text.as_deref(),
+            Some("\"lodash\": \"^4.17.21\"")
+        );
+        let item = backend.completion_resolve(item).await.unwrap();
+        assert_eq!(item.insert_text.as_deref(), Some("\"lodash\": \"^4.18.0\""));
+        assert_eq!(item.detail.as_deref(), Some("4.18.0"));
+    }
+
+    #[tokio::test]
+    async fn test_npm_alias() {
+        let registry = file_registry("alias-registry", &[("react", "18.3.1")]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = open(
+            backend,
+            &registry,
+            r#"{ "dependencies": { "my-react": "npm:react@^18" } }"#,
+        )
+        .await;
+        let position = |character| TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            position: Position::new(0, character),
+        };
+        let contents = hover_markup(backend, &uri, position(23).position).await;
+        assert!(contents.starts_with("**my-react** (alias for react)"));
+        assert!(contents.contains("✓ Up to date"));
+
+        let completions = backend
+            .completion(CompletionParams {
+                text_document_position: position(47),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            })
+            .await
+            .unwrap();
+        let Some(CompletionResponse::List(completions)) = completions else {
+            panic!("expected completions, got {completions:?}");
+        };
+        let labels: Vec<_> = completions.items.iter().map(|item| &item.label).collect();
+        assert_eq!(labels, ["18.3.1"]);
+    }
+
+    #[tokio::test]
+    async fn test_registry_without_publish_dates() {
+        let registry = file_registry("dateless-registry", &[]);
+        let document = json!({
+            "dist-tags": { "latest": "4.17.21" },
+            "versions": {
+                "4.17.20": { "version": "4.17.20", "description": "Lodash modular utilities." },
+                "4.17.21": { "version": "4.17.21", "description": "Lodash modular utilities." }
+            }
+        });
+        std::fs::write(registry.join("lodash"), document.to_string()).unwrap();
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = open(
+            backend,
+            &registry,
+            r#"{ "dependencies": { "lodash": "^4.17.20" } }"#,
+        )
+        .await;
+        let position = |character| TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            position: Position::new(0, character),
+        };
+        let contents = hover_markup(backend, &uri, position(23).position).await;
+        assert!(contents.contains("Latest version: 4.17.21 (published date unknown)"));
+
+        let completions = backend
+            .completion(CompletionParams {
+                text_document_position: position(39),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            })
+            .await
+            .unwrap();
+        let Some(CompletionResponse::List(completions)) = completions else {
+            panic!("expected completions, got {completions:?}");
+        };
+        let item = &completions.items[0];
+        assert_eq!(item.label, "4.17.20");
+        assert_eq!(
+            item.label_details
+                .as_ref()
+                .and_then(|details| details.description.as_deref()),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bundled_names() {
+        let registry = file_registry("bundled-registry", &[("lodash", "4.17.21")]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = open(
+            backend,
+            &registry,
+            r#"{
+  "bundleDependencies": ["lodash", "l"],
+  "dependencies": { "lodash": "^4.17.0", "left-pad": "^1.3.0", "react": "^18.0.0" },
+  "optionalDependencies": { "lru-cache": "^10.0.0" }
+}"#,
+        )
+        .await;
+        let position = |character| TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            position: Position::new(1, character),
+        };
+        let hover = backend.hover(hover_params(&uri, position(28).position));
+        let hover = hover.await.unwrap().unwrap();
+        let HoverContents::Markup(contents) = &hover.contents else {
+            panic!("expected markup, got {:?}", hover.contents);
+        };
+        assert!(contents.value.starts_with("**lodash**"));
+        assert!(contents.value.contains("Latest version: 4.17.21"));
+        assert!(!contents.value.contains("✓ Up to date"));
+        assert_eq!(
+            hover.range,
+            Some(Range::new(position(26).position, position(32).position))
+        );
+
+        let completions = backend
+            .completion(CompletionParams {
+                text_document_position: position(37),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            })
+            .await
+            .unwrap();
+        let Some(CompletionResponse::List(completions)) = completions else {
+            panic!("expected completions, got {completions:?}");
+        };
+        let labels: Vec<_> = completions.items.iter().map(|item| &item.label).collect();
+        assert_eq!(labels, ["left-pad", "lru-cache"]);
+    }
+
+    #[tokio::test]
+    async fn test_hover_before_open() {
+        let registry = file_registry("unopened-registry", &[("lodash", "4.17.21")]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        backend.apply_settings(
+            Settings::from_value(Some(json!({
+                "default_registry": format!("file://{}", registry.display())
+            })))
+            .unwrap(),
+        );
+        let project = registry.join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(
+            project.join("package.json"),
+            r#"{ "dependencies": { "lodash": "^4.17.0" } }"#,
+        )
+        .unwrap();
+        let uri = Url::from_file_path(project.join("package.json")).unwrap();
+        let contents = hover_markup(backend, &uri, Position::new(0, 23)).await;
+        assert!(contents.starts_with("**lodash**"));
+
+        let unsaved = Url::parse("untitled:/project/package.json").unwrap();
+        let hover = backend.hover(hover_params(&unsaved, Position::new(0, 23)));
+        assert!(hover.await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_project_npmrc() {
+        let registry = file_registry("project-npmrc-registry", &[("@acme/ui", "2.1.0")]);
+        let moved = file_registry("moved-npmrc-registry", &[("@acme/ui", "2.2.0")]);
+        let root = TempDir::new("project-npmrc");
+        let app = root.join("packages/app");
+        std::fs::create_dir_all(&app).unwrap();
+        let point_at = |registry: &Path| {
+            std::fs::write(
+                app.join(".npmrc"),
+                format!("@acme:registry=file://{}\n", registry.display()),
+            )
+            .unwrap();
+        };
+        point_at(&registry);
+        // Read from disk, as it isn't open.
+        std::fs::write(
+            app.join("package.json"),
+            r#"{ "dependencies": { "@acme/ui": "^2.0.0" } }"#,
+        )
+        .unwrap();
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        *backend.roots.write().unwrap() = vec![root.to_path_buf()];
+
+        let uri = Url::from_file_path(app.join("package.json")).unwrap();
+        let hover = || hover_markup(backend, &uri, Position::new(0, 23));
+        let contents = hover().await;
+        assert!(contents.contains("2.1.0"), "{contents}");
+        assert!(backend.project_npmrc.read().unwrap().contains_key(&app));
+
+        point_at(&moved);
+        backend
+            .did_change_watched_files(DidChangeWatchedFilesParams {
+                changes: vec![FileEvent {
+                    uri: Url::from_file_path(app.join(".npmrc")).unwrap(),
+                    typ: FileChangeType::CHANGED,
+                }],
+            })
+            .await;
+        let contents = hover().await;
+        assert!(contents.contains("2.2.0"), "{contents}");
+    }
+
+    #[tokio::test]
+    async fn test_completion_capability_profiles() {
+        let registry = file_registry("profiles-registry", &[("lodash", "4.17.21")]);
+        let complete = |completion: Option<CompletionClientCapabilities>| {
+            let registry = &registry;
+            async move {
+                let (service, _) = start_backend();
+                let backend = service.inner();
+                backend
+                    .initialize(InitializeParams {
+                        initialization_options: Some(json!({
+                            "default_registry": format!("file://{}", registry.display())
+                        })),
+                        capabilities: ClientCapabilities {
+                            text_document: Some(TextDocumentClientCapabilities {
+                                completion,
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap();
+                let uri = Url::parse("file:///project/package.json").unwrap();
+                backend.update_document(
+                    uri.clone(),
+                    r#"{ "dependencies": { "lodash": "^4.17" } }"#.to_string(),
+                );
+                let completions = backend
+                    .completion(CompletionParams {
+                        text_document_position: TextDocumentPositionParams {
+                            text_document: TextDocumentIdentifier { uri },
+                            position: Position::new(0, 36),
+                        },
+                        work_done_progress_params: Default::default(),
+                        partial_result_params: Default::default(),
+                        context: None,
+                    })
+                    .await
+                    .unwrap();
+                let Some(CompletionResponse::List(completions)) = completions else {
+                    panic!("expected completions, got {completions:?}");
+                };
+                completions.items.into_iter().next().unwrap()
+            }
+        };
+
+        let rich = complete(Some(
+            serde_json::from_value(json!({
+                "completionItem": { "snippetSupport": true, "labelDetailsSupport": true }
+            }))
+            .unwrap(),
+        ))
+        .await;
+        let Some(CompletionTextEdit::Edit(edit)) = &rich.text_edit else {
+            panic!("expected a text edit, got {rich:?}");
+        };
+        assert_eq!(edit.new_text, "^4.17.21");
+        assert!(rich.insert_text.is_none());
+        assert!(rich.label_details.is_some());
+
+        let plain = complete(None).await;
+        assert_eq!(plain.label, "4.17.21");
+        assert_eq!(plain.insert_text.as_deref(), Some("4.17.21"));
+        assert!(plain.text_edit.is_none());
+        assert!(plain.label_details.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_features() {
+        let registry = file_registry("features-registry", &[("lodash", "4.17.21")]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let result = backend
+            .initialize(InitializeParams {
+                initialization_options: Some(json!({
+                    "default_registry": format!("file://{}", registry.display()),
+                    "features": { "completion": false, "diagnostics": false }
+                })),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(result.capabilities.hover_provider.is_some());
+        assert!(result.capabilities.completion_provider.is_none());
+        assert!(result.capabilities.diagnostic_provider.is_none());
+
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend.update_document(
+            uri.clone(),
+            r#"{ "dependencies": { "lodash": "^3.0.0" } }"#.to_string(),
+        );
+        let completions = backend
+            .completion(CompletionParams {
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: Position::new(0, 32),
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            })
+            .await
+            .unwrap();
+        assert!(completions.is_none());
+        let report = backend
+            .diagnostic(DocumentDiagnosticParams {
+                text_document: TextDocumentIdentifier { uri },
+                identifier: None,
+                previous_result_id: None,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap();
+        let DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Full(report)) = report
+        else {
+            panic!("expected a full report, got {report:?}");
+        };
+        assert!(report.full_document_diagnostic_report.items.is_empty());
+
+        // Invalid options enable everything.
+        let (service, _) = start_backend();
+        let result = service
+            .inner()
+            .initialize(InitializeParams {
+                initialization_options: Some(json!({ "features": { "hover": 0 } })),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(result.capabilities.hover_provider.is_some());
+        assert!(result.capabilities.completion_provider.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_document_symbols() {
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend.update_document(
+            uri.clone(),
+            r#"{
+  "name": "app",
+  "dependencies": { "lodash": "^4.17.21", "react": "18.3.1" },
+  "devDependencies": {}
+}"#
+            .to_string(),
+        );
+        let Some(DocumentSymbolResponse::Nested(symbols)) = backend
+            .document_symbol(DocumentSymbolParams {
+                text_document: TextDocumentIdentifier { uri },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap()
+        else {
+            panic!("expected nested symbols");
+        };
+        let outline: Vec<_> = symbols
+            .iter()
+            .map(|symbol| {
+                let children: Vec<_> = symbol
+                    .children
+                    .iter()
+                    .flatten()
+                    .map(|child| (child.name.as_str(), child.detail.as_deref().unwrap()))
+                    .collect();
+                (symbol.name.as_str(), children)
+            })
+            .collect();
+        assert_eq!(
+            outline,
+            [
+                (
+                    "dependencies",
+                    vec![("lodash", "^4.17.21"), ("react", "18.3.1")]
+                ),
+                ("devDependencies", vec![]),
+            ]
+        );
+        let lodash = &symbols[0].children.as_ref().unwrap()[0];
+        assert_eq!(symbols[0].kind, SymbolKind::MODULE);
+        assert_eq!(lodash.kind, SymbolKind::CONSTANT);
+        assert_eq!(
+            lodash.selection_range,
+            Range::new(Position::new(2, 21), Position::new(2, 27))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rename_dependency_across_sections() {
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend.update_document(
+            uri.clone(),
+            r#"{
+  "dependencies": { "@acme/ui": "^1.0.0" },
+  "peerDependencies": { "@acme/ui": "^1.0.0" },
+  "peerDependenciesMeta": { "@acme/ui": { "optional": true } }
+}"#
+            .to_string(),
+        );
+        let position = TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            position: Position::new(1, 22),
+        };
+        let Some(PrepareRenameResponse::RangeWithPlaceholder { range, placeholder }) =
+            backend.prepare_rename(position.clone()).await.unwrap()
+        else {
+            panic!("expected a renameable dependency");
+        };
+        assert_eq!(
+            range,
+            Range::new(Position::new(1, 21), Position::new(1, 29))
+        );
+        assert_eq!(placeholder, "@acme/ui");
+
+        let edit = backend
+            .rename(RenameParams {
+                text_document_position: position,
+                new_name: "@acme/design".to_string(),
+                work_done_progress_params: Default::default(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let edits = &edit.changes.unwrap()[&uri];
+        let lines: Vec<_> = edits.iter().map(|edit| edit.range.start.line).collect();
+        assert_eq!(lines, [1, 2, 3]);
+        assert!(edits.iter().all(|edit| edit.new_text == "@acme/design"));
+
+        let outside = TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position: Position::new(1, 35),
+        };
+        assert!(backend.prepare_rename(outside).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_document_highlight() {
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        let uri = Url::parse("file:///project/package.json").unwrap();
+        backend.update_document(
+            uri.clone(),
+            r#"{
+  "dependencies": { "react": "^18.3.1", "react-dom": "^18.3.1" },
+  "devDependencies": { "react": "^18.3.1" },
+  "overrides": { "react@18": "18.3.1", "react-dom": { "react": "$react" } },
+  "peerDependenciesMeta": { "react": { "optional": true } }
+}"#
+            .to_string(),
+        );
+        let params = |line, character| DocumentHighlightParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position::new(line, character),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let highlights = backend
+            .document_highlight(params(2, 25))
+            .await
+            .unwrap()
+            .unwrap();
+        let ranges: Vec<_> = highlights
+            .iter()
+            .map(|highlight| {
+                let Range { start, end } = highlight.range;
+                (start.line, start.character, end.character)
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                (1, 21, 26),
+                (2, 24, 29),
+                (3, 18, 23),
+                (3, 55, 60),
+                (4, 29, 34)
+            ]
+        );
+        assert!(highlights
+            .iter()
+            .all(|highlight| highlight.kind == Some(DocumentHighlightKind::TEXT)));
+
+        // Versions aren't names.
+        assert_eq!(
+            backend.document_highlight(params(1, 31)).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fix_all_stays_within_ranges() {
+        let registry = file_registry("fix-all-registry", &[("lodash", "5.0.0")]);
+        let (service, _) = start_backend();
+        let backend = service.inner();
+        std::fs::write(
+            registry.join("lodash"),
+            json!({
+                "dist-tags": { "latest": "5.0.0" },
+                "time": {
+                    "4.17.0": "2023-01-01T00:00:00.000Z",
+                    "4.17.21": "2023-06-01T00:00:00.000Z",
+                    "5.0.0": "2024-01-01T00:00:00.000Z"
+                },
+                "versions": {
+                    "4.17.0": { "version": "4.17.0", "description": "" },
+                    "4.17.21": { "version": "4.17.21", "description": "" },
+                    "5.0.0": { "version": "5.0.0", "description": "" }
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let uri = open(
+            backend,
+            &registry,
+            r#"{"dependencies": {"lodash": "^4.17.0"}}"#,
+        )
+        .await;
+        let code_actions = |only: &'static str| {
+            backend.code_action(CodeActionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                range: Range::default(),
+                context: CodeActionContext {
+                    only: Some(vec![CodeActionKind::new(only)]),
+                    ..Default::default()
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+        };
+
+        let actions = code_actions("source.fixAll").await.unwrap().unwrap();
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected a single fix-all action, got {actions:?}");
+        };
+        assert_eq!(action.kind, Some(CodeActionKind::new(FIX_ALL_KIND)));
+        let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(edits[0].new_text, "^4.17.21");
+
+        let actions = code_actions("source").await.unwrap().unwrap();
+        assert!(actions.iter().all(|action| matches!(
+            action,
+            CodeActionOrCommand::CodeAction(CodeAction { kind: Some(kind), .. })
+                if *kind == CodeActionKind::SOURCE
+        )));
+    }
 }
@@ -0,0 +1,351 @@
+//! Support for Maven's `pom.xml` and Gradle build scripts, backed by Maven Central's search API.
+
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::Value;
+use tower_lsp::lsp_types::{Position, Range, Url};
+
+use crate::fetcher::PackageVersionFetcher;
+use crate::response_cache::ResponseCache;
+
+const SEARCH_API: &str = "https://search.maven.org/solrsearch/select";
+
+/// How long the latest release of an artifact is used before asking again.
+const ARTIFACT_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Gradle configurations that declare a dependency, like `implementation 'g:a:1.0'`.
+const GRADLE_CONFIGURATIONS: &[&str] = &[
+    "api",
+    "implementation",
+    "compileOnly",
+    "runtimeOnly",
+    "testImplementation",
+    "testCompileOnly",
+    "testRuntimeOnly",
+    "annotationProcessor",
+    "kapt",
+    "classpath",
+    "compile",
+    "testCompile",
+];
+
+pub(super) fn is_manifest(uri: &Url) -> bool {
+    let file_name = uri.path().rsplit('/').next().unwrap_or_default();
+    matches!(file_name, "pom.xml" | "build.gradle" | "build.gradle.kts")
+}
+
+/// A dependency on an artifact, such as `<dependency><groupId>...` or `'group:artifact:1.0'`.
+#[derive(Debug, PartialEq)]
+pub(super) struct Artifact {
+    pub group: String,
+    pub artifact: String,
+    pub version: Option<String>,
+    /// Range of the declaration that was hovered.
+    pub range: Range,
+}
+
+/// Returns the artifact declared where the cursor is, if any.
+pub(super) fn artifact_at(uri: &Url, text: &str, position: Position) -> Option<Artifact> {
+    if uri.path().ends_with("pom.xml") {
+        pom_artifact_at(text, position)
+    } else {
+        gradle_artifact_at(text, position)
+    }
+}
+
+/// Finds the `<dependency>` or `<plugin>` element around the cursor.
+fn pom_artifact_at(text: &str, position: Position) -> Option<Artifact> {
+    let offset = crate::offset_at(text, position);
+    for tag in ["dependency", "plugin"] {
+        let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+        let Some(start) = text[..offset].rfind(&open) else {
+            continue;
+        };
+        // The cursor might be past the end of the closest element, e.g. between two of them.
+        let Some(end) = text[start..]
+            .find(&close)
+            .map(|len| start + len + close.len())
+        else {
+            continue;
+        };
+        if offset > end {
+            continue;
+        }
+        let element = &text[start..end];
+        let child = |name: &str| {
+            let (open, close) = (format!("<{name}>"), format!("</{name}>"));
+            let content_start = element.find(&open)? + open.len();
+            let len = element[content_start..].find(&close)?;
+            Some((
+                start + content_start,
+                element[content_start..content_start + len].trim(),
+            ))
+        };
+        // Plugins from `org.apache.maven.plugins` may leave their group out.
+        let group = child("groupId")
+            .map(|(_, group)| group)
+            .or((tag == "plugin").then_some("org.apache.maven.plugins"))?;
+        let (_, artifact) = child("artifactId")?;
+        let version = child("version");
+        let range = match version {
+            Some((version_start, version)) => range_of(text, version_start, version.len()),
+            None => range_of(text, start, element.len()),
+        };
+        return Some(Artifact {
+            group: group.to_string(),
+            artifact: artifact.to_string(),
+            version: version.map(|(_, version)| version.to_string()),
+            range,
+        });
+    }
+    None
+}
+
+/// Finds a dependency declared on the cursor's line, either as a `"group:artifact:version"`
+/// string or with named arguments (`group: 'g', name: 'a', version: '1.0'`).
+fn gradle_artifact_at(text: &str, position: Position) -> Option<Artifact> {
+    let line = text.lines().nth(position.line as usize)?;
+    let statement = line.trim_start();
+    let configuration = statement
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .map_or(statement, |len| &statement[..len]);
+    if !GRADLE_CONFIGURATIONS.contains(&configuration) {
+        return None;
+    }
+    let strings = quoted_strings(line);
+    let line_start = line.as_ptr() as usize - text.as_ptr() as usize;
+    let range = |start: usize, len: usize| range_of(text, line_start + start, len);
+    if let Some(&(start, notation)) = strings.iter().find(|(_, string)| string.contains(':')) {
+        let mut parts = notation.split(':');
+        let (group, artifact) = (parts.next()?, parts.next()?);
+        return Some(Artifact {
+            group: group.to_string(),
+            artifact: artifact.to_string(),
+            version: parts.next().map(ToString::to_string),
+            range: range(start, notation.len()),
+        });
+    }
+    let named = |name: &str| {
+        strings.iter().find(|(start, _)| {
+            line[..*start - 1]
+                .trim_end()
+                .strip_suffix([':', '='])
+                .is_some_and(|before| before.trim_end().ends_with(name))
+        })
+    };
+    let group = named("group")?;
+    let artifact = named("name")?;
+    let version = named("version");
+    let (start, len) = version.map_or((artifact.0, artifact.1.len()), |(start, version)| {
+        (*start, version.len())
+    });
+    Some(Artifact {
+        group: group.1.to_string(),
+        artifact: artifact.1.to_string(),
+        version: version.map(|(_, version)| version.to_string()),
+        range: range(start, len),
+    })
+}
+
+/// The contents of the string literals on a line, along with the offsets they start at.
+fn quoted_strings(line: &str) -> Vec<(usize, &str)> {
+    let mut strings = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = line[offset..].find(['\'', '"']).map(|ix| offset + ix) {
+        let quote = &line[start..start + 1];
+        let Some(len) = line[start + 1..].find(quote) else {
+            break;
+        };
+        strings.push((start + 1, &line[start + 1..start + 1 + len]));
+        offset = start + len + 2;
+    }
+    strings
+}
+
+fn range_of(text: &str, start: usize, len: usize) -> Range {
+    Range::new(
+        crate::position_at(text, start),
+        crate::position_at(text, start + len),
+    )
+}
+
+/// The latest release of an artifact on Maven Central.
+#[derive(Clone, Debug)]
+pub(super) struct ArtifactInfo {
+    pub latest_version: String,
+    pub date: Option<DateTime<FixedOffset>>,
+}
+
+/// Remembers the latest release of each artifact, keyed by group and artifact, so that hovering it
+/// again doesn't ask Maven Central again.
+pub(super) struct ArtifactCache {
+    artifacts: ResponseCache<(String, String), ArtifactInfo>,
+}
+
+impl Default for ArtifactCache {
+    fn default() -> Self {
+        Self {
+            artifacts: ResponseCache::new(ARTIFACT_TTL),
+        }
+    }
+}
+
+impl ArtifactCache {
+    pub(super) async fn info(
+        &self,
+        fetcher: &PackageVersionFetcher,
+        group: &str,
+        artifact: &str,
+    ) -> Option<ArtifactInfo> {
+        let key = (group.to_string(), artifact.to_string());
+        self.artifacts
+            .get(&key, || fetch_info(fetcher, group, artifact))
+            .await
+    }
+}
+
+async fn fetch_info(
+    fetcher: &PackageVersionFetcher,
+    group: &str,
+    artifact: &str,
+) -> Option<ArtifactInfo> {
+    let query = format!("g:\"{group}\" AND a:\"{artifact}\"");
+    let response = fetcher
+        .request_json(|client| {
+            client
+                .get(SEARCH_API)
+                .query(&[("q", query.as_str()), ("rows", "1"), ("wt", "json")])
+        })
+        .await?;
+    parse_info(&response)
+}
+
+fn parse_info(response: &Value) -> Option<ArtifactInfo> {
+    let document = &response["response"]["docs"][0];
+    Some(ArtifactInfo {
+        latest_version: document["latestVersion"].as_str()?.to_string(),
+        date: document["timestamp"]
+            .as_i64()
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|date| date.fixed_offset()),
+    })
+}
+
+/// The artifact's page on Maven Central.
+pub(super) fn central_page(group: &str, artifact: &str) -> String {
+    format!("https://central.sonatype.com/artifact/{group}/{artifact}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn artifact(file_name: &str, text: &str, line: u32, character: u32) -> Option<Artifact> {
+        let uri = Url::parse(&format!("file:///project/{file_name}")).unwrap();
+        artifact_at(&uri, text, Position::new(line, character))
+    }
+
+    #[test]
+    fn test_is_manifest() {
+        for (file_name, expected) in [
+            ("pom.xml", true),
+            ("build.gradle", true),
+            ("build.gradle.kts", true),
+            ("settings.gradle", false),
+            ("package.json", false),
+        ] {
+            let uri = Url::parse(&format!("file:///project/{file_name}")).unwrap();
+            assert_eq!(is_manifest(&uri), expected, "{file_name}");
+        }
+    }
+
+    #[test]
+    fn test_pom_artifact_at() {
+        let pom = r#"<project>
+  <dependencies>
+    <dependency>
+      <groupId>com.google.guava</groupId>
+      <artifactId>guava</artifactId>
+      <version>32.1.2-jre</version>
+    </dependency>
+  </dependencies>
+  <build>
+    <plugins>
+      <plugin>
+        <artifactId>maven-compiler-plugin</artifactId>
+      </plugin>
+    </plugins>
+  </build>
+</project>"#;
+        let guava = artifact("pom.xml", pom, 5, 15).unwrap();
+        assert_eq!(
+            guava,
+            Artifact {
+                group: "com.google.guava".into(),
+                artifact: "guava".into(),
+                version: Some("32.1.2-jre".into()),
+                range: Range::new(Position::new(5, 15), Position::new(5, 25)),
+            }
+        );
+        assert_eq!(artifact("pom.xml", pom, 3, 6).unwrap().artifact, "guava");
+        let plugin = artifact("pom.xml", pom, 11, 20).unwrap();
+        assert_eq!(plugin.group, "org.apache.maven.plugins");
+        assert_eq!(plugin.version, None);
+        assert_eq!(artifact("pom.xml", pom, 7, 4), None);
+    }
+
+    #[test]
+    fn test_gradle_artifact_at() {
+        let gradle = r#"dependencies {
+    implementation 'com.google.guava:guava:32.1.2-jre'
+    testImplementation("org.junit.jupiter:junit-jupiter:5.10.0")
+    compileOnly group: 'org.projectlombok', name: 'lombok', version: '1.18.30'
+    println 'a:b:c'
+}"#;
+        let guava = artifact("build.gradle", gradle, 1, 20).unwrap();
+        assert_eq!(guava.group, "com.google.guava");
+        assert_eq!(guava.artifact, "guava");
+        assert_eq!(guava.version.as_deref(), Some("32.1.2-jre"));
+        assert_eq!(
+            guava.range,
+            Range::new(Position::new(1, 20), Position::new(1, 53))
+        );
+        let junit = artifact("build.gradle.kts", gradle, 2, 30).unwrap();
+        assert_eq!(junit.artifact, "junit-jupiter");
+        let lombok = artifact("build.gradle", gradle, 3, 10).unwrap();
+        assert_eq!(
+            (lombok.group.as_str(), lombok.artifact.as_str()),
+            ("org.projectlombok", "lombok")
+        );
+        assert_eq!(lombok.version.as_deref(), Some("1.18.30"));
+        assert_eq!(artifact("build.gradle", gradle, 4, 10), None);
+
+        let accented =
+            "dependencies {\n    implementation group: 'ü', name: 'a', version: '1.0'\n}";
+        let artifact = artifact("build.gradle", accented, 1, 30).unwrap();
+        assert_eq!(
+            artifact.range,
+            Range::new(Position::new(1, 52), Position::new(1, 55))
+        );
+    }
+
+    #[test]
+    fn test_parse_info() {
+        let info = parse_info(&json!({
+            "response": {
+                "numFound": 1,
+                "docs": [{
+                    "id": "com.google.guava:guava",
+                    "latestVersion": "33.3.1-jre",
+                    "timestamp": 1726000000000i64
+                }]
+            }
+        }))
+        .unwrap();
+        assert_eq!(info.latest_version, "33.3.1-jre");
+        assert!(info.date.is_some());
+        assert!(parse_info(&json!({ "response": { "numFound": 0, "docs": [] } })).is_none());
+    }
+}
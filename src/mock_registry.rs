@@ -0,0 +1,116 @@
+//! A registry served over HTTP on a local port, for tests that fetch packages end to end.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+/// What the mock registry answers a request with.
+pub(super) struct MockResponse {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub(super) fn json(body: &Value) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("content-type", "application/json".to_string())],
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    pub(super) fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub(super) fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Answers with `body` as is, like an already encoded one.
+    pub(super) fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = body;
+        self
+    }
+}
+
+/// A registry served over HTTP on a local port, so that fetches can be tested end to end
+/// without reaching the network. Each path is answered with a canned response, and anything
+/// else with a 404.
+pub(super) struct MockRegistry {
+    pub url: String,
+    /// Every request received so far, lowercased, from the request line to the headers.
+    requests: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl MockRegistry {
+    /// Serves the responses, keyed by path like `/lodash` or `/%40acme%2Fui`.
+    pub(super) async fn start(responses: Vec<(&str, MockResponse)>) -> Self {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let responses: Arc<HashMap<_, _>> = Arc::new(
+            responses
+                .into_iter()
+                .map(|(path, response)| (path.to_string(), response))
+                .collect(),
+        );
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn({
+            let requests = requests.clone();
+            async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let (responses, requests) = (responses.clone(), requests.clone());
+                    tokio::spawn(async move {
+                        let mut request = Vec::new();
+                        let mut buffer = [0; 4096];
+                        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                            match stream.read(&mut buffer).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(len) => request.extend_from_slice(&buffer[..len]),
+                            }
+                        }
+                        let request = String::from_utf8_lossy(&request).to_string();
+                        let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+                        requests.lock().unwrap().push(request.to_lowercase());
+                        let not_found = MockResponse::status(404);
+                        let response = responses.get(&path).unwrap_or(&not_found);
+                        let mut head = format!(
+                            "HTTP/1.1 {} Mock\r\ncontent-length: {}\r\nconnection: close\r\n",
+                            response.status,
+                            response.body.len()
+                        );
+                        for (name, value) in &response.headers {
+                            head.push_str(&format!("{name}: {value}\r\n"));
+                        }
+                        head.push_str("\r\n");
+                        let _ = stream.write_all(head.as_bytes()).await;
+                        let _ = stream.write_all(&response.body).await;
+                    });
+                }
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub(super) fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// How many requests asked for `path`.
+    pub(super) fn request_count(&self, path: &str) -> usize {
+        let request_line = format!("get {} ", path.to_lowercase());
+        self.requests()
+            .iter()
+            .filter(|request| request.starts_with(&request_line))
+            .count()
+    }
+}
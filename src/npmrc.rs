@@ -0,0 +1,568 @@
+//! Registries configured in npm's `.npmrc` files, where teams usually set up their private ones,
+//! so that the server asks the same registries npm does. Yarn's `.yarnrc.yml` and Bun's
+//! `bunfig.toml` are read alongside them.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::fetcher::interpolate_env;
+use crate::{bunfig, yarnrc};
+
+/// The registries an `.npmrc` configures, or several of them layered on top of each other.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(super) struct NpmrcRegistries {
+    /// The `registry` setting.
+    pub default_registry: Option<String>,
+    /// `@scope:registry` settings, keyed by scope including the leading `@`.
+    pub scope_registries: HashMap<String, String>,
+    /// Credentials of the registries, keyed by the URL prefix they are sent to without its scheme,
+    /// like `//npm.acme.dev/`.
+    pub credentials: HashMap<String, Authorization>,
+}
+
+/// The value of an `Authorization` header, which is kept out of debug output.
+#[derive(Clone, PartialEq)]
+pub(super) struct Authorization(pub String);
+
+impl fmt::Debug for Authorization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Authorization(<redacted>)")
+    }
+}
+
+impl NpmrcRegistries {
+    /// Reads the registries and their credentials out of an `.npmrc`, ignoring every other
+    /// setting. Credentials referring to environment variables that `env` doesn't know are left
+    /// out, as they would be refused anyway.
+    pub(super) fn parse(text: &str, env: impl Fn(&str) -> Option<String>) -> Self {
+        let mut registries = Self::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with([';', '#']) {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .or_else(|| {
+                    value
+                        .strip_prefix('\'')
+                        .and_then(|value| value.strip_suffix('\''))
+                })
+                .unwrap_or(value);
+            if value.is_empty() {
+                continue;
+            }
+            match key.trim() {
+                "registry" => registries.default_registry = Some(value.to_string()),
+                key if key.starts_with("//") => {
+                    let Some((prefix, setting)) = key.rsplit_once(':') else {
+                        continue;
+                    };
+                    let scheme = match setting {
+                        "_authToken" => "Bearer",
+                        // Base64 of `username:password`, from before tokens existed.
+                        "_auth" => "Basic",
+                        _ => continue,
+                    };
+                    let Ok(credential) = interpolate_env(value, &env) else {
+                        continue;
+                    };
+                    let prefix = match prefix.ends_with('/') {
+                        true => prefix.to_string(),
+                        false => format!("{prefix}/"),
+                    };
+                    registries
+                        .credentials
+                        .insert(prefix, Authorization(format!("{scheme} {credential}")));
+                }
+                key => {
+                    let Some(scope) = key.strip_suffix(":registry") else {
+                        continue;
+                    };
+                    if scope.starts_with('@') && scope.len() > 1 {
+                        registries
+                            .scope_registries
+                            .insert(scope.to_string(), value.to_string());
+                    }
+                }
+            }
+        }
+        registries
+    }
+
+    /// Reads the registries set through environment variables like `npm_config_registry` or
+    /// `NPM_CONFIG_@acme:registry`, which CI images often export instead of writing an `.npmrc`.
+    /// The lowercase variable wins when both are set, whatever the case of the rest of its name.
+    fn from_env(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut registries = Self::default();
+        let mut vars: Vec<_> = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let setting = name
+                    .get(..NPM_CONFIG_PREFIX.len())
+                    .filter(|prefix| prefix.eq_ignore_ascii_case(NPM_CONFIG_PREFIX))
+                    .map(|_| name[NPM_CONFIG_PREFIX.len()..].to_ascii_lowercase())?;
+                let is_lowercase = name.starts_with(NPM_CONFIG_PREFIX);
+                (!value.is_empty()).then_some((is_lowercase, setting, value))
+            })
+            .collect();
+        vars.sort();
+        for (_, setting, value) in vars {
+            if setting == "registry" {
+                registries.default_registry = Some(value);
+            } else if let Some(scope) = setting.strip_suffix(":registry") {
+                if scope.starts_with('@') && scope.len() > 1 {
+                    registries.scope_registries.insert(scope.to_string(), value);
+                }
+            }
+        }
+        registries
+    }
+
+    /// Layers `self` over `fallback`, keeping each setting of `self` that is set.
+    fn or(mut self, fallback: Self) -> Self {
+        self.default_registry = self.default_registry.or(fallback.default_registry);
+        for (scope, registry) in fallback.scope_registries {
+            self.scope_registries.entry(scope).or_insert(registry);
+        }
+        for (prefix, credential) in fallback.credentials {
+            self.credentials.entry(prefix).or_insert(credential);
+        }
+        self
+    }
+}
+
+/// The prefix of a registry's URLs without their scheme, like `.npmrc` writes it (e.g.
+/// `//npm.acme.dev/`).
+pub(super) fn credential_prefix(registry: &str) -> Option<String> {
+    let (_, address) = registry.split_once(':')?;
+    address
+        .starts_with("//")
+        .then(|| format!("{}/", address.trim_end_matches('/')))
+}
+
+/// What the names of the environment variables setting npm's configuration start with.
+const NPM_CONFIG_PREFIX: &str = "npm_config_";
+
+/// Where the user's own npm, Yarn and Bun configuration is.
+#[derive(Debug, Default)]
+pub(super) struct UserConfig {
+    pub npmrc: Option<PathBuf>,
+    pub yarnrc: Option<PathBuf>,
+    pub bunfig: Option<PathBuf>,
+}
+
+impl UserConfig {
+    /// Where npm, Yarn and Bun read the user's configuration from, unless they're told otherwise.
+    pub(super) fn locate() -> Self {
+        let home = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(PathBuf::from);
+        let npmrc = ["NPM_CONFIG_USERCONFIG", "npm_config_userconfig"]
+            .into_iter()
+            .find_map(std::env::var_os)
+            .map(PathBuf::from)
+            .or_else(|| Some(home.as_ref()?.join(".npmrc")));
+        let bunfig = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.clone())
+            .map(|directory| directory.join(".bunfig.toml"));
+        Self {
+            npmrc,
+            yarnrc: home.map(|home| home.join(".yarnrc.yml")),
+            bunfig,
+        }
+    }
+}
+
+/// Files that set up the registries of the project in their directory.
+pub(super) const REGISTRY_CONFIGS: [&str; 3] = [".npmrc", ".yarnrc.yml", "bunfig.toml"];
+
+/// Whether `path` is a file that sets up the registries of a project.
+pub(super) fn is_registry_config(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| REGISTRY_CONFIGS.iter().any(|config| name == *config))
+}
+
+/// The directory of the project a document belongs to as far as its registries go: the closest
+/// one to the document with an `.npmrc`, a `.yarnrc.yml` or a `bunfig.toml`, without leaving the
+/// workspace folder `root`.
+pub(super) async fn project_directory(document: &Path, root: &Path) -> Option<PathBuf> {
+    for directory in document.ancestors().skip(1) {
+        if !directory.starts_with(root) {
+            break;
+        }
+        for config in REGISTRY_CONFIGS {
+            if tokio::fs::try_exists(directory.join(config))
+                .await
+                .unwrap_or(false)
+            {
+                return Some(directory.to_path_buf());
+            }
+        }
+    }
+    None
+}
+
+/// The name of the package manager the `packageManager` field of a project's `package.json`
+/// names, like `yarn` for `yarn@4.5.0`. Projects that don't name one are taken to use Bun when
+/// they have its lockfile.
+async fn package_manager(project: &Path) -> Option<String> {
+    let manifest = tokio::fs::read(project.join("package.json")).await.ok();
+    let manifest: Option<serde_json::Value> =
+        manifest.and_then(|manifest| serde_json::from_slice(&manifest).ok());
+    if let Some(package_manager) = manifest
+        .as_ref()
+        .and_then(|manifest| manifest["packageManager"].as_str())
+    {
+        let name = package_manager.split('@').next().unwrap_or_default();
+        return Some(name.to_string());
+    }
+    for lockfile in ["bun.lock", "bun.lockb"] {
+        if tokio::fs::try_exists(project.join(lockfile))
+            .await
+            .unwrap_or(false)
+        {
+            return Some("bun".to_string());
+        }
+    }
+    None
+}
+
+/// The formats of the configuration files registries are read from.
+#[derive(Clone, Copy)]
+enum Format {
+    Npmrc,
+    Yarnrc,
+    Bunfig,
+}
+
+/// Reads the configuration at `path` in the given format.
+async fn read_config(path: Option<PathBuf>, format: Format) -> NpmrcRegistries {
+    let Some(path) = path else {
+        return NpmrcRegistries::default();
+    };
+    let Ok(text) = tokio::fs::read_to_string(path).await else {
+        return NpmrcRegistries::default();
+    };
+    let env = |name: &str| std::env::var(name).ok();
+    match format {
+        Format::Npmrc => NpmrcRegistries::parse(&text, env),
+        Format::Yarnrc => yarnrc::parse(&text, env),
+        Format::Bunfig => bunfig::parse(&text, env),
+    }
+}
+
+/// The registries configured by the project in `project`, by the environment and by the user, in
+/// that order of precedence. Missing or unreadable files configure nothing.
+///
+/// Along with the settings, which override all of them, and npm's public registry, which is used
+/// when none of them configures one, registries are chosen in this order:
+///
+/// 1. `default_registry` and `scope_registries` from the settings
+/// 2. the project's `.npmrc`, `.yarnrc.yml` and `bunfig.toml`
+/// 3. `npm_config_registry` and `npm_config_@scope:registry` environment variables
+/// 4. the user's `.npmrc`, `.yarnrc.yml` and `.bunfig.toml`
+/// 5. <https://registry.npmjs.org>
+///
+/// Where both an `.npmrc` and a `.yarnrc.yml` configure something, the `.yarnrc.yml` wins if the
+/// project's `packageManager` is Yarn, and the `.npmrc` otherwise. `bunfig.toml` files are only
+/// read for projects that use Bun, and win over both.
+pub(super) async fn read_registries(project: Option<&Path>, user: &UserConfig) -> NpmrcRegistries {
+    let package_manager = match project {
+        Some(project) => package_manager(project).await,
+        None => None,
+    };
+    let package_manager = package_manager.as_deref();
+    let read = |npmrc, yarnrc, bunfig| async move {
+        let npmrc = read_config(npmrc, Format::Npmrc).await;
+        let yarnrc = read_config(yarnrc, Format::Yarnrc).await;
+        match package_manager {
+            Some("yarn") => yarnrc.or(npmrc),
+            Some("bun") => read_config(bunfig, Format::Bunfig)
+                .await
+                .or(npmrc)
+                .or(yarnrc),
+            _ => npmrc.or(yarnrc),
+        }
+    };
+    let project_config = read(
+        project.map(|project| project.join(".npmrc")),
+        project.map(|project| project.join(".yarnrc.yml")),
+        project.map(|project| project.join("bunfig.toml")),
+    )
+    .await;
+    let user_config = read(user.npmrc.clone(), user.yarnrc.clone(), user.bunfig.clone()).await;
+    let env = NpmrcRegistries::from_env(std::env::vars());
+    project_config.or(env).or(user_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn test_parse() {
+        let registries = NpmrcRegistries::parse(
+            r#"; npm's own settings
+registry = https://npm.acme.dev/
+@acme:registry="https://npm.acme.dev/scoped/"
+//npm.acme.dev/:_authToken=${NPM_TOKEN}
+//npm.acme.dev/scoped/:_authToken=${SCOPED_TOKEN}
+//legacy.acme.dev:8080:_auth=dXNlcjpwYXNz
+//npm.acme.dev/:always-auth=true
+# a comment
+@corp:registry=
+acme:registry=https://not-a-scope.dev
+save-exact=true
+"#,
+            |name| (name == "NPM_TOKEN").then(|| "npm_s3cr3t".to_string()),
+        );
+        assert_eq!(
+            registries,
+            NpmrcRegistries {
+                default_registry: Some("https://npm.acme.dev/".into()),
+                scope_registries: HashMap::from([(
+                    "@acme".to_string(),
+                    "https://npm.acme.dev/scoped/".to_string()
+                )]),
+                credentials: HashMap::from([
+                    (
+                        "//npm.acme.dev/".to_string(),
+                        Authorization("Bearer npm_s3cr3t".into())
+                    ),
+                    (
+                        "//legacy.acme.dev:8080/".to_string(),
+                        Authorization("Basic dXNlcjpwYXNz".into())
+                    ),
+                ]),
+            }
+        );
+        assert!(!format!("{registries:?}").contains("npm_s3cr3t"));
+    }
+
+    #[test]
+    fn test_from_env() {
+        let vars = |vars: &[(&str, &str)]| {
+            NpmrcRegistries::from_env(
+                vars.iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string())),
+            )
+        };
+        let registries = vars(&[
+            ("NPM_CONFIG_REGISTRY", "https://upper.dev"),
+            ("npm_config_registry", "https://lower.dev"),
+            ("NPM_CONFIG_@ACME:REGISTRY", "https://npm.acme.dev"),
+            ("npm_config_@corp:registry", "https://npm.corp.dev"),
+            ("npm_config_@:registry", "https://not-a-scope.dev"),
+            ("npm_config_save_exact", "true"),
+            ("PATH", "/usr/bin"),
+        ]);
+        assert_eq!(
+            registries,
+            NpmrcRegistries {
+                default_registry: Some("https://lower.dev".into()),
+                scope_registries: HashMap::from([
+                    ("@acme".to_string(), "https://npm.acme.dev".to_string()),
+                    ("@corp".to_string(), "https://npm.corp.dev".to_string()),
+                ]),
+                credentials: HashMap::new(),
+            }
+        );
+        assert_eq!(
+            vars(&[("NPM_CONFIG_REGISTRY", "https://upper.dev")]).default_registry,
+            Some("https://upper.dev".into())
+        );
+        assert_eq!(vars(&[("npm_config_registry", "")]), Default::default());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let project = NpmrcRegistries::parse("@acme:registry=https://project.acme.dev\n", |_| None);
+        let env = NpmrcRegistries::from_env([
+            (
+                "npm_config_registry".to_string(),
+                "https://env.dev".to_string(),
+            ),
+            (
+                "npm_config_@acme:registry".to_string(),
+                "https://env.acme.dev".to_string(),
+            ),
+            (
+                "npm_config_@corp:registry".to_string(),
+                "https://env.corp.dev".to_string(),
+            ),
+        ]);
+        let user = NpmrcRegistries::parse(
+            "registry=https://user.dev\n@corp:registry=https://user.corp.dev\n@tools:registry=https://user.tools.dev\n",
+            |_| None,
+        );
+        let registries = project.or(env).or(user);
+        assert_eq!(
+            registries.default_registry.as_deref(),
+            Some("https://env.dev")
+        );
+        assert_eq!(
+            registries.scope_registries["@acme"],
+            "https://project.acme.dev"
+        );
+        assert_eq!(registries.scope_registries["@corp"], "https://env.corp.dev");
+        assert_eq!(
+            registries.scope_registries["@tools"],
+            "https://user.tools.dev"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_project_overrides_user() {
+        let root = TempDir::new("npmrc");
+        let project = root.join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(
+            project.join(".npmrc"),
+            "@acme:registry=https://project.acme.dev\n",
+        )
+        .unwrap();
+        let user = root.join(".npmrc");
+        std::fs::write(
+            &user,
+            "registry=https://mirror.dev\n@acme:registry=https://user.acme.dev\n@corp:registry=https://npm.corp.dev\n",
+        )
+        .unwrap();
+
+        let user = UserConfig {
+            npmrc: Some(user),
+            yarnrc: None,
+            bunfig: None,
+        };
+        let registries = read_registries(Some(&project), &user).await;
+        assert_eq!(
+            registries.default_registry.as_deref(),
+            Some("https://mirror.dev")
+        );
+        assert_eq!(
+            registries.scope_registries["@acme"],
+            "https://project.acme.dev"
+        );
+        assert_eq!(registries.scope_registries["@corp"], "https://npm.corp.dev");
+        assert_eq!(
+            read_registries(Some(&root.join("missing")), &UserConfig::default()).await,
+            NpmrcRegistries::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_package_manager_preference() {
+        let project = TempDir::new("yarnrc");
+        std::fs::write(
+            project.join(".npmrc"),
+            "registry=https://npm.acme.dev\n@acme:registry=https://npm.acme.dev/scoped\n",
+        )
+        .unwrap();
+        std::fs::write(
+            project.join(".yarnrc.yml"),
+            "npmRegistryServer: https://yarn.acme.dev\n",
+        )
+        .unwrap();
+        let user = UserConfig::default();
+        let read = || read_registries(Some(&project), &user);
+
+        let registries = read().await;
+        assert_eq!(
+            registries.default_registry.as_deref(),
+            Some("https://npm.acme.dev")
+        );
+        std::fs::write(
+            project.join("package.json"),
+            r#"{ "packageManager": "yarn@4.5.0" }"#,
+        )
+        .unwrap();
+        let registries = read().await;
+        assert_eq!(
+            registries.default_registry.as_deref(),
+            Some("https://yarn.acme.dev")
+        );
+        // What only the `.npmrc` configures still applies.
+        assert_eq!(
+            registries.scope_registries["@acme"],
+            "https://npm.acme.dev/scoped"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bunfig() {
+        let project = TempDir::new("bunfig");
+        std::fs::write(
+            project.join(".npmrc"),
+            "registry=https://npm.acme.dev\n@acme:registry=https://npm.acme.dev/scoped\n",
+        )
+        .unwrap();
+        std::fs::write(
+            project.join("bunfig.toml"),
+            "[install]\nregistry = \"https://bun.acme.dev\"\n",
+        )
+        .unwrap();
+        let user = UserConfig::default();
+        let read = || read_registries(Some(&project), &user);
+
+        // Without a sign of Bun, its configuration is left alone.
+        let registries = read().await;
+        assert_eq!(
+            registries.default_registry.as_deref(),
+            Some("https://npm.acme.dev")
+        );
+        std::fs::write(project.join("bun.lockb"), []).unwrap();
+        let registries = read().await;
+        assert_eq!(
+            registries.default_registry.as_deref(),
+            Some("https://bun.acme.dev")
+        );
+        assert_eq!(
+            registries.scope_registries["@acme"],
+            "https://npm.acme.dev/scoped"
+        );
+        // `packageManager` decides over the lockfile.
+        std::fs::write(
+            project.join("package.json"),
+            r#"{ "packageManager": "npm@10.8.2" }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            read().await.default_registry.as_deref(),
+            Some("https://npm.acme.dev")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_project_directory() {
+        let root = TempDir::new("npmrc-projects");
+        let app = root.join("packages/app");
+        std::fs::create_dir_all(app.join("src")).unwrap();
+        std::fs::write(
+            root.join("packages/.npmrc"),
+            "registry=https://npm.acme.dev\n",
+        )
+        .unwrap();
+
+        let document = app.join("package.json");
+        assert_eq!(
+            project_directory(&document, &root).await,
+            Some(root.join("packages"))
+        );
+        std::fs::write(app.join(".npmrc"), "registry=https://npm.acme.dev/app\n").unwrap();
+        assert_eq!(project_directory(&document, &root).await, Some(app.clone()));
+        // Nothing outside of the workspace folder is looked at.
+        assert_eq!(project_directory(&document, &app.join("src")).await, None);
+        assert_eq!(
+            project_directory(&root.join("package.json"), &root).await,
+            None
+        );
+    }
+}
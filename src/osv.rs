@@ -0,0 +1,300 @@
+//! Known vulnerabilities of the installed versions of packages, from the OSV database.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures::future::join_all;
+use serde_json::{json, Value};
+
+use crate::fetcher::PackageVersionFetcher;
+
+const OSV_API: &str = "https://api.osv.dev/v1";
+
+/// Advisories are published and amended over days, not minutes, so they are asked for rarely.
+const ADVISORY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A package at the version it installs, like `("lodash", "4.17.20")`.
+pub(super) type Installed = (String, String);
+
+/// How bad an advisory rates a vulnerability, as the GitHub advisory database puts it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Severity {
+    Low,
+    Moderate,
+    High,
+    Critical,
+}
+
+impl Severity {
+    fn parse(severity: &str) -> Option<Self> {
+        match severity.to_ascii_uppercase().as_str() {
+            "LOW" => Some(Self::Low),
+            "MODERATE" | "MEDIUM" => Some(Self::Moderate),
+            "HIGH" => Some(Self::High),
+            "CRITICAL" => Some(Self::Critical),
+            _ => None,
+        }
+    }
+
+    pub(super) fn label(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Moderate => "moderate",
+            Self::High => "high",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Advisory {
+    /// Like `GHSA-35jh-r3h4-6jhm` or `CVE-2021-23337`.
+    pub id: String,
+    /// `None` when the advisory doesn't rate the vulnerability.
+    pub severity: Option<Severity>,
+}
+
+/// Remembers the advisories of each installed package, along with their severity.
+#[derive(Default)]
+pub(super) struct AdvisoryCache {
+    packages: Mutex<HashMap<Installed, (Instant, Vec<Advisory>)>>,
+    /// The severity of each advisory, which many packages and versions share.
+    severities: Mutex<HashMap<String, (Instant, Option<Severity>)>>,
+}
+
+impl AdvisoryCache {
+    /// The advisories of every package, asking OSV in a single batch about the ones that aren't
+    /// cached. Returns `None` when OSV couldn't be asked, so that nothing is reported instead of
+    /// every package looking safe. Offline, only the cached packages are answered for.
+    pub(super) async fn check(
+        &self,
+        fetcher: &PackageVersionFetcher,
+        packages: &[Installed],
+    ) -> Option<HashMap<Installed, Vec<Advisory>>> {
+        let mut known = HashMap::new();
+        let mut unknown = Vec::new();
+        {
+            let cache = self.packages.lock().unwrap();
+            for package in packages {
+                match cache.get(package) {
+                    Some((fetched, advisories)) if fetched.elapsed() < ADVISORY_TTL => {
+                        known.insert(package.clone(), advisories.clone());
+                    }
+                    _ => unknown.push(package.clone()),
+                }
+            }
+        }
+        if unknown.is_empty() || fetcher.is_offline() {
+            return Some(known);
+        }
+        let ids = query_batch(fetcher, &unknown).await?;
+        // The batch only names the advisories, so the severity of the ones that aren't cached is
+        // looked up one by one.
+        let mut severities = HashMap::new();
+        let mut unrated = Vec::new();
+        {
+            let cache = self.severities.lock().unwrap();
+            for id in ids.iter().flatten() {
+                match cache.get(id) {
+                    Some((fetched, severity)) if fetched.elapsed() < ADVISORY_TTL => {
+                        severities.insert(id.clone(), *severity);
+                    }
+                    _ => unrated.push(id.clone()),
+                }
+            }
+        }
+        unrated.sort();
+        unrated.dedup();
+        let fetched = join_all(unrated.into_iter().map(|id| async move {
+            let severity = fetch_severity(fetcher, &id).await;
+            (id, severity)
+        }))
+        .await;
+        {
+            let mut cache = self.severities.lock().unwrap();
+            for (id, severity) in fetched {
+                // Advisories that couldn't be looked up are asked about again next time.
+                if let Some(severity) = severity {
+                    cache.insert(id.clone(), (Instant::now(), severity));
+                }
+                severities.insert(id, severity.flatten());
+            }
+        }
+        let mut cache = self.packages.lock().unwrap();
+        for (package, ids) in unknown.into_iter().zip(ids) {
+            let advisories: Vec<_> = ids
+                .into_iter()
+                .map(|id| Advisory {
+                    severity: severities.get(&id).copied().flatten(),
+                    id,
+                })
+                .collect();
+            cache.insert(package.clone(), (Instant::now(), advisories.clone()));
+            known.insert(package, advisories);
+        }
+        Some(known)
+    }
+}
+
+#[cfg(test)]
+impl AdvisoryCache {
+    pub(super) fn insert_for_tests(&self, package: Installed, advisories: Vec<Advisory>) {
+        self.packages
+            .lock()
+            .unwrap()
+            .insert(package, (Instant::now(), advisories));
+    }
+}
+
+/// Asks OSV which advisories affect each package, in the order they were given.
+async fn query_batch(
+    fetcher: &PackageVersionFetcher,
+    packages: &[Installed],
+) -> Option<Vec<Vec<String>>> {
+    let queries: Vec<_> = packages
+        .iter()
+        .map(|(name, version)| {
+            json!({ "package": { "name": name, "ecosystem": "npm" }, "version": version })
+        })
+        .collect();
+    let response = fetcher
+        .request_json(|client| {
+            client
+                .post(format!("{OSV_API}/querybatch"))
+                .json(&json!({ "queries": queries }))
+        })
+        .await?;
+    let ids = parse_batch(&response)?;
+    (ids.len() == packages.len()).then_some(ids)
+}
+
+fn parse_batch(response: &Value) -> Option<Vec<Vec<String>>> {
+    let results = response["results"].as_array()?.iter().map(|result| {
+        result["vulns"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|vulnerability| Some(vulnerability["id"].as_str()?.to_string()))
+            .collect()
+    });
+    Some(results.collect())
+}
+
+/// `Some(None)` when the advisory doesn't rate the vulnerability, `None` when it couldn't be asked
+/// for.
+async fn fetch_severity(fetcher: &PackageVersionFetcher, id: &str) -> Option<Option<Severity>> {
+    let vulnerability = fetcher
+        .request_json(|client| client.get(format!("{OSV_API}/vulns/{}", urlencoding::encode(id))))
+        .await?;
+    Some(parse_severity(&vulnerability))
+}
+
+/// The severity GitHub's advisory database rated a vulnerability with, which OSV passes along.
+fn parse_severity(vulnerability: &Value) -> Option<Severity> {
+    Severity::parse(vulnerability["database_specific"]["severity"].as_str()?)
+}
+
+/// Where OSV describes an advisory.
+pub(super) fn advisory_page(id: &str) -> String {
+    format!("https://osv.dev/vulnerability/{}", urlencoding::encode(id))
+}
+
+/// The worst severity among the advisories, if any of them is rated.
+pub(super) fn max_severity(advisories: &[Advisory]) -> Option<Severity> {
+    advisories
+        .iter()
+        .filter_map(|advisory| advisory.severity)
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::{FetchMode, RegistryConfig};
+
+    fn installed(name: &str, version: &str) -> Installed {
+        (name.to_string(), version.to_string())
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let ids = parse_batch(&json!({
+            "results": [
+                { "vulns": [
+                    { "id": "GHSA-35jh-r3h4-6jhm", "modified": "2024-02-02T00:00:00Z" },
+                    { "id": "GHSA-29mw-wpgm-hmr9", "modified": "2024-02-02T00:00:00Z" }
+                ] },
+                {}
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            ids,
+            [
+                vec!["GHSA-35jh-r3h4-6jhm", "GHSA-29mw-wpgm-hmr9"],
+                Vec::<&str>::new()
+            ]
+        );
+        assert_eq!(parse_batch(&json!({})), None);
+    }
+
+    #[test]
+    fn test_severity() {
+        assert_eq!(
+            parse_severity(&json!({ "database_specific": { "severity": "MODERATE" } })),
+            Some(Severity::Moderate)
+        );
+        assert_eq!(parse_severity(&json!({ "id": "CVE-2021-23337" })), None);
+        let advisory = |id: &str, severity| Advisory {
+            id: id.into(),
+            severity,
+        };
+        assert_eq!(
+            max_severity(&[
+                advisory("GHSA-1", Some(Severity::Moderate)),
+                advisory("GHSA-2", None),
+                advisory("GHSA-3", Some(Severity::High)),
+            ]),
+            Some(Severity::High)
+        );
+        assert_eq!(max_severity(&[advisory("GHSA-2", None)]), None);
+    }
+
+    #[tokio::test]
+    async fn test_cached_advisories() {
+        let cache = AdvisoryCache::default();
+        let advisories = vec![Advisory {
+            id: "GHSA-35jh-r3h4-6jhm".into(),
+            severity: Some(Severity::High),
+        }];
+        cache.packages.lock().unwrap().extend([
+            (
+                installed("lodash", "4.17.20"),
+                (Instant::now(), advisories.clone()),
+            ),
+            (installed("react", "18.3.1"), (Instant::now(), Vec::new())),
+        ]);
+        // Cached packages are answered without asking OSV, which isn't asked about the others
+        // offline.
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            fetch_mode: FetchMode::Offline,
+            ..Default::default()
+        });
+        let checked = cache
+            .check(
+                &fetcher,
+                &[
+                    installed("lodash", "4.17.20"),
+                    installed("react", "18.3.1"),
+                    installed("minimist", "1.2.5"),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq!(checked[&installed("lodash", "4.17.20")], advisories);
+        assert!(checked[&installed("react", "18.3.1")].is_empty());
+        assert!(!checked.contains_key(&installed("minimist", "1.2.5")));
+    }
+}
@@ -1,82 +1,964 @@
-use std::sync::Arc;
+use std::ops;
+use std::sync::{Arc, OnceLock};
 
-use tower_lsp::lsp_types::{Position, Range};
-use tree_sitter::{Point, Query, QueryCursor, Tree};
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+use tree_sitter::{Node, Point, Query, QueryCursor, Tree};
 use tree_sitter_json::language;
 
+/// The section of `package.json` that a dependency is declared in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum DependencyKind {
+    Dependencies,
+    DevDependencies,
+    PeerDependencies,
+    OptionalDependencies,
+    BundledDependencies,
+}
+
+impl DependencyKind {
+    pub(super) const ALL: [Self; 5] = [
+        Self::Dependencies,
+        Self::DevDependencies,
+        Self::PeerDependencies,
+        Self::OptionalDependencies,
+        Self::BundledDependencies,
+    ];
+
+    /// A reminder of the section's install semantics, shown in hover.
+    pub(super) fn hover_note(self) -> Option<&'static str> {
+        match self {
+            Self::OptionalDependencies => Some("(optional — install failure is non-fatal)"),
+            Self::PeerDependencies => Some("(peer — expected to be provided by the consumer)"),
+            Self::Dependencies | Self::DevDependencies | Self::BundledDependencies => None,
+        }
+    }
+
+    /// The key the section is declared under in `package.json`.
+    pub(super) fn section_name(self) -> &'static str {
+        match self {
+            Self::Dependencies => "dependencies",
+            Self::DevDependencies => "devDependencies",
+            Self::PeerDependencies => "peerDependencies",
+            Self::OptionalDependencies => "optionalDependencies",
+            Self::BundledDependencies => "bundledDependencies",
+        }
+    }
+
+    pub(super) fn from_section_name(name: &str) -> Option<Self> {
+        match name {
+            "dependencies" => Some(Self::Dependencies),
+            "devDependencies" => Some(Self::DevDependencies),
+            "peerDependencies" => Some(Self::PeerDependencies),
+            "optionalDependencies" => Some(Self::OptionalDependencies),
+            "bundledDependencies" | "bundleDependencies" => Some(Self::BundledDependencies),
+            _ => None,
+        }
+    }
+}
+
+/// Matches the dependencies declared in any of the given sections.
+fn dependency_query_source(sections: &[DependencyKind]) -> String {
+    let mut names = Vec::new();
+    for section in sections {
+        names.push(format!("\"{}\"", section.section_name()));
+        // npm accepts both spellings.
+        if *section == DependencyKind::BundledDependencies {
+            names.push("\"bundleDependencies\"".to_string());
+        }
+    }
+    format!(
+        r#"
+    (pair
+        key: (string (string_content) @root_name)
+        value:
+            (object
+                (pair
+                    key: (string (string_content) @name)
+                    value: (string) @version
+                ) @_dep_specifier
+            )
+        (#any-of? @root_name {})
+    )
+"#,
+        names.join(" ")
+    )
+}
+
+/// The query hover and completion look dependencies up with, compiled once for the sections they
+/// are enabled in.
+pub(super) struct DependencyQuery {
+    sections: Vec<DependencyKind>,
+    /// Missing when no section is enabled, as the predicate needs at least one name.
+    query: Option<Query>,
+}
+
+impl DependencyQuery {
+    pub(super) fn new(sections: &[DependencyKind]) -> Self {
+        let query = (!sections.is_empty())
+            .then(|| Query::new(&language(), &dependency_query_source(sections)).ok())
+            .flatten();
+        Self {
+            sections: sections.to_vec(),
+            query,
+        }
+    }
+
+    pub(super) fn includes(&self, section: DependencyKind) -> bool {
+        self.sections.contains(&section)
+    }
+}
+
+impl Default for DependencyQuery {
+    fn default() -> Self {
+        Self::new(&DependencyKind::ALL)
+    }
+}
+
+const SECTION_QUERY: &str = r#"
+    (document
+        (object
+            (pair
+                key: (string (string_content) @name)
+                value: (object)
+            ) @section
+        )
+    )
+"#;
+
+#[derive(Debug)]
 pub(super) struct ParseResult {
     pub package_name: String,
     pub version: String,
+    pub section: DependencyKind,
     pub match_range: Range,
+    /// Range of the version specifier, excluding the quotes around it.
+    pub version_range: Range,
+    /// Whether the version specifier is a JSON string. It might not be while the user is typing.
+    pub quoted: bool,
 }
 
-pub fn extract_package_name(text: Arc<str>, tree: Tree, position: Position) -> Option<ParseResult> {
-    let point = Point {
-        row: position.line as usize,
-        column: position.character as usize,
-    };
+impl ParseResult {
+    /// Points a dependency declared as `npm:<name>@<range>` at the package it installs, leaving
+    /// `version` and `version_range` with just the range. Returns the name it is installed as.
+    pub(super) fn resolve_npm_alias(&mut self) -> Option<String> {
+        if !self.quoted {
+            return None;
+        }
+        let (package_name, range) = npm_alias(&self.version)?;
+        let prefix = &self.version[..self.version.trim_end().len() - range.len()];
+        self.version_range.start.character += prefix.encode_utf16().count() as u32;
+        let (package_name, range) = (package_name.to_string(), range.to_string());
+        self.version = range;
+        Some(std::mem::replace(&mut self.package_name, package_name))
+    }
+}
+
+/// Splits a specifier like `npm:react@^18`, which installs a package under another name, into the
+/// name of that package and its range. The range is empty when left out.
+pub(super) fn npm_alias(specifier: &str) -> Option<(&str, &str)> {
+    let aliased = specifier.trim().strip_prefix("npm:")?;
+    // The range is separated by the first `@` that doesn't start a scope.
+    Some(match aliased.get(1..).and_then(|rest| rest.find('@')) {
+        Some(ix) => (&aliased[..ix + 1], &aliased[ix + 2..]),
+        None => (aliased, ""),
+    })
+}
+
+/// A dependency declared in one of the dependency sections.
+#[derive(Debug)]
+pub(super) struct Dependency {
+    pub name: String,
+    pub version: String,
+    pub section: DependencyKind,
+    /// Range of the package name, excluding the quotes around it.
+    pub name_range: Range,
+    /// Range of the version specifier, excluding the quotes around it.
+    pub version_range: Range,
+}
 
-    let query_str = r#"
-        (pair
-            key: (string (string_content) @root_name)
-            value:
-                (object
-                    (pair
-                        key: (string (string_content) @name)
-                        value: (string (string_content) @version)
-                    ) @_dep_specifier
-                )+
-            (#any-of? @root_name "dependencies" "devDependencies" "peerDependencies" "optionalDependencies" "bundledDependencies" "bundleDependencies")
-        )+
-    "#;
-
-    let query = Query::new(&language(), query_str).ok()?;
+/// A package named in the array form of `bundleDependencies`, which lists names without versions.
+#[derive(Debug)]
+pub(super) struct BundledName {
+    pub name: String,
+    /// Range of the name, excluding the quotes around it.
+    pub range: Range,
+}
+
+/// A dependency being added to a section, whose name is typed before it has a version.
+#[derive(Debug, PartialEq)]
+pub(super) struct NewDependency {
+    pub section: DependencyKind,
+    /// What has been typed of the name so far, without quotes.
+    pub query: String,
+    /// Range of what has been typed, including its quotes, which the whole entry replaces.
+    pub range: Range,
+    /// Whether other entries follow, so that the new one needs a comma after it.
+    pub needs_comma: bool,
+}
+
+/// A top-level dependency section, such as `"devDependencies": { ... }`.
+#[derive(Debug)]
+pub(super) struct DependencySection {
+    pub kind: DependencyKind,
+    /// Range of the whole section, from its key to the end of its object.
+    pub range: Range,
+    /// Range of the section's key, excluding the quotes around it.
+    pub name_range: Range,
+}
+
+/// A dependency key that the cursor is placed in, which might not have a value yet.
+#[derive(Debug)]
+pub(super) struct DependencyKey {
+    pub name: String,
+    pub section: DependencyKind,
+    /// Range of the typed name, excluding the quotes around it.
+    pub range: Range,
+}
+
+pub fn extract_package_name(
+    text: Arc<str>,
+    tree: Tree,
+    position: Position,
+    dependency_query: &DependencyQuery,
+) -> Option<ParseResult> {
+    let text = &Text::new(&text);
+    let point = to_point(text, position);
+
+    let query = dependency_query.query.as_ref()?;
     let mut cursor = QueryCursor::new();
 
     let root_node = tree.root_node();
-    let matches = cursor.matches(&query, root_node, text.as_bytes());
+    let matches = cursor.matches(query, root_node, text.as_bytes());
     let capture_names = query.capture_names();
     for m in matches {
         let mut package_name = None;
         let mut version = None;
+        let mut section = None;
         let mut match_range = None;
+        let mut pair_range = None;
         for capture in m.captures {
             let capture_name = capture_names[capture.index as usize];
-            if capture_name == "name" {
+            if capture_name == "root_name" {
+                section = capture
+                    .node
+                    .utf8_text(text.as_bytes())
+                    .ok()
+                    .and_then(DependencyKind::from_section_name);
+                continue;
+            } else if capture_name == "name" {
                 package_name = Some(capture.node.utf8_text(text.as_bytes()).ok()?.to_string());
             } else if capture_name == "version" {
-                version = Some(capture.node.utf8_text(text.as_bytes()).ok()?.to_string());
-            } else if capture_name == "root_name" {
+                version = Some(capture.node);
+            } else if capture_name == "_dep_specifier" {
+                let node_range = capture.node.range();
+                if node_range.start_point <= point && node_range.end_point >= point {
+                    pair_range = Some(to_range(text, node_range.start_point, node_range.end_point));
+                }
+                continue;
+            } else {
                 continue;
             }
             let node_range = capture.node.range();
             if node_range.start_point <= point && node_range.end_point >= point {
-                if capture_name != "name" && capture_name != "version" {
-                    continue;
-                }
-                match_range = Some(Range {
-                    start: Position {
-                        line: node_range.start_point.row as u32,
-                        character: node_range.start_point.column as u32,
-                    },
-                    end: Position {
-                        line: node_range.end_point.row as u32,
-                        character: node_range.end_point.column as u32,
-                    },
-                });
+                match_range = Some(to_range(text, node_range.start_point, node_range.end_point));
             }
         }
-        if let Some(((package_name, match_range), version)) =
-            package_name.zip(match_range).zip(version)
+        // Between the name and the version, e.g. on the colon, the whole pair is highlighted.
+        let match_range = match_range.or(pair_range);
+        if let Some((((package_name, match_range), version), section)) =
+            package_name.zip(match_range).zip(version).zip(section)
         {
+            let (version_bytes, version_range) = string_contents(version, text);
             return Some(ParseResult {
                 package_name,
+                version: text.get(version_bytes)?.to_string(),
+                section,
                 match_range,
-                version,
+                version_range,
+                quoted: true,
             });
         }
     }
-    None
+    extract_bare_version(text, &tree, point)
+        .filter(|result| dependency_query.includes(result.section))
+}
+
+/// Matches dependencies in every section, whichever ones hover and completion are enabled in.
+/// Compiled on first use, as it's needed on most requests.
+static ALL_DEPENDENCIES_QUERY: OnceLock<Option<Query>> = OnceLock::new();
+
+static SECTIONS_QUERY: OnceLock<Option<Query>> = OnceLock::new();
+
+fn all_dependencies_query() -> Option<&'static Query> {
+    ALL_DEPENDENCIES_QUERY
+        .get_or_init(|| {
+            Query::new(&language(), &dependency_query_source(&DependencyKind::ALL)).ok()
+        })
+        .as_ref()
+}
+
+fn sections_query() -> Option<&'static Query> {
+    SECTIONS_QUERY
+        .get_or_init(|| Query::new(&language(), SECTION_QUERY).ok())
+        .as_ref()
+}
+
+/// Returns every dependency declared with a string version in the document.
+pub(super) fn extract_all_dependencies(text: &str, tree: &Tree) -> Vec<Dependency> {
+    let text = &Text::new(text);
+    let Some(query) = all_dependencies_query() else {
+        return Vec::new();
+    };
+    let root_name = query.capture_index_for_name("root_name");
+    let name = query.capture_index_for_name("name");
+    let version = query.capture_index_for_name("version");
+    let mut cursor = QueryCursor::new();
+    cursor
+        .matches(query, tree.root_node(), text.as_bytes())
+        .filter_map(|m| {
+            let node = |index| {
+                m.captures
+                    .iter()
+                    .find(|capture| Some(capture.index) == index)
+                    .map(|capture| capture.node)
+            };
+            let section = DependencyKind::from_section_name(
+                node(root_name)?.utf8_text(text.as_bytes()).ok()?,
+            )?;
+            let name = node(name)?;
+            let (version_bytes, version_range) = string_contents(node(version)?, text);
+            Some(Dependency {
+                name: name.utf8_text(text.as_bytes()).ok()?.to_string(),
+                version: text.get(version_bytes)?.to_string(),
+                section,
+                name_range: to_range(text, name.start_position(), name.end_position()),
+                version_range,
+            })
+        })
+        .collect()
+}
+
+/// Returns the names listed in a `bundleDependencies` array, the form npm documents for it. The
+/// object form is a regular dependency section.
+pub(super) fn extract_bundled_names(text: &str, tree: &Tree) -> Vec<BundledName> {
+    let text = &Text::new(text);
+    let mut names = Vec::new();
+    let Some(document) = tree.root_node().named_child(0) else {
+        return names;
+    };
+    let mut cursor = document.walk();
+    for field in document.named_children(&mut cursor) {
+        let Some((key, value)) = field
+            .child_by_field_name("key")
+            .zip(field.child_by_field_name("value"))
+        else {
+            continue;
+        };
+        let (key_bytes, _) = string_contents(key, text);
+        let is_bundled = text
+            .get(key_bytes)
+            .and_then(DependencyKind::from_section_name)
+            == Some(DependencyKind::BundledDependencies);
+        if !is_bundled || value.kind() != "array" {
+            continue;
+        }
+        let mut cursor = value.walk();
+        for entry in value.named_children(&mut cursor) {
+            if entry.kind() != "string" {
+                continue;
+            }
+            let (entry_bytes, range) = string_contents(entry, text);
+            if let Some(name) = text.get(entry_bytes) {
+                names.push(BundledName {
+                    name: name.to_string(),
+                    range,
+                });
+            }
+        }
+    }
+    names
+}
+
+/// The name in a `bundleDependencies` array that the cursor is placed in.
+pub(super) fn extract_bundled_name(
+    text: &str,
+    tree: &Tree,
+    position: Position,
+) -> Option<BundledName> {
+    extract_bundled_names(text, tree)
+        .into_iter()
+        .find(|entry| entry.range.start <= position && position <= entry.range.end)
+}
+
+/// Returns the dependency sections declared at the top level of the document.
+pub(super) fn extract_sections(text: &str, tree: &Tree) -> Vec<DependencySection> {
+    let text = &Text::new(text);
+    let Some(query) = sections_query() else {
+        return Vec::new();
+    };
+    let name = query.capture_index_for_name("name");
+    let section = query.capture_index_for_name("section");
+    let mut cursor = QueryCursor::new();
+    cursor
+        .matches(query, tree.root_node(), text.as_bytes())
+        .filter_map(|m| {
+            let node = |index| {
+                m.captures
+                    .iter()
+                    .find(|capture| Some(capture.index) == index)
+                    .map(|capture| capture.node)
+            };
+            let (name, section) = (node(name)?, node(section)?);
+            Some(DependencySection {
+                kind: DependencyKind::from_section_name(name.utf8_text(text.as_bytes()).ok()?)?,
+                range: to_range(text, section.start_position(), section.end_position()),
+                name_range: to_range(text, name.start_position(), name.end_position()),
+            })
+        })
+        .collect()
+}
+
+/// Top-level fields whose keys name packages, without declaring dependencies themselves.
+const PACKAGE_KEYED_FIELDS: &[&str] = &[
+    "overrides",
+    "resolutions",
+    "peerDependenciesMeta",
+    "dependenciesMeta",
+];
+
+/// Returns every place in the document that refers to the package by name: its keys in the
+/// dependency sections, keys of package-keyed fields such as `overrides` (including nested ones and
+/// the name part of `name@range` keys), and entries of a `bundleDependencies` array.
+pub(super) fn extract_package_references(
+    text: &str,
+    tree: &Tree,
+    package_name: &str,
+) -> Vec<Range> {
+    let text = &Text::new(text);
+    let mut references: Vec<_> = extract_all_dependencies(text, tree)
+        .into_iter()
+        .filter(|dependency| dependency.name == package_name)
+        .map(|dependency| dependency.name_range)
+        .collect();
+    let Some(document) = tree.root_node().named_child(0) else {
+        return references;
+    };
+    let mut cursor = document.walk();
+    for field in document.named_children(&mut cursor) {
+        let Some((key, value)) = field
+            .child_by_field_name("key")
+            .zip(field.child_by_field_name("value"))
+        else {
+            continue;
+        };
+        let (key_bytes, _) = string_contents(key, text);
+        match text.get(key_bytes) {
+            Some(key) if PACKAGE_KEYED_FIELDS.contains(&key) => {
+                collect_key_references(value, text, package_name, &mut references);
+            }
+            Some("bundledDependencies" | "bundleDependencies") if value.kind() == "array" => {
+                let mut cursor = value.walk();
+                for entry in value.named_children(&mut cursor) {
+                    let (entry_bytes, range) = string_contents(entry, text);
+                    if entry.kind() == "string" && text.get(entry_bytes) == Some(package_name) {
+                        references.push(range);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    references.sort_by_key(|range| range.start);
+    references
+}
+
+/// Collects the keys of `object` and of the objects nested in it that name the package.
+fn collect_key_references(
+    object: Node,
+    text: &Text,
+    package_name: &str,
+    references: &mut Vec<Range>,
+) {
+    if object.kind() != "object" {
+        return;
+    }
+    let mut cursor = object.walk();
+    for pair in object.named_children(&mut cursor) {
+        let Some(key) = pair.child_by_field_name("key") else {
+            continue;
+        };
+        let (key_bytes, range) = string_contents(key, text);
+        let key_text = text.get(key_bytes).unwrap_or_default();
+        let is_reference = key_text
+            .strip_prefix(package_name)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('@'));
+        if is_reference {
+            let mut end = key.start_position();
+            // Keys don't span lines, so the name ends right after its first quote.
+            end.column += 1 + package_name.len();
+            references.push(Range::new(range.start, to_position(text, end)));
+        }
+        if let Some(value) = pair.child_by_field_name("value") {
+            collect_key_references(value, text, package_name, references);
+        }
+    }
+}
+
+/// Returns the range of a top-level field's value, such as the version of a package.
+pub(super) fn extract_field(tree: &Tree, text: &str, field: &str) -> Option<Range> {
+    let text = &Text::new(text);
+    let document = tree.root_node().named_child(0)?;
+    let mut cursor = document.walk();
+    let pair = document.named_children(&mut cursor).find(|pair| {
+        pair.child_by_field_name("key")
+            .is_some_and(|key| text.get(string_contents(key, text).0) == Some(field))
+    })?;
+    let value = pair.child_by_field_name("value")?;
+    Some(to_range(text, value.start_position(), value.end_position()))
+}
+
+/// The edit that declares a new dependency in a section: between the existing entries so that
+/// they stay in alphabetical order, or in a section added at the end of the document when it's
+/// missing. Indentation follows the document's, and entries on a single line stay on it.
+pub(super) fn insert_dependency_edit(
+    text: &str,
+    tree: &Tree,
+    section: DependencyKind,
+    name: &str,
+    version: &str,
+) -> Option<TextEdit> {
+    let text = &Text::new(text);
+    let document = tree.root_node().named_child(0)?;
+    if document.kind() != "object" {
+        return None;
+    }
+    let entry = format!("\"{name}\": \"{version}\"");
+    let mut cursor = document.walk();
+    let fields: Vec<_> = document
+        .named_children(&mut cursor)
+        .filter(|node| node.kind() == "pair")
+        .collect();
+    let unit = fields
+        .first()
+        .map(|field| line_indent(text, field.start_position().row))
+        .filter(|indent| !indent.is_empty())
+        .unwrap_or("  ");
+    let section_value = fields.iter().find_map(|field| {
+        let key = field.child_by_field_name("key")?;
+        let value = field.child_by_field_name("value")?;
+        (text.get(string_contents(key, text).0) == Some(section.section_name())
+            && value.kind() == "object")
+            .then_some((field, value))
+    });
+    let Some((field, object)) = section_value else {
+        // A new section, as the last field of the document.
+        let new_text = format!(
+            "\"{}\": {{\n{unit}{unit}{entry}\n{unit}}}",
+            section.section_name()
+        );
+        return Some(append_to_object(
+            text, document, &fields, unit, "", &new_text,
+        ));
+    };
+    let mut cursor = object.walk();
+    let entries: Vec<_> = object
+        .named_children(&mut cursor)
+        .filter(|node| node.kind() == "pair")
+        .collect();
+    let outer_indent = line_indent(text, field.start_position().row);
+    let next = entries.iter().find(|entry| {
+        entry
+            .child_by_field_name("key")
+            .and_then(|key| text.get(string_contents(key, text).0))
+            .is_some_and(|key| key > name)
+    });
+    let Some(next) = next else {
+        return Some(append_to_object(
+            text,
+            object,
+            &entries,
+            unit,
+            outer_indent,
+            &entry,
+        ));
+    };
+    let separator = if next.start_position().row == object.start_position().row {
+        " ".to_string()
+    } else {
+        format!("\n{}", line_indent(text, next.start_position().row))
+    };
+    let start = to_range(text, next.start_position(), next.start_position()).start;
+    Some(TextEdit {
+        range: Range::new(start, start),
+        new_text: format!("{entry},{separator}"),
+    })
+}
+
+/// Edits that sort the entries of every dependency section by name, case-insensitively, leaving
+/// sections that are already sorted alone. Entries move between the separators already there, so
+/// that indentation and the commas between them stay as they are.
+pub(super) fn sort_dependencies_edits(text: &str, tree: &Tree) -> Vec<TextEdit> {
+    let text = &Text::new(text);
+    let Some(document) = tree.root_node().named_child(0) else {
+        return Vec::new();
+    };
+    let mut cursor = document.walk();
+    let sections: Vec<_> = document
+        .named_children(&mut cursor)
+        .filter_map(|field| {
+            let key = field.child_by_field_name("key")?;
+            DependencyKind::from_section_name(text.get(string_contents(key, text).0)?)?;
+            field
+                .child_by_field_name("value")
+                .filter(|value| value.kind() == "object")
+        })
+        .collect();
+    sections
+        .into_iter()
+        .filter_map(|object| {
+            let mut cursor = object.walk();
+            let entries: Vec<_> = object
+                .named_children(&mut cursor)
+                .filter(|node| node.kind() == "pair")
+                .filter_map(|pair| {
+                    let key = pair.child_by_field_name("key")?;
+                    let name = text.get(string_contents(key, text).0)?;
+                    Some((name.to_lowercase(), pair))
+                })
+                .collect();
+            let mut sorted = entries.clone();
+            sorted.sort_by(|(a, _), (b, _)| a.cmp(b));
+            if sorted
+                .iter()
+                .map(|(name, _)| name)
+                .eq(entries.iter().map(|(name, _)| name))
+            {
+                return None;
+            }
+            let (first, last) = (entries.first()?.1, entries.last()?.1);
+            let mut new_text = String::new();
+            for (ix, (_, pair)) in sorted.iter().enumerate() {
+                new_text.push_str(&text[pair.byte_range()]);
+                if let Some((_, next)) = entries.get(ix + 1) {
+                    let (_, current) = entries[ix];
+                    new_text.push_str(&text[current.end_byte()..next.start_byte()]);
+                }
+            }
+            Some(TextEdit {
+                range: to_range(text, first.start_position(), last.end_position()),
+                new_text,
+            })
+        })
+        .collect()
+}
+
+/// Removes the entry of a dependency from its section, along with the comma that separates it
+/// from its neighbours: the one after it, or the one before it when it's the last entry.
+pub(super) fn remove_dependency_edit(
+    text: &str,
+    tree: &Tree,
+    dependency: &Dependency,
+) -> Option<TextEdit> {
+    let text = &Text::new(text);
+    let point = to_point(text, dependency.name_range.start);
+    let mut pair = tree.root_node().descendant_for_point_range(point, point)?;
+    while pair.kind() != "pair" {
+        pair = pair.parent()?;
+    }
+    let object = pair.parent()?;
+    let mut cursor = object.walk();
+    let entries: Vec<_> = object
+        .named_children(&mut cursor)
+        .filter(|node| node.kind() == "pair")
+        .collect();
+    let ix = entries.iter().position(|entry| *entry == pair)?;
+    let range = match (ix.checked_sub(1).map(|ix| entries[ix]), entries.get(ix + 1)) {
+        (_, Some(next)) => to_range(text, pair.start_position(), next.start_position()),
+        (Some(previous), None) => to_range(text, previous.end_position(), pair.end_position()),
+        // The only entry, so the section is left empty.
+        (None, None) => {
+            let (mut start, mut end) = (object.start_position(), object.end_position());
+            start.column += 1;
+            end.column -= 1;
+            to_range(text, start, end)
+        }
+    };
+    Some(TextEdit {
+        range,
+        new_text: String::new(),
+    })
+}
+
+/// Adds `new_text` after the last of an object's pairs, or in place of the object when it has
+/// none. `indent` is that of the line the object is declared on.
+fn append_to_object(
+    text: &Text,
+    object: Node,
+    pairs: &[Node],
+    unit: &str,
+    indent: &str,
+    new_text: &str,
+) -> TextEdit {
+    let Some(last) = pairs.last() else {
+        return TextEdit {
+            range: to_range(text, object.start_position(), object.end_position()),
+            new_text: format!("{{\n{indent}{unit}{new_text}\n{indent}}}"),
+        };
+    };
+    let separator = if last.start_position().row == object.start_position().row {
+        " ".to_string()
+    } else {
+        format!("\n{}", line_indent(text, last.start_position().row))
+    };
+    let end = to_range(text, last.end_position(), last.end_position()).end;
+    TextEdit {
+        range: Range::new(end, end),
+        new_text: format!(",{separator}{new_text}"),
+    }
+}
+
+/// The whitespace a line starts with.
+fn line_indent<'a>(text: &Text<'a>, row: usize) -> &'a str {
+    let line = text.line(row);
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Returns the dependency key the cursor is in, if any. Unlike [`extract_package_name`], this does
+/// not require the key to have a value, so that it can be used while a new dependency is typed in.
+pub(super) fn extract_dependency_key(
+    text: &str,
+    tree: &Tree,
+    position: Position,
+) -> Option<DependencyKey> {
+    let text = &Text::new(text);
+    let point = to_point(text, position);
+    let node = tree.root_node().descendant_for_point_range(point, point)?;
+    let key =
+        std::iter::successors(Some(node), Node::parent).find(|node| node.kind() == "string")?;
+    let parent = key.parent()?;
+    let is_key = match parent.kind() {
+        "pair" => parent.child_by_field_name("key") == Some(key),
+        "ERROR" => true,
+        _ => false,
+    };
+    if !is_key {
+        return None;
+    }
+    let section = dependency_section(key, text)?;
+    let (name_bytes, range) = string_contents(key, text);
+    let (start, end) = (range.start, range.end);
+    if position < start || position > end {
+        return None;
+    }
+    Some(DependencyKey {
+        name: text.get(name_bytes)?.to_string(),
+        section,
+        range,
+    })
+}
+
+/// Returns the dependency being added at the cursor: a name typed into a dependency section,
+/// quoted or not, that isn't part of an entry yet, or nothing at all in an empty section. The
+/// name is located textually, as tree-sitter only sees errors until the entry is complete.
+pub(super) fn extract_new_dependency(
+    text: &str,
+    tree: &Tree,
+    position: Position,
+) -> Option<NewDependency> {
+    let text = &Text::new(text);
+    let point = to_point(text, position);
+    let node = tree.root_node().descendant_for_point_range(point, point)?;
+    // Entries in progress are errors within the section's object, unlike complete ones.
+    let object = std::iter::successors(Some(node), Node::parent)
+        .take_while(|node| node.kind() != "pair")
+        .find(|node| node.kind() == "object")?;
+    let section = object.parent().filter(|node| node.kind() == "pair")?;
+    let is_top_level = section
+        .parent()
+        .is_some_and(|document| document.parent().map(|node| node.kind()) == Some("document"));
+    if !is_top_level || section.child_by_field_name("value") != Some(object) {
+        return None;
+    }
+    let (section_name, _) = string_contents(section.child_by_field_name("key")?, text);
+    let section = DependencyKind::from_section_name(text.get(section_name)?)?;
+    // Right after an entry, the cursor is still on it rather than on a new one.
+    let mut cursor = object.walk();
+    let on_entry = object.named_children(&mut cursor).any(|entry| {
+        entry.kind() == "pair" && entry.start_position() <= point && point <= entry.end_position()
+    });
+    if on_entry {
+        return None;
+    }
+
+    let is_name_char = |c: char| c.is_alphanumeric() || matches!(c, '@' | '/' | '.' | '_' | '-');
+    let line = text.lines().nth(point.row).unwrap_or_default();
+    let before_cursor = line.get(..point.column)?;
+    let mut start = before_cursor
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !is_name_char(*c))
+        .map_or(0, |(ix, c)| ix + c.len_utf8());
+    let mut end = line[point.column..]
+        .find(|c| !is_name_char(c))
+        .map_or(line.len(), |ix| point.column + ix);
+    let query = line[start..end].to_string();
+    if line[..start].ends_with('"') {
+        start -= 1;
+        if line[end..].starts_with('"') {
+            end += 1;
+        }
+    }
+    let (start, end) = (Point::new(point.row, start), Point::new(point.row, end));
+    let mut cursor = object.walk();
+    let needs_comma = object
+        .named_children(&mut cursor)
+        .any(|entry| entry.kind() == "pair" && entry.start_position() >= end);
+    Some(NewDependency {
+        section,
+        query,
+        range: to_range(text, start, end),
+        needs_comma,
+    })
+}
+
+/// Best-effort recovery for a version that is not a JSON string (yet), e.g. `"lodash": ^4`.
+/// tree-sitter wraps such values in error nodes, so the version token is located textually and
+/// only its key is looked up in the parse tree.
+fn extract_bare_version(text: &Text, tree: &Tree, point: Point) -> Option<ParseResult> {
+    let is_delimiter =
+        |c: char| c.is_whitespace() || matches!(c, ':' | ',' | '{' | '}' | '[' | ']' | '"');
+    let line = text.lines().nth(point.row)?;
+    let before_cursor = line.get(..point.column)?;
+    let token_start = before_cursor
+        .char_indices()
+        .rev()
+        .find(|(_, c)| is_delimiter(*c))
+        .map_or(0, |(ix, c)| ix + c.len_utf8());
+    let token_end = line[point.column..]
+        .find(is_delimiter)
+        .map_or(line.len(), |ix| point.column + ix);
+
+    let key_end = line[..token_start]
+        .trim_end()
+        .strip_suffix(':')?
+        .trim_end()
+        .strip_suffix('"')?
+        .len();
+    let key_point = Point::new(point.row, key_end);
+    let key = tree
+        .root_node()
+        .descendant_for_point_range(key_point, key_point)?;
+    let key =
+        std::iter::successors(Some(key), Node::parent).find(|node| node.kind() == "string")?;
+    let section = dependency_section(key, text)?;
+
+    let (key_bytes, _) = string_contents(key, text);
+    let version_range = to_range(
+        text,
+        Point::new(point.row, token_start),
+        Point::new(point.row, token_end),
+    );
+    Some(ParseResult {
+        package_name: text.get(key_bytes)?.to_string(),
+        version: line[token_start..token_end].to_string(),
+        section,
+        match_range: version_range,
+        version_range,
+        quoted: false,
+    })
+}
+
+/// Returns the dependency section that the given key of a dependency belongs to.
+fn dependency_section(key: Node, text: &Text) -> Option<DependencyKind> {
+    let object = key
+        .parent()?
+        .parent()
+        .filter(|node| node.kind() == "object")?;
+    let section = object.parent().filter(|node| node.kind() == "pair")?;
+    let (section_name, _) = string_contents(section.child_by_field_name("key")?, text);
+    DependencyKind::from_section_name(text.get(section_name)?)
+}
+
+/// Byte range and position range of a string node's contents, without the surrounding quotes.
+fn string_contents(node: Node, text: &Text) -> (ops::Range<usize>, Range) {
+    let mut bytes = node.byte_range();
+    let (mut start, mut end) = (node.start_position(), node.end_position());
+    if text[bytes.clone()].starts_with('"') {
+        bytes.start += 1;
+        start.column += 1;
+    }
+    if !bytes.is_empty() && text[bytes.clone()].ends_with('"') {
+        bytes.end -= 1;
+        end.column -= 1;
+    }
+    (bytes, to_range(text, start, end))
+}
+
+fn to_range(text: &Text, start: Point, end: Point) -> Range {
+    Range::new(to_position(text, start), to_position(text, end))
+}
+
+/// A document's text along with the offsets its lines start at, found once so that converting
+/// the points of its nodes doesn't scan the lines before each of them.
+struct Text<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Text<'a> {
+    fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(ix, _)| ix + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    /// The line at `row`, without its `\n` or `\r\n` ending.
+    fn line(&self, row: usize) -> &'a str {
+        let Some(&start) = self.line_starts.get(row) else {
+            return "";
+        };
+        let end = self
+            .line_starts
+            .get(row + 1)
+            .map_or(self.text.len(), |next| next - 1);
+        let line = &self.text[start..end];
+        line.strip_suffix('\r').unwrap_or(line)
+    }
+}
+
+impl ops::Deref for Text<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.text
+    }
+}
+
+/// Position of a point, as tree-sitter counts columns in bytes while LSP counts them in UTF-16
+/// code units.
+fn to_position(text: &Text, point: Point) -> Position {
+    let line = text.line(point.row);
+    let column = line
+        .get(..point.column)
+        .map_or(point.column, |before| before.encode_utf16().count());
+    Position::new(point.row as u32, column as u32)
+}
+
+/// Point of a position, the inverse of [`to_position`]. Positions past the end of a line are
+/// clamped to it.
+fn to_point(text: &Text, position: Position) -> Point {
+    let line = text.line(position.line as usize);
+    let mut units = 0;
+    let column = line
+        .char_indices()
+        .find(|(_, c)| {
+            let reached = units >= position.character as usize;
+            units += c.len_utf16();
+            reached
+        })
+        .map_or(line.len(), |(ix, _)| ix);
+    Point::new(position.line as usize, column)
 }
 
 #[cfg(test)]
@@ -84,6 +966,34 @@ mod tests {
     use super::*;
     use tower_lsp::lsp_types::Position;
 
+    fn parse(text: &str) -> Tree {
+        let mut parser = tree_sitter::Parser::new();
+        parser.set_language(&language()).unwrap();
+        parser.parse(text, None).unwrap()
+    }
+
+    fn extract(text: &str, line: u32, character: u32) -> Option<ParseResult> {
+        extract_package_name(
+            text.into(),
+            parse(text),
+            Position { line, character },
+            &DependencyQuery::default(),
+        )
+    }
+
+    fn range(line: u32, start: u32, end: u32) -> Range {
+        Range {
+            start: Position {
+                line,
+                character: start,
+            },
+            end: Position {
+                line,
+                character: end,
+            },
+        }
+    }
+
     #[test]
     fn test_parse_package_json() {
         let package = r#"{
@@ -92,27 +1002,535 @@ mod tests {
   }
 }
 "#;
+        let result = extract(package, 2, 11).unwrap();
+        assert_eq!(result.package_name, "express");
+        assert_eq!(result.version, "^4.17.1");
+        assert_eq!(result.section, DependencyKind::Dependencies);
+        assert_eq!(result.match_range, range(2, 5, 12));
+        assert_eq!(result.version_range, range(2, 16, 23));
+        assert!(result.quoted);
+    }
+
+    #[test]
+    fn test_parse_matches_hovered_dependency() {
+        let package = r#"{
+  "devDependencies": {
+    "express": "^4.17.1",
+    "lodash": "",
+    "react": "18"
+  }
+}
+"#;
+        let result = extract(package, 4, 7).unwrap();
+        assert_eq!(result.package_name, "react");
+        assert_eq!(result.version, "18");
+        assert_eq!(result.section, DependencyKind::DevDependencies);
+
+        let result = extract(package, 3, 15).unwrap();
+        assert_eq!(result.package_name, "lodash");
+        assert_eq!(result.version, "");
+        assert_eq!(result.version_range, range(3, 15, 15));
+    }
+
+    #[test]
+    fn test_resolve_npm_alias() {
+        let package = r#"{
+  "dependencies": {
+    "my-react": "npm:react@^18",
+    "ui": "npm:@acme/ui",
+    "lodash": "^4.17.21"
+  }
+}
+"#;
+        let mut result = extract(package, 2, 6).unwrap();
+        assert_eq!(result.resolve_npm_alias().as_deref(), Some("my-react"));
+        assert_eq!(result.package_name, "react");
+        assert_eq!(result.version, "^18");
+        assert_eq!(result.version_range, range(2, 27, 30));
+
+        let mut result = extract(package, 3, 6).unwrap();
+        assert_eq!(result.resolve_npm_alias().as_deref(), Some("ui"));
+        assert_eq!(result.package_name, "@acme/ui");
+        assert_eq!(result.version, "");
+        assert_eq!(result.version_range, range(3, 23, 23));
+
+        let mut result = extract(package, 4, 6).unwrap();
+        assert_eq!(result.resolve_npm_alias(), None);
+        assert_eq!(result.package_name, "lodash");
+        assert_eq!(result.version, "^4.17.21");
+
+        assert_eq!(npm_alias("npm:@acme/ui@1.0.0"), Some(("@acme/ui", "1.0.0")));
+        assert_eq!(npm_alias("^18"), None);
+    }
+
+    #[test]
+    fn test_parse_between_name_and_version() {
+        let package = r#"{
+  "dependencies": {
+    "express": "^4.17.1"
+  }
+}
+"#;
+        let result = extract(package, 2, 13).unwrap();
+        assert_eq!(result.package_name, "express");
+        assert_eq!(result.version, "^4.17.1");
+        assert_eq!(result.match_range, range(2, 4, 24));
+        assert_eq!(result.version_range, range(2, 16, 23));
+    }
+
+    #[test]
+    fn test_parse_bare_version() {
+        let package = r#"{
+  "dependencies": {
+    "lodash": ^4,
+    "react":
+  }
+}
+"#;
+        let result = extract(package, 2, 16).unwrap();
+        assert_eq!(result.package_name, "lodash");
+        assert_eq!(result.version, "^4");
+        assert_eq!(result.version_range, range(2, 14, 16));
+        assert!(!result.quoted);
+
+        let result = extract(package, 3, 12).unwrap();
+        assert_eq!(result.package_name, "react");
+        assert_eq!(result.version, "");
+        assert!(!result.quoted);
+
+        assert!(extract(package, 2, 7).is_none());
+    }
+
+    #[test]
+    fn test_extract_all_dependencies() {
+        let package = r#"{
+  "name": "app",
+  "dependencies": {
+    "express": "^4.17.1",
+    "@scope/pkg": "1.0.0"
+  },
+  "devDependencies": {
+    "typescript": "~5.4"
+  }
+}
+"#;
+        let dependencies = extract_all_dependencies(package, &parse(package));
+        let summary: Vec<_> = dependencies
+            .iter()
+            .map(|dependency| (dependency.name.as_str(), dependency.section))
+            .collect();
         assert_eq!(
+            summary,
+            [
+                ("express", DependencyKind::Dependencies),
+                ("@scope/pkg", DependencyKind::Dependencies),
+                ("typescript", DependencyKind::DevDependencies),
+            ]
+        );
+        assert_eq!(dependencies[1].version, "1.0.0");
+        assert_eq!(dependencies[0].name_range, range(3, 5, 12));
+        assert_eq!(dependencies[0].version_range, range(3, 16, 23));
+    }
+
+    #[test]
+    fn test_queries_are_compiled_once() {
+        let package = r#"{ "dependencies": { "express": "^4.17.1" } }"#;
+        let tree = parse(package);
+        for _ in 0..2 {
+            let dependencies = extract_all_dependencies(package, &tree);
+            assert_eq!(dependencies.len(), 1);
+            assert_eq!(dependencies[0].name, "express");
+            assert_eq!(extract_sections(package, &tree).len(), 1);
+        }
+        // Later calls reuse the queries compiled by the first one.
+        let compiled = |query: &'static OnceLock<Option<Query>>| {
+            query.get().and_then(Option::as_ref).unwrap() as *const Query
+        };
+        assert_eq!(
+            compiled(&ALL_DEPENDENCIES_QUERY),
+            all_dependencies_query().unwrap() as *const Query
+        );
+        assert_eq!(
+            compiled(&SECTIONS_QUERY),
+            sections_query().unwrap() as *const Query
+        );
+    }
+
+    #[test]
+    fn test_extract_sections() {
+        let package = r#"{
+  "scripts": { "build": "tsc" },
+  "dependencies": {
+    "express": "^4.17.1"
+  },
+  "devDependencies": {},
+  "config": { "dependencies": {} }
+}
+"#;
+        let sections = extract_sections(package, &parse(package));
+        let summary: Vec<_> = sections
+            .iter()
+            .map(|section| (section.kind, section.name_range))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (DependencyKind::Dependencies, range(2, 3, 15)),
+                (DependencyKind::DevDependencies, range(5, 3, 18)),
+            ]
+        );
+        assert_eq!(sections[0].range.start, Position::new(2, 2));
+        assert_eq!(sections[0].range.end, Position::new(4, 3));
+    }
+
+    #[test]
+    fn test_extract_dependency_key() {
+        let package = r#"{
+  "devDependencies": {
+    "typescript": "~5.4",
+    "@ty"
+  }
+}
+"#;
+        let tree = parse(package);
+        let key = extract_dependency_key(package, &tree, Position::new(3, 8)).unwrap();
+        assert_eq!(key.name, "@ty");
+        assert_eq!(key.section, DependencyKind::DevDependencies);
+        assert_eq!(key.range, range(3, 5, 8));
+
+        let key = extract_dependency_key(package, &tree, Position::new(2, 6)).unwrap();
+        assert_eq!(key.name, "typescript");
+        assert!(extract_dependency_key(package, &tree, Position::new(2, 20)).is_none());
+    }
+
+    #[test]
+    fn test_extract_new_dependency() {
+        let extract = |text: &str, line, character| {
+            extract_new_dependency(text, &parse(text), Position::new(line, character))
+        };
+        let empty = r#"{ "dependencies": {  } }"#;
+        assert_eq!(
+            extract(empty, 0, 20),
+            Some(NewDependency {
+                section: DependencyKind::Dependencies,
+                query: String::new(),
+                range: range(0, 20, 20),
+                needs_comma: false,
+            })
+        );
+
+        let bare = "{\n  \"devDependencies\": {\n    lod\n  }\n}";
+        let entry = extract(bare, 2, 7).unwrap();
+        assert_eq!(entry.section, DependencyKind::DevDependencies);
+        assert_eq!((entry.query.as_str(), entry.range), ("lod", range(2, 4, 7)));
+
+        let quoted = r#"{ "dependencies": { "@acme/u", "react": "^18.3.1" } }"#;
+        let entry = extract(quoted, 0, 28).unwrap();
+        assert_eq!(entry.query, "@acme/u");
+        assert_eq!(entry.range, range(0, 20, 29));
+        assert!(entry.needs_comma);
+
+        let after = r#"{ "dependencies": { "react": "^18.3.1", lod } }"#;
+        let entry = extract(after, 0, 43).unwrap();
+        assert_eq!((entry.query.as_str(), entry.needs_comma), ("lod", false));
+        // Columns count UTF-16 code units, whatever precedes the name.
+        let accented = r#"{ "dependencies": { "réact": "^18.3.1", lod } }"#;
+        let entry = extract(accented, 0, 40).unwrap();
+        assert_eq!(
+            (entry.query.as_str(), entry.range),
+            ("lod", range(0, 40, 43))
+        );
+
+        // Complete entries, other fields and nested objects aren't new dependencies.
+        assert_eq!(extract(after, 0, 23), None);
+        assert_eq!(extract(r#"{ "scripts": {  } }"#, 0, 15), None);
+        assert_eq!(
+            extract(r#"{ "overrides": { "react": {  } } }"#, 0, 28),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_ignores_other_sections() {
+        let package = r#"{
+  "scripts": {
+    "build": ^4
+  }
+}
+"#;
+        assert!(extract(package, 2, 15).is_none());
+    }
+
+    #[test]
+    fn test_parse_only_matches_enabled_sections() {
+        let package = r#"{
+  "dependencies": {
+    "lodash": "^4.17.0"
+  },
+  "devDependencies": {
+    "typescript": ^5
+  }
+}"#;
+        let query = DependencyQuery::new(&[DependencyKind::DevDependencies]);
+        let extract = |line, character| {
             extract_package_name(
                 package.into(),
-                Position {
-                    line: 2,
-                    character: 11,
-                },
+                parse(package),
+                Position { line, character },
+                &query,
+            )
+        };
+        assert!(extract(2, 6).is_none());
+        assert_eq!(extract(5, 20).unwrap().package_name, "typescript");
+
+        let query = DependencyQuery::new(&[]);
+        assert!(
+            extract_package_name(package.into(), parse(package), Position::new(2, 6), &query)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_extract_bundled_names() {
+        let package = r#"{
+  "bundleDependencies": ["lodash", "", 4],
+  "dependencies": {
+    "lodash": "^4.17.21"
+  }
+}"#;
+        let tree = parse(package);
+        let names: Vec<_> = extract_bundled_names(package, &tree)
+            .into_iter()
+            .map(|entry| (entry.name, entry.range))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("lodash".to_string(), range(1, 26, 32)),
+                (String::new(), range(1, 36, 36))
+            ]
+        );
+        let entry = extract_bundled_name(package, &tree, Position::new(1, 28)).unwrap();
+        assert_eq!(entry.name, "lodash");
+        let entry = extract_bundled_name(package, &tree, Position::new(1, 36)).unwrap();
+        assert_eq!(entry.name, "");
+        assert!(extract_bundled_name(package, &tree, Position::new(3, 6)).is_none());
+
+        let object_form = r#"{ "bundledDependencies": { "lodash": "^4" } }"#;
+        assert!(extract_bundled_names(object_form, &parse(object_form)).is_empty());
+    }
+
+    #[test]
+    fn test_extract_package_references() {
+        let package = r#"{
+  "dependencies": {
+    "lodash": "^4.17.21",
+    "lodash.merge": "^4.6.2"
+  },
+  "devDependencies": {
+    "lodash": "^4.17.21"
+  },
+  "bundleDependencies": ["lodash"],
+  "overrides": {
+    "express": {
+      "lodash@^4": "4.17.21"
+    }
+  },
+  "description": "lodash"
+}
+"#;
+        let references = extract_package_references(package, &parse(package), "lodash");
+        assert_eq!(
+            references,
+            [
+                range(2, 5, 11),
+                range(6, 5, 11),
+                range(8, 26, 32),
+                range(11, 7, 13),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_utf16_columns() {
+        let package = r#"{ "//": "日本", "dependencies": { "lodash": "^4" } }"#;
+        let tree = parse(package);
+        let dependencies = extract_all_dependencies(package, &tree);
+        assert_eq!(dependencies[0].name_range, range(0, 33, 39));
+        assert_eq!(
+            extract_package_references(package, &tree, "lodash"),
+            [range(0, 33, 39)]
+        );
+        let key = extract_dependency_key(package, &tree, Position::new(0, 35)).unwrap();
+        assert_eq!(key.name, "lodash");
+    }
+
+    #[test]
+    fn test_crlf_line_endings() {
+        let package = "{\r\n  \"dependencies\": {\r\n    \"lodash\": \"^4\"\r\n  }\r\n}\r\n";
+        let tree = parse(package);
+        let dependencies = extract_all_dependencies(package, &tree);
+        assert_eq!(dependencies[0].name_range, range(2, 5, 11));
+        let key = extract_dependency_key(package, &tree, Position::new(2, 7)).unwrap();
+        assert_eq!(key.name, "lodash");
+        // Past the end of a line is its end, not its `\r`.
+        let text = Text::new(package);
+        assert_eq!(to_point(&text, Position::new(2, 40)), Point::new(2, 18));
+        assert_eq!(text.line(5), "");
+    }
+
+    #[test]
+    fn test_extract_field() {
+        let package = r#"{
+  "name": "lodash",
+  "dependencies": { "version": "1.0.0" },
+  "version": "4.17.21"
+}
+"#;
+        assert_eq!(
+            extract_field(&parse(package), package, "version"),
+            Some(range(3, 13, 22))
+        );
+        assert_eq!(extract_field(&parse(package), package, "license"), None);
+    }
+
+    #[test]
+    fn test_insert_dependency_edit() {
+        let insert = |text: &str, name: &str| {
+            let edit = insert_dependency_edit(
+                text,
+                &parse(text),
+                DependencyKind::DevDependencies,
+                name,
+                "^4.17.21",
+            )
+            .unwrap();
+            let offset = |position: Position| {
+                let line_start: usize = text
+                    .split_inclusive('\n')
+                    .take(position.line as usize)
+                    .map(str::len)
+                    .sum();
+                line_start + position.character as usize
+            };
+            let mut text = text.to_string();
+            text.replace_range(
+                offset(edit.range.start)..offset(edit.range.end),
+                &edit.new_text,
+            );
+            text
+        };
+
+        let package = "{\n  \"devDependencies\": {\n    \"@types/express\": \"^4.17.0\",\n    \"typescript\": \"^5.6.3\"\n  }\n}\n";
+        assert_eq!(
+            insert(package, "@types/lodash"),
+            "{\n  \"devDependencies\": {\n    \"@types/express\": \"^4.17.0\",\n    \"@types/lodash\": \"^4.17.21\",\n    \"typescript\": \"^5.6.3\"\n  }\n}\n"
+        );
+        assert_eq!(
+            insert(package, "vitest"),
+            "{\n  \"devDependencies\": {\n    \"@types/express\": \"^4.17.0\",\n    \"typescript\": \"^5.6.3\",\n    \"vitest\": \"^4.17.21\"\n  }\n}\n"
+        );
+        assert_eq!(
+            insert("{\n\t\"devDependencies\": {}\n}", "@types/lodash"),
+            "{\n\t\"devDependencies\": {\n\t\t\"@types/lodash\": \"^4.17.21\"\n\t}\n}"
+        );
+        assert_eq!(
+            insert(
+                "{ \"devDependencies\": { \"vitest\": \"^2.0.0\" } }",
+                "@types/lodash"
             ),
-            Some((
-                "express".into(),
-                Range {
-                    start: Position {
-                        line: 2,
-                        character: 5,
-                    },
-                    end: Position {
-                        line: 2,
-                        character: 12,
-                    },
-                }
-            ))
+            "{ \"devDependencies\": { \"@types/lodash\": \"^4.17.21\", \"vitest\": \"^2.0.0\" } }"
+        );
+        assert_eq!(
+            insert("{\n    \"dependencies\": { \"lodash\": \"^4.17.21\" }\n}", "@types/lodash"),
+            "{\n    \"dependencies\": { \"lodash\": \"^4.17.21\" },\n    \"devDependencies\": {\n        \"@types/lodash\": \"^4.17.21\"\n    }\n}"
+        );
+        assert_eq!(
+            insert("{}", "@types/lodash"),
+            "{\n  \"devDependencies\": {\n    \"@types/lodash\": \"^4.17.21\"\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn test_sort_dependencies_edits() {
+        let package = r#"{
+  "name": "app",
+  "dependencies": {
+    "react": "^18.3.1",
+    "@acme/ui": "^1.0.0",
+    "Lodash": "^4.17.21",
+    "@acme/icons": "^1.0.0"
+  },
+  "devDependencies": { "typescript": "^5.6.3", "vitest": "^2.1.0" }
+}"#;
+        let edits = sort_dependencies_edits(package, &parse(package));
+        let [edit] = edits.as_slice() else {
+            panic!("expected a single edit, got {edits:?}");
+        };
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(3, 4), Position::new(6, 27))
+        );
+        assert_eq!(
+            edit.new_text,
+            r#""@acme/icons": "^1.0.0",
+    "@acme/ui": "^1.0.0",
+    "Lodash": "^4.17.21",
+    "react": "^18.3.1""#
+        );
+
+        let sorted = r#"{ "dependencies": { "a": "1", "B": "1", "c": "1" } }"#;
+        assert!(sort_dependencies_edits(sorted, &parse(sorted)).is_empty());
+    }
+
+    #[test]
+    fn test_remove_dependency_edit() {
+        let remove = |text: &str, name: &str| {
+            let tree = parse(text);
+            let dependency = extract_all_dependencies(text, &tree)
+                .into_iter()
+                .find(|dependency| dependency.name == name)
+                .unwrap();
+            let edit = remove_dependency_edit(text, &tree, &dependency).unwrap();
+            let offset = |position: Position| crate::offset_at(text, position);
+            let mut text = text.to_string();
+            text.replace_range(
+                offset(edit.range.start)..offset(edit.range.end),
+                &edit.new_text,
+            );
+            text
+        };
+
+        let package = "{\n  \"dependencies\": {\n    \"lodash\": \"^4.17.21\",\n    \"react\": \"^18.3.1\",\n    \"vue\": \"^3.5.0\"\n  }\n}\n";
+        assert_eq!(
+            remove(package, "lodash"),
+            "{\n  \"dependencies\": {\n    \"react\": \"^18.3.1\",\n    \"vue\": \"^3.5.0\"\n  }\n}\n"
+        );
+        assert_eq!(
+            remove(package, "react"),
+            "{\n  \"dependencies\": {\n    \"lodash\": \"^4.17.21\",\n    \"vue\": \"^3.5.0\"\n  }\n}\n"
+        );
+        assert_eq!(
+            remove(package, "vue"),
+            "{\n  \"dependencies\": {\n    \"lodash\": \"^4.17.21\",\n    \"react\": \"^18.3.1\"\n  }\n}\n"
+        );
+        assert_eq!(
+            remove(
+                "{ \"dependencies\": { \"lodash\": \"1\", \"vue\": \"3\" } }",
+                "vue"
+            ),
+            "{ \"dependencies\": { \"lodash\": \"1\" } }"
+        );
+        assert_eq!(
+            remove("{\n  \"dependencies\": { \"lodash\": \"1\" }\n}", "lodash"),
+            "{\n  \"dependencies\": {}\n}"
+        );
+        assert_eq!(
+            remove(
+                "{ \"dependencies\": { \"日本\": \"1\", \"vue\": \"3\" } }",
+                "vue"
+            ),
+            "{ \"dependencies\": { \"日本\": \"1\" } }"
         );
     }
 }
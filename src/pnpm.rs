@@ -0,0 +1,131 @@
+//! pnpm catalogs, which let dependencies declare `catalog:` or `catalog:<name>` and keep their
+//! actual version in the workspace's `pnpm-workspace.yaml`.
+
+use std::path::Path;
+
+/// Resolves a `catalog:` specifier to the version the closest `pnpm-workspace.yaml` above
+/// `directory` declares for `package_name`.
+pub(super) async fn resolve_catalog(
+    directory: &Path,
+    specifier: &str,
+    package_name: &str,
+) -> Option<String> {
+    let catalog = specifier.trim().strip_prefix("catalog:")?.trim();
+    for ancestor in directory.ancestors() {
+        let Ok(workspace) = tokio::fs::read_to_string(ancestor.join("pnpm-workspace.yaml")).await
+        else {
+            continue;
+        };
+        return catalog_version(&workspace, catalog, package_name);
+    }
+    None
+}
+
+/// Looks `package_name` up in the `catalog` map of a `pnpm-workspace.yaml`, or in one of its
+/// `catalogs` when `catalog` names one. `default` is the same as leaving the name out.
+///
+/// Only the block mappings pnpm's docs use are understood, not YAML as a whole.
+fn catalog_version(workspace: &str, catalog: &str, package_name: &str) -> Option<String> {
+    let catalog = match catalog {
+        "" | "default" => None,
+        name => Some(name),
+    };
+    // Keys of the mappings that enclose the current line, along with their indentation.
+    let mut parents: Vec<(usize, String)> = Vec::new();
+    for line in workspace.lines() {
+        let content = line.trim_start();
+        if content.is_empty() || content.starts_with(['#', '-']) {
+            continue;
+        }
+        let indent = line.len() - content.len();
+        let Some((key, value)) = split_entry(content) else {
+            continue;
+        };
+        while parents.last().is_some_and(|(parent, _)| *parent >= indent) {
+            parents.pop();
+        }
+        let keys: Vec<_> = parents.iter().map(|(_, key)| key.as_str()).collect();
+        let is_requested_catalog = match (catalog, keys.as_slice()) {
+            (None, ["catalog"] | ["catalogs", "default"]) => true,
+            (Some(name), ["catalogs", parent]) => *parent == name,
+            _ => false,
+        };
+        if is_requested_catalog && key == package_name && !value.is_empty() {
+            return Some(value.to_string());
+        }
+        parents.push((indent, key.to_string()));
+    }
+    None
+}
+
+/// Splits a `key: value` line, unquoting both sides and dropping a trailing comment.
+pub(super) fn split_entry(content: &str) -> Option<(&str, &str)> {
+    let (key, rest) = match content.strip_prefix(['"', '\'']) {
+        Some(quoted) => {
+            let quote = &content[..1];
+            let (key, rest) = quoted.split_once(quote)?;
+            (key, rest.trim_start().strip_prefix(':')?)
+        }
+        None => {
+            let (key, rest) = content.split_once(':')?;
+            (key.trim_end(), rest)
+        }
+    };
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let value = rest.split(" #").next().unwrap_or_default().trim();
+    let value = value
+        .strip_prefix(['"', '\''])
+        .and_then(|value| value.strip_suffix(['"', '\'']))
+        .unwrap_or(value);
+    Some((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    const WORKSPACE: &str = r#"packages:
+  - "packages/*"
+
+catalog:
+  react: ^18.3.1
+  "@types/node": '20.11.0' # LTS
+
+catalogs:
+  default:
+    lodash: ^4.17.21
+  react17:
+    react: ^17.0.2
+    react-dom: ^17.0.2
+"#;
+
+    #[test]
+    fn test_catalog_version() {
+        let version = |catalog, package_name| catalog_version(WORKSPACE, catalog, package_name);
+        assert_eq!(version("", "react").as_deref(), Some("^18.3.1"));
+        assert_eq!(version("default", "react").as_deref(), Some("^18.3.1"));
+        assert_eq!(version("", "@types/node").as_deref(), Some("20.11.0"));
+        assert_eq!(version("", "lodash").as_deref(), Some("^4.17.21"));
+        assert_eq!(version("react17", "react").as_deref(), Some("^17.0.2"));
+        assert_eq!(version("react17", "react-dom").as_deref(), Some("^17.0.2"));
+        assert_eq!(version("react17", "lodash"), None);
+        assert_eq!(version("", "react-dom"), None);
+        assert_eq!(version("react18", "react"), None);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_catalog() {
+        let root = TempDir::new("pnpm-catalog");
+        let package = root.join("packages/app");
+        std::fs::create_dir_all(&package).unwrap();
+        std::fs::write(root.join("pnpm-workspace.yaml"), WORKSPACE).unwrap();
+
+        let resolve = |specifier| resolve_catalog(&package, specifier, "react");
+        assert_eq!(resolve("catalog:").await.as_deref(), Some("^18.3.1"));
+        assert_eq!(resolve("catalog:react17").await.as_deref(), Some("^17.0.2"));
+        assert_eq!(resolve("^18.0.0").await, None);
+    }
+}
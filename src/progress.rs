@@ -0,0 +1,72 @@
+//! Work done progress the server reports on its own, for clients to show e.g. in a status bar.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tower_lsp::lsp_types::notification::Progress as ProgressNotification;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
+use tower_lsp::lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressCreateParams, WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+use tower_lsp::Client;
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+pub(super) struct Progress {
+    client: Client,
+    token: NumberOrString,
+}
+
+impl Progress {
+    /// Asks the client to show a new progress, or returns `None` if it refuses to.
+    pub(super) async fn begin(client: &Client, title: &str) -> Option<Self> {
+        let token = NumberOrString::String(format!(
+            "{}/{}",
+            env!("CARGO_PKG_NAME"),
+            NEXT_TOKEN.fetch_add(1, Ordering::Relaxed)
+        ));
+        client
+            .send_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .ok()?;
+        let progress = Self {
+            client: client.clone(),
+            token,
+        };
+        progress
+            .send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                cancellable: Some(false),
+                message: None,
+                percentage: Some(0),
+            }))
+            .await;
+        Some(progress)
+    }
+
+    pub(super) async fn report(&self, done: usize, total: usize) {
+        let percentage = (done * 100).checked_div(total).unwrap_or(100) as u32;
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(format!("{done}/{total}")),
+            percentage: Some(percentage),
+        }))
+        .await;
+    }
+
+    pub(super) async fn end(self, message: Option<String>) {
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd { message }))
+            .await;
+    }
+
+    async fn send(&self, progress: WorkDoneProgress) {
+        self.client
+            .send_notification::<ProgressNotification>(ProgressParams {
+                token: self.token.clone(),
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+}
@@ -0,0 +1,139 @@
+//! Answers of the services asked about dependencies besides the registries, kept for a while so
+//! that hovering the same dependency again doesn't ask again.
+
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+use crate::fetcher::MAX_CACHE_ENTRIES;
+
+/// The latest answer for a key, along with when it was fetched.
+type Entry<V> = Arc<tokio::sync::Mutex<Option<(Instant, V)>>>;
+
+pub(super) struct ResponseCache<K, V> {
+    ttl: Duration,
+    /// Bounded like the package cache, so that answers about dependencies hovered long ago don't
+    /// pile up.
+    entries: Mutex<LruCache<K, Entry<V>>>,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> ResponseCache<K, V> {
+    /// Answers are fetched again once they are older than `ttl`.
+    pub(super) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(LruCache::new(MAX_CACHE_ENTRIES)),
+        }
+    }
+
+    /// The answer for `key`, asking with `fetch` unless the cached one is recent enough. Only one
+    /// fetch runs for a key at a time, and whoever asks meanwhile waits for its answer. When it
+    /// fails, like it does offline, the previous answer is used however old, and nothing is kept.
+    pub(super) async fn get<F>(&self, key: &K, fetch: impl FnOnce() -> F) -> Option<V>
+    where
+        F: Future<Output = Option<V>>,
+    {
+        let shared = self
+            .entries
+            .lock()
+            .unwrap()
+            .get_or_insert(key.clone(), Default::default)
+            .clone();
+        let mut entry = shared.lock().await;
+        if let Some((fetched, value)) = entry.as_ref() {
+            if fetched.elapsed() < self.ttl {
+                return Some(value.clone());
+            }
+        }
+        match fetch().await {
+            Some(value) => {
+                *entry = Some((Instant::now(), value.clone()));
+                Some(value)
+            }
+            None if entry.is_some() => entry.as_ref().map(|(_, value)| value.clone()),
+            None => {
+                // Failed fetches don't leave an empty entry behind, unless it was replaced already.
+                let mut entries = self.entries.lock().unwrap();
+                if entries
+                    .peek(key)
+                    .is_some_and(|kept| Arc::ptr_eq(kept, &shared))
+                {
+                    entries.pop(key);
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl<K: Clone + Eq + Hash, V: Clone> ResponseCache<K, V> {
+    pub(super) fn insert_for_tests(&self, key: K, fetched: Instant, value: V) {
+        self.entries.lock().unwrap().put(
+            key,
+            Arc::new(tokio::sync::Mutex::new(Some((fetched, value)))),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_response_cache() {
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        let fetches = AtomicUsize::new(0);
+        let fetch = |answer: Option<&'static str>| {
+            let fetches = &fetches;
+            move || async move {
+                fetches.fetch_add(1, Ordering::Relaxed);
+                // Gives the other lookups a chance to start fetching too.
+                tokio::task::yield_now().await;
+                answer
+            }
+        };
+
+        // Concurrent lookups share a single fetch.
+        let (first, second) = futures::join!(
+            cache.get(&"node", fetch(Some("22.11.0"))),
+            cache.get(&"node", fetch(Some("22.11.0")))
+        );
+        assert_eq!((first, second), (Some("22.11.0"), Some("22.11.0")));
+        assert_eq!(fetches.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.get(&"node", fetch(None)).await, Some("22.11.0"));
+        assert_eq!(fetches.load(Ordering::Relaxed), 1);
+
+        // Expired answers are kept until a new one is fetched.
+        let expired = Instant::now() - Duration::from_secs(120);
+        cache.insert_for_tests("python", expired, "3.12.7");
+        assert_eq!(cache.get(&"python", fetch(None)).await, Some("3.12.7"));
+        assert_eq!(
+            cache.get(&"python", fetch(Some("3.13.0"))).await,
+            Some("3.13.0")
+        );
+        assert_eq!(fetches.load(Ordering::Relaxed), 3);
+
+        assert_eq!(cache.get(&"ruby", fetch(None)).await, None);
+        // Failed fetches leave nothing behind.
+        assert!(!cache.entries.lock().unwrap().contains(&"ruby"));
+
+        // Only the most recently asked for answers are kept.
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .resize(std::num::NonZeroUsize::new(2).unwrap());
+        assert_eq!(
+            cache.get(&"go", fetch(Some("1.23.2"))).await,
+            Some("1.23.2")
+        );
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(!entries.contains(&"node"));
+    }
+}
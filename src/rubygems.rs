@@ -0,0 +1,526 @@
+//! Support for Ruby's `Gemfile` and `*.gemspec` manifests, backed by the rubygems.org API.
+
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::Value;
+use tower_lsp::lsp_types::{Position, Range, Url};
+
+use crate::fetcher::PackageVersionFetcher;
+use crate::response_cache::ResponseCache;
+
+const RUBYGEMS_API: &str = "https://rubygems.org/api/v1";
+
+/// How long what rubygems.org told about a gem is used before asking again.
+const GEM_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Methods that declare a dependency, in Gemfiles (`gem`) and gemspecs (`spec.add_dependency`).
+const DECLARATIONS: &[&str] = &[
+    "gem",
+    "add_dependency",
+    "add_runtime_dependency",
+    "add_development_dependency",
+];
+
+pub(super) fn is_manifest(uri: &Url) -> bool {
+    let file_name = uri.path().rsplit('/').next().unwrap_or_default();
+    file_name == "Gemfile" || file_name.ends_with(".gemspec")
+}
+
+/// A gem declaration such as `gem 'rails', '~> 7.1', '>= 7.1.2'`.
+#[derive(Debug, PartialEq)]
+pub(super) struct Gem {
+    pub name: String,
+    pub requirements: Vec<String>,
+    /// Range of the gem name, excluding the quotes around it.
+    pub name_range: Range,
+}
+
+/// Returns the gem declared on the line the cursor is on, if any.
+pub(super) fn gem_at(text: &str, position: Position) -> Option<Gem> {
+    let line = text.lines().nth(position.line as usize)?;
+    let indent = line.len() - line.trim_start().len();
+    let statement = &line[indent..];
+    let callee_len = statement
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+        .unwrap_or(statement.len());
+    let (receiver, method) = match statement[..callee_len].rsplit_once('.') {
+        Some((receiver, method)) => (Some(receiver), method),
+        None => (None, &statement[..callee_len]),
+    };
+    // `gem` is only ever called on the Gemfile itself, the `add_*` methods on a gem specification.
+    if !DECLARATIONS.contains(&method) || receiver.is_some() == (method == "gem") {
+        return None;
+    }
+    let arguments = string_arguments(line, indent + callee_len);
+    let ((name_start, name), requirements) = arguments.split_first()?;
+    let column = |offset: usize| line[..offset].encode_utf16().count() as u32;
+    let name_range = Range::new(
+        Position::new(position.line, column(*name_start)),
+        Position::new(position.line, column(*name_start + name.len())),
+    );
+    Some(Gem {
+        name: name.to_string(),
+        requirements: requirements
+            .iter()
+            .map(|(_, requirement)| requirement.to_string())
+            .collect(),
+        name_range,
+    })
+}
+
+/// Collects the leading string literal arguments of a call, along with the offsets they start at.
+/// Stops at the first argument that isn't a plain string, like `require: false`.
+pub(super) fn string_arguments(line: &str, mut offset: usize) -> Vec<(usize, &str)> {
+    let mut arguments = Vec::new();
+    let skip_whitespace =
+        |offset: usize| offset + line[offset..].len() - line[offset..].trim_start().len();
+    offset = skip_whitespace(offset);
+    if line[offset..].starts_with('(') {
+        offset = skip_whitespace(offset + 1);
+    }
+    while let Some(quote) = line[offset..]
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '\'' | '"'))
+    {
+        let start = offset + 1;
+        let Some(len) = line[start..].find(quote) else {
+            break;
+        };
+        arguments.push((start, &line[start..start + len]));
+        offset = skip_whitespace(start + len + 1);
+        if !line[offset..].starts_with(',') {
+            break;
+        }
+        offset = skip_whitespace(offset + 1);
+    }
+    arguments
+}
+
+/// A version in `Gem::Version` terms: any number of dot-separated segments, where letters start a
+/// prerelease (`1.0.0.rc1`, `2.0.0.pre`).
+#[derive(Clone, Debug)]
+pub(super) struct GemVersion {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Segment {
+    // Declared first so that prerelease segments sort before numbers.
+    Text(String),
+    Number(u64),
+}
+
+impl GemVersion {
+    pub(super) fn parse(version: &str) -> Option<Self> {
+        let version = version.trim();
+        if version.is_empty() || !version.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        let mut segments = Vec::new();
+        for part in version.split(['.', '-']) {
+            // Like rubygems, split `0a1` into `0`, `a`, `1`.
+            let mut rest = part;
+            while !rest.is_empty() {
+                let is_digit = rest.starts_with(|c: char| c.is_ascii_digit());
+                let len = rest
+                    .find(|c: char| c.is_ascii_digit() != is_digit)
+                    .unwrap_or(rest.len());
+                let (segment, tail) = rest.split_at(len);
+                if !segment.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    return None;
+                }
+                segments.push(if is_digit {
+                    Segment::Number(segment.parse().ok()?)
+                } else {
+                    Segment::Text(segment.to_string())
+                });
+                rest = tail;
+            }
+        }
+        Some(Self { segments })
+    }
+
+    pub(super) fn is_prerelease(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Text(_)))
+    }
+
+    /// The exclusive upper bound of `~>`: drops the last release segment and increments the one
+    /// before it, so `~> 1.2.3` allows anything below `1.3`.
+    fn bump(&self) -> Self {
+        let mut release: Vec<u64> = self
+            .segments
+            .iter()
+            .map_while(|segment| match segment {
+                Segment::Number(number) => Some(*number),
+                Segment::Text(_) => None,
+            })
+            .collect();
+        if release.len() > 1 {
+            release.pop();
+        }
+        if let Some(last) = release.last_mut() {
+            *last += 1;
+        }
+        Self {
+            segments: release.into_iter().map(Segment::Number).collect(),
+        }
+    }
+}
+
+impl Ord for GemVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.segments.len().max(other.segments.len());
+        let segment = |version: &Self, ix| {
+            version
+                .segments
+                .get(ix)
+                .cloned()
+                .unwrap_or(Segment::Number(0))
+        };
+        (0..len)
+            .map(|ix| segment(self, ix).cmp(&segment(other, ix)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for GemVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for GemVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for GemVersion {}
+
+/// A single constraint of a gem requirement, e.g. `~> 7.1` or `!= 7.1.1`.
+#[derive(Debug)]
+pub(super) struct Requirement {
+    operator: Operator,
+    version: GemVersion,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    Pessimistic,
+    Eq,
+    NotEq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl Requirement {
+    pub(super) fn parse(requirement: &str) -> Option<Self> {
+        // Longest operators first, so that `>=` isn't read as `>`.
+        const OPERATORS: &[(&str, Operator)] = &[
+            ("~>", Operator::Pessimistic),
+            ("!=", Operator::NotEq),
+            (">=", Operator::Gte),
+            ("<=", Operator::Lte),
+            ("=", Operator::Eq),
+            (">", Operator::Gt),
+            ("<", Operator::Lt),
+        ];
+        let requirement = requirement.trim();
+        let (operator, version) = OPERATORS
+            .iter()
+            .find_map(|(prefix, operator)| {
+                requirement
+                    .strip_prefix(prefix)
+                    .map(|version| (*operator, version))
+            })
+            .unwrap_or((Operator::Eq, requirement));
+        Some(Self {
+            operator,
+            version: GemVersion::parse(version)?,
+        })
+    }
+
+    pub(super) fn is_satisfied_by(&self, version: &GemVersion) -> bool {
+        let requirement = &self.version;
+        match self.operator {
+            Operator::Pessimistic => version >= requirement && *version < requirement.bump(),
+            Operator::Eq => version == requirement,
+            Operator::NotEq => version != requirement,
+            Operator::Gt => version > requirement,
+            Operator::Gte => version >= requirement,
+            Operator::Lt => version < requirement,
+            Operator::Lte => version <= requirement,
+        }
+    }
+}
+
+/// Whether `version` satisfies every one of the requirements a gem was declared with.
+/// Returns `None` if one of them can't be parsed.
+pub(super) fn satisfies(requirements: &[String], version: &GemVersion) -> Option<bool> {
+    requirements
+        .iter()
+        .try_fold(true, |satisfied, requirement| {
+            Some(satisfied && Requirement::parse(requirement)?.is_satisfied_by(version))
+        })
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct GemInfo {
+    pub latest_version: String,
+    pub info: String,
+    pub homepage: Option<String>,
+    pub date: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct GemRelease {
+    pub version: GemVersion,
+    pub number: String,
+}
+
+/// Remembers what rubygems.org told about each gem, so that hovering it again doesn't ask again.
+pub(super) struct GemCache {
+    infos: ResponseCache<String, GemInfo>,
+    releases: ResponseCache<String, Vec<GemRelease>>,
+}
+
+impl Default for GemCache {
+    fn default() -> Self {
+        Self {
+            infos: ResponseCache::new(GEM_TTL),
+            releases: ResponseCache::new(GEM_TTL),
+        }
+    }
+}
+
+impl GemCache {
+    pub(super) async fn info(
+        &self,
+        fetcher: &PackageVersionFetcher,
+        name: &str,
+    ) -> Option<GemInfo> {
+        self.infos
+            .get(&name.to_string(), || fetch_info(fetcher, name))
+            .await
+    }
+
+    pub(super) async fn releases(
+        &self,
+        fetcher: &PackageVersionFetcher,
+        name: &str,
+    ) -> Option<Vec<GemRelease>> {
+        self.releases
+            .get(&name.to_string(), || fetch_releases(fetcher, name))
+            .await
+    }
+}
+
+async fn fetch_info(fetcher: &PackageVersionFetcher, name: &str) -> Option<GemInfo> {
+    let gem = get_json(fetcher, &format!("gems/{}.json", urlencoding::encode(name))).await?;
+    parse_info(&gem)
+}
+
+async fn fetch_releases(fetcher: &PackageVersionFetcher, name: &str) -> Option<Vec<GemRelease>> {
+    let versions = get_json(
+        fetcher,
+        &format!("versions/{}.json", urlencoding::encode(name)),
+    )
+    .await?;
+    Some(parse_releases(&versions))
+}
+
+async fn get_json(fetcher: &PackageVersionFetcher, path: &str) -> Option<Value> {
+    fetcher
+        .request_json(|client| client.get(format!("{RUBYGEMS_API}/{path}")))
+        .await
+}
+
+fn parse_info(gem: &Value) -> Option<GemInfo> {
+    Some(GemInfo {
+        latest_version: gem["version"].as_str()?.to_string(),
+        info: gem["info"].as_str().unwrap_or_default().trim().to_string(),
+        homepage: gem["homepage_uri"]
+            .as_str()
+            .filter(|homepage| !homepage.is_empty())
+            .map(ToString::to_string),
+        date: gem["version_created_at"]
+            .as_str()
+            .and_then(|date| DateTime::parse_from_rfc3339(date).ok()),
+    })
+}
+
+fn parse_releases(versions: &Value) -> Vec<GemRelease> {
+    versions
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|release| {
+            let number = release["number"].as_str()?;
+            Some(GemRelease {
+                version: GemVersion::parse(number)?,
+                number: number.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The newest release allowed by the requirements. Prereleases are only considered when one of the
+/// requirements asks for one, as Bundler does.
+pub(super) fn newest_matching<'a>(
+    releases: &'a [GemRelease],
+    requirements: &[String],
+) -> Option<&'a GemRelease> {
+    let allow_prerelease = requirements.iter().any(|requirement| {
+        Requirement::parse(requirement)
+            .is_some_and(|requirement| requirement.version.is_prerelease())
+    });
+    releases
+        .iter()
+        .filter(|release| allow_prerelease || !release.version.is_prerelease())
+        .filter(|release| satisfies(requirements, &release.version) == Some(true))
+        .max_by(|a, b| a.version.cmp(&b.version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn columns(range: Range) -> std::ops::Range<u32> {
+        range.start.character..range.end.character
+    }
+
+    fn version(version: &str) -> GemVersion {
+        GemVersion::parse(version).unwrap()
+    }
+
+    fn requirements(requirements: &[&str]) -> Vec<String> {
+        requirements.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_is_manifest() {
+        assert!(is_manifest(&Url::parse("file:///app/Gemfile").unwrap()));
+        assert!(is_manifest(&Url::parse("file:///gem/foo.gemspec").unwrap()));
+        assert!(!is_manifest(
+            &Url::parse("file:///app/Gemfile.lock").unwrap()
+        ));
+        assert!(!is_manifest(
+            &Url::parse("file:///app/package.json").unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_gem_at() {
+        let text = r#"source "https://rubygems.org"
+
+gem 'rails', '~> 7.1', '>= 7.1.2'
+gem "puma", require: false
+  spec.add_development_dependency("rspec", "~> 3.12")
+"#;
+        let gem = gem_at(text, Position::new(2, 0)).unwrap();
+        assert_eq!(gem.name, "rails");
+        assert_eq!(gem.requirements, ["~> 7.1", ">= 7.1.2"]);
+        assert_eq!(columns(gem.name_range), 5..10);
+
+        let gem = gem_at(text, Position::new(3, 4)).unwrap();
+        assert_eq!(gem.name, "puma");
+        assert!(gem.requirements.is_empty());
+
+        let gem = gem_at(text, Position::new(4, 10)).unwrap();
+        assert_eq!(gem.name, "rspec");
+        assert_eq!(gem.requirements, ["~> 3.12"]);
+        assert_eq!(columns(gem.name_range), 35..40);
+
+        assert_eq!(gem_at(text, Position::new(0, 0)), None);
+        assert_eq!(gem_at(text, Position::new(1, 0)), None);
+        assert_eq!(gem_at("spec.gem 'rails'", Position::new(0, 0)), None);
+        assert_eq!(gem_at("add_dependency 'rails'", Position::new(0, 0)), None);
+
+        // Columns count UTF-16 code units, which the ideographic space is a single one of.
+        let gem = gem_at("\u{3000}gem 'rails'", Position::new(0, 0)).unwrap();
+        assert_eq!(columns(gem.name_range), 6..11);
+    }
+
+    #[test]
+    fn test_gem_version_ordering() {
+        assert!(version("1.10") > version("1.9"));
+        assert!(version("1.0") == version("1"));
+        assert!(version("2.0.0.rc1") < version("2.0.0"));
+        assert!(version("2.0.0.rc1") > version("1.9.9"));
+        assert!(version("1.0.0a1") < version("1.0.0b"));
+        assert!(version("7.1.3.pre").is_prerelease());
+        assert!(GemVersion::parse("latest").is_none());
+    }
+
+    #[test]
+    fn test_requirements() {
+        let pessimistic = requirements(&["~> 7.1"]);
+        assert_eq!(satisfies(&pessimistic, &version("7.9")), Some(true));
+        assert_eq!(satisfies(&pessimistic, &version("8.0")), Some(false));
+        assert_eq!(satisfies(&pessimistic, &version("7.0.8")), Some(false));
+
+        let pessimistic = requirements(&["~> 7.1.2"]);
+        assert_eq!(satisfies(&pessimistic, &version("7.1.9")), Some(true));
+        assert_eq!(satisfies(&pessimistic, &version("7.2.0")), Some(false));
+
+        let combined = requirements(&["~> 7.1", ">= 7.1.2", "!= 7.1.3"]);
+        assert_eq!(satisfies(&combined, &version("7.1.2")), Some(true));
+        assert_eq!(satisfies(&combined, &version("7.1.1")), Some(false));
+        assert_eq!(satisfies(&combined, &version("7.1.3")), Some(false));
+
+        assert_eq!(
+            satisfies(&requirements(&["1.2"]), &version("1.2.0")),
+            Some(true)
+        );
+        assert_eq!(satisfies(&requirements(&[]), &version("1.2.0")), Some(true));
+        assert_eq!(
+            satisfies(&requirements(&[">= banana"]), &version("1.0")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_newest_matching() {
+        let releases = parse_releases(&json!([
+            { "number": "8.0.0.beta1", "prerelease": true },
+            { "number": "7.1.3" },
+            { "number": "7.1.2" },
+            { "number": "7.0.8" },
+            { "number": "6.1.7" },
+        ]));
+        let newest = |constraints: &[&str]| {
+            newest_matching(&releases, &requirements(constraints))
+                .map(|release| release.number.as_str())
+        };
+        assert_eq!(newest(&[]), Some("7.1.3"));
+        assert_eq!(newest(&["~> 7.0.0"]), Some("7.0.8"));
+        assert_eq!(newest(&["< 7"]), Some("6.1.7"));
+        assert_eq!(newest(&[">= 8.0.0.alpha"]), Some("8.0.0.beta1"));
+        assert_eq!(newest(&["> 9"]), None);
+    }
+
+    #[test]
+    fn test_parse_info() {
+        let info = parse_info(&json!({
+            "name": "rails",
+            "version": "7.1.3",
+            "version_created_at": "2024-01-16T21:46:43.222Z",
+            "info": "Ruby on Rails is a full-stack web framework.\n",
+            "homepage_uri": "https://rubyonrails.org",
+        }))
+        .unwrap();
+        assert_eq!(info.latest_version, "7.1.3");
+        assert_eq!(info.info, "Ruby on Rails is a full-stack web framework.");
+        assert_eq!(info.homepage.as_deref(), Some("https://rubyonrails.org"));
+        assert!(info.date.is_some());
+    }
+}
@@ -0,0 +1,147 @@
+//! Semantic tokens for the versions of outdated and deprecated dependencies, so that themes can
+//! style them.
+
+use tower_lsp::lsp_types::{
+    Diagnostic, NumberOrString, Position, SemanticToken, SemanticTokenModifier, SemanticTokenType,
+    SemanticTokensLegend,
+};
+
+use crate::diagnostics;
+use crate::parser::Dependency;
+
+/// Indices into the legend's token types.
+const VERSION: u32 = 0;
+const OUTDATED: u32 = 1;
+
+/// Bit of the legend's `deprecated` modifier.
+const DEPRECATED: u32 = 1 << 0;
+
+pub(super) fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![
+            SemanticTokenType::STRING,
+            SemanticTokenType::new("outdated"),
+        ],
+        token_modifiers: vec![SemanticTokenModifier::DEPRECATED],
+    }
+}
+
+/// Tokens for the versions of dependencies that the diagnostics report as outdated or deprecated.
+/// Other versions get no token, and keep their regular highlighting.
+pub(super) fn tokens(
+    dependencies: &[Dependency],
+    diagnostics: &[Diagnostic],
+) -> Vec<SemanticToken> {
+    let has_diagnostic = |code: &str, range| {
+        diagnostics.iter().any(|diagnostic| {
+            diagnostic.range == range
+                && diagnostic.code == Some(NumberOrString::String(code.to_string()))
+        })
+    };
+    let mut flagged: Vec<_> = dependencies
+        .iter()
+        .filter(|dependency| {
+            dependency.version_range.start.line == dependency.version_range.end.line
+        })
+        .filter_map(|dependency| {
+            let outdated = has_diagnostic(diagnostics::OUTDATED, dependency.version_range);
+            let deprecated = has_diagnostic(diagnostics::DEPRECATED, dependency.name_range);
+            let token_type = if outdated { OUTDATED } else { VERSION };
+            let modifiers = if deprecated { DEPRECATED } else { 0 };
+            (outdated || deprecated).then_some((dependency.version_range, token_type, modifiers))
+        })
+        .collect();
+    flagged.sort_by_key(|(range, _, _)| range.start);
+
+    let mut previous = Position::default();
+    flagged
+        .into_iter()
+        .map(|(range, token_type, token_modifiers_bitset)| {
+            let delta_line = range.start.line - previous.line;
+            let delta_start = if delta_line == 0 {
+                range.start.character - previous.character
+            } else {
+                range.start.character
+            };
+            previous = range.start;
+            SemanticToken {
+                delta_line,
+                delta_start,
+                length: range.end.character - range.start.character,
+                token_type,
+                token_modifiers_bitset,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Range;
+
+    use crate::parser::DependencyKind;
+
+    fn dependency(line: u32, version: &str) -> Dependency {
+        Dependency {
+            name: "lodash".into(),
+            version: version.into(),
+            section: DependencyKind::Dependencies,
+            name_range: Range::new(Position::new(line, 5), Position::new(line, 11)),
+            version_range: Range::new(
+                Position::new(line, 15),
+                Position::new(line, 15 + version.len() as u32),
+            ),
+        }
+    }
+
+    fn diagnostic(code: &str, range: Range) -> Diagnostic {
+        Diagnostic {
+            range,
+            code: Some(NumberOrString::String(code.into())),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tokens() {
+        let dependencies = [
+            dependency(2, "^3.0.0"),
+            dependency(3, "^4.17.0"),
+            dependency(4, "^2.88.0"),
+            dependency(5, "^1"),
+        ];
+        let diagnostics = [
+            diagnostic(diagnostics::OUTDATED, dependencies[0].version_range),
+            diagnostic(diagnostics::DEPRECATED, dependencies[2].name_range),
+            diagnostic(diagnostics::OUTDATED, dependencies[2].version_range),
+            diagnostic(diagnostics::INVALID_RANGE, dependencies[3].version_range),
+        ];
+        let tokens: Vec<_> = tokens(&dependencies, &diagnostics)
+            .into_iter()
+            .map(|token| {
+                (
+                    token.delta_line,
+                    token.delta_start,
+                    token.length,
+                    token.token_type,
+                    token.token_modifiers_bitset,
+                )
+            })
+            .collect();
+        assert_eq!(
+            tokens,
+            [(2, 15, 6, OUTDATED, 0), (2, 15, 7, OUTDATED, DEPRECATED)]
+        );
+
+        let tokens = super::tokens(
+            &dependencies[1..2],
+            &[diagnostic(
+                diagnostics::DEPRECATED,
+                dependencies[1].name_range,
+            )],
+        );
+        assert_eq!(tokens[0].token_type, VERSION);
+        assert_eq!(tokens[0].token_modifiers_bitset, DEPRECATED);
+    }
+}
@@ -0,0 +1,647 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+use crate::fetcher::{
+    normalize_registry, parse_registry_header, registry_prefix, Credential, FetchMode,
+    RegistryConfig, RegistryHeaders, DEFAULT_REGISTRY, MAX_CACHE_ENTRIES, MAX_CONCURRENT_REQUESTS,
+    REFRESH_DURATION,
+};
+use crate::npmrc::NpmrcRegistries;
+use crate::parser::DependencyKind;
+
+/// User-facing configuration, read from `initializationOptions` and updated through
+/// `workspace/didChangeConfiguration`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct Settings {
+    #[serde(alias = "codeLens")]
+    pub code_lens: CodeLensSettings,
+    pub completion: CompletionSettings,
+    pub diagnostics: DiagnosticSettings,
+    pub features: FeatureSettings,
+    pub inlay_hints: InlayHintSettings,
+    /// Registry to fetch package metadata from.
+    #[serde(alias = "defaultRegistry")]
+    pub default_registry: Option<String>,
+    /// Registries to fetch scoped packages from, keyed by scope.
+    pub scope_registries: HashMap<String, ScopeRegistries>,
+    /// Headers sent along with the requests to a registry, keyed by registry and then by name.
+    /// Their values can refer to environment variables as `${NAME}`.
+    #[serde(alias = "extra_headers")]
+    pub registry_headers: HashMap<String, HashMap<String, String>>,
+    /// How long fetched package metadata is reused before asking the registry again.
+    pub cache_ttl_secs: Option<u64>,
+    /// How many packages' metadata is kept in memory at most.
+    pub max_cache_entries: Option<usize>,
+    /// How many requests are sent to registries at once at most.
+    pub max_concurrent_requests: Option<usize>,
+    /// Whether the registry is asked for metadata that has been cached before.
+    pub fetch_mode: FetchMode,
+    /// Whether opening a document reports how many of its dependencies have a new major version.
+    pub notify_major_updates: bool,
+    /// Whether hover warns about the known advisories of the version a dependency installs.
+    pub show_advisories: bool,
+    /// Whether hover shows the first paragraph of the package's README.
+    pub show_readme_excerpt: bool,
+    /// Names of the sections hover and completion work in. Every section if missing.
+    pub sections: Option<Vec<String>>,
+}
+
+/// One registry for a scope, or several to try in order when the previous ones lack a package.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub(super) enum ScopeRegistries {
+    One(String),
+    Fallbacks(Vec<String>),
+}
+
+impl ScopeRegistries {
+    fn as_slice(&self) -> &[String] {
+        match self {
+            Self::One(registry) => std::slice::from_ref(registry),
+            Self::Fallbacks(registries) => registries,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct CodeLensSettings {
+    /// Whether every dependency gets a code lens with its latest version, on top of the sections.
+    #[serde(alias = "perDependency")]
+    pub per_dependency: bool,
+    /// Whether each section gets a code lens counting its dependencies with known vulnerabilities.
+    pub security: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub(super) struct CompletionSettings {
+    pub style: CompletionStyle,
+    pub mode: CompletionMode,
+    /// `strftime`-style format of the publish date shown next to each version.
+    pub date_format: String,
+}
+
+impl Default for CompletionSettings {
+    fn default() -> Self {
+        Self {
+            style: Default::default(),
+            mode: Default::default(),
+            date_format: String::from("%d/%m/%Y %H:%M"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum CompletionStyle {
+    /// Only offer the newest release of each recent minor/older major until the user narrows the query down.
+    #[default]
+    Grouped,
+    /// Offer every published version matching the query.
+    All,
+}
+
+/// Severity of each kind of diagnostic, which can also be turned off.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub(super) struct DiagnosticSettings {
+    /// Version specifiers npm can't parse.
+    #[serde(alias = "invalidRange")]
+    pub invalid_range: Severity,
+    /// Packages the registry doesn't know about.
+    #[serde(alias = "notFound")]
+    pub not_found: Severity,
+    /// Packages whose latest version is deprecated.
+    #[serde(alias = "deprecated_severity")]
+    pub deprecated: Severity,
+    /// Ranges that exclude the latest version.
+    pub outdated: Severity,
+    /// Packages declared more than once.
+    pub duplicate: Severity,
+    /// Ranges that exclude the version the lockfile installed.
+    pub lockfile_drift: Severity,
+    /// Ranges that no published version satisfies.
+    pub unsatisfiable: Severity,
+    /// Ranges that only prereleases satisfy, which npm doesn't install for them.
+    pub prerelease_only: Severity,
+    /// Wildcards and dist-tags, which install a different version whenever a new one is published.
+    pub unpinned: Severity,
+    /// Whether `unpinned` applies to `devDependencies` too.
+    pub unpinned_dev_dependencies: bool,
+    /// Production dependencies that resolve to a prerelease.
+    pub prerelease: Severity,
+    /// Specifiers starting with a protocol package managers don't know, like `workpace:`.
+    pub unknown_protocol: Severity,
+    /// When documents are analyzed.
+    #[serde(alias = "runOn")]
+    pub run_on: RunOn,
+    /// Whether installed versions are checked for known vulnerabilities.
+    pub security: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum RunOn {
+    /// Shortly after each change.
+    #[default]
+    Change,
+    /// Only once the document is saved, to spare the registry on slow or metered connections.
+    Save,
+}
+
+impl Default for DiagnosticSettings {
+    fn default() -> Self {
+        Self {
+            invalid_range: Severity::Warning,
+            not_found: Severity::Error,
+            deprecated: Severity::Warning,
+            outdated: Severity::Information,
+            duplicate: Severity::Warning,
+            lockfile_drift: Severity::Warning,
+            unsatisfiable: Severity::Error,
+            prerelease_only: Severity::Warning,
+            unpinned: Severity::Hint,
+            unpinned_dev_dependencies: false,
+            prerelease: Severity::Warning,
+            unknown_protocol: Severity::Warning,
+            run_on: RunOn::Change,
+            security: true,
+        }
+    }
+}
+
+/// Features that can be turned off entirely. Capabilities are advertised once, when the server is
+/// initialized, so turning a feature back on only takes effect after a restart.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub(super) struct FeatureSettings {
+    pub hover: bool,
+    pub completion: bool,
+    pub diagnostics: bool,
+}
+
+impl Default for FeatureSettings {
+    fn default() -> Self {
+        Self {
+            hover: true,
+            completion: true,
+            diagnostics: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(super) struct InlayHintSettings {
+    /// Whether dependencies whose range includes the latest version get a checkmark.
+    pub show_up_to_date: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Severity {
+    Error,
+    #[default]
+    Warning,
+    Information,
+    Hint,
+    /// The diagnostic isn't reported at all.
+    Off,
+}
+
+impl Severity {
+    pub(super) fn diagnostic_severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            Self::Error => Some(DiagnosticSeverity::ERROR),
+            Self::Warning => Some(DiagnosticSeverity::WARNING),
+            Self::Information => Some(DiagnosticSeverity::INFORMATION),
+            Self::Hint => Some(DiagnosticSeverity::HINT),
+            Self::Off => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum CompletionMode {
+    /// Offer every version matching the typed prefix.
+    #[default]
+    All,
+    /// Only offer versions that satisfy the range being edited.
+    Compatible,
+}
+
+impl Settings {
+    pub(super) fn from_value(value: Option<Value>) -> serde_json::Result<Self> {
+        match value {
+            Some(Value::Null) | None => Ok(Self::default()),
+            Some(value) => serde_json::from_value(value),
+        }
+    }
+
+    /// The sections hover and completion work in, leaving out names that aren't sections.
+    pub(super) fn active_sections(&self) -> Vec<DependencyKind> {
+        let Some(sections) = &self.sections else {
+            return DependencyKind::ALL.to_vec();
+        };
+        let mut active = Vec::new();
+        for kind in sections
+            .iter()
+            .filter_map(|name| DependencyKind::from_section_name(name))
+        {
+            // `bundleDependencies` and `bundledDependencies` name the same section.
+            if !active.contains(&kind) {
+                active.push(kind);
+            }
+        }
+        active
+    }
+
+    /// Configured section names that don't name a dependency section.
+    pub(super) fn unknown_sections(&self) -> Vec<&str> {
+        self.sections
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|name| DependencyKind::from_section_name(name).is_none())
+            .collect()
+    }
+
+    /// Configured registries that can't be used, along with the reason why. The default registry is
+    /// used instead of them.
+    pub(super) fn invalid_registries(&self) -> Vec<(&str, String)> {
+        let mut invalid: Vec<_> = self
+            .default_registry
+            .iter()
+            .chain(
+                self.scope_registries
+                    .values()
+                    .flat_map(ScopeRegistries::as_slice),
+            )
+            .filter_map(|registry| Some((registry.as_str(), normalize_registry(registry).err()?)))
+            .collect();
+        invalid.sort();
+        invalid
+    }
+
+    /// Configured registry headers that can't be sent, along with their registry and the reason
+    /// why. They are left out of requests.
+    pub(super) fn invalid_registry_headers(&self) -> Vec<(&str, &str, String)> {
+        let mut invalid: Vec<_> = self
+            .registry_headers
+            .iter()
+            .flat_map(|(registry, headers)| {
+                let prefix = registry_prefix(registry);
+                headers.iter().filter_map(move |(name, value)| {
+                    let error = match &prefix {
+                        Ok(_) => parse_registry_header(name, value, env_var).err()?,
+                        Err(error) => format!("invalid registry: {error}"),
+                    };
+                    Some((registry.as_str(), name.as_str(), error))
+                })
+            })
+            .collect();
+        invalid.sort();
+        invalid
+    }
+
+    /// Where and how packages are fetched. Registries configured here take precedence over the
+    /// ones from `.npmrc` files.
+    pub(super) fn registry_config(&self, npmrc: &NpmrcRegistries) -> RegistryConfig {
+        let npmrc_scopes = npmrc.scope_registries.iter().map(|(scope, registry)| {
+            let registries = normalize_registry(registry).ok().into_iter().collect();
+            (scope.clone(), registries)
+        });
+        let scopes = self.scope_registries.iter().map(|(scope, registries)| {
+            let scope = format!("@{}", scope.trim_start_matches('@'));
+            let registries = registries
+                .as_slice()
+                .iter()
+                .filter_map(|registry| normalize_registry(registry).ok())
+                .collect();
+            (scope, registries)
+        });
+        RegistryConfig {
+            default_registry: [&self.default_registry, &npmrc.default_registry]
+                .into_iter()
+                .flatten()
+                .find_map(|registry| normalize_registry(registry).ok())
+                .unwrap_or_else(|| DEFAULT_REGISTRY.to_string()),
+            // Later entries replace earlier ones for the same scope.
+            scope_registries: npmrc_scopes.chain(scopes).collect(),
+            cache_ttl: self
+                .cache_ttl_secs
+                .map_or(REFRESH_DURATION, Duration::from_secs),
+            max_cache_entries: self
+                .max_cache_entries
+                .and_then(NonZeroUsize::new)
+                .unwrap_or(MAX_CACHE_ENTRIES),
+            max_concurrent_requests: self
+                .max_concurrent_requests
+                .and_then(NonZeroUsize::new)
+                .unwrap_or(MAX_CONCURRENT_REQUESTS),
+            fetch_mode: self.fetch_mode,
+            headers: self
+                .registry_headers
+                .iter()
+                .filter_map(|(registry, headers)| {
+                    Some(RegistryHeaders {
+                        prefix: registry_prefix(registry).ok()?,
+                        headers: headers
+                            .iter()
+                            .filter_map(|(name, value)| {
+                                parse_registry_header(name, value, env_var).ok()
+                            })
+                            .collect(),
+                    })
+                })
+                .collect(),
+            credentials: npmrc
+                .credentials
+                .iter()
+                .filter_map(|(prefix, authorization)| Credential::new(prefix, &authorization.0))
+                .collect(),
+        }
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::npmrc::Authorization;
+    use serde_json::json;
+
+    #[test]
+    fn test_missing_options_use_defaults() {
+        let settings = Settings::from_value(None).unwrap();
+        assert_eq!(settings.completion.style, CompletionStyle::Grouped);
+        assert!(!settings.code_lens.per_dependency);
+
+        let settings = Settings::from_value(Some(json!({
+            "code_lens": { "per_dependency": true }
+        })))
+        .unwrap();
+        assert!(settings.code_lens.per_dependency);
+        assert!(!settings.inlay_hints.show_up_to_date);
+        assert!(settings.features.hover && settings.features.diagnostics);
+
+        let settings = Settings::from_value(Some(json!({
+            "codeLens": { "perDependency": true }
+        })))
+        .unwrap();
+        assert!(settings.code_lens.per_dependency);
+        assert!(!settings.code_lens.security);
+
+        let settings = Settings::from_value(Some(json!({ "codeLens": { "security": true } })));
+        assert!(settings.unwrap().code_lens.security);
+    }
+
+    #[test]
+    fn test_parse_features() {
+        let settings = Settings::from_value(Some(json!({
+            "features": { "completion": false, "diagnostics": false }
+        })))
+        .unwrap();
+        assert!(settings.features.hover);
+        assert!(!settings.features.completion);
+        assert!(!settings.features.diagnostics);
+
+        assert!(Settings::from_value(Some(json!({ "features": { "hover": "no" } }))).is_err());
+    }
+
+    #[test]
+    fn test_parse_completion_style() {
+        let settings = Settings::from_value(Some(json!({
+            "completion": { "style": "all" }
+        })))
+        .unwrap();
+        assert_eq!(settings.completion.style, CompletionStyle::All);
+        assert_eq!(settings.completion.mode, CompletionMode::All);
+
+        let settings = Settings::from_value(Some(json!({
+            "completion": { "mode": "compatible" }
+        })))
+        .unwrap();
+        assert_eq!(settings.completion.mode, CompletionMode::Compatible);
+
+        assert!(Settings::from_value(Some(json!({
+            "completion": { "style": "sometimes" }
+        })))
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_diagnostic_severity() {
+        let settings = Settings::from_value(None).unwrap();
+        assert_eq!(settings.diagnostics.deprecated, Severity::Warning);
+        assert_eq!(settings.diagnostics.outdated, Severity::Information);
+
+        let settings = Settings::from_value(Some(json!({
+            "diagnostics": { "outdated": "off", "not_found": "warning" }
+        })))
+        .unwrap();
+        assert_eq!(settings.diagnostics.outdated.diagnostic_severity(), None);
+        assert_eq!(
+            settings.diagnostics.not_found.diagnostic_severity(),
+            Some(DiagnosticSeverity::WARNING)
+        );
+        assert_eq!(settings.diagnostics.invalid_range, Severity::Warning);
+
+        let settings = Settings::from_value(Some(json!({
+            "diagnostics": { "deprecated_severity": "hint" }
+        })))
+        .unwrap();
+        assert_eq!(
+            settings.diagnostics.deprecated.diagnostic_severity(),
+            Some(DiagnosticSeverity::HINT)
+        );
+
+        let settings = Settings::from_value(Some(json!({
+            "diagnostics": { "notFound": "hint", "invalidRange": "error" }
+        })))
+        .unwrap();
+        assert_eq!(settings.diagnostics.not_found, Severity::Hint);
+        assert_eq!(settings.diagnostics.invalid_range, Severity::Error);
+
+        let settings = Settings::from_value(Some(json!({
+            "diagnostics": { "runOn": "save" }
+        })))
+        .unwrap();
+        assert_eq!(settings.diagnostics.run_on, RunOn::Save);
+    }
+
+    #[test]
+    fn test_active_sections() {
+        let settings = Settings::from_value(None).unwrap();
+        assert_eq!(settings.active_sections(), DependencyKind::ALL);
+        assert!(settings.unknown_sections().is_empty());
+
+        let settings = Settings::from_value(Some(json!({
+            "sections": ["dependencies", "bundleDependencies", "scripts", "bundledDependencies"]
+        })))
+        .unwrap();
+        assert_eq!(
+            settings.active_sections(),
+            [
+                DependencyKind::Dependencies,
+                DependencyKind::BundledDependencies
+            ]
+        );
+        assert_eq!(settings.unknown_sections(), ["scripts"]);
+    }
+
+    #[test]
+    fn test_registry_config() {
+        let settings = Settings::from_value(Some(json!({
+            "default_registry": "https://npm.example.com",
+            "scope_registries": {
+                "acme": "https://npm.acme.dev",
+                "@corp": ["https://corp.dev", "https://registry.npmjs.org/"]
+            },
+            "cache_ttl_secs": 300,
+            "max_cache_entries": 50,
+            "max_concurrent_requests": 2,
+            "fetch_mode": "prefer_cache"
+        })))
+        .unwrap();
+        let config = settings.registry_config(&NpmrcRegistries::default());
+        assert_eq!(config.default_registry, "https://npm.example.com");
+        assert_eq!(config.registries_for("@acme/ui"), ["https://npm.acme.dev"]);
+        assert_eq!(
+            config.registries_for("@corp/ui"),
+            ["https://corp.dev", DEFAULT_REGISTRY]
+        );
+        assert_eq!(config.cache_ttl, Duration::from_secs(300));
+        assert_eq!(config.max_cache_entries.get(), 50);
+        assert_eq!(config.max_concurrent_requests.get(), 2);
+        assert_eq!(config.fetch_mode, FetchMode::PreferCache);
+
+        let config = Settings::default().registry_config(&NpmrcRegistries::default());
+        assert_eq!(config, RegistryConfig::default());
+    }
+
+    #[test]
+    fn test_npmrc_registries() {
+        let npmrc = NpmrcRegistries {
+            default_registry: Some("https://mirror.dev/".into()),
+            scope_registries: HashMap::from([
+                ("@acme".to_string(), "https://npm.acme.dev".to_string()),
+                ("@corp".to_string(), "https://npmrc.corp.dev".to_string()),
+            ]),
+            credentials: HashMap::from([
+                (
+                    "//npm.acme.dev/".to_string(),
+                    Authorization("Bearer s3cr3t".into()),
+                ),
+                (
+                    "//broken.dev/".to_string(),
+                    Authorization("Bearer \n".into()),
+                ),
+            ]),
+        };
+        let config = Settings::default().registry_config(&npmrc);
+        assert_eq!(config.default_registry, "https://mirror.dev");
+        assert_eq!(config.registries_for("@acme/ui"), ["https://npm.acme.dev"]);
+        assert_eq!(config.credentials.len(), 1);
+        assert_eq!(config.credentials[0].prefix, "//npm.acme.dev/");
+        assert!(config.credentials[0].authorization.is_sensitive());
+
+        let settings = Settings::from_value(Some(json!({
+            "default_registry": "https://npm.example.com",
+            "scope_registries": { "corp": "https://corp.dev" }
+        })))
+        .unwrap();
+        let config = settings.registry_config(&npmrc);
+        assert_eq!(config.default_registry, "https://npm.example.com");
+        assert_eq!(config.registries_for("@acme/ui"), ["https://npm.acme.dev"]);
+        assert_eq!(config.registries_for("@corp/ui"), ["https://corp.dev"]);
+    }
+
+    #[test]
+    fn test_invalid_registries() {
+        let settings = Settings::from_value(Some(json!({
+            "default_registry": "ftp://npm.example.com",
+            "scope_registries": {
+                "acme": "npm.acme.dev/",
+                "corp": "https://",
+                "mirror": ["https://", "mirror.dev"]
+            }
+        })))
+        .unwrap();
+        let invalid: Vec<_> = settings
+            .invalid_registries()
+            .into_iter()
+            .map(|(registry, _)| registry)
+            .collect();
+        assert_eq!(invalid, ["ftp://npm.example.com", "https://", "https://"]);
+        let config = settings.registry_config(&NpmrcRegistries::default());
+        assert_eq!(config.default_registry, DEFAULT_REGISTRY);
+        assert_eq!(config.registries_for("@acme/ui"), ["https://npm.acme.dev"]);
+        assert_eq!(config.registries_for("@corp/ui"), [DEFAULT_REGISTRY]);
+        assert_eq!(config.registries_for("@mirror/ui"), ["https://mirror.dev"]);
+    }
+
+    #[test]
+    fn test_registry_headers() {
+        let settings = Settings::from_value(Some(json!({
+            "registry_headers": {
+                "https://artifactory.acme.dev/api/npm": {
+                    "X-Artifactory-Override-Base-Url": "https://artifactory.acme.dev",
+                    "X-Api-Key": "${PACKAGE_VERSION_SERVER_UNSET_KEY}",
+                    "Not a header": "value",
+                    "X-Multiline": "first\nsecond"
+                },
+                "ftp://npm.acme.dev": { "X-Api-Key": "s3cr3t" }
+            }
+        })))
+        .unwrap();
+        let registry = "https://artifactory.acme.dev/api/npm";
+        assert_eq!(
+            settings.invalid_registry_headers(),
+            [
+                (
+                    "ftp://npm.acme.dev",
+                    "X-Api-Key",
+                    "invalid registry: unsupported scheme `ftp`".to_string()
+                ),
+                (registry, "Not a header", "invalid header name".to_string()),
+                (
+                    registry,
+                    "X-Api-Key",
+                    "environment variable `PACKAGE_VERSION_SERVER_UNSET_KEY` isn't set".to_string()
+                ),
+                (registry, "X-Multiline", "invalid header value".to_string()),
+            ]
+        );
+        let headers = settings
+            .registry_config(&NpmrcRegistries::default())
+            .headers;
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].prefix, "//artifactory.acme.dev/api/npm/");
+        assert_eq!(headers[0].headers.len(), 1);
+        assert_eq!(
+            headers[0].headers["x-artifactory-override-base-url"],
+            "https://artifactory.acme.dev"
+        );
+
+        let settings = Settings::from_value(Some(json!({
+            "extra_headers": { "//npm.acme.dev/": { "X-Api-Key": "s3cr3t" } }
+        })))
+        .unwrap();
+        let headers = settings
+            .registry_config(&NpmrcRegistries::default())
+            .headers;
+        assert_eq!(headers[0].prefix, "//npm.acme.dev/");
+    }
+}
@@ -0,0 +1,38 @@
+//! Directories for tests that read manifests, registries and configuration from disk.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A directory under the system's temporary one, named after the test and the process, which is
+/// removed along with its contents once dropped, so that failing tests don't leave it behind.
+pub(super) struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates the directory, emptying any a previous run left behind.
+    pub(super) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,395 @@
+//! Support for the tool versions pinned by asdf's `.tool-versions` and mise's `mise.toml`, backed
+//! by the release index of each tool.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{Position, Range, Url};
+
+use crate::fetcher::PackageVersionFetcher;
+use crate::response_cache::ResponseCache;
+
+const NODE_INDEX: &str = "https://nodejs.org/dist/index.json";
+const PYTHON_CYCLES: &str = "https://endoflife.date/api/python.json";
+
+/// Tools ship a few releases a week at most, and Node's index alone is hundreds of kilobytes.
+const RELEASES_TTL: Duration = Duration::from_secs(60 * 60);
+
+pub(super) fn is_manifest(uri: &Url) -> bool {
+    let file_name = uri.path().rsplit('/').next().unwrap_or_default();
+    matches!(file_name, ".tool-versions" | "mise.toml" | ".mise.toml")
+}
+
+/// The tools whose releases are known, each with its own index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(super) enum Tool {
+    Node,
+    Python,
+}
+
+impl Tool {
+    /// The tool a plugin name refers to, e.g. asdf's `nodejs` or mise's `node`.
+    pub(super) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "node" | "nodejs" => Some(Self::Node),
+            "python" => Some(Self::Python),
+            _ => None,
+        }
+    }
+
+    /// How many leading components of a version make up its release line: `20` for Node, whose
+    /// majors are supported separately, and `3.12` for Python.
+    fn line_components(self) -> usize {
+        match self {
+            Self::Node => 1,
+            Self::Python => 2,
+        }
+    }
+
+    /// The release line of a version, like `20.x` for `20.11.0`. `None` for aliases like `lts`.
+    pub(super) fn release_line(self, version: &str) -> Option<String> {
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let components: Vec<_> = version.split('.').take(self.line_components()).collect();
+        let is_numeric = components.iter().all(|component| {
+            !component.is_empty() && component.bytes().all(|b| b.is_ascii_digit())
+        });
+        is_numeric.then(|| format!("{}.x", components.join(".")))
+    }
+
+    /// Fetches the releases of the tool, newest first.
+    async fn fetch_releases(self, fetcher: &PackageVersionFetcher) -> Option<Vec<Release>> {
+        let url = match self {
+            Self::Node => NODE_INDEX,
+            Self::Python => PYTHON_CYCLES,
+        };
+        let response = fetcher.request_json(|client| client.get(url)).await?;
+        match self {
+            Self::Node => parse_node_index(&response),
+            Self::Python => parse_python_cycles(&response),
+        }
+    }
+}
+
+/// The releases of a tool, newest first, shared by the hovers showing them.
+pub(super) type Releases = Arc<Vec<Release>>;
+
+/// Remembers the releases of each tool, so that hovering doesn't download its index every time.
+pub(super) struct ReleaseCache {
+    tools: ResponseCache<Tool, Releases>,
+}
+
+impl Default for ReleaseCache {
+    fn default() -> Self {
+        Self {
+            tools: ResponseCache::new(RELEASES_TTL),
+        }
+    }
+}
+
+impl ReleaseCache {
+    /// The releases of a tool, newest first, fetched again once they are older than an hour.
+    /// Hovers waiting for an index that is already being downloaded share it.
+    pub(super) async fn releases(
+        &self,
+        tool: Tool,
+        fetcher: &PackageVersionFetcher,
+    ) -> Option<Releases> {
+        self.tools
+            .get(&tool, || async {
+                Some(Arc::new(tool.fetch_releases(fetcher).await?))
+            })
+            .await
+    }
+}
+
+/// A tool pinned to a version, such as `node 20.11.0`.
+#[derive(Debug, PartialEq)]
+pub(super) struct ToolVersion {
+    pub name: String,
+    /// The first version listed, which is the one installed. Later ones are fallbacks.
+    pub version: String,
+    /// Range of the version.
+    pub range: Range,
+}
+
+/// Returns the tool pinned on the line the cursor is on, if any.
+pub(super) fn tool_at(uri: &Url, text: &str, position: Position) -> Option<ToolVersion> {
+    let line = text.lines().nth(position.line as usize)?;
+    let (name, start, version) = if uri.path().ends_with(".tool-versions") {
+        tool_versions_entry(line)?
+    } else {
+        mise_entry(text, position.line as usize)?
+    };
+    let column = |offset: usize| line[..offset].encode_utf16().count() as u32;
+    Some(ToolVersion {
+        name: name.to_string(),
+        version: version.to_string(),
+        range: Range::new(
+            Position::new(position.line, column(start)),
+            Position::new(position.line, column(start + version.len())),
+        ),
+    })
+}
+
+/// Splits a `.tool-versions` line like `node 20.11.0 18.19.0 # comment` into the tool, the offset
+/// of its first version and that version.
+fn tool_versions_entry(line: &str) -> Option<(&str, usize, &str)> {
+    let line = line.split('#').next().unwrap_or_default();
+    let mut words = line
+        .split_whitespace()
+        .map(|word| (word.as_ptr() as usize - line.as_ptr() as usize, word));
+    let (_, name) = words.next()?;
+    let (start, version) = words.next()?;
+    Some((name, start, version))
+}
+
+/// Finds an entry of the `[tools]` table of a `mise.toml` on the given line, like
+/// `node = "20.11.0"` or `python = ["3.12", "3.11"]`.
+fn mise_entry(text: &str, line_number: usize) -> Option<(&str, usize, &str)> {
+    let mut in_tools = false;
+    for (index, line) in text.lines().enumerate() {
+        let content = line.trim();
+        if content.starts_with('[') && !content.starts_with("[[") {
+            in_tools = content == "[tools]";
+        }
+        if index < line_number {
+            continue;
+        }
+        if !in_tools {
+            return None;
+        }
+        let (key, value) = line.split_once('=')?;
+        let name = key.trim().trim_matches('"');
+        let value = value.trim_start();
+        // Inline tables carry options that aren't looked at.
+        if value.starts_with('{') {
+            return None;
+        }
+        let quote_start = line.len() - value.len() + value.find('"')? + 1;
+        let len = line[quote_start..].find('"')?;
+        return Some((name, quote_start, &line[quote_start..quote_start + len]));
+    }
+    None
+}
+
+/// A release of a tool.
+#[derive(Debug, PartialEq)]
+pub(super) struct Release {
+    pub version: String,
+    /// Whether the release is from a long-term support line.
+    pub lts: bool,
+}
+
+/// Reads Node's release index, which lists every release newest first, each along with the name
+/// of its LTS line, or `false`.
+fn parse_node_index(response: &Value) -> Option<Vec<Release>> {
+    let releases = response.as_array()?.iter().filter_map(|release| {
+        Some(Release {
+            version: release["version"]
+                .as_str()?
+                .trim_start_matches('v')
+                .to_string(),
+            lts: release["lts"].is_string(),
+        })
+    });
+    Some(releases.collect())
+}
+
+/// Reads the Python release cycles from endoflife.date, newest first, each with its latest patch
+/// release.
+fn parse_python_cycles(response: &Value) -> Option<Vec<Release>> {
+    let releases = response.as_array()?.iter().filter_map(|cycle| {
+        Some(Release {
+            version: cycle["latest"].as_str()?.to_string(),
+            lts: cycle["lts"].as_bool().unwrap_or(false),
+        })
+    });
+    Some(releases.collect())
+}
+
+/// What hover tells about a pinned tool: the newest LTS and current releases, and the newest of
+/// the pinned release line if it's behind.
+pub(super) fn summary(tool: Tool, pinned: &str, releases: &[Release]) -> String {
+    let mut lines = Vec::new();
+    let (current, lts) = (
+        releases.first(),
+        releases.iter().find(|release| release.lts),
+    );
+    let latest = [("latest LTS", lts), ("current", current)]
+        .into_iter()
+        .filter_map(|(label, release)| {
+            let release = release?;
+            let line = tool
+                .release_line(&release.version)
+                .unwrap_or_else(|| release.version.clone());
+            Some(format!("{label} {line} ({})", release.version))
+        })
+        .collect::<Vec<_>>();
+    if !latest.is_empty() {
+        lines.push(capitalize(&latest.join(" / ")));
+    }
+    let pinned = pinned.strip_prefix('v').unwrap_or(pinned);
+    if let Some(line) = tool.release_line(pinned) {
+        let prefix = &line[..line.len() - 1];
+        let newest = releases
+            .iter()
+            .find(|release| release.version == pinned || release.version.starts_with(prefix));
+        match newest {
+            Some(newest) if newest.version == pinned => lines.push("✓ Up to date".to_string()),
+            Some(newest) => lines.push(format!("Newest {line}: {}", newest.version)),
+            None => {}
+        }
+    }
+    lines.join("\n\n")
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::{FetchMode, RegistryConfig};
+    use serde_json::json;
+    use std::time::Instant;
+
+    fn uri(file_name: &str) -> Url {
+        Url::parse(&format!("file:///project/{file_name}")).unwrap()
+    }
+
+    fn releases(versions: &[(&str, bool)]) -> Vec<Release> {
+        versions
+            .iter()
+            .map(|&(version, lts)| Release {
+                version: version.into(),
+                lts,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_is_manifest() {
+        for (file_name, expected) in [
+            (".tool-versions", true),
+            ("mise.toml", true),
+            (".mise.toml", true),
+            ("Cargo.toml", false),
+        ] {
+            assert_eq!(is_manifest(&uri(file_name)), expected, "{file_name}");
+        }
+    }
+
+    #[test]
+    fn test_tool_at() {
+        let tool_versions = "# runtimes\nnodejs 20.11.0 18.19.0\npython  3.12.1\n";
+        let asdf = uri(".tool-versions");
+        assert_eq!(
+            tool_at(&asdf, tool_versions, Position::new(1, 2)),
+            Some(ToolVersion {
+                name: "nodejs".into(),
+                version: "20.11.0".into(),
+                range: Range::new(Position::new(1, 7), Position::new(1, 14)),
+            })
+        );
+        let python = tool_at(&asdf, tool_versions, Position::new(2, 0)).unwrap();
+        assert_eq!(
+            (python.name.as_str(), python.version.as_str()),
+            ("python", "3.12.1")
+        );
+        assert_eq!(python.range.start.character, 8);
+        assert_eq!(tool_at(&asdf, tool_versions, Position::new(0, 3)), None);
+
+        let mise = r#"[env]
+NODE_ENV = "production"
+
+[tools]
+node = "22"
+python = ["3.12", "3.11"]
+"go" = { version = "1.23" }
+"#;
+        let mise_uri = uri("mise.toml");
+        let node = tool_at(&mise_uri, mise, Position::new(4, 1)).unwrap();
+        assert_eq!((node.name.as_str(), node.version.as_str()), ("node", "22"));
+        assert_eq!(
+            node.range,
+            Range::new(Position::new(4, 8), Position::new(4, 10))
+        );
+        let python = tool_at(&mise_uri, mise, Position::new(5, 1)).unwrap();
+        assert_eq!(python.version, "3.12");
+        assert_eq!(tool_at(&mise_uri, mise, Position::new(6, 1)), None);
+        assert_eq!(tool_at(&mise_uri, mise, Position::new(1, 1)), None);
+
+        let commented = uri(".tool-versions");
+        let node = tool_at(&commented, "nodejs\u{3000}20.11.0\n", Position::new(0, 0)).unwrap();
+        assert_eq!(
+            node.range,
+            Range::new(Position::new(0, 7), Position::new(0, 14))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_release_cache() {
+        let cache = ReleaseCache::default();
+        let cached = Arc::new(releases(&[("22.11.0", true)]));
+        cache
+            .tools
+            .insert_for_tests(Tool::Node, Instant::now(), cached.clone());
+        // Answered from the cache, as nothing can be reached offline.
+        let fetcher = PackageVersionFetcher::new().unwrap();
+        fetcher.configure(RegistryConfig {
+            fetch_mode: FetchMode::Offline,
+            ..Default::default()
+        });
+        let releases = cache.releases(Tool::Node, &fetcher).await.unwrap();
+        assert!(Arc::ptr_eq(&releases, &cached));
+        assert_eq!(cache.releases(Tool::Python, &fetcher).await, None);
+    }
+
+    #[test]
+    fn test_parse_releases() {
+        let node = parse_node_index(&json!([
+            { "version": "v23.3.0", "lts": false },
+            { "version": "v22.11.0", "lts": "Jod" }
+        ]))
+        .unwrap();
+        assert_eq!(node, releases(&[("23.3.0", false), ("22.11.0", true)]));
+        let python = parse_python_cycles(&json!([
+            { "cycle": "3.13", "latest": "3.13.0", "lts": false },
+            { "cycle": "3.12", "latest": "3.12.7", "lts": false }
+        ]))
+        .unwrap();
+        assert_eq!(python, releases(&[("3.13.0", false), ("3.12.7", false)]));
+    }
+
+    #[test]
+    fn test_summary() {
+        let node = releases(&[
+            ("23.3.0", false),
+            ("22.11.0", true),
+            ("20.18.1", true),
+            ("20.11.0", true),
+        ]);
+        assert_eq!(
+            summary(Tool::Node, "20.11.0", &node),
+            "Latest LTS 22.x (22.11.0) / current 23.x (23.3.0)\n\nNewest 20.x: 20.18.1"
+        );
+        assert!(summary(Tool::Node, "v20.18.1", &node).ends_with("✓ Up to date"));
+        assert!(summary(Tool::Node, "20", &node).ends_with("Newest 20.x: 20.18.1"));
+        assert_eq!(
+            summary(Tool::Node, "lts", &node),
+            "Latest LTS 22.x (22.11.0) / current 23.x (23.3.0)"
+        );
+
+        let python = releases(&[("3.13.0", false), ("3.12.7", false)]);
+        assert_eq!(
+            summary(Tool::Python, "3.12.1", &python),
+            "Current 3.13.x (3.13.0)\n\nNewest 3.12.x: 3.12.7"
+        );
+    }
+}
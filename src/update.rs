@@ -0,0 +1,814 @@
+use tower_lsp::lsp_types::TextEdit;
+
+use crate::completion::strip_operator;
+use crate::diagnostics;
+use crate::fetcher::MetadataFromRegistry;
+use crate::parser::{Dependency, DependencyKind};
+
+/// A new version specifier that a dependency can be moved to.
+#[derive(Debug)]
+pub(super) struct Update {
+    pub title: String,
+    pub edit: TextEdit,
+    /// Whether the update crosses a major version, and might thus break things.
+    pub is_major: bool,
+}
+
+/// Splits a specifier made of an optional operator and a full version, like `^4.17.0`.
+fn plain_version(specifier: &str) -> Option<(&str, semver_rs::Version)> {
+    let specifier = specifier.trim();
+    let version = strip_operator(specifier);
+    let operator = &specifier[..specifier.len() - version.len()];
+    let core = version.split(['-', '+']).next().unwrap_or_default();
+    let is_plain = core.split('.').count() == 3
+        && core
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+    if !is_plain {
+        return None;
+    }
+    Some((operator, semver_rs::Version::new(version).parse().ok()?))
+}
+
+/// How far a version specifier lets a dependency move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum SpecifierKind {
+    /// A single version, like `4.17.1`.
+    Exact,
+    /// Like `^4.17.1`, which allows minor and patch updates.
+    Caret,
+    /// Like `~4.17.1`, which allows patch updates.
+    Tilde,
+    /// Any other range npm understands, like `4.x` or `>=4 <5`.
+    Range,
+}
+
+/// Classifies a version specifier, or returns `None` for ones that aren't ranges, like dist-tags
+/// and protocols.
+pub(super) fn specifier_kind(specifier: &str) -> Option<SpecifierKind> {
+    if let Some((operator, _)) = plain_version(specifier) {
+        return Some(match operator {
+            "" | "=" => SpecifierKind::Exact,
+            "^" => SpecifierKind::Caret,
+            "~" => SpecifierKind::Tilde,
+            _ => SpecifierKind::Range,
+        });
+    }
+    diagnostics::parse_range(specifier.trim()).map(|_| SpecifierKind::Range)
+}
+
+/// Rewrites a version specifier so that it allows `target`, keeping an operator like `^` or `~`
+/// in front of a plain version. Anything more elaborate, like `4.x` or `>=1 <3`, becomes a caret
+/// range.
+pub(super) fn updated_specifier(specifier: &str, target: &semver_rs::Version) -> String {
+    match plain_version(specifier) {
+        Some((operator, _)) => format!("{operator}{target}"),
+        None => format!("^{target}"),
+    }
+}
+
+/// The edit that moves an outdated dependency to `latest`, if it needs one.
+pub(super) fn update_edit(
+    dependency: &Dependency,
+    latest: &semver_rs::Version,
+) -> Option<TextEdit> {
+    diagnostics::is_outdated(dependency, latest).then(|| TextEdit {
+        range: dependency.version_range,
+        new_text: updated_specifier(&dependency.version, latest),
+    })
+}
+
+fn update_title(name: &str, specifier: &str, is_major: bool) -> String {
+    if is_major {
+        format!("Update {name} to {specifier} (new major version)")
+    } else {
+        format!("Update {name} to {specifier}")
+    }
+}
+
+/// Title of an update to the newest release of the major version in use, like
+/// `Update to newest 4.x (4.18.0)`.
+fn in_major_title(version: &semver_rs::Version) -> String {
+    format!("Update to newest {}.x ({version})", version.major)
+}
+
+/// Title of the quick fix carried by a diagnostic with the given code, named like the update the
+/// same fix gets when it is computed from the registry.
+pub(super) fn fix_title(code: &str, fix: &diagnostics::Fix) -> Option<String> {
+    match code {
+        diagnostics::OUTDATED => Some(
+            plain_version(&fix.version)
+                .filter(|_| !fix.is_major)
+                .map(|(_, version)| in_major_title(&version))
+                .unwrap_or_else(|| update_title(&fix.name, &fix.version, fix.is_major)),
+        ),
+        diagnostics::UNPINNED => Some(format!("Pin to {}", fix.version)),
+        diagnostics::PRERELEASE => Some(format!("Use newest stable version {}", fix.version)),
+        _ => None,
+    }
+}
+
+/// The updates offered for a single dependency: to the newest release of the major version in
+/// use, unless the range already resolves to it, and separately to the latest version if that is
+/// a new major.
+pub(super) fn available_updates(
+    dependency: &Dependency,
+    metadata: &MetadataFromRegistry,
+) -> Vec<Update> {
+    let latest = &metadata.latest_version.version;
+    let update = |target: &semver_rs::Version, is_major| {
+        let new_text = updated_specifier(&dependency.version, target);
+        Update {
+            title: update_title(&dependency.name, &new_text, is_major),
+            edit: TextEdit {
+                range: dependency.version_range,
+                new_text,
+            },
+            is_major,
+        }
+    };
+    let Some((_, current)) = plain_version(&dependency.version) else {
+        return update_edit(dependency, latest)
+            .map(|_| update(latest, false))
+            .into_iter()
+            .collect();
+    };
+    if latest.major == current.major {
+        return (*latest > current)
+            .then(|| Update {
+                title: in_major_title(latest),
+                ..update(latest, false)
+            })
+            .into_iter()
+            .collect();
+    }
+    let versions = || {
+        metadata
+            .package_versions
+            .iter()
+            .map(|package_version| &package_version.version)
+    };
+    let newest_in_major = versions()
+        .filter(|version| {
+            version.major == current.major && !version.has_prerelease() && **version > current
+        })
+        .max();
+    let resolved = diagnostics::parse_range(&dependency.version)
+        .and_then(|range| versions().filter(|version| range.test(version)).max());
+    newest_in_major
+        .filter(|&newest| Some(newest) != resolved)
+        .map(|newest| Update {
+            title: in_major_title(newest),
+            ..update(newest, false)
+        })
+        .into_iter()
+        .chain((*latest > current).then(|| update(latest, true)))
+        .collect()
+}
+
+/// Raises the version a range starts at to the newest release it already allows, keeping the
+/// operator, so `^4.17.0` becomes `^4.21.2`. Never crosses a major version, unlike
+/// [`update_edit`].
+pub(super) fn in_range_update(
+    dependency: &Dependency,
+    metadata: &MetadataFromRegistry,
+) -> Option<TextEdit> {
+    let (operator, current) = plain_version(&dependency.version)?;
+    let range = diagnostics::parse_range(&dependency.version)?;
+    let newest = metadata
+        .package_versions
+        .iter()
+        .map(|package_version| &package_version.version)
+        .filter(|version| {
+            version.major == current.major && !version.has_prerelease() && range.test(version)
+        })
+        .max()?;
+    (*newest > current).then(|| TextEdit {
+        range: dependency.version_range,
+        new_text: format!("{operator}{newest}"),
+    })
+}
+
+/// Whether the latest version is a new major that the declared range doesn't allow yet. The range's
+/// major version is the first number in it, e.g. 4 for `^4.17.0`, `4.x` or `>=4 <4.5`.
+pub(super) fn has_newer_major(dependency: &Dependency, latest: &semver_rs::Version) -> bool {
+    let specifier = strip_operator(dependency.version.trim());
+    let digits = specifier
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(specifier.len());
+    let Ok(major) = specifier[..digits].parse::<i64>() else {
+        return false;
+    };
+    diagnostics::is_outdated(dependency, latest) && latest.major > major
+}
+
+/// Rewrites between a range and an exact version: pins a range to the newest version it allows,
+/// or relaxes an exact version to a caret range.
+pub(super) fn range_rewrites(
+    dependency: &Dependency,
+    metadata: &MetadataFromRegistry,
+) -> Vec<Update> {
+    let rewrite = |title: String, new_text: String| Update {
+        title,
+        edit: TextEdit {
+            range: dependency.version_range,
+            new_text,
+        },
+        is_major: false,
+    };
+    if let Some(("" | "=", version)) = plain_version(&dependency.version) {
+        return vec![rewrite(
+            format!("Relax to ^{version}"),
+            format!("^{version}"),
+        )];
+    }
+    let Some(range) = diagnostics::parse_range(&dependency.version) else {
+        return Vec::new();
+    };
+    metadata
+        .package_versions
+        .iter()
+        .map(|package_version| &package_version.version)
+        .filter(|version| range.test(version))
+        .max()
+        .map(|best| rewrite(format!("Pin to {best}"), best.to_string()))
+        .into_iter()
+        .collect()
+}
+
+/// Moves a production dependency that resolves to a prerelease to the newest stable version.
+pub(super) fn stable_update(
+    dependency: &Dependency,
+    metadata: &MetadataFromRegistry,
+) -> Option<Update> {
+    let prerelease = diagnostics::prerelease_match(dependency, &metadata.package_versions)?;
+    let stable = diagnostics::newest_stable(&metadata.package_versions)?;
+    let specifier = updated_specifier(&dependency.version, stable);
+    Some(Update {
+        title: format!("Use newest stable version {specifier}"),
+        edit: TextEdit {
+            range: dependency.version_range,
+            new_text: specifier,
+        },
+        is_major: stable.major != prerelease.major,
+    })
+}
+
+/// Pins a wildcard or dist-tag to a caret range of the version it currently resolves to.
+pub(super) fn unpinned_pin(
+    dependency: &Dependency,
+    metadata: &MetadataFromRegistry,
+) -> Option<Update> {
+    let version = match diagnostics::unpinned_tag(&dependency.version)? {
+        "latest" => metadata.latest_version.version.to_string(),
+        tag => metadata.dist_tags.get(tag)?.clone(),
+    };
+    Some(Update {
+        title: format!("Pin to ^{version}"),
+        edit: TextEdit {
+            range: dependency.version_range,
+            new_text: format!("^{version}"),
+        },
+        is_major: false,
+    })
+}
+
+/// Whether a specifier points at a git repository or a tarball instead of the registry, like
+/// `github:user/repo`, `git+https://...#v1.2.0`, `https://.../package.tgz` or `user/repo`.
+fn is_git_or_tarball(specifier: &str) -> bool {
+    const PREFIXES: &[&str] = &[
+        "git+",
+        "git://",
+        "github:",
+        "gitlab:",
+        "bitbucket:",
+        "gist:",
+        "http://",
+        "https://",
+    ];
+    if PREFIXES.iter().any(|prefix| specifier.starts_with(prefix)) {
+        return true;
+    }
+    // GitHub shorthand, as opposed to a path.
+    !specifier.contains(':')
+        && specifier
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .split('/')
+            .count()
+            == 2
+        && !specifier.starts_with(['.', '/', '~', '@'])
+}
+
+/// Replaces a git or tarball specifier with a caret range on the registry. The version comes from
+/// a `#v1.2.0` fragment when the registry has it, and is the latest version otherwise.
+pub(super) fn registry_replacement(
+    dependency: &Dependency,
+    metadata: &MetadataFromRegistry,
+) -> Option<Update> {
+    let specifier = dependency.version.trim();
+    if !is_git_or_tarball(specifier) {
+        return None;
+    }
+    let tagged = specifier
+        .split_once('#')
+        .map(|(_, fragment)| fragment.strip_prefix("semver:").unwrap_or(fragment))
+        .and_then(|fragment| plain_version(fragment.trim_start_matches('v')))
+        .map(|(_, version)| version)
+        .filter(|version| {
+            metadata
+                .package_versions
+                .iter()
+                .any(|package_version| package_version.version == *version)
+        });
+    let version = tagged.unwrap_or_else(|| metadata.latest_version.version.clone());
+    Some(Update {
+        title: format!("Replace with registry version ^{version}"),
+        edit: TextEdit {
+            range: dependency.version_range,
+            new_text: format!("^{version}"),
+        },
+        is_major: false,
+    })
+}
+
+/// Words that follow "use" in deprecation messages without naming a package.
+const NOT_PACKAGES: &[&str] = &[
+    "another", "at", "built-in", "instead", "it", "its", "latest", "native", "new", "of", "one",
+    "this", "that", "version", "your",
+];
+
+/// Whether `name` is a valid name for a package published to the registry.
+fn is_package_name(name: &str) -> bool {
+    let is_valid_part = |part: &str| {
+        !part.is_empty()
+            && !part.starts_with(['.', '_'])
+            && part.chars().all(|c| {
+                c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '.' | '_' | '~')
+            })
+    };
+    let unscoped = match name.strip_prefix('@') {
+        Some(scoped) => match scoped.split_once('/') {
+            Some((scope, unscoped)) if is_valid_part(scope) => unscoped,
+            _ => return false,
+        },
+        None => name,
+    };
+    name.len() <= 214 && is_valid_part(unscoped)
+}
+
+/// The package a deprecation message points to instead, as in "use `got` instead" or "renamed to
+/// @acme/ui". Packages named after "use" only count when quoted, scoped or followed by "instead",
+/// as the word is just as often followed by prose.
+pub(super) fn deprecation_successor(message: &str) -> Option<&str> {
+    let lowercase = message.to_ascii_lowercase();
+    for marker in ["use ", "replaced by ", "renamed to ", "moved to "] {
+        for (ix, _) in lowercase.match_indices(marker) {
+            if lowercase[..ix].ends_with(|c: char| c.is_ascii_alphanumeric()) {
+                continue;
+            }
+            let mut words = message[ix + marker.len()..]
+                .split_whitespace()
+                .skip_while(|word| {
+                    ["a", "an", "the"].contains(&word.to_ascii_lowercase().as_str())
+                });
+            let Some(word) = words.next() else {
+                continue;
+            };
+            let is_quoted = word.starts_with(['`', '\'', '"']);
+            let name = word.trim_matches(|c: char| "`'\".,;:!()".contains(c));
+            // Drops a version, as in `uuid@9`.
+            let name = match name.get(1..).and_then(|rest| rest.find('@')) {
+                Some(ix) => &name[..ix + 1],
+                None => name,
+            };
+            if !is_package_name(name) || (!is_quoted && NOT_PACKAGES.contains(&name)) {
+                continue;
+            }
+            let is_followed_by_instead = words.next().is_some_and(|next| {
+                next.trim_end_matches(|c: char| c.is_ascii_punctuation())
+                    .eq_ignore_ascii_case("instead")
+            });
+            if marker == "use " && !is_quoted && !is_followed_by_instead && !name.starts_with('@') {
+                continue;
+            }
+            return Some(name);
+        }
+    }
+    None
+}
+
+/// How two declarations of the same package are merged into one.
+#[derive(Debug)]
+pub(super) struct Merge<'a> {
+    pub title: String,
+    pub removed: &'a Dependency,
+    pub kept: &'a Dependency,
+    /// The range the kept declaration takes over from the removed one, when that one's is newer.
+    pub version: Option<String>,
+}
+
+/// Decides which of two declarations of a package stays. Like npm, an optional dependency wins
+/// over a production one, which wins over the rest. Within one section the later declaration
+/// stays, as it's the one `JSON.parse` keeps. When the ranges differ, the newer one is kept, or
+/// the stricter one if they start at the same version.
+pub(super) fn merge_duplicates<'a>(a: &'a Dependency, b: &'a Dependency) -> Merge<'a> {
+    let precedence = |section| match section {
+        DependencyKind::OptionalDependencies => 0,
+        DependencyKind::Dependencies => 1,
+        DependencyKind::PeerDependencies => 2,
+        DependencyKind::DevDependencies => 3,
+        DependencyKind::BundledDependencies => 4,
+    };
+    let keeps_a = match precedence(a.section).cmp(&precedence(b.section)) {
+        std::cmp::Ordering::Equal => a.name_range.start > b.name_range.start,
+        ordering => ordering.is_lt(),
+    };
+    let (kept, removed) = if keeps_a { (a, b) } else { (b, a) };
+    let version = (range_order(&removed.version) > range_order(&kept.version)
+        && range_order(&kept.version).is_some())
+    .then(|| removed.version.trim().to_string());
+    let title = if kept.version.trim() == removed.version.trim() {
+        format!(
+            "Remove duplicate {} from {}",
+            removed.name,
+            removed.section.section_name()
+        )
+    } else {
+        let same_section = kept.section == removed.section;
+        let section = removed.section.section_name();
+        let removal = match (&version, same_section) {
+            (Some(_), true) => "the other declaration".to_string(),
+            (Some(_), false) => format!("the one in {section}"),
+            (None, true) => format!("the earlier {}", removed.version.trim()),
+            (None, false) => format!("{} from {section}", removed.version.trim()),
+        };
+        format!(
+            "Keep {}@{} in {}, removing {removal}",
+            kept.name,
+            version.as_deref().unwrap_or(kept.version.trim()),
+            kept.section.section_name(),
+        )
+    };
+    Merge {
+        title,
+        removed,
+        kept,
+        version,
+    }
+}
+
+/// Orders plain version ranges by the version they start at, then by how little they allow.
+fn range_order(specifier: &str) -> Option<(semver_rs::Version, u8)> {
+    let strictness = match specifier_kind(specifier)? {
+        SpecifierKind::Range => 0,
+        SpecifierKind::Caret => 1,
+        SpecifierKind::Tilde => 2,
+        SpecifierKind::Exact => 3,
+    };
+    Some((plain_version(specifier)?.1, strictness))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use crate::fetcher::tests::package_version;
+
+    fn dependency(version: &str) -> Dependency {
+        Dependency {
+            name: "lodash".into(),
+            version: version.into(),
+            section: DependencyKind::Dependencies,
+            name_range: Range::default(),
+            version_range: Range::default(),
+        }
+    }
+
+    fn metadata(latest: &str, versions: &[&str]) -> MetadataFromRegistry {
+        MetadataFromRegistry::for_tests(
+            package_version(latest),
+            versions
+                .iter()
+                .map(|version| package_version(version))
+                .collect(),
+        )
+    }
+
+    fn titles(updates: Vec<Update>) -> Vec<String> {
+        updates.into_iter().map(|update| update.title).collect()
+    }
+
+    #[test]
+    fn test_available_updates() {
+        let metadata = metadata(
+            "5.1.0",
+            &["4.17.0", "4.17.21", "4.18.0", "5.0.0-rc.1", "5.1.0"],
+        );
+        let updates = available_updates(&dependency("~4.17.0"), &metadata);
+        assert_eq!(updates[0].edit.new_text, "~4.18.0");
+        assert!(!updates[0].is_major);
+        assert_eq!(
+            titles(updates),
+            [
+                "Update to newest 4.x (4.18.0)",
+                "Update lodash to ~5.1.0 (new major version)"
+            ]
+        );
+        // `^4.17.0` already resolves to the newest 4.x.
+        assert_eq!(
+            titles(available_updates(&dependency("^4.17.0"), &metadata)),
+            ["Update lodash to ^5.1.0 (new major version)"]
+        );
+        assert_eq!(
+            titles(available_updates(&dependency("4.17.0"), &metadata))[0],
+            "Update to newest 4.x (4.18.0)"
+        );
+        let updates = available_updates(&dependency("^4.18.0"), &metadata);
+        assert_eq!(
+            titles(updates),
+            ["Update lodash to ^5.1.0 (new major version)"]
+        );
+        let updates = available_updates(&dependency("5.0.0"), &metadata);
+        assert_eq!(updates[0].edit.new_text, "5.1.0");
+        assert!(!updates[0].is_major);
+        assert_eq!(updates[0].title, "Update to newest 5.x (5.1.0)");
+        assert!(available_updates(&dependency("^5.1.0"), &metadata).is_empty());
+        assert_eq!(
+            titles(available_updates(&dependency("4.x"), &metadata)),
+            ["Update lodash to ^5.1.0"]
+        );
+        assert!(available_updates(&dependency("*"), &metadata).is_empty());
+    }
+
+    #[test]
+    fn test_in_range_update() {
+        let metadata = metadata(
+            "5.0.0",
+            &["4.17.0", "4.17.21", "4.21.2", "4.22.0-rc.1", "5.0.0"],
+        );
+        let new_text = |specifier| {
+            in_range_update(&dependency(specifier), &metadata).map(|edit| edit.new_text)
+        };
+        assert_eq!(new_text("^4.17.0").as_deref(), Some("^4.21.2"));
+        assert_eq!(new_text("~4.17.0").as_deref(), Some("~4.17.21"));
+        assert_eq!(new_text(">=4.17.0").as_deref(), Some(">=4.21.2"));
+        assert_eq!(new_text("4.17.0"), None);
+        assert_eq!(new_text("^4.21.2"), None);
+        assert_eq!(new_text("^4"), None);
+    }
+
+    #[test]
+    fn test_range_rewrites() {
+        let metadata = metadata("5.0.0", &["4.17.0", "4.21.2", "4.22.0-rc.1", "5.0.0"]);
+        let rewrites = range_rewrites(&dependency("^4.17.0"), &metadata);
+        assert_eq!(titles(rewrites), ["Pin to 4.21.2"]);
+        let rewrites = range_rewrites(&dependency(">=4 <6"), &metadata);
+        assert_eq!(rewrites[0].edit.new_text, "5.0.0");
+
+        let rewrites = range_rewrites(&dependency("4.21.2"), &metadata);
+        assert_eq!(titles(rewrites), ["Relax to ^4.21.2"]);
+        let rewrites = range_rewrites(&dependency("=4.21.2"), &metadata);
+        assert_eq!(rewrites[0].edit.new_text, "^4.21.2");
+
+        assert!(range_rewrites(&dependency("^3"), &metadata).is_empty());
+        assert!(range_rewrites(&dependency("latest"), &metadata).is_empty());
+    }
+
+    #[test]
+    fn test_updated_specifier() {
+        let latest = semver_rs::Version::new("5.1.0").parse().unwrap();
+        assert_eq!(updated_specifier("^4.17.21", &latest), "^5.1.0");
+        assert_eq!(updated_specifier("~4.17.21", &latest), "~5.1.0");
+        assert_eq!(updated_specifier("4.17.21", &latest), "5.1.0");
+        assert_eq!(updated_specifier(">=4.0.0-rc.1", &latest), ">=5.1.0");
+        assert_eq!(updated_specifier("4.x", &latest), "^5.1.0");
+        assert_eq!(updated_specifier("^4", &latest), "^5.1.0");
+        assert_eq!(updated_specifier(">=3 <5", &latest), "^5.1.0");
+    }
+
+    #[test]
+    fn test_has_newer_major() {
+        let latest = semver_rs::Version::new("5.1.0").parse().unwrap();
+        assert!(has_newer_major(&dependency("^4.17.0"), &latest));
+        assert!(has_newer_major(&dependency("4.x"), &latest));
+        assert!(has_newer_major(&dependency(">=3 <5"), &latest));
+        assert!(!has_newer_major(&dependency("~5.0.0"), &latest));
+        assert!(!has_newer_major(&dependency("^5.0.0"), &latest));
+        assert!(!has_newer_major(&dependency("latest"), &latest));
+        let prerelease = semver_rs::Version::new("6.0.0-rc.1").parse().unwrap();
+        assert!(!has_newer_major(&dependency("^5.0.0"), &prerelease));
+    }
+
+    #[test]
+    fn test_registry_replacement() {
+        let metadata = metadata("4.17.21", &["4.17.0", "4.17.21"]);
+        let new_text = |specifier| {
+            registry_replacement(&dependency(specifier), &metadata)
+                .map(|update| update.edit.new_text)
+        };
+        assert_eq!(
+            new_text("git+https://github.com/lodash/lodash.git#v4.17.0").as_deref(),
+            Some("^4.17.0")
+        );
+        assert_eq!(
+            new_text("github:lodash/lodash#semver:4.17.0").as_deref(),
+            Some("^4.17.0")
+        );
+        assert_eq!(
+            new_text("lodash/lodash#v3.0.0").as_deref(),
+            Some("^4.17.21")
+        );
+        assert_eq!(
+            new_text("https://example.com/lodash-4.17.0.tgz").as_deref(),
+            Some("^4.17.21")
+        );
+        let replacement = registry_replacement(&dependency("lodash/lodash"), &metadata).unwrap();
+        assert_eq!(replacement.title, "Replace with registry version ^4.17.21");
+        assert_eq!(new_text("^4.17.0"), None);
+        assert_eq!(new_text("file:../lodash"), None);
+        assert_eq!(new_text("workspace:*"), None);
+        assert_eq!(new_text("npm:lodash@4"), None);
+        assert_eq!(new_text("./vendor/lodash"), None);
+    }
+
+    #[test]
+    fn test_stable_update() {
+        let metadata = metadata(
+            "18.3.1",
+            &["18.2.0", "18.3.1", "18.4.0-beta.1", "19.0.0-rc.1"],
+        );
+        let update = stable_update(&dependency("19.0.0-rc.1"), &metadata).unwrap();
+        assert_eq!(update.title, "Use newest stable version 18.3.1");
+        assert_eq!(update.edit.new_text, "18.3.1");
+        assert!(update.is_major);
+        let update = stable_update(&dependency("^18.4.0-beta.0"), &metadata).unwrap();
+        assert_eq!(update.edit.new_text, "^18.3.1");
+        assert!(!update.is_major);
+        assert!(stable_update(&dependency("^18.2.0"), &metadata).is_none());
+    }
+
+    #[test]
+    fn test_unpinned_pin() {
+        let mut metadata = metadata("4.17.21", &[]);
+        metadata
+            .dist_tags
+            .insert("next".into(), "5.0.0-rc.1".into());
+        let pin = |specifier| {
+            unpinned_pin(&dependency(specifier), &metadata).map(|update| update.edit.new_text)
+        };
+        assert_eq!(pin("*").as_deref(), Some("^4.17.21"));
+        assert_eq!(pin("latest").as_deref(), Some("^4.17.21"));
+        assert_eq!(pin("next").as_deref(), Some("^5.0.0-rc.1"));
+        assert_eq!(pin("beta"), None);
+        assert_eq!(pin("^4.17.0"), None);
+    }
+
+    #[test]
+    fn test_specifier_kind() {
+        for (specifier, kind) in [
+            ("4.17.1", Some(SpecifierKind::Exact)),
+            ("=4.17.1", Some(SpecifierKind::Exact)),
+            ("^4.17.1", Some(SpecifierKind::Caret)),
+            (" ~4.17.1 ", Some(SpecifierKind::Tilde)),
+            (">=4.17.1", Some(SpecifierKind::Range)),
+            ("4.x", Some(SpecifierKind::Range)),
+            (">=4 <5", Some(SpecifierKind::Range)),
+            ("latest", None),
+            ("file:../lodash", None),
+        ] {
+            assert_eq!(specifier_kind(specifier), kind, "{specifier}");
+        }
+    }
+
+    #[test]
+    fn test_fix_title() {
+        let fix = |version: &str, is_major| diagnostics::Fix {
+            name: "lodash".into(),
+            section: "dependencies".into(),
+            version: version.into(),
+            is_major,
+        };
+        let same_as_update = |code, fix: diagnostics::Fix, update: Option<Update>| {
+            assert_eq!(fix_title(code, &fix), update.map(|update| update.title));
+        };
+        let lodash = metadata("4.17.21", &["3.10.1", "4.17.21"]);
+        same_as_update(
+            diagnostics::OUTDATED,
+            fix("^4.17.21", true),
+            available_updates(&dependency("^3.0.0"), &lodash).pop(),
+        );
+        same_as_update(
+            diagnostics::OUTDATED,
+            fix("~4.17.21", false),
+            available_updates(&dependency("~4.17.0"), &lodash).pop(),
+        );
+        same_as_update(
+            diagnostics::UNPINNED,
+            fix("^4.17.21", false),
+            unpinned_pin(&dependency("*"), &lodash),
+        );
+        let react = metadata("18.3.1", &["18.3.1", "19.0.0-rc.1"]);
+        same_as_update(
+            diagnostics::PRERELEASE,
+            fix("18.3.1", true),
+            stable_update(&dependency("19.0.0-rc.1"), &react),
+        );
+        assert_eq!(
+            fix_title(diagnostics::DEPRECATED, &fix("^4.17.21", false)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_merge_duplicates() {
+        let declared = |section, version: &str, line| Dependency {
+            section,
+            name_range: Range::new(Position::new(line, 4), Position::new(line, 10)),
+            ..dependency(version)
+        };
+        let merge = |a: &Dependency, b: &Dependency| {
+            let merge = merge_duplicates(a, b);
+            (
+                merge.removed.name_range.start.line,
+                merge.version,
+                merge.title,
+            )
+        };
+
+        let production = declared(DependencyKind::Dependencies, "^4.17.21", 2);
+        let dev = declared(DependencyKind::DevDependencies, "^4.17.21", 5);
+        let expected = (
+            5,
+            None,
+            "Remove duplicate lodash from devDependencies".into(),
+        );
+        assert_eq!(merge(&production, &dev), expected);
+        assert_eq!(merge(&dev, &production), expected);
+
+        let dev = declared(DependencyKind::DevDependencies, "^4.18.0", 5);
+        assert_eq!(
+            merge(&production, &dev),
+            (
+                5,
+                Some("^4.18.0".into()),
+                "Keep lodash@^4.18.0 in dependencies, removing the one in devDependencies".into()
+            )
+        );
+        let dev = declared(DependencyKind::DevDependencies, "~4.17.21", 5);
+        assert_eq!(merge(&production, &dev).1.as_deref(), Some("~4.17.21"));
+        let dev = declared(DependencyKind::DevDependencies, "^4.0.0", 5);
+        assert_eq!(
+            merge(&production, &dev),
+            (
+                5,
+                None,
+                "Keep lodash@^4.17.21 in dependencies, removing ^4.0.0 from devDependencies".into()
+            )
+        );
+        let dev = declared(DependencyKind::DevDependencies, "^5.0.0", 5);
+        let tag = declared(DependencyKind::Dependencies, "latest", 2);
+        assert_eq!(merge(&tag, &dev).1, None);
+
+        let optional = declared(DependencyKind::OptionalDependencies, "^4.17.21", 8);
+        assert_eq!(merge(&production, &optional).0, 2);
+
+        let later = declared(DependencyKind::Dependencies, "^4.17.0", 3);
+        assert_eq!(
+            merge(&later, &production),
+            (
+                2,
+                Some("^4.17.21".into()),
+                "Keep lodash@^4.17.21 in dependencies, removing the other declaration".into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_deprecation_successor() {
+        for (message, successor) in [
+            ("Use `uuid` instead.", Some("uuid")),
+            ("Please use the 'got' package", Some("got")),
+            (
+                "request is deprecated, use node-fetch instead",
+                Some("node-fetch"),
+            ),
+            (
+                "This package has been renamed to @acme/ui.",
+                Some("@acme/ui"),
+            ),
+            ("Replaced by `uuid@9`, see the changelog", Some("uuid")),
+            ("moved to @scope/pkg", Some("@scope/pkg")),
+            ("Use String.prototype.padStart()", None),
+            ("Please use the new version (2.x)", None),
+            ("use it at your own risk", None),
+            ("use another package", None),
+            ("no longer maintained", None),
+            ("Misuse of this package is discouraged, use with care", None),
+        ] {
+            assert_eq!(deprecation_successor(message), successor, "{message}");
+        }
+    }
+}
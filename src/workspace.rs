@@ -0,0 +1,357 @@
+//! An index of the dependencies declared by every `package.json` of the workspace, for
+//! `workspace/symbol`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{Location, Range, SymbolInformation, SymbolKind, Url};
+use tree_sitter::Parser;
+
+use crate::parser::{self, DependencyKind};
+
+/// How many directories deep manifests are looked for, so that huge repositories don't stall
+/// startup.
+const MAX_DEPTH: usize = 6;
+
+/// How many symbols a query returns at most.
+const MAX_RESULTS: usize = 100;
+
+/// Dependencies of each indexed manifest.
+pub(super) type WorkspaceIndex = HashMap<Url, Vec<IndexedDependency>>;
+
+#[derive(Debug)]
+pub(super) struct IndexedDependency {
+    pub name: String,
+    pub section: DependencyKind,
+    /// Range of the package name, excluding the quotes around it.
+    pub range: Range,
+}
+
+/// Finds the manifests of a workspace. When the root manifest declares `workspaces`, only the
+/// packages matching those globs are included; otherwise every manifest outside `node_modules`
+/// is.
+pub(super) fn discover_manifests(root: &Path) -> Vec<PathBuf> {
+    let root_manifest = root.join("package.json");
+    let patterns = root_patterns(root);
+    let mut manifests = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), 0)];
+    while let Some((directory, depth)) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&directory) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if depth < MAX_DEPTH && name != "node_modules" && !name.starts_with('.') {
+                    pending.push((path, depth + 1));
+                }
+            } else if name == "package.json" {
+                let relative = directory.strip_prefix(root).unwrap_or(&directory);
+                if path == root_manifest
+                    || patterns.is_empty()
+                    || is_workspace_package(&patterns, relative)
+                {
+                    manifests.push(path);
+                }
+            }
+        }
+    }
+    manifests.sort();
+    manifests
+}
+
+/// Whether [`discover_manifests`] finds the manifest at `path` in the workspace at `root`, as
+/// manifests that change are indexed again.
+pub(super) fn is_workspace_manifest(root: &Path, path: &Path) -> bool {
+    let Some(relative) = path
+        .parent()
+        .and_then(|directory| directory.strip_prefix(root).ok())
+    else {
+        return false;
+    };
+    if relative.as_os_str().is_empty() {
+        return true;
+    }
+    let is_searched = relative.components().count() <= MAX_DEPTH
+        && relative.components().all(|component| {
+            let name = component.as_os_str().to_string_lossy();
+            name != "node_modules" && !name.starts_with('.')
+        });
+    let patterns = root_patterns(root);
+    is_searched && (patterns.is_empty() || is_workspace_package(&patterns, relative))
+}
+
+/// The globs of the `workspaces` of the manifest at the root of a workspace.
+fn root_patterns(root: &Path) -> Vec<String> {
+    std::fs::read(root.join("package.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_slice::<Value>(&contents).ok())
+        .map(|manifest| workspace_patterns(&manifest))
+        .unwrap_or_default()
+}
+
+/// The globs of a root manifest's `workspaces`, which npm and yarn accept as an array and yarn
+/// also as `{ "packages": [...] }`.
+fn workspace_patterns(manifest: &Value) -> Vec<String> {
+    let workspaces = &manifest["workspaces"];
+    workspaces
+        .as_array()
+        .or_else(|| workspaces["packages"].as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(|pattern| {
+            pattern
+                .trim_start_matches("./")
+                .trim_end_matches('/')
+                .to_string()
+        })
+        .collect()
+}
+
+/// Whether a directory, relative to the workspace root, matches the workspace globs. Globs
+/// starting with `!` exclude what earlier ones included.
+fn is_workspace_package(patterns: &[String], directory: &Path) -> bool {
+    let segments: Vec<_> = directory
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(AsRef::as_ref).collect();
+    let mut included = false;
+    for pattern in patterns {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern.as_str()),
+        };
+        let glob: Vec<_> = pattern.split('/').collect();
+        if matches_glob(&glob, &segments) {
+            included = !negated;
+        }
+    }
+    included
+}
+
+/// Matches path segments against glob segments, where `**` stands for any number of segments and
+/// `*` for any part of a single one.
+fn matches_glob(glob: &[&str], segments: &[&str]) -> bool {
+    match glob.split_first() {
+        None => segments.is_empty(),
+        Some((&"**", rest)) => {
+            (0..=segments.len()).any(|skipped| matches_glob(rest, &segments[skipped..]))
+        }
+        Some((pattern, rest)) => segments.split_first().is_some_and(|(segment, segments)| {
+            matches_segment(pattern, segment) && matches_glob(rest, segments)
+        }),
+    }
+}
+
+fn matches_segment(pattern: &str, segment: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = segment.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(ix) => rest = &rest[ix + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The dependencies declared by a manifest.
+pub(super) fn index_manifest(parser: &mut Parser, text: &str) -> Vec<IndexedDependency> {
+    let Some(tree) = parser.parse(text, None) else {
+        return Vec::new();
+    };
+    parser::extract_all_dependencies(text, &tree)
+        .into_iter()
+        .map(|dependency| IndexedDependency {
+            name: dependency.name,
+            section: dependency.section,
+            range: dependency.name_range,
+        })
+        .collect()
+}
+
+/// How well `candidate` matches a query whose characters it contains in order, if it does at
+/// all. Lower is better: exact matches come first, then prefixes, substrings and scattered
+/// matches.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<usize> {
+    let query = query.to_lowercase();
+    let candidate = candidate.to_lowercase();
+    if candidate == query {
+        return Some(0);
+    }
+    if candidate.starts_with(&query) {
+        return Some(1);
+    }
+    if candidate.contains(&query) {
+        return Some(2);
+    }
+    let mut characters = candidate.chars();
+    query
+        .chars()
+        .all(|c| characters.any(|candidate| candidate == c))
+        .then_some(3)
+}
+
+/// Dependencies whose name matches the query, best matches first.
+pub(super) fn search(index: &WorkspaceIndex, query: &str) -> Vec<SymbolInformation> {
+    let mut matches: Vec<_> = index
+        .iter()
+        .flat_map(|(uri, dependencies)| {
+            dependencies.iter().filter_map(move |dependency| {
+                let score = fuzzy_score(query, &dependency.name)?;
+                Some((score, uri, dependency))
+            })
+        })
+        .collect();
+    matches.sort_by(|(a, a_uri, a_dependency), (b, b_uri, b_dependency)| {
+        a.cmp(b)
+            .then_with(|| a_dependency.name.cmp(&b_dependency.name))
+            .then_with(|| a_uri.as_str().cmp(b_uri.as_str()))
+            .then_with(|| a_dependency.range.start.cmp(&b_dependency.range.start))
+    });
+    #[allow(deprecated)]
+    matches
+        .into_iter()
+        .take(MAX_RESULTS)
+        .map(|(_, uri, dependency)| SymbolInformation {
+            name: dependency.name.clone(),
+            kind: SymbolKind::CONSTANT,
+            tags: None,
+            deprecated: None,
+            location: Location::new(uri.clone(), dependency.range),
+            container_name: Some(dependency.section.section_name().to_string()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+    use tree_sitter_json::language;
+
+    #[test]
+    fn test_workspace_globs() {
+        let patterns: Vec<_> = ["packages/*", "apps/**", "!packages/legacy"]
+            .map(String::from)
+            .into();
+        let matches = |directory: &str| is_workspace_package(&patterns, Path::new(directory));
+        assert!(matches("packages/ui"));
+        assert!(matches("apps/web/admin"));
+        assert!(!matches("packages/legacy"));
+        assert!(!matches("packages/ui/fixtures"));
+        assert!(!matches("docs"));
+
+        assert!(matches_segment("ui-*", "ui-kit"));
+        assert!(matches_segment("*-kit", "ui-kit"));
+        assert!(!matches_segment("ui-*", "web"));
+        assert_eq!(
+            workspace_patterns(&serde_json::json!({ "workspaces": { "packages": ["./libs/*/"] } })),
+            ["libs/*"]
+        );
+    }
+
+    #[test]
+    fn test_discover_manifests() {
+        let root = TempDir::new("workspace");
+        let write = |path: &str, contents: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write("package.json", r#"{ "workspaces": ["packages/*"] }"#);
+        write("packages/ui/package.json", "{}");
+        write("packages/ui/node_modules/lodash/package.json", "{}");
+        write("examples/demo/package.json", "{}");
+
+        let manifests: Vec<_> = discover_manifests(&root)
+            .into_iter()
+            .map(|path| path.strip_prefix(&root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            manifests,
+            [
+                PathBuf::from("package.json"),
+                PathBuf::from("packages/ui/package.json")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_workspace_manifest() {
+        let root = TempDir::new("workspace-manifest");
+        std::fs::write(
+            root.join("package.json"),
+            r#"{ "workspaces": ["packages/*"] }"#,
+        )
+        .unwrap();
+
+        for (path, expected) in [
+            ("package.json", true),
+            ("packages/ui/package.json", true),
+            ("packages/ui/node_modules/lodash/package.json", false),
+            ("examples/demo/package.json", false),
+            (".cache/package.json", false),
+        ] {
+            assert_eq!(
+                is_workspace_manifest(&root, &root.join(path)),
+                expected,
+                "{path}"
+            );
+        }
+        assert!(!is_workspace_manifest(
+            &root,
+            Path::new("/elsewhere/package.json")
+        ));
+    }
+
+    #[test]
+    fn test_search() {
+        let mut parser = Parser::new();
+        parser.set_language(&language()).unwrap();
+        let app = Url::parse("file:///repo/apps/web/package.json").unwrap();
+        let ui = Url::parse("file:///repo/packages/ui/package.json").unwrap();
+        let index = WorkspaceIndex::from([
+            (
+                app.clone(),
+                index_manifest(
+                    &mut parser,
+                    r#"{ "dependencies": { "react": "^18", "react-dom": "^18" } }"#,
+                ),
+            ),
+            (
+                ui.clone(),
+                index_manifest(&mut parser, r#"{ "peerDependencies": { "react": "*" } }"#),
+            ),
+        ]);
+        let results: Vec<_> = search(&index, "react")
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.location.uri, symbol.container_name))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("react".into(), app.clone(), Some("dependencies".into())),
+                ("react".into(), ui, Some("peerDependencies".into())),
+                ("react-dom".into(), app, Some("dependencies".into())),
+            ]
+        );
+        assert_eq!(search(&index, "rdm").len(), 1);
+        assert!(search(&index, "vue").is_empty());
+    }
+}
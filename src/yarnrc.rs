@@ -0,0 +1,156 @@
+//! Registries configured in Yarn's `.yarnrc.yml`, which Yarn 2 and later read instead of
+//! `.npmrc`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+
+use crate::fetcher::{interpolate_env, DEFAULT_REGISTRY};
+use crate::npmrc::{credential_prefix, Authorization, NpmrcRegistries};
+use crate::pnpm::split_entry;
+
+/// Reads `npmRegistryServer`, `npmAuthToken` and `npmAuthIdent` at the top level and for each scope
+/// of `npmScopes`, ignoring every other setting. Credentials are sent to the registry configured
+/// next to them, which is npm's public one at the top level unless another one is set. Those of a
+/// scope without a registry of its own are left out, as they would be sent to every package.
+///
+/// Only the block mappings Yarn's docs use are understood, not YAML as a whole.
+pub(super) fn parse(text: &str, env: impl Fn(&str) -> Option<String>) -> NpmrcRegistries {
+    // The settings of the top level, keyed by `None`, and of each scope, without its `@`.
+    let mut levels: BTreeMap<Option<&str>, HashMap<&str, String>> = BTreeMap::new();
+    // Keys of the mappings that enclose the current line, along with their indentation.
+    let mut parents: Vec<(usize, &str)> = Vec::new();
+    for line in text.lines() {
+        let content = line.trim_start();
+        if content.is_empty() || content.starts_with(['#', '-']) {
+            continue;
+        }
+        let indent = line.len() - content.len();
+        let Some((key, value)) = split_entry(content) else {
+            continue;
+        };
+        while parents.last().is_some_and(|(parent, _)| *parent >= indent) {
+            parents.pop();
+        }
+        let keys: Vec<_> = parents.iter().map(|(_, key)| *key).collect();
+        let level = match keys.as_slice() {
+            [] => Some(None),
+            ["npmScopes", scope] => Some(Some(scope.trim_start_matches('@'))),
+            _ => None,
+        };
+        if let Some(level) = level {
+            // Settings referring to environment variables that aren't set are left out.
+            if let Ok(value) = interpolate_env(value, &env) {
+                if !value.is_empty() {
+                    levels.entry(level).or_default().insert(key, value);
+                }
+            }
+        }
+        parents.push((indent, key));
+    }
+
+    let mut registries = NpmrcRegistries::default();
+    for (scope, settings) in levels {
+        let server = settings.get("npmRegistryServer").cloned();
+        let registry = match scope {
+            None => {
+                registries.default_registry = server.clone();
+                server.unwrap_or_else(|| DEFAULT_REGISTRY.to_string())
+            }
+            Some(scope) => {
+                let Some(server) = server else {
+                    continue;
+                };
+                registries
+                    .scope_registries
+                    .insert(format!("@{scope}"), server.clone());
+                server
+            }
+        };
+        if let (Some(prefix), Some(authorization)) =
+            (credential_prefix(&registry), authorization(&settings))
+        {
+            registries
+                .credentials
+                .entry(prefix)
+                .or_insert(authorization);
+        }
+    }
+    registries
+}
+
+/// The `Authorization` header of a level's `npmAuthToken`, or else of its `npmAuthIdent`.
+fn authorization(settings: &HashMap<&str, String>) -> Option<Authorization> {
+    if let Some(token) = settings.get("npmAuthToken") {
+        return Some(Authorization(format!("Bearer {token}")));
+    }
+    let ident = settings.get("npmAuthIdent")?;
+    // Yarn encodes `username:password` itself, and takes anything else as encoded already.
+    let ident = match ident.contains(':') {
+        true => BASE64_STANDARD.encode(ident),
+        false => ident.clone(),
+    };
+    Some(Authorization(format!("Basic {ident}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let registries = parse(
+            r#"nodeLinker: node-modules
+npmRegistryServer: "https://npm.acme.dev"
+npmAuthToken: ${NPM_TOKEN}
+
+npmScopes:
+  acme:
+    npmRegistryServer: https://npm.acme.dev/scoped/
+    npmAuthIdent: "user:pass"
+  "@corp":
+    npmRegistryServer: 'https://npm.corp.dev'
+    npmAuthToken: "${CORP_TOKEN}"
+  tools:
+    npmAuthToken: tools-token
+
+packageExtensions:
+  npmRegistryServer: https://not-a-setting.dev
+"#,
+            |name| (name == "NPM_TOKEN").then(|| "npm_s3cr3t".to_string()),
+        );
+        assert_eq!(
+            registries,
+            NpmrcRegistries {
+                default_registry: Some("https://npm.acme.dev".into()),
+                scope_registries: HashMap::from([
+                    (
+                        "@acme".to_string(),
+                        "https://npm.acme.dev/scoped/".to_string()
+                    ),
+                    ("@corp".to_string(), "https://npm.corp.dev".to_string()),
+                ]),
+                credentials: HashMap::from([
+                    (
+                        "//npm.acme.dev/".to_string(),
+                        Authorization("Bearer npm_s3cr3t".into())
+                    ),
+                    (
+                        "//npm.acme.dev/scoped/".to_string(),
+                        Authorization("Basic dXNlcjpwYXNz".into())
+                    ),
+                ]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_default_registry_credentials() {
+        let registries = parse("npmAuthIdent: dXNlcjpwYXNz\n", |_| None);
+        assert_eq!(registries.default_registry, None);
+        assert_eq!(
+            registries.credentials["//registry.npmjs.org/"],
+            Authorization("Basic dXNlcjpwYXNz".into())
+        );
+    }
+}